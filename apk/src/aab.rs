@@ -0,0 +1,1110 @@
+use crate::arsc::{Entry, Item, Package, ResourceTable, Type, Value};
+use crate::compiler::Output;
+use crate::compiler::{StringPool, Symbols};
+use crate::jar::JarDigest;
+use crate::manifest::{AndroidManifest, ANDROID_NS};
+use crate::pb::{Message, Reader};
+use crate::res::{
+    Chunk, ResTableConfig, ResTableRef, ResValueType, ResXmlAttribute, ResXmlCdata,
+    ResXmlEndElement, ResXmlNamespace, ResXmlNodeHeader, ResXmlStartElement, ARRAY_ITEM, ATTR_MANY,
+    ATTR_MAX, ATTR_MIN, ATTR_OTHER, ATTR_TYPE,
+};
+use crate::{IconBackground, Target};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use xcommon::{Scaler, Signer, Zip, ZipFileOptions};
+
+/// Version of bundletool the bundle is compatible with.
+pub(crate) const BUNDLETOOL_VERSION: &str = "1.15.6";
+
+/// Writes an android app bundle with a single `base` module. The manifest,
+/// resource table and xml resources are stored in the protobuf format of
+/// aapt2.
+pub struct Aab {
+    manifest: AndroidManifest,
+    path: PathBuf,
+    zip: Zip,
+    icon_background: IconBackground,
+    page_size: Option<u16>,
+}
+
+impl Aab {
+    pub fn new(path: PathBuf, manifest: AndroidManifest, compress: bool) -> Result<Self> {
+        let zip = Zip::new(&path, compress)?;
+        Ok(Self {
+            manifest,
+            path,
+            zip,
+            icon_background: Default::default(),
+            page_size: None,
+        })
+    }
+
+    /// Background of the adaptive launcher icon, defaults to white. Must be
+    /// set before calling [`Aab::add_res`].
+    pub fn set_icon_background(&mut self, background: IconBackground) {
+        self.icon_background = background;
+    }
+
+    /// Stores native libraries uncompressed and page aligned like
+    /// [`Apk::set_page_size`](crate::Apk::set_page_size) and tells
+    /// bundletool to keep them uncompressed in the generated apks. Must be
+    /// set before calling [`Aab::add_res`].
+    pub fn set_page_size(&mut self, page_size: u16) -> Result<()> {
+        anyhow::ensure!(
+            crate::align::PAGE_SIZES.contains(&page_size),
+            "invalid page size {}, expected one of {:?}",
+            page_size,
+            crate::align::PAGE_SIZES
+        );
+        self.page_size = Some(page_size);
+        self.manifest.application.extract_native_libs = Some(false);
+        Ok(())
+    }
+
+    /// Compiles the `res` directories, the icon and the manifest like
+    /// [`Apk::add_res`](crate::Apk::add_res).
+    pub fn add_res(
+        &mut self,
+        res: Option<&Path>,
+        libraries: &[PathBuf],
+        icon: Option<&Scaler>,
+        android: &Path,
+    ) -> Result<Symbols> {
+        let (outputs, symbols) = crate::compiler::compile_resources(
+            &mut self.manifest,
+            &self.icon_background,
+            res,
+            libraries,
+            icon,
+            android,
+        )?;
+        for output in outputs {
+            let (path, bytes) = match output {
+                Output::Table(chunk) => ("resources.pb".into(), table_to_proto(&chunk)?),
+                Output::Xml(path, chunk) => {
+                    let path = if path == "AndroidManifest.xml" {
+                        "manifest/AndroidManifest.xml".into()
+                    } else {
+                        path
+                    };
+                    (path, xml_to_proto(&chunk)?)
+                }
+                Output::File(path, bytes) => (path, bytes),
+            };
+            self.zip.create_file(
+                &Path::new("base").join(path),
+                ZipFileOptions::Compressed,
+                &bytes,
+            )?;
+        }
+        Ok(symbols)
+    }
+
+    pub fn add_asset(&mut self, asset: &Path, opts: ZipFileOptions) -> Result<()> {
+        self.add_asset_with(asset, |_| opts)
+    }
+
+    /// Adds an asset with the options returned by `opts` for the path of
+    /// each file relative to the asset directory, or the file name of an
+    /// asset file.
+    pub fn add_asset_with(
+        &mut self,
+        asset: &Path,
+        opts: impl Fn(&Path) -> ZipFileOptions,
+    ) -> Result<()> {
+        let file_name = asset
+            .file_name()
+            .context("Asset must have file_name component")?;
+        let dest = Path::new("base").join("assets").join(file_name);
+        if asset.is_dir() {
+            self.zip.add_directory_with(asset, &dest, opts)
+        } else {
+            self.zip.add_file(asset, &dest, opts(Path::new(file_name)))
+        }
+        .with_context(|| format!("While embedding asset `{}`", asset.display()))
+    }
+
+    pub fn add_dex(&mut self, dex: &Path) -> Result<()> {
+        self.zip.add_file(
+            dex,
+            Path::new("base/dex/classes.dex"),
+            ZipFileOptions::Compressed,
+        )
+    }
+
+    /// Adds the dex files of a multidex app as `classes.dex`, `classes2.dex`,
+    /// ... in order.
+    pub fn add_dexes(&mut self, dexes: &[PathBuf]) -> Result<()> {
+        for (i, dex) in dexes.iter().enumerate() {
+            self.zip.add_file(
+                dex,
+                &Path::new("base/dex").join(crate::dex_name(i)),
+                ZipFileOptions::Compressed,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn add_lib(&mut self, target: Target, path: &Path) -> Result<()> {
+        let name = path.file_name().context("invalid path")?;
+        let opts = crate::lib_options(path, self.page_size)?;
+        self.zip.add_file(
+            path,
+            &Path::new("base/lib").join(target.as_str()).join(name),
+            opts,
+        )
+    }
+
+    /// Writes `BundleConfig.pb` and JAR signs the bundle.
+    pub fn finish(mut self, signer: Option<Signer>) -> Result<()> {
+        self.zip.create_file(
+            Path::new("BundleConfig.pb"),
+            ZipFileOptions::Compressed,
+            bundle_config(self.page_size).as_bytes(),
+        )?;
+        self.zip.finish()?;
+        let signer = signer.map(Ok).unwrap_or_else(crate::sign::debug_signer)?;
+        crate::jar::sign(&self.path, &signer, false, JarDigest::Sha256)
+    }
+}
+
+/// Bundletool configuration splitting the apks by abi, screen density and
+/// language. With a `page_size` native libraries stay uncompressed.
+fn bundle_config(page_size: Option<u16>) -> Message {
+    let mut bundletool = Message::new();
+    bundletool.string(2, BUNDLETOOL_VERSION);
+    let mut splits = Message::new();
+    // ABI, SCREEN_DENSITY and LANGUAGE
+    for dimension in [1, 2, 3] {
+        splits.message(1, Message::new().uint(1, dimension));
+    }
+    let mut optimizations = Message::new();
+    optimizations.message(1, &splits);
+    if let Some(page_size) = page_size {
+        // PAGE_ALIGNMENT_4K or PAGE_ALIGNMENT_16K
+        let alignment = if page_size == 16384 { 2 } else { 1 };
+        let mut uncompress = Message::new();
+        uncompress.bool(1, true).uint(2, alignment);
+        optimizations.message(2, &uncompress);
+    }
+    let mut config = Message::new();
+    config.message(1, &bundletool).message(2, &optimizations);
+    config
+}
+
+/// Returns the page size native libraries are aligned to if a
+/// `BundleConfig.pb` keeps them uncompressed.
+pub fn bundle_page_size(config: &[u8]) -> Result<Option<u16>> {
+    let uncompress = Reader::new(config)?
+        .message(2)?
+        .map(|optimizations| optimizations.message(2))
+        .transpose()?
+        .flatten()
+        .filter(|uncompress| uncompress.bool(1));
+    Ok(uncompress.map(|uncompress| match uncompress.uint(2) {
+        2 => 16384,
+        _ => 4096,
+    }))
+}
+
+/// Converts a binary xml document into an `aapt.pb.XmlNode`.
+pub fn xml_to_proto(chunk: &Chunk) -> Result<Vec<u8>> {
+    let chunks = if let Chunk::Xml(chunks) = chunk {
+        chunks
+    } else {
+        anyhow::bail!("expected an xml chunk");
+    };
+    let mut strings: &[String] = &[];
+    let mut resource_map: &[u32] = &[];
+    for chunk in chunks {
+        match chunk {
+            Chunk::StringPool(s, _) => strings = s,
+            Chunk::XmlResourceMap(map) => resource_map = map,
+            _ => {}
+        }
+    }
+    let string = |i: i32| -> Result<&str> {
+        strings
+            .get(i as usize)
+            .map(|s| s.as_str())
+            .with_context(|| format!("invalid string index {}", i))
+    };
+    let mut namespaces = vec![];
+    let mut stack: Vec<Message> = vec![];
+    let mut root = None;
+    for chunk in chunks {
+        match chunk {
+            Chunk::XmlStartNamespace(_, ns) => {
+                let mut namespace = Message::new();
+                if ns.prefix >= 0 {
+                    namespace.string(1, string(ns.prefix)?);
+                }
+                namespace.string(2, string(ns.uri)?);
+                namespaces.push(namespace);
+            }
+            Chunk::XmlStartElement(_, el, attrs) => {
+                let mut element = Message::new();
+                for namespace in namespaces.drain(..) {
+                    element.message(1, &namespace);
+                }
+                if el.namespace >= 0 {
+                    element.string(2, string(el.namespace)?);
+                }
+                element.string(3, string(el.name)?);
+                for attr in attrs {
+                    let mut attribute = Message::new();
+                    if attr.namespace >= 0 {
+                        attribute.string(1, string(attr.namespace)?);
+                    }
+                    attribute.string(2, string(attr.name)?);
+                    if attr.raw_value >= 0 {
+                        attribute.string(3, string(attr.raw_value)?);
+                    }
+                    if let Some(id) = resource_map.get(attr.name as usize) {
+                        attribute.uint(5, *id as u64);
+                    }
+                    if attr.typed_value.data_type != ResValueType::String as u8 {
+                        let item = Item::new(&attr.typed_value, strings);
+                        attribute.message(6, &item_to_proto(&item, ""));
+                    }
+                    element.message(4, &attribute);
+                }
+                stack.push(element);
+            }
+            Chunk::XmlCdata(_, cdata) => {
+                let mut node = Message::new();
+                node.string(2, string(cdata.data)?);
+                stack
+                    .last_mut()
+                    .context("text outside of the root element")?
+                    .message(5, &node);
+            }
+            Chunk::XmlEndElement(_, _) => {
+                let element = stack.pop().context("unbalanced end element")?;
+                let mut node = Message::new();
+                node.message(1, &element);
+                if let Some(parent) = stack.last_mut() {
+                    parent.message(5, &node);
+                } else {
+                    root = Some(node);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(root.context("missing root element")?.as_bytes().to_vec())
+}
+
+/// Converts a binary resource table into an `aapt.pb.ResourceTable`.
+pub fn table_to_proto(chunk: &Chunk) -> Result<Vec<u8>> {
+    let table = ResourceTable::from_chunk(chunk)?;
+    let mut pb = Message::new();
+    for package in &table.packages {
+        let mut pkg = Message::new();
+        pkg.message(1, Message::new().uint(1, package.id as u64));
+        pkg.string(2, &package.name);
+        for ty in &package.types {
+            let mut t = Message::new();
+            t.message(1, Message::new().uint(1, ty.id as u64));
+            t.string(2, &ty.name);
+            for entry in ty.entries.iter().flatten() {
+                let mut e = Message::new();
+                e.message(1, Message::new().uint(1, entry.id.entry() as u64));
+                e.string(2, &entry.name);
+                for (config, value) in &entry.values {
+                    let mut config_value = Message::new();
+                    config_value.message(1, &config_to_proto(config));
+                    config_value.message(2, &value_to_proto(value, &ty.name));
+                    e.message(6, &config_value);
+                }
+                t.message(3, &e);
+            }
+            pkg.message(3, &t);
+        }
+        pb.message(2, &pkg);
+    }
+    Ok(pb.as_bytes().to_vec())
+}
+
+fn reference(id: u32, attribute: bool) -> Message {
+    let mut pb = Message::new();
+    if attribute {
+        pb.uint(1, 1);
+    }
+    if id != 0 {
+        pb.uint(2, id as u64);
+    }
+    pb
+}
+
+/// Converts an item into an `aapt.pb.Item`. Strings starting with `res/`
+/// of types other than `string` are file references.
+fn item_to_proto(item: &Item, ty: &str) -> Message {
+    let mut pb = Message::new();
+    let mut prim = Message::new();
+    match item {
+        Item::Reference(id) => return pb.message(1, &reference(*id, false)).clone(),
+        Item::Attribute(id) => return pb.message(1, &reference(*id, true)).clone(),
+        Item::String(path) if ty != "string" && path.starts_with("res/") => {
+            let file_type = if path.ends_with(".xml") {
+                // PROTO_XML
+                3
+            } else if path.ends_with(".png") {
+                // PNG
+                1
+            } else {
+                0
+            };
+            let mut file = Message::new();
+            file.string(1, path).uint(2, file_type);
+            return pb.message(5, &file).clone();
+        }
+        Item::String(s) => return pb.message(2, Message::new().string(1, s)).clone(),
+        Item::Boolean(false) if ty == "id" => return pb.message(6, &Message::new()).clone(),
+        Item::Null => prim.message(1, &Message::new()),
+        Item::Float(f) => prim.float(3, *f),
+        Item::Dimension(data) => prim.uint(13, *data as u64),
+        Item::Fraction(data) => prim.uint(14, *data as u64),
+        Item::Integer(n) => prim.int(6, *n as i64),
+        Item::Hex(n) => prim.uint(7, *n as u64),
+        Item::Boolean(b) => prim.bool(8, *b),
+        Item::Color(color) => prim.uint(9, *color as u64),
+        Item::Unknown { data, .. } => prim.uint(7, *data as u64),
+    };
+    pb.message(7, &prim);
+    pb
+}
+
+/// Converts a value into an `aapt.pb.Value`.
+fn value_to_proto(value: &Value, ty: &str) -> Message {
+    let mut pb = Message::new();
+    let (parent, items) = match value {
+        Value::Item(item) => return pb.message(4, &item_to_proto(item, ty)).clone(),
+        Value::Bag { parent, items } => (*parent, items),
+    };
+    let mut compound = Message::new();
+    match ty {
+        "attr" | "^attr-private" => {
+            let mut attr = Message::new();
+            for (key, item) in items {
+                let (data, data_type) = match item {
+                    Item::Integer(n) => (*n as u32, ResValueType::IntDec),
+                    Item::Hex(n) => (*n, ResValueType::IntHex),
+                    _ => continue,
+                };
+                match *key {
+                    ATTR_TYPE => attr.uint(1, data as u64),
+                    ATTR_MIN => attr.int(2, data as i32 as i64),
+                    ATTR_MAX => attr.int(3, data as i32 as i64),
+                    key => {
+                        let mut symbol = Message::new();
+                        symbol
+                            .message(3, &reference(key, false))
+                            .uint(4, data as u64)
+                            .uint(5, data_type as u64);
+                        attr.message(4, &symbol)
+                    }
+                };
+            }
+            compound.message(1, &attr);
+        }
+        "array" => {
+            let mut array = Message::new();
+            for (_, item) in items {
+                array.message(1, Message::new().message(3, &item_to_proto(item, ty)));
+            }
+            compound.message(4, &array);
+        }
+        "plurals" => {
+            let mut plural = Message::new();
+            for (key, item) in items {
+                if !(ATTR_OTHER..=ATTR_MANY).contains(key) {
+                    continue;
+                }
+                // ZERO, ONE, TWO, FEW, MANY and OTHER
+                let arity = (key - ATTR_OTHER + 5) % 6;
+                let mut entry = Message::new();
+                entry
+                    .uint(3, arity as u64)
+                    .message(4, &item_to_proto(item, ty));
+                plural.message(1, &entry);
+            }
+            compound.message(5, &plural);
+        }
+        _ => {
+            let mut style = Message::new();
+            if parent != 0 {
+                style.message(1, &reference(parent, false));
+            }
+            for (key, item) in items {
+                let mut entry = Message::new();
+                entry
+                    .message(3, &reference(*key, false))
+                    .message(4, &item_to_proto(item, ty));
+                style.message(3, &entry);
+            }
+            compound.message(2, &style);
+        }
+    }
+    pb.message(5, &compound);
+    pb
+}
+
+/// Swaps `1` and `2`. Binary configs use `NO = 1, YES = 2` while the
+/// protobuf enums list the qualifier first.
+fn swap_yes_no(value: u8) -> u8 {
+    match value {
+        1 => 2,
+        2 => 1,
+        _ => 0,
+    }
+}
+
+/// Converts a config into an `aapt.pb.Configuration`.
+fn config_to_proto(c: &ResTableConfig) -> Message {
+    let mut pb = Message::new();
+    let mut field = |field: u32, value: u64| {
+        if value != 0 {
+            pb.uint(field, value);
+        }
+    };
+    field(1, c.mcc as u64);
+    field(2, c.mnc as u64);
+    field(
+        4,
+        ((c.screen_layout & ResTableConfig::MASK_LAYOUTDIR) >> 6) as u64,
+    );
+    field(5, c.screen_width as u64);
+    field(6, c.screen_height as u64);
+    field(7, c.screen_width_dp as u64);
+    field(8, c.screen_height_dp as u64);
+    field(9, c.smallest_screen_width_dp as u64);
+    field(
+        10,
+        (c.screen_layout & ResTableConfig::MASK_SCREENSIZE) as u64,
+    );
+    field(
+        11,
+        swap_yes_no((c.screen_layout & ResTableConfig::MASK_SCREENLONG) >> 4) as u64,
+    );
+    field(
+        12,
+        swap_yes_no(c.screen_layout2 & ResTableConfig::MASK_SCREENROUND) as u64,
+    );
+    field(
+        13,
+        swap_yes_no(c.color_mode & ResTableConfig::MASK_WIDE_COLOR_GAMUT) as u64,
+    );
+    field(
+        14,
+        swap_yes_no((c.color_mode & ResTableConfig::MASK_HDR) >> 2) as u64,
+    );
+    field(15, c.orientation as u64);
+    field(16, (c.ui_mode & ResTableConfig::MASK_UI_MODE_TYPE) as u64);
+    field(
+        17,
+        swap_yes_no((c.ui_mode & ResTableConfig::MASK_UI_MODE_NIGHT) >> 4) as u64,
+    );
+    field(18, c.density as u64);
+    field(19, c.touchscreen as u64);
+    field(20, (c.input_flags & ResTableConfig::MASK_KEYSHIDDEN) as u64);
+    field(21, c.keyboard as u64);
+    field(
+        22,
+        ((c.input_flags & ResTableConfig::MASK_NAVHIDDEN) >> 2) as u64,
+    );
+    field(23, c.navigation as u64);
+    field(24, c.sdk_version as u64);
+    if let Some(language) = c.language() {
+        let mut locale = language;
+        let script = trim_nul(&c.locale_script);
+        if !script.is_empty() && !c.locale_script_was_computed {
+            locale = format!("{}-{}", locale, script);
+        }
+        if let Some(region) = c.region() {
+            locale = format!("{}-{}", locale, region);
+        }
+        let variant = trim_nul(&c.locale_variant);
+        if !variant.is_empty() {
+            locale = format!("{}-{}", locale, variant);
+        }
+        pb.string(3, &locale);
+    }
+    pb
+}
+
+fn trim_nul(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+/// Converts an `aapt.pb.XmlNode` into a binary xml document.
+pub fn xml_from_proto(bytes: &[u8]) -> Result<Chunk> {
+    let node = Reader::new(bytes)?;
+    let root = node.message(1)?.context("missing root element")?;
+    // attributes with a resource id come first in the string pool
+    let mut ids = BTreeMap::new();
+    collect_resource_ids(&root, &mut ids)?;
+    let mut strings = StringPool::default();
+    for name in ids.values() {
+        strings.id(name);
+    }
+    let mut chunks = vec![Chunk::Null, Chunk::Null];
+    element_from_proto(&root, &mut strings, &mut chunks)?;
+    chunks[0] = Chunk::StringPool(strings.into_strings(), vec![]);
+    chunks[1] = Chunk::XmlResourceMap(ids.into_keys().collect());
+    Ok(Chunk::Xml(chunks))
+}
+
+fn collect_resource_ids(element: &Reader, ids: &mut BTreeMap<u32, String>) -> Result<()> {
+    for attr in element.messages(4)? {
+        let id = attr.uint(5) as u32;
+        if id != 0 {
+            ids.insert(id, attr.string(2)?.to_string());
+        }
+    }
+    for child in element.messages(5)? {
+        if let Some(element) = child.message(1)? {
+            collect_resource_ids(&element, ids)?;
+        }
+    }
+    Ok(())
+}
+
+fn element_from_proto(
+    element: &Reader,
+    strings: &mut StringPool,
+    chunks: &mut Vec<Chunk>,
+) -> Result<()> {
+    let mut string = |s: &str| {
+        if s.is_empty() {
+            -1
+        } else {
+            strings.id(s) as i32
+        }
+    };
+    let mut namespaces = vec![];
+    for ns in element.messages(1)? {
+        namespaces.push(ResXmlNamespace {
+            prefix: string(ns.string(1)?),
+            uri: string(ns.string(2)?),
+        });
+    }
+    for ns in &namespaces {
+        chunks.push(Chunk::XmlStartNamespace(ResXmlNodeHeader::default(), *ns));
+    }
+    let namespace = string(element.string(2)?);
+    let name = string(element.string(3)?);
+    let mut attrs = vec![];
+    for attr in element.messages(4)? {
+        let namespace_uri = attr.string(1)?;
+        let value = attr.string(3)?;
+        let item = attr
+            .message(6)?
+            .map(|item| item_from_proto(&item))
+            .transpose()?;
+        let raw_value = if item.is_some() && namespace_uri == ANDROID_NS {
+            -1
+        } else {
+            strings.id(value) as i32
+        };
+        let typed_value = if let Some(item) = item {
+            item.to_value(strings)
+        } else {
+            Item::String(value.to_string()).to_value(strings)
+        };
+        let namespace = if namespace_uri.is_empty() {
+            -1
+        } else {
+            strings.id(namespace_uri) as i32
+        };
+        attrs.push(ResXmlAttribute {
+            namespace,
+            name: strings.id(attr.string(2)?) as i32,
+            raw_value,
+            typed_value,
+        });
+    }
+    // attributes are sorted by resource id
+    attrs.sort_by_key(|attr| attr.name);
+    let mut id_index = 0;
+    let mut class_index = 0;
+    let mut style_index = 0;
+    for (i, attr) in attrs.iter().enumerate() {
+        let index = i as u16 + 1;
+        match strings.get(attr.name as u32) {
+            Some("id") => id_index = index,
+            Some("class") => class_index = index,
+            Some("style") => style_index = index,
+            _ => {}
+        }
+    }
+    chunks.push(Chunk::XmlStartElement(
+        ResXmlNodeHeader::default(),
+        ResXmlStartElement {
+            namespace,
+            name,
+            attribute_start: 0x0014,
+            attribute_size: 0x0014,
+            attribute_count: attrs.len() as _,
+            id_index,
+            class_index,
+            style_index,
+        },
+        attrs,
+    ));
+    for child in element.messages(5)? {
+        if let Some(element) = child.message(1)? {
+            element_from_proto(&element, strings, chunks)?;
+        } else {
+            chunks.push(Chunk::XmlCdata(
+                ResXmlNodeHeader::default(),
+                ResXmlCdata {
+                    data: strings.id(child.string(2)?) as i32,
+                    typed_data: Item::Null.to_value(strings),
+                },
+            ));
+        }
+    }
+    chunks.push(Chunk::XmlEndElement(
+        ResXmlNodeHeader::default(),
+        ResXmlEndElement { namespace, name },
+    ));
+    for ns in namespaces.into_iter().rev() {
+        chunks.push(Chunk::XmlEndNamespace(ResXmlNodeHeader::default(), ns));
+    }
+    Ok(())
+}
+
+/// Converts an `aapt.pb.ResourceTable` into a resource table.
+pub fn table_from_proto(bytes: &[u8]) -> Result<ResourceTable> {
+    let table = Reader::new(bytes)?;
+    let mut packages = vec![];
+    for package in table.messages(2)? {
+        let id = package.message(1)?.unwrap_or_default().uint(1) as u8;
+        let mut types = vec![];
+        for ty in package.messages(3)? {
+            let type_id = ty.message(1)?.unwrap_or_default().uint(1) as u8;
+            let name = ty.string(2)?;
+            let mut entries: Vec<Option<Entry>> = vec![];
+            for entry in ty.messages(3)? {
+                let entry_id = entry.message(1)?.unwrap_or_default().uint(1) as u16;
+                let mut values = vec![];
+                for config_value in entry.messages(6)? {
+                    let config = config_value.message(1)?.unwrap_or_default();
+                    let value = config_value.message(2)?.unwrap_or_default();
+                    if let Some(value) = value_from_proto(&value)? {
+                        values.push((config_from_proto(&config)?, value));
+                    }
+                }
+                let i = entry_id as usize;
+                if i >= entries.len() {
+                    entries.resize(i + 1, None);
+                }
+                entries[i] = Some(Entry {
+                    id: ResTableRef::new(id, type_id, entry_id),
+                    name: entry.string(2)?.to_string(),
+                    values,
+                });
+            }
+            types.push(Type {
+                id: type_id,
+                name: name.to_string(),
+                entries,
+            });
+        }
+        packages.push(Package {
+            id,
+            name: package.string(2)?.to_string(),
+            types,
+        });
+    }
+    Ok(ResourceTable { packages })
+}
+
+fn reference_from_proto(reference: &Reader) -> Item {
+    let id = reference.uint(2) as u32;
+    if reference.uint(1) == 1 {
+        Item::Attribute(id)
+    } else {
+        Item::Reference(id)
+    }
+}
+
+/// Converts an `aapt.pb.Item`.
+fn item_from_proto(item: &Reader) -> Result<Item> {
+    if let Some(reference) = item.message(1)? {
+        return Ok(reference_from_proto(&reference));
+    }
+    for field in [2, 3, 4, 5] {
+        if let Some(s) = item.message(field)? {
+            return Ok(Item::String(s.string(1)?.to_string()));
+        }
+    }
+    if item.has(6) {
+        return Ok(Item::Boolean(false));
+    }
+    let prim = if let Some(prim) = item.message(7)? {
+        prim
+    } else {
+        anyhow::bail!("unsupported item");
+    };
+    Ok(if prim.has(2) {
+        Item::Unknown {
+            data_type: ResValueType::Null as u8,
+            data: 1,
+        }
+    } else if prim.has(3) {
+        Item::Float(prim.float(3))
+    } else if prim.has(6) {
+        Item::Integer(prim.int(6) as i32)
+    } else if prim.has(7) {
+        Item::Hex(prim.uint(7) as u32)
+    } else if prim.has(8) {
+        Item::Boolean(prim.bool(8))
+    } else if let Some(field) = (9..=12).find(|field| prim.has(*field)) {
+        Item::Color(prim.uint(field) as u32)
+    } else if prim.has(13) {
+        Item::Dimension(prim.uint(13) as u32)
+    } else if prim.has(14) {
+        Item::Fraction(prim.uint(14) as u32)
+    } else {
+        Item::Null
+    })
+}
+
+/// Converts an `aapt.pb.Value`. Styleables aren't part of binary resource
+/// tables and return `None`.
+fn value_from_proto(value: &Reader) -> Result<Option<Value>> {
+    if let Some(item) = value.message(4)? {
+        return Ok(Some(Value::Item(item_from_proto(&item)?)));
+    }
+    let compound = value.message(5)?.context("value without item")?;
+    let mut parent = 0;
+    let mut items = vec![];
+    if let Some(attr) = compound.message(1)? {
+        items.push((ATTR_TYPE, Item::Integer(attr.uint(1) as i32)));
+        if attr.has(2) {
+            items.push((ATTR_MIN, Item::Integer(attr.int(2) as i32)));
+        }
+        if attr.has(3) {
+            items.push((ATTR_MAX, Item::Integer(attr.int(3) as i32)));
+        }
+        for symbol in attr.messages(4)? {
+            let key = symbol.message(3)?.unwrap_or_default().uint(2) as u32;
+            let data = symbol.uint(4) as u32;
+            let item = if symbol.uint(5) == ResValueType::IntHex as u64 {
+                Item::Hex(data)
+            } else {
+                Item::Integer(data as i32)
+            };
+            items.push((key, item));
+        }
+    } else if let Some(style) = compound.message(2)? {
+        parent = style.message(1)?.unwrap_or_default().uint(2) as u32;
+        for entry in style.messages(3)? {
+            let key = entry.message(3)?.unwrap_or_default().uint(2) as u32;
+            let item = entry.message(4)?.context("style entry without item")?;
+            items.push((key, item_from_proto(&item)?));
+        }
+    } else if let Some(array) = compound.message(4)? {
+        for (i, element) in array.messages(1)?.iter().enumerate() {
+            let item = element.message(3)?.context("array element without item")?;
+            items.push((ARRAY_ITEM + i as u32, item_from_proto(&item)?));
+        }
+    } else if let Some(plural) = compound.message(5)? {
+        for entry in plural.messages(1)? {
+            // ZERO, ONE, TWO, FEW, MANY and OTHER
+            let key = ATTR_OTHER + (entry.uint(3) as u32 + 1) % 6;
+            let item = entry.message(4)?.context("plural without item")?;
+            items.push((key, item_from_proto(&item)?));
+        }
+    } else {
+        return Ok(None);
+    }
+    items.sort_by_key(|(key, _)| *key);
+    Ok(Some(Value::Bag { parent, items }))
+}
+
+/// Converts an `aapt.pb.Configuration`.
+fn config_from_proto(pb: &Reader) -> Result<ResTableConfig> {
+    let field = |field: u32| pb.uint(field) as u8;
+    let mut c = ResTableConfig {
+        mcc: pb.uint(1) as u16,
+        mnc: pb.uint(2) as u16,
+        screen_width: pb.uint(5) as u16,
+        screen_height: pb.uint(6) as u16,
+        screen_width_dp: pb.uint(7) as u16,
+        screen_height_dp: pb.uint(8) as u16,
+        smallest_screen_width_dp: pb.uint(9) as u16,
+        orientation: field(15),
+        density: pb.uint(18) as u16,
+        touchscreen: field(19),
+        keyboard: field(21),
+        navigation: field(23),
+        sdk_version: pb.uint(24) as u16,
+        ..Default::default()
+    };
+    c.screen_layout = field(4) << 6 | field(10) | swap_yes_no(field(11)) << 4;
+    c.screen_layout2 = swap_yes_no(field(12));
+    c.color_mode = swap_yes_no(field(13)) | swap_yes_no(field(14)) << 2;
+    c.ui_mode = field(16) | swap_yes_no(field(17)) << 4;
+    c.input_flags = field(20) | field(22) << 2;
+    let locale = pb.string(3)?;
+    if !locale.is_empty() {
+        let qualifiers = format!("b+{}", locale.replace('-', "+"));
+        let locale = ResTableConfig::from_qualifiers(&qualifiers)
+            .with_context(|| format!("invalid locale {}", locale))?;
+        c.language = locale.language;
+        c.country = locale.country;
+        c.locale_script = locale.locale_script;
+        c.locale_variant = locale.locale_variant;
+        c.locale_script_was_computed = locale.locale_script_was_computed;
+    }
+    Ok(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{PackageBuilder, Table, Value as SourceValue};
+    use crate::res::ResAttributeType;
+    use std::io::Read;
+
+    #[test]
+    fn test_config_to_proto() -> Result<()> {
+        let config = ResTableConfig::from_qualifiers("de-rDE-night-xhdpi")?;
+        let mut expected = Message::new();
+        expected
+            .uint(17, 1)
+            .uint(18, 320)
+            .uint(24, 8)
+            .string(3, "de-DE");
+        assert_eq!(config_to_proto(&config), expected);
+        assert_eq!(config_to_proto(&Default::default()), Message::new());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_to_proto() -> Result<()> {
+        let mut package = PackageBuilder::new("com.example.helloworld");
+        package.add(
+            "string",
+            "app_name",
+            Default::default(),
+            SourceValue::Item {
+                format: ResAttributeType::String as u32,
+                value: "Hello".into(),
+            },
+        )?;
+        package.add(
+            "xml",
+            "paths",
+            Default::default(),
+            SourceValue::File("res/xml/paths.xml".into()),
+        )?;
+        let chunk = package.build(&mut Table::default())?;
+        let pb = table_to_proto(&chunk)?;
+
+        let string_entry = {
+            let mut value = Message::new();
+            value.message(
+                4,
+                Message::new().message(2, Message::new().string(1, "Hello")),
+            );
+            let mut config_value = Message::new();
+            config_value.message(1, &Message::new()).message(2, &value);
+            let mut entry = Message::new();
+            entry
+                .message(1, Message::new().uint(1, 0))
+                .string(2, "app_name")
+                .message(6, &config_value);
+            entry
+        };
+        let file_entry = {
+            let mut file = Message::new();
+            file.string(1, "res/xml/paths.xml").uint(2, 3);
+            let mut value = Message::new();
+            value.message(4, Message::new().message(5, &file));
+            let mut config_value = Message::new();
+            config_value.message(1, &Message::new()).message(2, &value);
+            let mut entry = Message::new();
+            entry
+                .message(1, Message::new().uint(1, 0))
+                .string(2, "paths")
+                .message(6, &config_value);
+            entry
+        };
+        let mut package = Message::new();
+        package
+            .message(1, Message::new().uint(1, 0x7f))
+            .string(2, "com.example.helloworld")
+            .message(
+                3,
+                Message::new()
+                    .message(1, Message::new().uint(1, 1))
+                    .string(2, "string")
+                    .message(3, &string_entry),
+            )
+            .message(
+                3,
+                Message::new()
+                    .message(1, Message::new().uint(1, 2))
+                    .string(2, "xml")
+                    .message(3, &file_entry),
+            );
+        let mut expected = Message::new();
+        expected.message(2, &package);
+        assert_eq!(pb, expected.as_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_from_proto() -> Result<()> {
+        let mut package = PackageBuilder::new("com.example.helloworld");
+        let mut add = |ty: &str, name: &str, config: &str, format, value: &str| {
+            package.add(
+                ty,
+                name,
+                ResTableConfig::from_qualifiers(config)?,
+                SourceValue::Item {
+                    format: format as u32,
+                    value: value.into(),
+                },
+            )
+        };
+        add("string", "app_name", "", ResAttributeType::String, "Hello")?;
+        add(
+            "string",
+            "app_name",
+            "de-rAT",
+            ResAttributeType::String,
+            "Servus",
+        )?;
+        add("color", "accent", "night", ResAttributeType::Color, "#f00")?;
+        add(
+            "dimen",
+            "margin",
+            "sw600dp",
+            ResAttributeType::Dimension,
+            "16dp",
+        )?;
+        add("integer", "count", "", ResAttributeType::Integer, "-3")?;
+        add(
+            "bool",
+            "enabled",
+            "round",
+            ResAttributeType::Boolean,
+            "true",
+        )?;
+        add("id", "title", "", ResAttributeType::Boolean, "false")?;
+        package.add(
+            "xml",
+            "paths",
+            Default::default(),
+            SourceValue::File("res/xml/paths.xml".into()),
+        )?;
+        let chunk = package.build(&mut Table::default())?;
+        let table = table_from_proto(&table_to_proto(&chunk)?)?;
+        assert_eq!(table, ResourceTable::from_chunk(&chunk)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_xml_from_proto() -> Result<()> {
+        let chunk = crate::compiler::xml::compile_xml(
+            r#"<paths xmlns:app="http://example.com/app"><path app:shared="true" name="files">text</path></paths>"#,
+            &Table::default(),
+        )?;
+        let roundtrip = xml_from_proto(&xml_to_proto(&chunk)?)?;
+        assert_eq!(
+            crate::compiler::decompile_xml(&roundtrip)?.to_string(),
+            crate::compiler::decompile_xml(&chunk)?.to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_xml_to_proto() -> Result<()> {
+        let chunk = crate::compiler::xml::compile_xml(
+            r#"<paths xmlns:app="http://example.com/app"><path app:shared="true">text</path></paths>"#,
+            &Table::default(),
+        )?;
+        let pb = xml_to_proto(&chunk)?;
+
+        let mut item = Message::new();
+        item.message(7, Message::new().bool(8, true));
+        let mut attribute = Message::new();
+        attribute
+            .string(1, "http://example.com/app")
+            .string(2, "shared")
+            .string(3, "true")
+            .message(6, &item);
+        let mut path = Message::new();
+        path.string(3, "path")
+            .message(4, &attribute)
+            .message(5, Message::new().string(2, "text"));
+        let mut namespace = Message::new();
+        namespace
+            .string(1, "app")
+            .string(2, "http://example.com/app");
+        let mut paths = Message::new();
+        paths
+            .message(1, &namespace)
+            .string(3, "paths")
+            .message(5, Message::new().message(1, &path));
+        let mut expected = Message::new();
+        expected.message(1, &paths);
+        assert_eq!(pb, expected.as_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn test_aab_layout() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("aab_layout.aab");
+        let lib = tmp.path().join("libhello.so");
+        std::fs::write(&lib, b"lib")?;
+        let mut aab = Aab::new(path.clone(), Default::default(), true)?;
+        aab.add_lib(Target::Arm64V8a, &lib)?;
+        aab.finish(None)?;
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&path)?)?;
+        let mut names = archive.file_names().collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                "BundleConfig.pb",
+                "META-INF/CERT.RSA",
+                "META-INF/CERT.SF",
+                "META-INF/MANIFEST.MF",
+                "base/lib/arm64-v8a/libhello.so",
+            ]
+        );
+        let mut config = vec![];
+        archive
+            .by_name("BundleConfig.pb")?
+            .read_to_end(&mut config)?;
+        assert_eq!(config, bundle_config(None).as_bytes());
+        assert_eq!(bundle_page_size(&config)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundle_page_size() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        for page_size in [4096, 16384] {
+            let config = bundle_config(Some(page_size));
+            assert_eq!(bundle_page_size(config.as_bytes())?, Some(page_size));
+        }
+        assert!(
+            Aab::new(tmp.path().join("page_size.aab"), Default::default(), true)?
+                .set_page_size(8192)
+                .is_err()
+        );
+        Ok(())
+    }
+}
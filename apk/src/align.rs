@@ -0,0 +1,173 @@
+//! Page alignment of native libraries, which are mapped directly from the
+//! apk when `android:extractNativeLibs` is `false`.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::path::Path;
+use xcommon::{ZipEntry, ZipInfo};
+
+const PT_LOAD: u32 = 1;
+
+/// Page sizes native libraries can be aligned to.
+pub const PAGE_SIZES: [u16; 2] = [4096, 16384];
+
+fn read<const N: usize>(bytes: &[u8], offset: u64) -> Result<[u8; N]> {
+    let offset = usize::try_from(offset)?;
+    bytes
+        .get(offset..offset + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .context("unexpected end of elf file")
+}
+
+fn read_u16(bytes: &[u8], offset: u64) -> Result<u16> {
+    Ok(u16::from_le_bytes(read(bytes, offset)?))
+}
+
+fn read_u32(bytes: &[u8], offset: u64) -> Result<u32> {
+    Ok(u32::from_le_bytes(read(bytes, offset)?))
+}
+
+fn read_u64(bytes: &[u8], offset: u64) -> Result<u64> {
+    Ok(u64::from_le_bytes(read(bytes, offset)?))
+}
+
+/// Returns the alignment of the `LOAD` segments of a little endian ELF
+/// shared library.
+pub fn segment_alignments(lib: &[u8]) -> Result<Vec<u64>> {
+    anyhow::ensure!(lib.starts_with(b"\x7fELF"), "not an elf file");
+    anyhow::ensure!(read::<1>(lib, 5)? == [1], "not a little endian elf file");
+    let is_64 = match read::<1>(lib, 4)? {
+        [1] => false,
+        [2] => true,
+        [class] => anyhow::bail!("invalid elf class {}", class),
+    };
+    let (phoff, phentsize, phnum) = if is_64 {
+        (
+            read_u64(lib, 0x20)?,
+            read_u16(lib, 0x36)?,
+            read_u16(lib, 0x38)?,
+        )
+    } else {
+        (
+            read_u32(lib, 0x1c)? as u64,
+            read_u16(lib, 0x2a)?,
+            read_u16(lib, 0x2c)?,
+        )
+    };
+    let mut alignments = vec![];
+    for i in 0..phnum as u64 {
+        let header = phoff + i * phentsize as u64;
+        if read_u32(lib, header)? != PT_LOAD {
+            continue;
+        }
+        let align = if is_64 {
+            read_u64(lib, header + 0x30)?
+        } else {
+            read_u32(lib, header + 0x1c)? as u64
+        };
+        alignments.push(align);
+    }
+    Ok(alignments)
+}
+
+/// Checks that the `LOAD` segments of the shared library at `path` are
+/// aligned to `page_size`, so that it can be mapped from the apk on devices
+/// with that page size.
+pub fn check_lib(path: &Path, page_size: u16) -> Result<()> {
+    let lib = std::fs::read(path)?;
+    let alignments =
+        segment_alignments(&lib).with_context(|| format!("invalid library {}", path.display()))?;
+    for align in alignments {
+        anyhow::ensure!(
+            align % page_size as u64 == 0,
+            "LOAD segment of {} is aligned to {} bytes instead of {}, link it with \
+             `-Wl,-z,max-page-size={}`",
+            path.display(),
+            align,
+            page_size,
+            page_size
+        );
+    }
+    Ok(())
+}
+
+/// Returns the entries of the zip at `path` that aren't aligned like
+/// `zipalign -c -p`. Uncompressed entries must start at a 4 byte boundary,
+/// uncompressed shared libraries at a `page_size` boundary.
+pub fn check_zip(path: &Path, page_size: u16) -> Result<Vec<ZipEntry>> {
+    let mut r = File::open(path)?;
+    let entries = ZipInfo::new(&mut r)?.entries(&mut r)?;
+    Ok(entries
+        .into_iter()
+        .filter(|entry| {
+            let align = if entry.name.ends_with(".so") {
+                page_size as u64
+            } else {
+                4
+            };
+            !entry.compressed && entry.data_start % align != 0
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcommon::{Zip, ZipFileOptions};
+
+    /// A minimal 64 bit shared library with two `LOAD` segments.
+    fn elf64(align: u64) -> Vec<u8> {
+        let mut elf = vec![0; 0x40 + 2 * 0x38];
+        elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        elf[0x20..0x28].copy_from_slice(&0x40u64.to_le_bytes());
+        elf[0x36..0x38].copy_from_slice(&0x38u16.to_le_bytes());
+        elf[0x38..0x3a].copy_from_slice(&2u16.to_le_bytes());
+        for i in 0..2 {
+            let header = 0x40 + i * 0x38;
+            elf[header..header + 4].copy_from_slice(&PT_LOAD.to_le_bytes());
+            elf[header + 0x30..header + 0x38].copy_from_slice(&align.to_le_bytes());
+        }
+        elf
+    }
+
+    #[test]
+    fn test_check_lib() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let dir = tmp.path();
+        let lib = dir.join("libhello.so");
+        assert_eq!(segment_alignments(&elf64(0x4000))?, [0x4000, 0x4000]);
+        std::fs::write(&lib, elf64(0x4000))?;
+        check_lib(&lib, 4096)?;
+        check_lib(&lib, 16384)?;
+        std::fs::write(&lib, elf64(0x1000))?;
+        check_lib(&lib, 4096)?;
+        assert!(check_lib(&lib, 16384).is_err());
+        std::fs::write(&lib, b"hello")?;
+        assert!(check_lib(&lib, 4096).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_zip() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("check_zip.apk");
+        let mut zip = Zip::new(&path, true)?;
+        zip.create_file(Path::new("classes.dex"), ZipFileOptions::Compressed, b"dex")?;
+        zip.create_file(
+            Path::new("lib/x86_64/liba.so"),
+            ZipFileOptions::Aligned(16384),
+            b"a",
+        )?;
+        zip.create_file(
+            Path::new("lib/x86_64/libb.so"),
+            ZipFileOptions::Aligned(4096),
+            b"b",
+        )?;
+        zip.finish()?;
+        assert!(check_zip(&path, 4096)?.is_empty());
+        let misaligned = check_zip(&path, 16384)?;
+        assert_eq!(misaligned.len(), 1);
+        assert_eq!(misaligned[0].name, "lib/x86_64/libb.so");
+        Ok(())
+    }
+}
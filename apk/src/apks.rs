@@ -0,0 +1,534 @@
+use crate::aab::{bundle_page_size, table_from_proto, xml_from_proto, BUNDLETOOL_VERSION};
+use crate::arsc::ResourceTable;
+use crate::compiler::Output;
+use crate::pb::Message;
+use crate::res::{Chunk, ResTableConfig};
+use crate::split::{density_split, path_config, split_manifest, split_path};
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use xcommon::{Signer, Zip, ZipFileOptions};
+use zip::ZipArchive;
+
+/// Name of the master split in an apk set.
+const MASTER: &str = "master";
+
+/// API level split apks are supported since.
+const SPLIT_MIN_SDK: u32 = 21;
+
+/// Abi names of the splits and their `Abi.AbiAlias`.
+const ABI_ALIASES: [(&str, u64); 8] = [
+    ("armeabi", 1),
+    ("armeabi_v7a", 2),
+    ("arm64_v8a", 3),
+    ("x86", 4),
+    ("x86_64", 5),
+    ("mips", 6),
+    ("mips64", 7),
+    ("riscv64", 8),
+];
+
+/// Density names of the splits and their `ScreenDensity.DensityAlias`.
+const DENSITY_ALIASES: [(&str, u64); 7] = [
+    ("ldpi", 2),
+    ("mdpi", 3),
+    ("tvdpi", 4),
+    ("hdpi", 5),
+    ("xhdpi", 6),
+    ("xxhdpi", 7),
+    ("xxxhdpi", 8),
+];
+
+/// Builds an apk set from the base module of an android app bundle like
+/// `bundletool build-apks`. The set contains `splits/base-master.apk`, a
+/// split per abi, density and language like `splits/base-arm64_v8a.apk`,
+/// `universal.apk` containing everything and a `toc.pb` describing the
+/// variants, so that it can be installed with `bundletool install-apks`.
+/// The universal apk is the standalone variant for devices before API level
+/// 21, it is only listed in the `toc.pb` if the app supports them.
+pub fn build_apks(aab: &Path, apks: &Path, signer: Option<Signer>) -> Result<()> {
+    let signer = signer.map(Ok).unwrap_or_else(crate::sign::debug_signer)?;
+    let mut archive = ZipArchive::new(File::open(aab)?)?;
+    let mut manifest = None;
+    let mut table = None;
+    let mut page_size = None;
+    let mut files = vec![];
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_string();
+        if name == "BundleConfig.pb" {
+            let mut config = vec![];
+            file.read_to_end(&mut config)?;
+            page_size = bundle_page_size(&config)?;
+            continue;
+        }
+        let path = if let Some(path) = name.strip_prefix("base/") {
+            path
+        } else {
+            anyhow::ensure!(
+                !name.ends_with("/manifest/AndroidManifest.xml"),
+                "only the base module is supported, found {}",
+                name
+            );
+            continue;
+        };
+        let mut bytes = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut bytes)?;
+        match path {
+            "manifest/AndroidManifest.xml" => manifest = Some(xml_from_proto(&bytes)?),
+            "resources.pb" => table = Some(table_from_proto(&bytes)?),
+            "assets.pb" | "native.pb" => {}
+            _ => {
+                let path = path
+                    .strip_prefix("dex/")
+                    .or_else(|| path.strip_prefix("root/"))
+                    .unwrap_or(path);
+                let bytes = if path.starts_with("res/")
+                    && path.ends_with(".xml")
+                    && !path.starts_with("res/raw")
+                {
+                    let mut buf = vec![];
+                    xml_from_proto(&bytes)
+                        .with_context(|| format!("invalid xml resource {}", path))?
+                        .write(&mut Cursor::new(&mut buf))?;
+                    buf
+                } else {
+                    bytes
+                };
+                files.push((path.to_string(), bytes));
+            }
+        }
+    }
+    let manifest = manifest.context("bundle is missing base/manifest/AndroidManifest.xml")?;
+    let android_manifest = crate::compiler::decompile_manifest(&manifest)?;
+    let min_sdk = android_manifest.sdk.min_sdk_version.unwrap_or(1);
+
+    let mut splits = BTreeSet::new();
+    for (path, _) in &files {
+        splits.extend(file_split(path)?);
+    }
+    if let Some(table) = &table {
+        for package in &table.packages {
+            for ty in &package.types {
+                for entry in ty.entries.iter().flatten() {
+                    splits.extend(entry.values.iter().filter_map(|(c, _)| config_split(c)));
+                }
+            }
+        }
+    }
+
+    let dir = apks.with_extension("apks.tmp");
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    std::fs::create_dir_all(&dir)?;
+    let mut set = vec![];
+
+    let universal = dir.join("universal.apk");
+    let all_files = files.iter().collect::<Vec<_>>();
+    write_apk(&universal, &manifest, table.as_ref(), &all_files, page_size)?;
+    set.push(("universal.apk".to_string(), universal));
+
+    let master_files = files
+        .iter()
+        .filter(|(path, _)| matches!(file_split(path), Ok(None)))
+        .collect::<Vec<_>>();
+    let master_table = table
+        .as_ref()
+        .map(|table| filter_table(table, |config| config_split(config).is_none()));
+    let master = dir.join("base-master.apk");
+    write_apk(
+        &master,
+        &manifest,
+        master_table.as_ref(),
+        &master_files,
+        page_size,
+    )?;
+    set.push((format!("splits/base-{}.apk", MASTER), master));
+
+    for split in &splits {
+        let split_files = files
+            .iter()
+            .filter(|(path, _)| matches!(file_split(path), Ok(Some(s)) if &s == split))
+            .collect::<Vec<_>>();
+        let split_table = table.as_ref().and_then(|table| {
+            let table = filter_table(table, |config| config_split(config).as_ref() == Some(split));
+            has_values(&table).then_some(table)
+        });
+        let name = split.strip_prefix("config.").unwrap();
+        let path = dir.join(format!("base-{}.apk", name));
+        let manifest = split_manifest(&android_manifest, split)?;
+        write_apk(
+            &path,
+            &manifest,
+            split_table.as_ref(),
+            &split_files,
+            page_size,
+        )?;
+        set.push((format!("splits/base-{}.apk", name), path));
+    }
+
+    let package = android_manifest
+        .package
+        .as_deref()
+        .context("missing manifest.package")?;
+    let toc = build_apks_result(package, min_sdk, &splits);
+    let mut zip = Zip::new(apks, false)?;
+    zip.create_file(
+        Path::new("toc.pb"),
+        ZipFileOptions::Compressed,
+        toc.as_bytes(),
+    )?;
+    for (name, path) in &set {
+        crate::sign_apk(path, &signer, min_sdk, None, false)?;
+        zip.add_file(path, Path::new(name), ZipFileOptions::Unaligned)?;
+    }
+    zip.finish()?;
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+/// Extracts the master and config splits of an apk set into `dir` and
+/// returns the path of the base apk. The splits are named like the
+/// splits of [`Apk::set_split`](crate::Apk::set_split).
+pub fn extract_apks(apks: &Path, dir: &Path) -> Result<PathBuf> {
+    if dir.exists() {
+        std::fs::remove_dir_all(dir)?;
+    }
+    std::fs::create_dir_all(dir)?;
+    let stem = apks.file_stem().context("invalid path")?.to_str().unwrap();
+    let base = dir.join(format!("{}.apk", stem));
+    let mut archive = ZipArchive::new(File::open(apks)?)?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let split = file
+            .name()
+            .strip_prefix("splits/base-")
+            .and_then(|name| name.strip_suffix(".apk"));
+        let path = match split {
+            Some(MASTER) => base.clone(),
+            Some(split) => split_path(&base, &format!("config.{}", split)),
+            None => continue,
+        };
+        std::io::copy(&mut file, &mut File::create(path)?)?;
+    }
+    anyhow::ensure!(base.exists(), "apk set is missing a master split");
+    Ok(base)
+}
+
+/// Table of contents of an apk set, a `BuildApksResult` of bundletool. Lists
+/// a split apk variant for API level 21 and up, and the universal apk as
+/// standalone variant if `min_sdk` is lower.
+fn build_apks_result(package: &str, min_sdk: u32, splits: &BTreeSet<String>) -> Message {
+    let sdk_targeting = |value: u32, alternative: Option<u32>| {
+        let sdk = |min: u32| {
+            // google.protobuf.Int32Value
+            Message::new()
+                .message(1, Message::new().uint(1, min as u64))
+                .clone()
+        };
+        let mut targeting = Message::new();
+        targeting.message(1, &sdk(value));
+        if let Some(alternative) = alternative {
+            targeting.message(2, &sdk(alternative));
+        }
+        targeting
+    };
+    let mut module = Message::new();
+    // INSTALL_TIME
+    module.string(1, "base").uint(6, 1);
+    let mut variants = vec![];
+
+    if min_sdk < SPLIT_MIN_SDK {
+        let sdk = sdk_targeting(min_sdk, Some(SPLIT_MIN_SDK));
+        let mut apk = Message::new();
+        apk.message(1, Message::new().message(5, &sdk))
+            .string(2, "universal.apk")
+            .message(4, Message::new().string(1, "base"));
+        let mut apk_set = Message::new();
+        apk_set.message(1, &module).message(2, &apk);
+        variants.push((sdk, apk_set));
+    }
+
+    let sdk = sdk_targeting(
+        min_sdk.max(SPLIT_MIN_SDK),
+        (min_sdk < SPLIT_MIN_SDK).then_some(min_sdk),
+    );
+    let mut apk_set = Message::new();
+    apk_set.message(1, &module);
+    let mut master = Message::new();
+    master
+        .message(1, Message::new().message(5, &sdk))
+        .string(2, &format!("splits/base-{}.apk", MASTER))
+        .message(3, Message::new().string(1, "").bool(2, true));
+    apk_set.message(2, &master);
+    for split in splits {
+        let mut targeting = split_targeting(split, splits);
+        targeting.message(5, &sdk);
+        let name = split.strip_prefix("config.").unwrap();
+        let mut apk = Message::new();
+        apk.message(1, &targeting)
+            .string(2, &format!("splits/base-{}.apk", name))
+            .message(3, Message::new().string(1, split).bool(2, false));
+        apk_set.message(2, &apk);
+    }
+    variants.push((sdk, apk_set));
+
+    let mut result = Message::new();
+    for (i, (sdk, apk_set)) in variants.iter().enumerate() {
+        let mut variant = Message::new();
+        variant
+            .message(1, Message::new().message(1, sdk))
+            .message(2, apk_set)
+            .uint(3, i as u64);
+        result.message(1, &variant);
+    }
+    result
+        .message(2, Message::new().string(2, BUNDLETOOL_VERSION))
+        .string(4, package);
+    result
+}
+
+/// `ApkTargeting` of a config split, with the other splits of its dimension
+/// as alternatives.
+fn split_targeting(split: &str, splits: &BTreeSet<String>) -> Message {
+    let (field, value) = split_dimension(split);
+    let mut targeting = Message::new();
+    targeting.bytes(1, &value);
+    for other in splits.iter().filter(|other| *other != split) {
+        let (other_field, alternative) = split_dimension(other);
+        if other_field == field {
+            targeting.bytes(2, &alternative);
+        }
+    }
+    let mut apk_targeting = Message::new();
+    apk_targeting.message(field, &targeting);
+    apk_targeting
+}
+
+/// Returns the `ApkTargeting` field of the dimension of a config split and
+/// the encoded targeting value, an `Abi`, a `ScreenDensity` or a language.
+fn split_dimension(split: &str) -> (u32, Vec<u8>) {
+    let name = split.strip_prefix("config.").unwrap_or(split);
+    let alias = |aliases: &[(&str, u64)]| {
+        aliases
+            .iter()
+            .find(|(alias, _)| *alias == name)
+            .map(|(_, alias)| *alias)
+    };
+    if let Some(abi) = alias(&ABI_ALIASES) {
+        (1, Message::new().uint(1, abi).as_bytes().to_vec())
+    } else if let Some(density) = alias(&DENSITY_ALIASES) {
+        (4, Message::new().uint(1, density).as_bytes().to_vec())
+    } else if let Some(dpi) = name.strip_suffix("dpi").and_then(|dpi| dpi.parse().ok()) {
+        (4, Message::new().uint(2, dpi).as_bytes().to_vec())
+    } else {
+        (3, name.as_bytes().to_vec())
+    }
+}
+
+/// Returns the split of a config, densities take precedence over languages.
+fn config_split(config: &ResTableConfig) -> Option<String> {
+    density_split(config.density).or_else(|| {
+        config
+            .language()
+            .map(|language| format!("config.{}", language))
+    })
+}
+
+/// Returns the split of a file of the base module.
+fn file_split(path: &str) -> Result<Option<String>> {
+    if let Some(lib) = path.strip_prefix("lib/") {
+        let abi = lib.split('/').next().unwrap();
+        return Ok(Some(format!("config.{}", abi.replace('-', "_"))));
+    }
+    if path.starts_with("res/") {
+        return Ok(config_split(&path_config(path)?));
+    }
+    Ok(None)
+}
+
+/// Returns a copy of the table that only contains the values of configs
+/// matching `filter`. Entries keep their ids.
+fn filter_table(table: &ResourceTable, filter: impl Fn(&ResTableConfig) -> bool) -> ResourceTable {
+    let mut table = table.clone();
+    for package in &mut table.packages {
+        for ty in &mut package.types {
+            for entry in ty.entries.iter_mut().flatten() {
+                entry.values.retain(|(config, _)| filter(config));
+            }
+        }
+    }
+    table
+}
+
+fn has_values(table: &ResourceTable) -> bool {
+    table.packages.iter().any(|package| {
+        package.types.iter().any(|ty| {
+            ty.entries
+                .iter()
+                .flatten()
+                .any(|entry| !entry.values.is_empty())
+        })
+    })
+}
+
+fn write_apk(
+    path: &Path,
+    manifest: &Chunk,
+    table: Option<&ResourceTable>,
+    files: &[&(String, Vec<u8>)],
+    page_size: Option<u16>,
+) -> Result<()> {
+    let mut zip = Zip::new(path, true)?;
+    let mut outputs = vec![Output::Xml("AndroidManifest.xml".into(), manifest.clone())];
+    if let Some(table) = table {
+        outputs.push(Output::Table(table.to_chunk()?));
+    }
+    crate::write_outputs(&mut zip, outputs)?;
+    for (path, bytes) in files {
+        // images are memory mapped from the apk
+        let opts = if path.starts_with("res/") && !path.ends_with(".xml") {
+            ZipFileOptions::Aligned(4)
+        } else if let Some(page_size) = page_size.filter(|_| path.starts_with("lib/")) {
+            ZipFileOptions::Aligned(page_size)
+        } else {
+            ZipFileOptions::Compressed
+        };
+        zip.create_file(Path::new(path), opts, bytes)?;
+    }
+    zip.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aab::{table_to_proto, xml_to_proto};
+    use crate::compiler::{compile_mipmap, PackageBuilder, Table, Value};
+    use crate::pb::Reader;
+    use crate::res::ResAttributeType;
+
+    #[test]
+    fn test_build_apks() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let dir = tmp.path();
+
+        let mut package = PackageBuilder::new("com.example.helloworld");
+        let string = |value: &str| Value::Item {
+            format: ResAttributeType::String as u32,
+            value: value.into(),
+        };
+        package.add("string", "app_name", Default::default(), string("Hello"))?;
+        let de = ResTableConfig::from_qualifiers("de")?;
+        package.add("string", "app_name", de, string("Hallo"))?;
+        let mipmap = compile_mipmap(&mut package, "icon", 48)?;
+        let table = package.build(&mut Table::default())?;
+        let manifest = crate::compiler::xml::compile_xml(
+            r#"<manifest package="com.example.helloworld"><application/></manifest>"#,
+            &Table::default(),
+        )?;
+
+        let aab = dir.join("app.aab");
+        let mut zip = Zip::new(&aab, true)?;
+        let mut add = |name: &str, bytes: &[u8]| {
+            zip.create_file(Path::new(name), ZipFileOptions::Compressed, bytes)
+        };
+        add(
+            "base/manifest/AndroidManifest.xml",
+            &xml_to_proto(&manifest)?,
+        )?;
+        add("base/resources.pb", &table_to_proto(&table)?)?;
+        add("base/dex/classes.dex", b"dex")?;
+        add("base/lib/arm64-v8a/libhello.so", b"lib")?;
+        for (path, _) in mipmap.variants() {
+            add(&format!("base/{}", path), b"png")?;
+        }
+        add("BundleConfig.pb", &[])?;
+        zip.finish()?;
+
+        let apks = dir.join("app.apks");
+        build_apks(&aab, &apks, None)?;
+        let archive = ZipArchive::new(File::open(&apks)?)?;
+        let mut names = archive.file_names().collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                "splits/base-arm64_v8a.apk",
+                "splits/base-de.apk",
+                "splits/base-hdpi.apk",
+                "splits/base-master.apk",
+                "splits/base-mdpi.apk",
+                "splits/base-xhdpi.apk",
+                "splits/base-xxhdpi.apk",
+                "splits/base-xxxhdpi.apk",
+                "toc.pb",
+                "universal.apk",
+            ]
+        );
+
+        let toc = xcommon::extract_zip_file(&apks, "toc.pb")?;
+        let toc = Reader::new(&toc)?;
+        assert_eq!(toc.string(4)?, "com.example.helloworld");
+        let variants = toc.messages(1)?;
+        assert_eq!(variants.len(), 2);
+        let apk_descriptions = |variant: &Reader| -> Result<Vec<(String, Vec<u8>)>> {
+            let apk_set = variant.message(2)?.unwrap();
+            assert_eq!(apk_set.message(1)?.unwrap().string(1)?, "base");
+            apk_set
+                .messages(2)?
+                .iter()
+                .map(|apk| Ok((apk.string(2)?.to_string(), apk.bytes(1)?.to_vec())))
+                .collect()
+        };
+        let standalone = apk_descriptions(&variants[0])?;
+        assert_eq!(standalone.len(), 1);
+        assert_eq!(standalone[0].0, "universal.apk");
+        let splits = apk_descriptions(&variants[1])?;
+        assert_eq!(splits.len(), 8);
+        let targeting = |name: &str| {
+            let (_, targeting) = splits.iter().find(|(path, _)| path == name).unwrap();
+            targeting.clone()
+        };
+        let de = targeting("splits/base-de.apk");
+        let de = Reader::new(&de)?.message(3)?.unwrap();
+        assert_eq!(de.string(1)?, "de");
+        let arm64 = targeting("splits/base-arm64_v8a.apk");
+        let arm64 = Reader::new(&arm64)?.message(1)?.unwrap();
+        assert_eq!(arm64.message(1)?.unwrap().uint(1), 3);
+        let hdpi = targeting("splits/base-hdpi.apk");
+        let hdpi = Reader::new(&hdpi)?.message(4)?.unwrap();
+        assert_eq!(hdpi.message(1)?.unwrap().uint(1), 5);
+        assert_eq!(hdpi.messages(2)?.len(), 4);
+
+        let base = extract_apks(&apks, &dir.join("app"))?;
+        assert_eq!(crate::Apk::splits(&base)?.len(), 7);
+        crate::sign::verify(&base)?;
+        let master = ResourceTable::from_apk(&base)?;
+        let app_name = master.entry_by_name("@string/app_name").unwrap();
+        assert_eq!(app_name.values.len(), 1);
+        assert!(master.entry_by_name("@mipmap/icon").is_none());
+        xcommon::extract_zip_file(&base, "classes.dex")?;
+
+        let de = split_path(&base, "config.de");
+        let table = ResourceTable::from_apk(&de)?;
+        let app_name = table.entry_by_name("@string/app_name").unwrap();
+        assert_eq!(app_name.values.len(), 1);
+        assert_eq!(
+            app_name.values[0].1,
+            crate::arsc::Value::Item(crate::arsc::Item::String("Hallo".into()))
+        );
+        let manifest = crate::Apk::manifest(&de)?;
+        assert_eq!(manifest.split.as_deref(), Some("config.de"));
+        assert_eq!(manifest.application.has_code, Some(false));
+
+        let arm64 = split_path(&base, "config.arm64_v8a");
+        xcommon::extract_zip_file(&arm64, "lib/arm64-v8a/libhello.so")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,612 @@
+//! Typed view of a compiled resource table.
+use crate::compiler::StringPool;
+use crate::res::{
+    Chunk, ResTableConfig, ResTableEntry, ResTableHeader, ResTableMap, ResTableMapEntry,
+    ResTablePackageHeader, ResTableRef, ResTableTypeHeader, ResTableTypeSpecHeader, ResTableValue,
+    ResValue, ResValueType,
+};
+use anyhow::{Context, Result};
+use std::fmt::Write;
+use std::io::Cursor;
+use std::path::Path;
+
+/// A parsed `resources.arsc`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResourceTable {
+    pub packages: Vec<Package>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Package {
+    pub id: u8,
+    pub name: String,
+    pub types: Vec<Type>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Type {
+    pub id: u8,
+    pub name: String,
+    /// Entries indexed by their entry id. Ids without an entry in any
+    /// configuration are `None`.
+    pub entries: Vec<Option<Entry>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub id: ResTableRef,
+    pub name: String,
+    /// The value of each configuration the entry is defined for.
+    pub values: Vec<(ResTableConfig, Value)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Item(Item),
+    /// Styles, attributes, arrays and plurals. `parent` is `0` if the bag
+    /// doesn't inherit from another bag.
+    Bag {
+        parent: u32,
+        items: Vec<(u32, Item)>,
+    },
+}
+
+/// A typed [`ResValue`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Item {
+    Null,
+    Reference(u32),
+    Attribute(u32),
+    String(String),
+    Float(f32),
+    /// A complex value with a unit, see `Dimension` in [`ResValueType`].
+    Dimension(u32),
+    Fraction(u32),
+    Integer(i32),
+    Hex(u32),
+    Boolean(bool),
+    /// An argb8 color.
+    Color(u32),
+    Unknown {
+        data_type: u8,
+        data: u32,
+    },
+}
+
+impl Item {
+    /// Converts a value, strings are looked up in `strings`.
+    pub fn new(value: &ResValue, strings: &[String]) -> Self {
+        let data = value.data;
+        match ResValueType::from_u8(value.data_type) {
+            Some(ResValueType::Null) => Self::Null,
+            Some(ResValueType::Reference) => Self::Reference(data),
+            Some(ResValueType::Attribute) => Self::Attribute(data),
+            Some(ResValueType::String) => {
+                Self::String(strings.get(data as usize).cloned().unwrap_or_default())
+            }
+            Some(ResValueType::Float) => Self::Float(f32::from_bits(data)),
+            Some(ResValueType::Dimension) => Self::Dimension(data),
+            Some(ResValueType::Fraction) => Self::Fraction(data),
+            Some(ResValueType::IntDec) => Self::Integer(data as i32),
+            Some(ResValueType::IntHex) => Self::Hex(data),
+            Some(ResValueType::IntBoolean) => Self::Boolean(data != 0),
+            Some(
+                ResValueType::IntColorArgb8
+                | ResValueType::IntColorRgb8
+                | ResValueType::IntColorArgb4
+                | ResValueType::IntColorRgb4,
+            ) => Self::Color(data),
+            None => Self::Unknown {
+                data_type: value.data_type,
+                data,
+            },
+        }
+    }
+}
+
+impl Item {
+    /// Converts the item into a value, strings are added to `strings`.
+    pub fn to_value(&self, strings: &mut StringPool) -> ResValue {
+        let (data_type, data) = match self {
+            Self::Null => (ResValueType::Null as u8, 0),
+            Self::Reference(id) => (ResValueType::Reference as u8, *id),
+            Self::Attribute(id) => (ResValueType::Attribute as u8, *id),
+            Self::String(s) => (ResValueType::String as u8, strings.id(s)),
+            Self::Float(n) => (ResValueType::Float as u8, n.to_bits()),
+            Self::Dimension(data) => (ResValueType::Dimension as u8, *data),
+            Self::Fraction(data) => (ResValueType::Fraction as u8, *data),
+            Self::Integer(n) => (ResValueType::IntDec as u8, *n as u32),
+            Self::Hex(n) => (ResValueType::IntHex as u8, *n),
+            Self::Boolean(b) => (
+                ResValueType::IntBoolean as u8,
+                if *b { 0xffff_ffff } else { 0 },
+            ),
+            Self::Color(color) => (ResValueType::IntColorArgb8 as u8, *color),
+            Self::Unknown { data_type, data } => (*data_type, *data),
+        };
+        ResValue {
+            size: 8,
+            res0: 0,
+            data_type,
+            data,
+        }
+    }
+}
+
+/// Formats an item the way it would be written in a source file.
+/// References are printed as resource ids like `@0x7f010000`.
+impl std::fmt::Display for Item {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Null => Ok(()),
+            Self::Reference(0) => write!(f, "@null"),
+            Self::Reference(id) => write!(f, "@0x{:08x}", id),
+            Self::Attribute(id) => write!(f, "?0x{:08x}", id),
+            Self::String(s) => write!(f, "{}", s),
+            Self::Float(n) => write!(f, "{}", n),
+            Self::Dimension(data) => {
+                const UNITS: [&str; 6] = ["px", "dp", "sp", "pt", "in", "mm"];
+                let unit = UNITS.get(*data as usize & 0xf).unwrap_or(&"");
+                write!(f, "{}{}", complex_to_float(*data), unit)
+            }
+            Self::Fraction(data) => {
+                let unit = if data & 0xf == 1 { "%p" } else { "%" };
+                write!(f, "{}{}", complex_to_float(*data) * 100.0, unit)
+            }
+            Self::Integer(n) => write!(f, "{}", n),
+            Self::Hex(n) => write!(f, "0x{:08x}", n),
+            Self::Boolean(b) => write!(f, "{}", b),
+            Self::Color(color) => write!(f, "#{:08x}", color),
+            Self::Unknown { data_type, data } => write!(f, "(0x{:02x}) 0x{:08x}", data_type, data),
+        }
+    }
+}
+
+/// The mantissa is the signed upper 24 bits and the radix selects the
+/// position of the binary point.
+fn complex_to_float(data: u32) -> f64 {
+    let mantissa = (data & 0xffff_ff00) as i32 as f64 / (1 << 8) as f64;
+    let radix = [0, 7, 15, 23][(data as usize >> 4) & 0x3];
+    mantissa / (1u32 << radix) as f64
+}
+
+impl ResourceTable {
+    /// Reads the `resources.arsc` of an apk.
+    pub fn from_apk(path: &Path) -> Result<Self> {
+        let resources = xcommon::extract_zip_file(path, "resources.arsc")?;
+        Self::parse(&resources)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        Self::from_chunk(&Chunk::parse(&mut Cursor::new(bytes))?)
+    }
+
+    pub fn from_chunk(chunk: &Chunk) -> Result<Self> {
+        let chunks = if let Chunk::Table(_, chunks) = chunk {
+            chunks
+        } else {
+            anyhow::bail!("expected a resource table");
+        };
+        let strings = if let Some(Chunk::StringPool(strings, _)) = chunks.first() {
+            strings
+        } else {
+            anyhow::bail!("resource table is missing a string pool");
+        };
+        let mut packages = vec![];
+        for chunk in chunks {
+            if let Chunk::TablePackage(header, chunks) = chunk {
+                packages.push(
+                    Package::new(header.id as u8, &header.name, chunks, strings)
+                        .with_context(|| format!("invalid package {}", header.name))?,
+                );
+            }
+        }
+        Ok(Self { packages })
+    }
+
+    /// Converts the table into the binary format of `resources.arsc`.
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let mut strings = StringPool::default();
+        let mut packages = vec![];
+        for package in &self.packages {
+            packages.push(package.to_chunk(&mut strings)?);
+        }
+        let mut chunks = vec![Chunk::StringPool(strings.into_strings(), vec![])];
+        chunks.extend(packages);
+        Ok(Chunk::Table(
+            ResTableHeader {
+                package_count: self.packages.len() as u32,
+            },
+            chunks,
+        ))
+    }
+
+    /// Looks up an entry by id.
+    pub fn entry(&self, id: ResTableRef) -> Option<&Entry> {
+        self.packages
+            .iter()
+            .find(|package| package.id == id.package())?
+            .types
+            .iter()
+            .find(|ty| ty.id == id.ty())?
+            .entries
+            .get(id.entry() as usize)?
+            .as_ref()
+    }
+
+    /// Looks up an entry by a reference like `@string/app_name` or
+    /// `@android:attr/label`. Without a package name the first package
+    /// is searched.
+    pub fn entry_by_name(&self, name: &str) -> Option<&Entry> {
+        let name = name.strip_prefix('@').unwrap_or(name);
+        let (ty, name) = name.split_once('/')?;
+        let (package, ty) = match ty.split_once(':') {
+            Some((package, ty)) => (self.packages.iter().find(|p| p.name == package)?, ty),
+            None => (self.packages.first()?, ty),
+        };
+        package
+            .types
+            .iter()
+            .find(|t| t.name == ty)?
+            .entries
+            .iter()
+            .flatten()
+            .find(|entry| entry.name == name)
+    }
+
+    /// Returns the name of a resource like `@android:style/Theme`. The
+    /// package is omitted for the first package.
+    pub fn name(&self, id: ResTableRef) -> Option<String> {
+        let (i, package) = self
+            .packages
+            .iter()
+            .enumerate()
+            .find(|(_, package)| package.id == id.package())?;
+        let ty = package.types.iter().find(|ty| ty.id == id.ty())?;
+        let entry = ty.entries.get(id.entry() as usize)?.as_ref()?;
+        Some(if i == 0 {
+            format!("@{}/{}", ty.name, entry.name)
+        } else {
+            format!("@{}:{}/{}", package.name, ty.name, entry.name)
+        })
+    }
+
+    /// Formats an item, references are resolved to resource names if the
+    /// resource is part of the table.
+    pub fn format(&self, item: &Item) -> String {
+        let resolve = |id: u32| self.name(ResTableRef::from(id));
+        match item {
+            Item::Reference(id) if *id != 0 => resolve(*id).unwrap_or_else(|| item.to_string()),
+            Item::Attribute(id) => resolve(*id)
+                .map(|name| format!("?{}", &name[1..]))
+                .unwrap_or_else(|| item.to_string()),
+            Item::String(s) => format!("{:?}", s),
+            _ => item.to_string(),
+        }
+    }
+
+    fn write_entry(&self, f: &mut dyn Write, ty: &str, entry: &Entry) -> std::fmt::Result {
+        writeln!(
+            f,
+            "    resource 0x{:08x} {}/{}",
+            u32::from(entry.id),
+            ty,
+            entry.name
+        )?;
+        for (config, value) in &entry.values {
+            match value {
+                Value::Item(item) => writeln!(f, "      ({}) {}", config, self.format(item))?,
+                Value::Bag { parent, items } => {
+                    write!(f, "      ({}) size={}", config, items.len())?;
+                    if *parent != 0 {
+                        write!(f, " parent={}", self.format(&Item::Reference(*parent)))?;
+                    }
+                    writeln!(f)?;
+                    for (name, item) in items {
+                        let name = self
+                            .name(ResTableRef::from(*name))
+                            .map(|name| name[1..].to_string())
+                            .unwrap_or_else(|| format!("0x{:08x}", name));
+                        writeln!(f, "        {}={}", name, self.format(item))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Formats a single entry like the table dump.
+    pub fn dump_entry(&self, entry: &Entry) -> String {
+        let ty = self
+            .packages
+            .iter()
+            .filter(|package| package.id == entry.id.package())
+            .flat_map(|package| &package.types)
+            .find(|ty| ty.id == entry.id.ty())
+            .map(|ty| ty.name.as_str())
+            .unwrap_or_default();
+        let mut s = String::new();
+        self.write_entry(&mut s, ty, entry).unwrap();
+        s
+    }
+}
+
+/// Dumps the table similar to `aapt2 dump resources`.
+impl std::fmt::Display for ResourceTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for package in &self.packages {
+            writeln!(f, "Package name={} id={:02x}", package.name, package.id)?;
+            for ty in &package.types {
+                writeln!(
+                    f,
+                    "  type {} id={:02x} entryCount={}",
+                    ty.name,
+                    ty.id,
+                    ty.entries.len()
+                )?;
+                for entry in ty.entries.iter().flatten() {
+                    self.write_entry(f, &ty.name, entry)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Package {
+    fn new(id: u8, name: &str, chunks: &[Chunk], strings: &[String]) -> Result<Self> {
+        let (type_names, keys) = match chunks {
+            [Chunk::StringPool(types, _), Chunk::StringPool(keys, _), ..] => (types, keys),
+            _ => anyhow::bail!("missing type or key strings"),
+        };
+        let mut types: Vec<Type> = vec![];
+        for chunk in &chunks[2..] {
+            match chunk {
+                Chunk::TableTypeSpec(header, _) => {
+                    let name = type_names
+                        .get(header.id as usize - 1)
+                        .with_context(|| format!("invalid type id {}", header.id))?;
+                    types.push(Type {
+                        id: header.id,
+                        name: name.clone(),
+                        entries: vec![None; header.entry_count as usize],
+                    });
+                }
+                Chunk::TableType(header, _, entries) => {
+                    let ty = types
+                        .iter_mut()
+                        .find(|ty| ty.id == header.id)
+                        .with_context(|| format!("missing type spec for type {}", header.id))?;
+                    for (i, table_entry) in entries.iter().enumerate() {
+                        let table_entry = if let Some(table_entry) = table_entry {
+                            table_entry
+                        } else {
+                            continue;
+                        };
+                        if i >= ty.entries.len() {
+                            ty.entries.resize(i + 1, None);
+                        }
+                        let entry = ty.entries[i].get_or_insert_with(|| Entry {
+                            id: ResTableRef::new(id, header.id, i as u16),
+                            name: keys
+                                .get(table_entry.key as usize)
+                                .cloned()
+                                .unwrap_or_default(),
+                            values: vec![],
+                        });
+                        let value = match &table_entry.value {
+                            ResTableValue::Simple(value) => Value::Item(Item::new(value, strings)),
+                            ResTableValue::Complex(entry, map) => Value::Bag {
+                                parent: entry.parent,
+                                items: map
+                                    .iter()
+                                    .map(|item| (item.name, Item::new(&item.value, strings)))
+                                    .collect(),
+                            },
+                        };
+                        entry.values.push((header.config.clone(), value));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(Self {
+            id,
+            name: name.to_string(),
+            types,
+        })
+    }
+
+    fn to_chunk(&self, strings: &mut StringPool) -> Result<Chunk> {
+        let type_count = self.types.iter().map(|ty| ty.id).max().unwrap_or_default();
+        let mut type_names = vec![String::new(); type_count as usize];
+        for ty in &self.types {
+            type_names[ty.id as usize - 1] = ty.name.clone();
+        }
+        let mut keys = StringPool::default();
+        let mut chunks = vec![Chunk::StringPool(type_names, vec![]), Chunk::Null];
+        let mut types = self.types.iter().collect::<Vec<_>>();
+        types.sort_by_key(|ty| ty.id);
+        for ty in types {
+            let mut configs: Vec<&ResTableConfig> = vec![];
+            let mut spec = Vec::with_capacity(ty.entries.len());
+            for entry in &ty.entries {
+                let mut mask = 0;
+                for (config, _) in entry.iter().flat_map(|entry| &entry.values) {
+                    if !configs.contains(&config) {
+                        configs.push(config);
+                    }
+                    // the sdk version doesn't change at runtime
+                    mask |=
+                        config.diff(&ResTableConfig::default()) & !ResTableConfig::CONFIG_VERSION;
+                }
+                spec.push(mask);
+            }
+            let entry_count = ty.entries.len() as u32;
+            chunks.push(Chunk::TableTypeSpec(
+                ResTableTypeSpecHeader {
+                    id: ty.id,
+                    res0: 0,
+                    res1: 0,
+                    entry_count,
+                },
+                spec,
+            ));
+            for config in configs {
+                let mut index = Vec::with_capacity(ty.entries.len());
+                let mut table_entries = Vec::with_capacity(ty.entries.len());
+                let mut offset = 0;
+                for entry in &ty.entries {
+                    let value = entry.as_ref().and_then(|entry| {
+                        let (_, value) = entry.values.iter().find(|(c, _)| c == config)?;
+                        Some((entry, value))
+                    });
+                    let (entry, value) = if let Some(value) = value {
+                        value
+                    } else {
+                        index.push(0xffff_ffff);
+                        table_entries.push(None);
+                        continue;
+                    };
+                    let value = match value {
+                        Value::Item(item) => ResTableValue::Simple(item.to_value(strings)),
+                        Value::Bag { parent, items } => ResTableValue::Complex(
+                            ResTableMapEntry {
+                                parent: *parent,
+                                count: items.len() as u32,
+                            },
+                            items
+                                .iter()
+                                .map(|(name, item)| ResTableMap {
+                                    name: *name,
+                                    value: item.to_value(strings),
+                                })
+                                .collect(),
+                        ),
+                    };
+                    let (size, flags, len) = match &value {
+                        ResTableValue::Simple(_) => (8, 0, 16),
+                        ResTableValue::Complex(_, map) => (16, 1, 16 + 12 * map.len() as u32),
+                    };
+                    index.push(offset);
+                    offset += len;
+                    table_entries.push(Some(ResTableEntry {
+                        size,
+                        flags,
+                        key: keys.id(&entry.name),
+                        value,
+                    }));
+                }
+                chunks.push(Chunk::TableType(
+                    ResTableTypeHeader {
+                        id: ty.id,
+                        res0: 0,
+                        res1: 0,
+                        entry_count,
+                        entries_start: 84 + 4 * entry_count,
+                        config: config.clone(),
+                    },
+                    index,
+                    table_entries,
+                ));
+            }
+        }
+        let key_count = keys.len() as u32;
+        chunks[1] = Chunk::StringPool(keys.into_strings(), vec![]);
+        // the type strings follow the package header
+        let type_strings = 288;
+        let mut buf = vec![];
+        chunks[0].write(&mut Cursor::new(&mut buf))?;
+        Ok(Chunk::TablePackage(
+            ResTablePackageHeader {
+                id: self.id as u32,
+                name: self.name.clone(),
+                type_strings,
+                last_public_type: type_count as u32,
+                key_strings: type_strings + buf.len() as u32,
+                last_public_key: key_count,
+                type_id_offset: 0,
+            },
+            chunks,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{PackageBuilder, Table, Value as SourceValue};
+    use crate::res::ResAttributeType;
+
+    #[test]
+    fn test_item_display() {
+        let item = |data_type: ResValueType, data| {
+            Item::new(
+                &ResValue {
+                    size: 8,
+                    res0: 0,
+                    data_type: data_type as u8,
+                    data,
+                },
+                &[],
+            )
+            .to_string()
+        };
+        assert_eq!(item(ResValueType::Dimension, 0x1001), "16dp");
+        assert_eq!(item(ResValueType::Dimension, 0x00c0_0021), "1.5dp");
+        assert_eq!(item(ResValueType::Dimension, 0xffff_ff00), "-1px");
+        assert_eq!(item(ResValueType::Reference, 0x7f01_0000), "@0x7f010000");
+        assert_eq!(item(ResValueType::IntBoolean, 0xffff_ffff), "true");
+        assert_eq!(item(ResValueType::IntColorRgb4, 0xffff_0000), "#ffff0000");
+    }
+
+    #[test]
+    fn test_resource_table() -> Result<()> {
+        let mut package = PackageBuilder::new("com.example.helloworld");
+        let string = |value: &str| SourceValue::Item {
+            format: ResAttributeType::String as u32 | ResAttributeType::Reference as u32,
+            value: value.into(),
+        };
+        package.add("string", "app_name", Default::default(), string("Hello"))?;
+        let de = ResTableConfig::from_qualifiers("de")?;
+        package.add("string", "app_name", de, string("Hallo"))?;
+        package.add(
+            "string",
+            "title",
+            Default::default(),
+            string("@string/app_name"),
+        )?;
+        let chunk = package.build(&mut Table::default())?;
+        let table = ResourceTable::from_chunk(&chunk)?;
+
+        let app_name = table.entry_by_name("@string/app_name").unwrap();
+        assert_eq!(u32::from(app_name.id), 0x7f01_0000);
+        assert_eq!(app_name.values.len(), 2);
+        assert_eq!(
+            app_name.values[1],
+            (
+                ResTableConfig::from_qualifiers("de")?,
+                Value::Item(Item::String("Hallo".into()))
+            )
+        );
+        let title = table.entry(ResTableRef::from(0x7f01_0001)).unwrap();
+        assert_eq!(title.name, "title");
+        assert_eq!(
+            table.dump_entry(title),
+            "    resource 0x7f010001 string/title\n      () @string/app_name\n"
+        );
+        assert_eq!(
+            table.to_string(),
+            r#"Package name=com.example.helloworld id=7f
+  type string id=01 entryCount=2
+    resource 0x7f010000 string/app_name
+      () "Hello"
+      (de) "Hallo"
+    resource 0x7f010001 string/title
+      () @string/app_name
+"#
+        );
+        assert_eq!(ResourceTable::from_chunk(&table.to_chunk()?)?, table);
+        Ok(())
+    }
+}
@@ -1,41 +1,78 @@
-use crate::compiler::table::{Ref, Table};
+use crate::compiler::package::PACKAGE_ID;
+use crate::compiler::table::{Entry, Ref, Table};
+use crate::manifest::ANDROID_NS;
 use crate::res::{ResAttributeType, ResValue, ResValueType};
 use anyhow::{Context, Result};
 use roxmltree::Attribute;
 use std::collections::{BTreeMap, BTreeSet};
 
-pub fn compile_attr(table: &Table, name: &str, value: &str, strings: &Strings) -> Result<ResValue> {
-    let entry = table.entry_by_ref(Ref::attr(name))?;
-    let attr_type = entry.attribute_type().unwrap();
-    let (data, data_type) = match attr_type {
-        ResAttributeType::Reference => {
-            let id = table.entry_by_ref(Ref::parse(value)?)?.id();
+/// Namespace of the attributes declared by the app and its libraries.
+pub const RES_AUTO_NS: &str = "http://schemas.android.com/apk/res-auto";
+
+/// Returns the attribute resource of an attribute in the android namespace or
+/// in the `res-auto` namespace.
+pub fn attr_entry<'a>(table: &'a Table, attr: &Attribute) -> Result<Option<Entry<'a>>> {
+    let package = match attr.namespace() {
+        Some(ANDROID_NS) => Some("android"),
+        Some(RES_AUTO_NS) => None,
+        _ => return Ok(None),
+    };
+    let entry = table.entry_by_ref(Ref::new(package, "attr", attr.name()))?;
+    Ok(Some(entry))
+}
+
+pub fn compile_attr(
+    table: &Table,
+    entry: Entry,
+    name: &str,
+    value: &str,
+    strings: &Strings,
+) -> Result<ResValue> {
+    let format = entry
+        .attribute_format()
+        .with_context(|| format!("{} is not an attribute", name))?;
+    compile_value(table, Some(entry), format, value, &mut |s| {
+        strings.id(s) as u32
+    })
+    .with_context(|| format!("invalid value for attribute {}: {}", name, value))
+}
+
+/// Compiles a value of an attribute or resource. `format` is a mask of
+/// [`ResAttributeType`]s the value may have, `attr` is used to resolve enum
+/// and flag names and `string` returns the index of a string in the string
+/// pool the value is stored in.
+pub fn compile_value(
+    table: &Table,
+    attr: Option<Entry>,
+    format: u32,
+    value: &str,
+    string: &mut dyn FnMut(&str) -> u32,
+) -> Result<ResValue> {
+    let allows = |ty: ResAttributeType| format & ty as u32 != 0;
+    let trimmed = value.trim();
+    let (data, data_type) = if allows(ResAttributeType::Reference) && trimmed.starts_with('@') {
+        if trimmed == "@null" {
+            (0, ResValueType::Reference)
+        } else {
+            let id = table.entry_by_ref(Ref::parse(trimmed)?)?.id();
             (u32::from(id), ResValueType::Reference)
         }
-        ResAttributeType::String => (strings.id(value) as u32, ResValueType::String),
-        ResAttributeType::Integer => (value.parse()?, ResValueType::IntDec),
-        ResAttributeType::Boolean => match value {
-            "true" => (0xffff_ffff, ResValueType::IntBoolean),
-            "false" => (0x0000_0000, ResValueType::IntBoolean),
-            _ => anyhow::bail!("expected boolean"),
-        },
-        ResAttributeType::Enum => {
-            let id = table.entry_by_ref(Ref::id(value))?.id();
-            let value = entry.lookup_value(id).unwrap();
-            (value.data, ResValueType::from_u8(value.data_type).unwrap())
-        }
-        ResAttributeType::Flags => {
-            let mut data = 0;
-            let mut data_type = ResValueType::Null;
-            for flag in value.split('|') {
-                let id = table.entry_by_ref(Ref::id(flag))?.id();
-                let value = entry.lookup_value(id).unwrap();
-                data |= value.data;
-                data_type = ResValueType::from_u8(value.data_type).unwrap();
-            }
-            (data, data_type)
-        }
-        _ => anyhow::bail!("unsupported attribute type"),
+    } else if allows(ResAttributeType::Reference) && trimmed.starts_with('?') {
+        // `?android:attr/name`, `?android:name` or `?attr/name`
+        let reference = trimmed[1..].strip_prefix("attr/").unwrap_or(&trimmed[1..]);
+        let reference = reference.replace(":attr/", ":");
+        let (package, name) = match reference.split_once(':') {
+            Some((package, name)) => (Some(package), name),
+            None => (None, reference.as_str()),
+        };
+        let id = table.entry_by_ref(Ref::new(package, "attr", name))?.id();
+        (u32::from(id), ResValueType::Attribute)
+    } else if let Some(value) = compile_literal(table, attr, format, trimmed) {
+        value
+    } else if allows(ResAttributeType::String) {
+        (string(value), ResValueType::String)
+    } else {
+        anyhow::bail!("unsupported value {} for format 0x{:x}", value, format);
     };
     Ok(ResValue {
         size: 8,
@@ -45,6 +82,145 @@ pub fn compile_attr(table: &Table, name: &str, value: &str, strings: &Strings) -
     })
 }
 
+fn compile_literal(
+    table: &Table,
+    attr: Option<Entry>,
+    format: u32,
+    value: &str,
+) -> Option<(u32, ResValueType)> {
+    let allows = |ty: ResAttributeType| format & ty as u32 != 0;
+    if allows(ResAttributeType::Boolean) {
+        match value {
+            "true" => return Some((0xffff_ffff, ResValueType::IntBoolean)),
+            "false" => return Some((0x0000_0000, ResValueType::IntBoolean)),
+            _ => {}
+        }
+    }
+    if allows(ResAttributeType::Integer) {
+        if let Some(hex) = value.strip_prefix("0x") {
+            if let Ok(data) = u32::from_str_radix(hex, 16) {
+                return Some((data, ResValueType::IntHex));
+            }
+        }
+        if let Ok(data) = value.parse::<i32>() {
+            return Some((data as u32, ResValueType::IntDec));
+        }
+    }
+    if allows(ResAttributeType::Color) {
+        if let Some(color) = parse_color(value) {
+            return Some(color);
+        }
+    }
+    if allows(ResAttributeType::Dimension) {
+        if let Some(data) = parse_complex(value, &DIMENSION_UNITS, 1.0) {
+            return Some((data, ResValueType::Dimension));
+        }
+    }
+    if allows(ResAttributeType::Fraction) {
+        if let Some(data) = parse_complex(value, &FRACTION_UNITS, 100.0) {
+            return Some((data, ResValueType::Fraction));
+        }
+    }
+    if allows(ResAttributeType::Float) {
+        if let Ok(data) = value.parse::<f32>() {
+            return Some((data.to_bits(), ResValueType::Float));
+        }
+    }
+    let attr = attr?;
+    // the symbols of an attribute are ids of the package declaring it
+    let package = (attr.id().package() != PACKAGE_ID).then_some("android");
+    if allows(ResAttributeType::Enum) {
+        if let Ok(id) = table.entry_by_ref(Ref::new(package, "id", value)) {
+            if let Some(value) = attr.lookup_value(id.id()) {
+                return Some((value.data, ResValueType::from_u8(value.data_type)?));
+            }
+        }
+    }
+    if allows(ResAttributeType::Flags) {
+        let mut data = 0;
+        let mut data_type = ResValueType::Null;
+        for flag in value.split('|') {
+            let id = table
+                .entry_by_ref(Ref::new(package, "id", flag.trim()))
+                .ok()?
+                .id();
+            let value = attr.lookup_value(id)?;
+            data |= value.data;
+            data_type = ResValueType::from_u8(value.data_type)?;
+        }
+        return Some((data, data_type));
+    }
+    None
+}
+
+/// Parses `#rgb`, `#argb`, `#rrggbb` and `#aarrggbb` colors. The data is
+/// always stored as argb8.
+fn parse_color(value: &str) -> Option<(u32, ResValueType)> {
+    let hex = value.strip_prefix('#')?;
+    let color = u32::from_str_radix(hex, 16).ok()?;
+    // expands a 4 bit channel at `shift` to 8 bits
+    let expand = |shift: u32| {
+        let c = (color >> shift) & 0xf;
+        c << 4 | c
+    };
+    Some(match hex.len() {
+        3 => (
+            0xff00_0000 | expand(8) << 16 | expand(4) << 8 | expand(0),
+            ResValueType::IntColorRgb4,
+        ),
+        4 => (
+            expand(12) << 24 | expand(8) << 16 | expand(4) << 8 | expand(0),
+            ResValueType::IntColorArgb4,
+        ),
+        6 => (0xff00_0000 | color, ResValueType::IntColorRgb8),
+        8 => (color, ResValueType::IntColorArgb8),
+        _ => return None,
+    })
+}
+
+const DIMENSION_UNITS: [(&str, u32); 7] = [
+    ("px", 0),
+    ("dip", 1),
+    ("dp", 1),
+    ("sp", 2),
+    ("pt", 3),
+    ("in", 4),
+    ("mm", 5),
+];
+
+const FRACTION_UNITS: [(&str, u32); 2] = [("%p", 1), ("%", 0)];
+
+/// Parses a number with a unit into a complex value. The number is divided
+/// by `scale` and stored as a 24 bit mantissa with a radix.
+fn parse_complex(value: &str, units: &[(&str, u32)], scale: f64) -> Option<u32> {
+    let (number, unit) = units
+        .iter()
+        .find_map(|(suffix, unit)| Some((value.strip_suffix(suffix)?, *unit)))?;
+    let number = number.trim().parse::<f64>().ok()? / scale;
+    let negative = number < 0.0;
+    let bits = (number.abs() * (1 << 23) as f64 + 0.5) as u64;
+    let (radix, shift) = if bits & 0x7f_ffff == 0 {
+        // 23p0
+        (0, 23)
+    } else if bits & 0xffff_ffff_ff80_0000 == 0 {
+        // 0p23
+        (3, 0)
+    } else if bits & 0xffff_ffff_8000_0000 == 0 {
+        // 8p15
+        (2, 8)
+    } else if bits & 0xffff_ff80_0000_0000 == 0 {
+        // 16p7
+        (1, 16)
+    } else {
+        (0, 23)
+    };
+    let mut mantissa = ((bits >> shift) & 0xff_ffff) as u32;
+    if negative {
+        mantissa = mantissa.wrapping_neg() & 0xff_ffff;
+    }
+    Some(mantissa << 8 | radix << 4 | unit)
+}
+
 pub struct StringPoolBuilder<'a> {
     table: &'a Table,
     attributes: BTreeMap<u32, &'a str>,
@@ -61,15 +237,13 @@ impl<'a> StringPoolBuilder<'a> {
     }
 
     pub fn add_attribute(&mut self, attr: Attribute<'a, 'a>) -> Result<()> {
-        if let Some(ns) = attr.namespace() {
-            if ns == "http://schemas.android.com/apk/res/android" {
-                let entry = self.table.entry_by_ref(Ref::attr(attr.name()))?;
-                self.attributes.insert(entry.id().into(), attr.name());
-                if entry.attribute_type() == Some(ResAttributeType::String) {
-                    self.strings.insert(attr.value());
-                }
-                return Ok(());
+        if let Some(entry) = attr_entry(self.table, &attr)? {
+            self.attributes.insert(entry.id().into(), attr.name());
+            let format = entry.attribute_format().unwrap_or_default();
+            if format & ResAttributeType::String as u32 != 0 {
+                self.strings.insert(attr.value());
             }
+            return Ok(());
         }
         if attr.name() == "platformBuildVersionCode" || attr.name() == "platformBuildVersionName" {
             self.strings.insert(attr.name());
@@ -104,6 +278,15 @@ pub struct Strings {
 }
 
 impl Strings {
+    /// Returns the index of the name of the attribute with resource `id`.
+    pub fn attribute(&self, id: u32) -> i32 {
+        self.map
+            .iter()
+            .position(|attr| *attr == id)
+            .with_context(|| format!("all attributes added to the string pool: 0x{:x}", id))
+            .unwrap() as i32
+    }
+
     pub fn id(&self, s2: &str) -> i32 {
         self.strings
             .iter()
@@ -112,3 +295,44 @@ impl Strings {
             .unwrap() as i32
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(
+            parse_color("#f00"),
+            Some((0xffff_0000, ResValueType::IntColorRgb4))
+        );
+        assert_eq!(
+            parse_color("#8f00"),
+            Some((0x88ff_0000, ResValueType::IntColorArgb4))
+        );
+        assert_eq!(
+            parse_color("#123456"),
+            Some((0xff12_3456, ResValueType::IntColorRgb8))
+        );
+        assert_eq!(parse_color("#12345"), None);
+    }
+
+    #[test]
+    fn test_parse_complex() {
+        assert_eq!(parse_complex("16dp", &DIMENSION_UNITS, 1.0), Some(0x1001));
+        assert_eq!(parse_complex("12sp", &DIMENSION_UNITS, 1.0), Some(0x0c02));
+        assert_eq!(
+            parse_complex("-1px", &DIMENSION_UNITS, 1.0),
+            Some(0xffff_ff00)
+        );
+        assert_eq!(
+            parse_complex("1.5dp", &DIMENSION_UNITS, 1.0),
+            Some(0x00c0_0021)
+        );
+        assert_eq!(
+            parse_complex("50%", &FRACTION_UNITS, 100.0),
+            Some(0x4000_0030)
+        );
+        assert_eq!(parse_complex("16", &DIMENSION_UNITS, 1.0), None);
+    }
+}
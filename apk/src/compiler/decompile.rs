@@ -0,0 +1,690 @@
+use crate::arsc::Item;
+use crate::manifest::{
+    Activity, ActivityAlias, AndroidManifest, Application, Feature, IntentFilter, IntentFilterData,
+    MetaData, Permission, PermissionDeclaration, Profileable, Provider, Queries, Receiver, Sdk,
+    Service, UsesLibrary, ANDROID_NS,
+};
+use crate::res::Chunk;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+const ACTION_MAIN: &str = "android.intent.action.MAIN";
+const CATEGORY_LAUNCHER: &str = "android.intent.category.LAUNCHER";
+
+/// Element of a decompiled binary xml document. Attribute names are
+/// prefixed with the namespace prefix like `android:name`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct XmlElement {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<XmlElement>,
+    pub text: String,
+}
+
+impl XmlElement {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the child elements named `name`.
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn write(&self, f: &mut String, indent: usize) -> std::fmt::Result {
+        write!(f, "{:indent$}<{}", "", self.name, indent = indent)?;
+        for (name, value) in &self.attributes {
+            write!(f, " {}=\"{}\"", name, escape(value))?;
+        }
+        if self.children.is_empty() && self.text.is_empty() {
+            return writeln!(f, "/>");
+        }
+        write!(f, ">")?;
+        if self.children.is_empty() {
+            return writeln!(f, "{}</{}>", escape(&self.text), self.name);
+        }
+        writeln!(f)?;
+        if !self.text.is_empty() {
+            writeln!(
+                f,
+                "{:indent$}{}",
+                "",
+                escape(&self.text),
+                indent = indent + 2
+            )?;
+        }
+        for child in &self.children {
+            child.write(f, indent + 2)?;
+        }
+        writeln!(f, "{:indent$}</{}>", "", self.name, indent = indent)
+    }
+}
+
+impl std::fmt::Display for XmlElement {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut s = String::new();
+        self.write(&mut s, 0)?;
+        f.write_str(&s)
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Decompiles a binary xml document. References are printed as resource
+/// ids like `@0x7f010000` since the resource table isn't available.
+pub fn decompile_xml(chunk: &Chunk) -> Result<XmlElement> {
+    let chunks = if let Chunk::Xml(chunks) = chunk {
+        chunks
+    } else {
+        anyhow::bail!("expected an xml chunk");
+    };
+    let strings = chunks
+        .iter()
+        .find_map(|chunk| match chunk {
+            Chunk::StringPool(strings, _) => Some(strings),
+            _ => None,
+        })
+        .context("missing string pool")?;
+    let string = |i: i32| -> Result<&str> {
+        strings
+            .get(i as usize)
+            .map(|s| s.as_str())
+            .with_context(|| format!("invalid string index {}", i))
+    };
+    let mut prefixes = BTreeMap::new();
+    let mut namespaces = vec![];
+    let mut stack: Vec<XmlElement> = vec![];
+    let mut root = None;
+    for chunk in chunks {
+        match chunk {
+            Chunk::XmlStartNamespace(_, ns) => {
+                let uri = string(ns.uri)?;
+                let prefix = if uri == ANDROID_NS {
+                    "android"
+                } else if ns.prefix >= 0 {
+                    string(ns.prefix)?
+                } else {
+                    ""
+                };
+                prefixes.insert(uri, prefix);
+                namespaces.push((prefix, uri));
+            }
+            Chunk::XmlStartElement(_, el, attrs) => {
+                let mut element = XmlElement {
+                    name: string(el.name)?.to_string(),
+                    ..Default::default()
+                };
+                for (prefix, uri) in namespaces.drain(..) {
+                    let name = if prefix.is_empty() {
+                        "xmlns".to_string()
+                    } else {
+                        format!("xmlns:{}", prefix)
+                    };
+                    element.attributes.push((name, uri.to_string()));
+                }
+                for attr in attrs {
+                    let mut name = string(attr.name)?.to_string();
+                    if attr.namespace >= 0 {
+                        let ns = string(attr.namespace)?;
+                        match prefixes.get(ns) {
+                            Some(prefix) if !prefix.is_empty() => {
+                                name = format!("{}:{}", prefix, name);
+                            }
+                            _ => {}
+                        }
+                    }
+                    let value = if attr.raw_value >= 0 {
+                        string(attr.raw_value)?.to_string()
+                    } else {
+                        Item::new(&attr.typed_value, strings).to_string()
+                    };
+                    element.attributes.push((name, value));
+                }
+                stack.push(element);
+            }
+            Chunk::XmlCdata(_, cdata) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(string(cdata.data)?);
+                }
+            }
+            Chunk::XmlEndElement(_, _) => {
+                let element = stack.pop().context("unbalanced end element")?;
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(element);
+                } else {
+                    root = Some(element);
+                }
+            }
+            _ => {}
+        }
+    }
+    root.context("missing root element")
+}
+
+/// Converts a decompiled manifest into the manifest model. Elements and
+/// attributes which aren't part of the model are ignored.
+pub fn manifest(root: &XmlElement) -> Result<AndroidManifest> {
+    anyhow::ensure!(
+        root.name == "manifest",
+        "expected <manifest> found <{}>",
+        root.name
+    );
+    let mut manifest = AndroidManifest::default();
+    manifest.package = string(root, "package");
+    manifest.split = string(root, "split");
+    manifest.version_code = number(root, "android:versionCode")?;
+    manifest.version_name = string(root, "android:versionName");
+    manifest.compile_sdk_version = number(root, "android:compileSdkVersion")?;
+    manifest.compile_sdk_version_codename = number(root, "android:compileSdkVersionCodename")?;
+    manifest.platform_build_version_code = number(root, "platformBuildVersionCode")?;
+    manifest.platform_build_version_name = number(root, "platformBuildVersionName")?;
+    if let Some(sdk) = root.children("uses-sdk").next() {
+        manifest.sdk = Sdk {
+            min_sdk_version: number(sdk, "android:minSdkVersion")?,
+            target_sdk_version: number(sdk, "android:targetSdkVersion")?,
+            max_sdk_version: number(sdk, "android:maxSdkVersion")?,
+        };
+    }
+    for feature in root.children("uses-feature") {
+        let opengles_version = number::<u32>(feature, "android:glEsVersion")?
+            .map(|version| ((version >> 16) as u8, version as u8));
+        manifest.uses_feature.push(Feature {
+            name: string(feature, "android:name"),
+            required: boolean(feature, "android:required")?,
+            version: number(feature, "android:version")?,
+            opengles_version,
+            ..Default::default()
+        });
+    }
+    for permission in root.children("uses-permission") {
+        manifest.uses_permission.push(Permission {
+            name: string(permission, "android:name").unwrap_or_default(),
+            max_sdk_version: number(permission, "android:maxSdkVersion")?,
+            ..Default::default()
+        });
+    }
+    for permission in root.children("uses-permission-sdk-23") {
+        manifest.uses_permission_sdk_23.push(Permission {
+            name: string(permission, "android:name").unwrap_or_default(),
+            max_sdk_version: number(permission, "android:maxSdkVersion")?,
+            ..Default::default()
+        });
+    }
+    for permission in root.children("permission") {
+        manifest.permission.push(PermissionDeclaration {
+            name: string(permission, "android:name").unwrap_or_default(),
+            protection_level: string(permission, "android:protectionLevel"),
+            permission_group: string(permission, "android:permissionGroup"),
+            label: string(permission, "android:label"),
+            description: string(permission, "android:description"),
+            icon: string(permission, "android:icon"),
+            ..Default::default()
+        });
+    }
+    if let Some(queries) = root.children("queries").next() {
+        manifest.queries = Some(Queries {
+            packages: queries
+                .children("package")
+                .filter_map(|package| string(package, "android:name"))
+                .collect(),
+            intents: queries.children("intent").map(intent_filter).collect(),
+            providers: queries
+                .children("provider")
+                .filter_map(|provider| string(provider, "android:authorities"))
+                .collect(),
+        });
+    }
+    if let Some(app) = root.children("application").next() {
+        manifest.application = Application {
+            debuggable: boolean(app, "android:debuggable")?,
+            theme: string(app, "android:theme"),
+            has_code: boolean(app, "android:hasCode")?,
+            icon: string(app, "android:icon"),
+            label: string(app, "android:label"),
+            app_component_factory: string(app, "android:appComponentFactory"),
+            extract_native_libs: boolean(app, "android:extractNativeLibs")?,
+            meta_data: meta_data(app),
+            activities: app
+                .children("activity")
+                .map(activity)
+                .collect::<Result<_>>()?,
+            activity_aliases: app
+                .children("activity-alias")
+                .map(activity_alias)
+                .collect::<Result<_>>()?,
+            services: app
+                .children("service")
+                .map(service)
+                .collect::<Result<_>>()?,
+            receivers: app
+                .children("receiver")
+                .map(receiver)
+                .collect::<Result<_>>()?,
+            providers: app
+                .children("provider")
+                .map(provider)
+                .collect::<Result<_>>()?,
+            uses_library: app
+                .children("uses-library")
+                .map(|library| {
+                    Ok(UsesLibrary {
+                        name: string(library, "android:name").unwrap_or_default(),
+                        required: boolean(library, "android:required")?,
+                        ..Default::default()
+                    })
+                })
+                .collect::<Result<_>>()?,
+            profileable: app
+                .children("profileable")
+                .next()
+                .map(|profileable| {
+                    Ok::<_, anyhow::Error>(Profileable {
+                        shell: boolean(profileable, "android:shell")?,
+                        enabled: boolean(profileable, "android:enabled")?,
+                    })
+                })
+                .transpose()?,
+            ..Default::default()
+        };
+    }
+    Ok(manifest)
+}
+
+/// Returns the name of the activity or activity alias handling the `MAIN`
+/// action in the `LAUNCHER` category. Falls back to the first activity.
+pub fn launcher_activity(root: &XmlElement) -> Option<&str> {
+    let app = root.children("application").next()?;
+    let activities = || {
+        app.children
+            .iter()
+            .filter(|child| child.name == "activity" || child.name == "activity-alias")
+    };
+    activities()
+        .find(|activity| {
+            activity.children("intent-filter").any(|filter| {
+                let has = |name, value| {
+                    filter
+                        .children(name)
+                        .any(|child| child.attribute("android:name") == Some(value))
+                };
+                has("action", ACTION_MAIN) && has("category", CATEGORY_LAUNCHER)
+            })
+        })
+        .or_else(|| app.children("activity").next())
+        .and_then(|activity| activity.attribute("android:name"))
+}
+
+fn intent_filter(filter: &XmlElement) -> IntentFilter {
+    let names = |name| {
+        filter
+            .children(name)
+            .filter_map(|child| string(child, "android:name"))
+            .collect()
+    };
+    IntentFilter {
+        actions: names("action"),
+        categories: names("category"),
+        data: filter
+            .children("data")
+            .map(|data| IntentFilterData {
+                scheme: string(data, "android:scheme"),
+                host: string(data, "android:host"),
+                port: string(data, "android:port"),
+                path: string(data, "android:path"),
+                path_pattern: string(data, "android:pathPattern"),
+                path_prefix: string(data, "android:pathPrefix"),
+                mime_type: string(data, "android:mimeType"),
+            })
+            .collect(),
+    }
+}
+
+fn intent_filters(element: &XmlElement) -> Vec<IntentFilter> {
+    element
+        .children("intent-filter")
+        .map(intent_filter)
+        .collect()
+}
+
+fn activity(activity: &XmlElement) -> Result<Activity> {
+    Ok(Activity {
+        config_changes: string(activity, "android:configChanges"),
+        label: string(activity, "android:label"),
+        launch_mode: string(activity, "android:launchMode"),
+        name: string(activity, "android:name"),
+        orientation: string(activity, "android:screenOrientation"),
+        window_soft_input_mode: string(activity, "android:windowSoftInputMode"),
+        exported: boolean(activity, "android:exported")?,
+        hardware_accelerated: boolean(activity, "android:hardwareAccelerated")?,
+        meta_data: meta_data(activity),
+        intent_filters: intent_filters(activity),
+        color_mode: string(activity, "android:colorMode"),
+
+        ..Default::default()
+    })
+}
+
+fn activity_alias(alias: &XmlElement) -> Result<ActivityAlias> {
+    Ok(ActivityAlias {
+        name: string(alias, "android:name"),
+        target_activity: string(alias, "android:targetActivity"),
+        label: string(alias, "android:label"),
+        icon: string(alias, "android:icon"),
+        enabled: boolean(alias, "android:enabled")?,
+        exported: boolean(alias, "android:exported")?,
+        meta_data: meta_data(alias),
+        intent_filters: intent_filters(alias),
+
+        ..Default::default()
+    })
+}
+
+fn service(service: &XmlElement) -> Result<Service> {
+    Ok(Service {
+        name: string(service, "android:name"),
+        label: string(service, "android:label"),
+        icon: string(service, "android:icon"),
+        enabled: boolean(service, "android:enabled")?,
+        exported: boolean(service, "android:exported")?,
+        foreground_service_type: string(service, "android:foregroundServiceType"),
+        permission: string(service, "android:permission"),
+        process: string(service, "android:process"),
+        stop_with_task: boolean(service, "android:stopWithTask")?,
+        meta_data: meta_data(service),
+        intent_filters: intent_filters(service),
+
+        ..Default::default()
+    })
+}
+
+fn receiver(receiver: &XmlElement) -> Result<Receiver> {
+    Ok(Receiver {
+        name: string(receiver, "android:name"),
+        label: string(receiver, "android:label"),
+        enabled: boolean(receiver, "android:enabled")?,
+        exported: boolean(receiver, "android:exported")?,
+        permission: string(receiver, "android:permission"),
+        process: string(receiver, "android:process"),
+        meta_data: meta_data(receiver),
+        intent_filters: intent_filters(receiver),
+
+        ..Default::default()
+    })
+}
+
+fn provider(provider: &XmlElement) -> Result<Provider> {
+    Ok(Provider {
+        name: string(provider, "android:name"),
+        authorities: string(provider, "android:authorities"),
+        enabled: boolean(provider, "android:enabled")?,
+        exported: boolean(provider, "android:exported")?,
+        grant_uri_permissions: boolean(provider, "android:grantUriPermissions")?,
+        permission: string(provider, "android:permission"),
+        read_permission: string(provider, "android:readPermission"),
+        write_permission: string(provider, "android:writePermission"),
+        process: string(provider, "android:process"),
+        meta_data: meta_data(provider),
+
+        ..Default::default()
+    })
+}
+
+fn meta_data(element: &XmlElement) -> Vec<MetaData> {
+    element
+        .children("meta-data")
+        .map(|meta_data| MetaData {
+            name: string(meta_data, "android:name").unwrap_or_default(),
+            value: string(meta_data, "android:value").unwrap_or_default(),
+            resource: string(meta_data, "android:resource"),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn string(element: &XmlElement, name: &str) -> Option<String> {
+    element.attribute(name).map(|value| value.to_string())
+}
+
+fn boolean(element: &XmlElement, name: &str) -> Result<Option<bool>> {
+    element
+        .attribute(name)
+        .map(|value| {
+            value
+                .parse()
+                .with_context(|| format!("invalid boolean {}=\"{}\"", name, value))
+        })
+        .transpose()
+}
+
+fn number<T: TryFrom<u64>>(element: &XmlElement, name: &str) -> Result<Option<T>> {
+    element
+        .attribute(name)
+        .map(|value| {
+            let number = if let Some(hex) = value.strip_prefix("0x") {
+                u64::from_str_radix(hex, 16).ok()
+            } else {
+                value.parse().ok()
+            };
+            number
+                .and_then(|number| T::try_from(number).ok())
+                .with_context(|| format!("invalid number {}=\"{}\"", name, value))
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{xml, Table};
+
+    fn element(name: &str, attributes: &[(&str, &str)], children: Vec<XmlElement>) -> XmlElement {
+        XmlElement {
+            name: name.into(),
+            attributes: attributes
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            children,
+            text: String::new(),
+        }
+    }
+
+    fn activity(name: &str, action: &str, category: &str) -> XmlElement {
+        element(
+            "activity",
+            &[("android:name", name)],
+            vec![element(
+                "intent-filter",
+                &[],
+                vec![
+                    element("action", &[("android:name", action)], vec![]),
+                    element("category", &[("android:name", category)], vec![]),
+                ],
+            )],
+        )
+    }
+
+    #[test]
+    fn test_decompile_xml() -> Result<()> {
+        let xml = r#"<paths xmlns:app="http://example.com/app">
+            <files-path app:name="images" path="images/" shared="true">text &amp; more</files-path>
+        </paths>"#;
+        let chunk = xml::compile_xml(xml, &Table::default())?;
+        let root = decompile_xml(&chunk)?;
+        assert_eq!(root.attribute("xmlns:app"), Some("http://example.com/app"));
+        let path = root.children("files-path").next().unwrap();
+        assert_eq!(path.attribute("app:name"), Some("images"));
+        assert_eq!(path.attribute("path"), Some("images/"));
+        assert_eq!(path.attribute("shared"), Some("true"));
+        assert_eq!(path.text, "text & more");
+        assert_eq!(
+            root.to_string(),
+            r#"<paths xmlns:app="http://example.com/app">
+  <files-path app:name="images" path="images/" shared="true">text &amp; more</files-path>
+</paths>
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest() -> Result<()> {
+        let root = element(
+            "manifest",
+            &[
+                ("package", "com.example.helloworld"),
+                ("android:versionCode", "3"),
+            ],
+            vec![
+                element(
+                    "uses-sdk",
+                    &[
+                        ("android:minSdkVersion", "21"),
+                        ("android:targetSdkVersion", "33"),
+                    ],
+                    vec![],
+                ),
+                element(
+                    "uses-permission",
+                    &[("android:name", "android.permission.INTERNET")],
+                    vec![],
+                ),
+                element(
+                    "uses-feature",
+                    &[("android:glEsVersion", "0x00030002")],
+                    vec![],
+                ),
+                element(
+                    "application",
+                    &[("android:debuggable", "true")],
+                    vec![
+                        activity(".Settings", "android.intent.action.VIEW", ""),
+                        activity(
+                            ".MainActivity",
+                            ACTION_MAIN,
+                            "android.intent.category.DEFAULT",
+                        ),
+                        element(
+                            "activity-alias",
+                            &[("android:name", ".Launcher")],
+                            activity("", ACTION_MAIN, CATEGORY_LAUNCHER).children,
+                        ),
+                    ],
+                ),
+            ],
+        );
+        let manifest = manifest(&root)?;
+        assert_eq!(manifest.package.as_deref(), Some("com.example.helloworld"));
+        assert_eq!(manifest.version_code, Some(3));
+        assert_eq!(manifest.sdk.min_sdk_version, Some(21));
+        assert_eq!(manifest.sdk.target_sdk_version, Some(33));
+        assert_eq!(
+            manifest.uses_permission[0].name,
+            "android.permission.INTERNET"
+        );
+        assert_eq!(manifest.uses_feature[0].opengles_version, Some((3, 2)));
+        assert_eq!(manifest.application.debuggable, Some(true));
+        assert_eq!(manifest.application.activities.len(), 2);
+        assert_eq!(
+            manifest.application.activities[1].intent_filters[0].actions,
+            [ACTION_MAIN]
+        );
+        assert_eq!(launcher_activity(&root), Some(".Launcher"));
+
+        let mut root = root;
+        root.children[3].children.pop();
+        assert_eq!(launcher_activity(&root), Some(".Settings"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_components() -> Result<()> {
+        let root = element(
+            "manifest",
+            &[],
+            vec![
+                element(
+                    "queries",
+                    &[],
+                    vec![element(
+                        "package",
+                        &[("android:name", "com.example.other")],
+                        vec![],
+                    )],
+                ),
+                element(
+                    "application",
+                    &[],
+                    vec![
+                        element(
+                            "service",
+                            &[
+                                ("android:name", ".AudioService"),
+                                ("android:foregroundServiceType", "mediaPlayback"),
+                            ],
+                            vec![],
+                        ),
+                        element(
+                            "receiver",
+                            &[("android:name", ".BootReceiver")],
+                            activity("", "android.intent.action.BOOT_COMPLETED", "").children,
+                        ),
+                        element(
+                            "provider",
+                            &[("android:authorities", "com.example.fileprovider")],
+                            vec![element(
+                                "meta-data",
+                                &[
+                                    ("android:name", "android.support.FILE_PROVIDER_PATHS"),
+                                    ("android:resource", "@0x7f030000"),
+                                ],
+                                vec![],
+                            )],
+                        ),
+                        element(
+                            "uses-library",
+                            &[
+                                ("android:name", "org.apache.http.legacy"),
+                                ("android:required", "false"),
+                            ],
+                            vec![],
+                        ),
+                        element("profileable", &[("android:shell", "true")], vec![]),
+                    ],
+                ),
+            ],
+        );
+        let manifest = manifest(&root)?;
+        assert_eq!(manifest.queries.unwrap().packages, ["com.example.other"]);
+        let app = manifest.application;
+        assert_eq!(
+            app.services[0].foreground_service_type.as_deref(),
+            Some("mediaPlayback")
+        );
+        assert_eq!(
+            app.receivers[0].intent_filters[0].actions,
+            ["android.intent.action.BOOT_COMPLETED"]
+        );
+        let provider = &app.providers[0];
+        assert_eq!(
+            provider.authorities.as_deref(),
+            Some("com.example.fileprovider")
+        );
+        assert_eq!(
+            provider.meta_data[0].resource.as_deref(),
+            Some("@0x7f030000")
+        );
+        assert_eq!(app.uses_library[0].required, Some(false));
+        assert_eq!(app.profileable.unwrap().shell, Some(true));
+        Ok(())
+    }
+}
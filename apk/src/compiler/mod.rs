@@ -1,118 +1,387 @@
 use crate::manifest::AndroidManifest;
-use crate::res::{
-    Chunk, ResTableConfig, ResTableEntry, ResTableHeader, ResTablePackageHeader,
-    ResTableTypeHeader, ResTableTypeSpecHeader, ResTableValue, ResValue, ScreenType,
-};
-use anyhow::Result;
+use crate::res::{Chunk, ResAttributeType, ResTableConfig};
+use crate::IconBackground;
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use xcommon::{Scaler, ScalerOpts, ScalerOptsBuilder};
 
 mod attributes;
+mod decompile;
+mod package;
 mod table;
-mod xml;
+mod values;
+pub mod xml;
 
+pub use decompile::{decompile_xml, launcher_activity, XmlElement};
+pub use package::{PackageBuilder, StringPool, Symbols, Value};
 pub use table::Table;
 
+/// A file of the compiled resources of an app.
+pub enum Output {
+    /// The resource table `resources.arsc`.
+    Table(Chunk),
+    /// `AndroidManifest.xml` or an xml resource.
+    Xml(String, Chunk),
+    /// Images and raw resources.
+    File(String, Vec<u8>),
+}
+
+/// Compiles the `res` directory, the `res` directories of libraries and the
+/// icon into a resource table and compiles the manifest. Resources of the app
+/// override resources of libraries, earlier libraries override later ones.
+/// References to resources in the manifest are resolved using the compiled
+/// resources and the android framework resources in `android`. Returns the
+/// outputs and the symbols of the package.
+pub fn compile_resources(
+    manifest: &mut AndroidManifest,
+    icon_background: &IconBackground,
+    res: Option<&Path>,
+    libraries: &[PathBuf],
+    icon: Option<&Scaler>,
+    android: &Path,
+) -> Result<(Vec<Output>, Symbols)> {
+    let mut outputs = vec![];
+    let mut symbols = Symbols::default();
+    let mut table = Table::default();
+    table.import_apk(android)?;
+    if res.is_some() || !libraries.is_empty() || icon.is_some() {
+        let package = if let Some(package) = manifest.package.as_ref() {
+            package
+        } else {
+            anyhow::bail!("missing manifest.package");
+        };
+        let mut package = PackageBuilder::new(package);
+        let mut res_files = if let Some(res) = res {
+            compile_res_dir(&mut package, res)?
+        } else {
+            vec![]
+        };
+        let mut adaptive_icon = None;
+        if let Some(scaler) = icon {
+            let mut scaler = scaler.clone();
+            scaler.optimize();
+            let mipmap = compile_mipmap(&mut package, "icon", 48)?;
+            mipmap.scale(&scaler, ScalerOpts::new, &mut outputs)?;
+
+            // the 108dp layers of adaptive icons are masked to 72dp
+            let mipmap = compile_mipmap(&mut package, "icon_foreground", 108)?;
+            mipmap.scale(
+                &scaler,
+                |size| {
+                    ScalerOptsBuilder::new(size, size)
+                        .padding(1.0 / 3.0)
+                        .build()
+                },
+                &mut outputs,
+            )?;
+            let background = match icon_background {
+                IconBackground::Color(color) => {
+                    package.add(
+                        "color",
+                        "icon_background",
+                        Default::default(),
+                        Value::Item {
+                            format: ResAttributeType::Reference as u32
+                                | ResAttributeType::Color as u32,
+                            value: color.clone(),
+                        },
+                    )?;
+                    "@color/icon_background"
+                }
+                IconBackground::Image(path) => {
+                    let scaler = Scaler::open(path)?;
+                    let mipmap = compile_mipmap(&mut package, "icon_background", 108)?;
+                    mipmap.scale(&scaler, ScalerOpts::new, &mut outputs)?;
+                    "@mipmap/icon_background"
+                }
+            };
+            adaptive_icon = Some(compile_adaptive_icon(
+                &mut package,
+                "icon",
+                background,
+                "@mipmap/icon_foreground",
+                Some("@mipmap/icon_foreground"),
+            )?);
+            manifest.application.icon = Some("@mipmap/icon".into());
+        }
+
+        let mut paths = res_files
+            .iter()
+            .map(|file| file.path.clone())
+            .chain(outputs.iter().filter_map(|output| match output {
+                Output::File(path, _) => Some(path.clone()),
+                _ => None,
+            }))
+            .collect();
+        res_files.extend(compile_library_res(&mut package, libraries, &mut paths)?);
+
+        outputs.push(Output::Table(package.build(&mut table)?));
+        symbols = package.symbols(&table)?;
+        for file in &res_files {
+            outputs.push(file.compile(&table)?);
+        }
+        if let Some(icon) = adaptive_icon {
+            outputs.push(Output::Xml(icon.path().into(), icon.compile(&table)?));
+        }
+    }
+    let manifest = compile_manifest(manifest, &table)?;
+    outputs.push(Output::Xml("AndroidManifest.xml".into(), manifest));
+    Ok((outputs, symbols))
+}
+
 pub fn compile_manifest(manifest: &AndroidManifest, table: &Table) -> Result<Chunk> {
     let xml = quick_xml::se::to_string(manifest)?;
     xml::compile_xml(&xml, table)
 }
 
-const DPI_SIZE: [u32; 5] = [48, 72, 96, 144, 192];
+/// Decompiles a binary manifest into the manifest model.
+pub fn decompile_manifest(chunk: &Chunk) -> Result<AndroidManifest> {
+    decompile::manifest(&decompile_xml(chunk)?)
+}
+
+/// Converts a manifest element into the manifest model.
+pub fn manifest_from_element(root: &XmlElement) -> Result<AndroidManifest> {
+    decompile::manifest(root)
+}
+
+/// Compiles a resource directory. The resources of `values` directories are
+/// added to the package, every other file is added as a file resource named
+/// after the file. Qualified directories like `values-de` or `xml-v24` add
+/// the resources for their configuration. Returns the files that need to be
+/// added to the apk.
+pub fn compile_res_dir(package: &mut PackageBuilder, res: &Path) -> Result<Vec<ResFile>> {
+    let mut res_files = vec![];
+    for dir in read_dir(res)? {
+        if !dir.is_dir() {
+            continue;
+        }
+        let dir_name = dir
+            .file_name()
+            .unwrap()
+            .to_str()
+            .context("invalid directory name")?;
+        let (ty, qualifiers) = dir_name.split_once('-').unwrap_or((dir_name, ""));
+        let config = ResTableConfig::from_qualifiers(qualifiers)
+            .with_context(|| format!("invalid resource directory {}", dir.display()))?;
+        for file in read_dir(&dir)? {
+            let file_name = file
+                .file_name()
+                .unwrap()
+                .to_str()
+                .context("invalid file name")?;
+            let is_xml = file.extension() == Some("xml".as_ref());
+            if ty == "values" {
+                if is_xml {
+                    let xml = std::fs::read_to_string(&file)?;
+                    values::compile_values(package, &xml, &config)
+                        .with_context(|| format!("failed to compile {}", file.display()))?;
+                }
+                continue;
+            }
+            // `name.9.png` is named `name`
+            let name = file_name.split('.').next().unwrap();
+            let path = format!("res/{}/{}", dir_name, file_name);
+            package.add(ty, name, config.clone(), Value::File(path.clone()))?;
+            // raw files are added as is
+            let is_xml = is_xml && ty != "raw";
+            if is_xml {
+                let xml = std::fs::read_to_string(&file)?;
+                declare_ids(package, &xml)
+                    .with_context(|| format!("failed to compile {}", file.display()))?;
+            }
+            res_files.push(ResFile {
+                path,
+                source: file,
+                is_xml,
+            });
+        }
+    }
+    Ok(res_files)
+}
 
-fn variants(name: &str) -> impl Iterator<Item = (String, u32)> + '_ {
-    DPI_SIZE
+/// Compiles the `res` directories of libraries and adds the resources that
+/// the package doesn't declare yet. Returns the files of the added resources
+/// and adds their paths to `paths`, a file is only returned if its path isn't
+/// in `paths` yet.
+fn compile_library_res(
+    package: &mut PackageBuilder,
+    libraries: &[PathBuf],
+    paths: &mut BTreeSet<String>,
+) -> Result<Vec<ResFile>> {
+    let mut library_files = vec![];
+    for library in libraries {
+        let mut library_package = PackageBuilder::new(package.name());
+        library_files.extend(compile_res_dir(&mut library_package, library)?);
+        package.merge(library_package);
+    }
+    // files of overridden library resources are dropped
+    let files = package.files().collect::<BTreeSet<_>>();
+    Ok(library_files
         .into_iter()
-        .map(move |size| (format!("res/{0}/{0}{1}.png", name, size), size))
-}
-
-pub fn compile_mipmap<'a>(package_name: &str, name: &'a str) -> Result<Mipmap<'a>> {
-    let chunk = Chunk::Table(
-        ResTableHeader { package_count: 1 },
-        vec![
-            Chunk::StringPool(variants(name).map(|(res, _)| res).collect(), vec![]),
-            Chunk::TablePackage(
-                ResTablePackageHeader {
-                    id: 127,
-                    name: package_name.to_string(),
-                    type_strings: 288,
-                    last_public_type: 1,
-                    key_strings: 332,
-                    last_public_key: 1,
-                    type_id_offset: 0,
-                },
-                vec![
-                    Chunk::StringPool(vec!["mipmap".to_string()], vec![]),
-                    Chunk::StringPool(vec!["icon".to_string()], vec![]),
-                    Chunk::TableTypeSpec(
-                        ResTableTypeSpecHeader {
-                            id: 1,
-                            res0: 0,
-                            res1: 0,
-                            entry_count: 1,
+        .filter(|file| files.contains(file.path.as_str()) && paths.insert(file.path.clone()))
+        .collect())
+}
+
+fn read_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+/// Adds the ids declared using `@+id/name` in an xml file.
+fn declare_ids(package: &mut PackageBuilder, xml: &str) -> Result<()> {
+    let doc = roxmltree::Document::parse(xml)?;
+    for node in doc.descendants() {
+        for attr in node.attributes() {
+            if let Some(name) = attr.value().strip_prefix("@+id/") {
+                if !package.contains("id", name) {
+                    package.add(
+                        "id",
+                        name,
+                        Default::default(),
+                        Value::Item {
+                            format: ResAttributeType::Boolean as u32,
+                            value: "false".into(),
                         },
-                        vec![256],
-                    ),
-                    mipmap_table_type(1, 160, 0),
-                    mipmap_table_type(1, 240, 1),
-                    mipmap_table_type(1, 320, 2),
-                    mipmap_table_type(1, 480, 3),
-                    mipmap_table_type(1, 640, 4),
-                ],
-            ),
-        ],
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A file of a resource directory.
+pub struct ResFile {
+    path: String,
+    source: PathBuf,
+    is_xml: bool,
+}
+
+impl ResFile {
+    /// Compiles xml files after the package was added to the `table`, other
+    /// files are returned as is.
+    pub fn compile(&self, table: &Table) -> Result<Output> {
+        if !self.is_xml {
+            return Ok(Output::File(
+                self.path.clone(),
+                std::fs::read(&self.source)?,
+            ));
+        }
+        let xml = std::fs::read_to_string(&self.source)?;
+        let chunk = xml::compile_xml(&xml, table)
+            .with_context(|| format!("failed to compile {}", self.source.display()))?;
+        Ok(Output::Xml(self.path.clone(), chunk))
+    }
+}
+
+const DPI_DENSITY: [u16; 5] = [
+    ResTableConfig::DENSITY_MEDIUM,
+    ResTableConfig::DENSITY_HIGH,
+    ResTableConfig::DENSITY_XHIGH,
+    ResTableConfig::DENSITY_XXHIGH,
+    ResTableConfig::DENSITY_XXXHIGH,
+];
+
+/// Adds a mipmap of `dp` x `dp` with an entry for each density.
+pub fn compile_mipmap(package: &mut PackageBuilder, name: &str, dp: u32) -> Result<Mipmap> {
+    let mut variants = Vec::with_capacity(DPI_DENSITY.len());
+    for density in DPI_DENSITY {
+        let config = ResTableConfig {
+            density,
+            sdk_version: 4,
+            ..Default::default()
+        };
+        let path = format!("res/mipmap-{}/{}.png", config, name);
+        package.add("mipmap", name, config, Value::File(path.clone()))?;
+        variants.push((
+            path,
+            dp * density as u32 / ResTableConfig::DENSITY_MEDIUM as u32,
+        ));
+    }
+    Ok(Mipmap { variants })
+}
+
+pub struct Mipmap {
+    variants: Vec<(String, u32)>,
+}
+
+impl Mipmap {
+    /// Returns the path and size in pixels of each density.
+    pub fn variants(&self) -> impl Iterator<Item = (String, u32)> + '_ {
+        self.variants.iter().cloned()
+    }
+
+    fn scale(
+        &self,
+        scaler: &Scaler,
+        opts: impl Fn(u32) -> ScalerOpts,
+        outputs: &mut Vec<Output>,
+    ) -> Result<()> {
+        for (path, size) in self.variants() {
+            let mut buf = vec![];
+            scaler.write(&mut Cursor::new(&mut buf), opts(size))?;
+            outputs.push(Output::File(path, buf));
+        }
+        Ok(())
+    }
+}
+
+/// Adds an adaptive icon for android 8 and later. The layers are references
+/// to drawables or colors. Launchers supporting themed icons use the alpha
+/// channel of the `monochrome` layer.
+pub fn compile_adaptive_icon(
+    package: &mut PackageBuilder,
+    name: &str,
+    background: &str,
+    foreground: &str,
+    monochrome: Option<&str>,
+) -> Result<AdaptiveIcon> {
+    let config = ResTableConfig {
+        density: ResTableConfig::DENSITY_ANY,
+        sdk_version: 26,
+        ..Default::default()
+    };
+    let path = format!("res/mipmap-{}/{}.xml", config, name);
+    package.add("mipmap", name, config, Value::File(path.clone()))?;
+    let mut xml = String::from(
+        r#"<adaptive-icon xmlns:android="http://schemas.android.com/apk/res/android">"#,
     );
-    Ok(Mipmap { name, chunk })
-}
-
-fn mipmap_table_type(type_id: u8, density: u16, string_id: u32) -> Chunk {
-    Chunk::TableType(
-        ResTableTypeHeader {
-            id: type_id,
-            res0: 0,
-            res1: 0,
-            entry_count: 1,
-            entries_start: 88,
-            config: ResTableConfig {
-                size: 28 + 36,
-                imsi: 0,
-                locale: 0,
-                screen_type: ScreenType {
-                    orientation: 0,
-                    touchscreen: 0,
-                    density,
-                },
-                input: 0,
-                screen_size: 0,
-                version: 4,
-                unknown: vec![0; 36],
-            },
-        },
-        vec![0],
-        vec![Some(ResTableEntry {
-            size: 8,
-            flags: 0,
-            key: 0,
-            value: ResTableValue::Simple(ResValue {
-                size: 8,
-                res0: 0,
-                data_type: 3,
-                data: string_id,
-            }),
-        })],
-    )
+    xml.push_str(&format!(
+        r#"<background android:drawable="{}"/>"#,
+        background
+    ));
+    xml.push_str(&format!(
+        r#"<foreground android:drawable="{}"/>"#,
+        foreground
+    ));
+    if let Some(monochrome) = monochrome {
+        xml.push_str(&format!(
+            r#"<monochrome android:drawable="{}"/>"#,
+            monochrome
+        ));
+    }
+    xml.push_str("</adaptive-icon>");
+    Ok(AdaptiveIcon { path, xml })
 }
 
-pub struct Mipmap<'a> {
-    name: &'a str,
-    chunk: Chunk,
+pub struct AdaptiveIcon {
+    path: String,
+    xml: String,
 }
 
-impl<'a> Mipmap<'a> {
-    pub fn chunk(&self) -> &Chunk {
-        &self.chunk
+impl AdaptiveIcon {
+    pub fn path(&self) -> &str {
+        &self.path
     }
 
-    pub fn variants(&self) -> impl Iterator<Item = (String, u32)> + 'a {
-        variants(self.name)
+    /// Compiles the icon after the layers were added to the `table`.
+    pub fn compile(&self, table: &Table) -> Result<Chunk> {
+        xml::compile_xml(&self.xml, table)
     }
 }
 
@@ -120,21 +389,255 @@ impl<'a> Mipmap<'a> {
 mod tests {
     use super::*;
     use crate::compiler::table::Ref;
-    use crate::manifest::Activity;
+    use crate::manifest::{Activity, Service};
+    use crate::res::{ResValueType, ResXmlCdata};
     use std::io::Cursor;
 
+    #[test]
+    fn test_compile_res_dir() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let res = tmp.path();
+        for (dir, label) in [
+            ("values", "Hello"),
+            ("values-de", "Hallo"),
+            ("values-night", "Night"),
+        ] {
+            std::fs::create_dir_all(res.join(dir))?;
+            std::fs::write(
+                res.join(dir).join("strings.xml"),
+                format!(
+                    r#"<resources><string name="label">{}</string></resources>"#,
+                    label
+                ),
+            )?;
+        }
+        let mut package = PackageBuilder::new("com.example.helloworld");
+        compile_res_dir(&mut package, res)?;
+        let chunk = package.build(&mut Table::default())?;
+        let chunks = match &chunk {
+            Chunk::Table(_, chunks) => match &chunks[1] {
+                Chunk::TablePackage(_, chunks) => chunks,
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        let configs = chunks
+            .iter()
+            .filter_map(|chunk| match chunk {
+                Chunk::TableType(header, _, _) => Some(header.config.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(configs, ["", "de", "night-v8"]);
+        let spec = chunks.iter().find_map(|chunk| match chunk {
+            Chunk::TableTypeSpec(_, spec) => Some(spec[0]),
+            _ => None,
+        });
+        assert_eq!(
+            spec,
+            Some(ResTableConfig::CONFIG_LOCALE | ResTableConfig::CONFIG_UI_MODE)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_res_files() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let res = tmp.path();
+        for dir in ["layout", "raw", "xml"] {
+            std::fs::create_dir_all(res.join(dir))?;
+        }
+        std::fs::write(
+            res.join("layout").join("main.xml"),
+            r#"<FrameLayout xmlns:android="http://schemas.android.com/apk/res/android">
+                <TextView android:id="@+id/title"/>
+            </FrameLayout>"#,
+        )?;
+        std::fs::write(res.join("raw").join("ca.pem"), "certificate")?;
+        std::fs::write(
+            res.join("xml").join("network_security_config.xml"),
+            r#"<network-security-config xmlns:tools="http://schemas.android.com/tools">
+                <domain-config tools:ignore="InsecureBaseConfiguration">
+                    <domain includeSubdomains="true">example.com</domain>
+                    <trust-anchors><certificates src="@raw/ca"/></trust-anchors>
+                </domain-config>
+            </network-security-config>"#,
+        )?;
+        let mut package = PackageBuilder::new("com.example.helloworld");
+        let files = compile_res_dir(&mut package, res)?;
+        let paths = files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "res/layout/main.xml",
+                "res/raw/ca.pem",
+                "res/xml/network_security_config.xml"
+            ]
+        );
+        let mut table = Table::default();
+        package.build(&mut table)?;
+        assert_eq!(
+            u32::from(table.entry_by_ref(Ref::parse("@id/title")?)?.id()),
+            0x7f01_0000
+        );
+        let ca = u32::from(table.entry_by_ref(Ref::parse("@raw/ca")?)?.id());
+        assert_eq!(ca, 0x7f03_0000);
+        table.entry_by_ref(Ref::parse("@xml/network_security_config")?)?;
+        match files[1].compile(&table)? {
+            Output::File(_, bytes) => assert_eq!(bytes, b"certificate"),
+            _ => unreachable!(),
+        }
+
+        let chunks = match files[2].compile(&table)? {
+            Output::Xml(_, Chunk::Xml(chunks)) => chunks,
+            _ => unreachable!(),
+        };
+        let strings = match &chunks[0] {
+            Chunk::StringPool(strings, _) => strings,
+            _ => unreachable!(),
+        };
+        assert!(!strings.iter().any(|s| s == "ignore" || s == "tools"));
+        assert!(!chunks
+            .iter()
+            .any(|chunk| matches!(chunk, Chunk::XmlStartNamespace(_, _))));
+        let text = chunks.iter().find_map(|chunk| match chunk {
+            Chunk::XmlCdata(_, ResXmlCdata { data, .. }) => Some(strings[*data as usize].as_str()),
+            _ => None,
+        });
+        assert_eq!(text, Some("example.com"));
+        let values = chunks
+            .iter()
+            .filter_map(|chunk| match chunk {
+                Chunk::XmlStartElement(_, _, attrs) if !attrs.is_empty() => {
+                    let value = attrs[0].typed_value;
+                    Some((ResValueType::from_u8(value.data_type).unwrap(), value.data))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [
+                (ResValueType::IntBoolean, 0xffff_ffff),
+                (ResValueType::Reference, ca)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_library_res() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let app = tmp.path().join("app");
+        let library = tmp.path().join("library");
+        for dir in [&app, &library] {
+            std::fs::create_dir_all(dir.join("values"))?;
+            std::fs::create_dir_all(dir.join("layout"))?;
+            std::fs::write(dir.join("layout").join("main.xml"), "<FrameLayout/>")?;
+        }
+        std::fs::write(
+            app.join("values").join("strings.xml"),
+            r#"<resources><string name="label">App</string></resources>"#,
+        )?;
+        std::fs::write(
+            library.join("values").join("values.xml"),
+            r#"<resources>
+                <string name="label">Library</string>
+                <string name="title">Title</string>
+                <attr name="title" format="reference|string" />
+            </resources>"#,
+        )?;
+        std::fs::write(
+            library.join("layout").join("title.xml"),
+            r#"<TextView xmlns:app="http://schemas.android.com/apk/res-auto"
+                app:title="@string/title"/>"#,
+        )?;
+        let mut package = PackageBuilder::new("com.example.helloworld");
+        let mut files = compile_res_dir(&mut package, &app)?;
+        let mut paths = files.iter().map(|file| file.path.clone()).collect();
+        files.extend(compile_library_res(&mut package, &[library], &mut paths)?);
+        let paths = files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["res/layout/main.xml", "res/layout/title.xml"]);
+
+        let mut table = Table::default();
+        let chunk = package.build(&mut table)?;
+        if let Chunk::Table(_, chunks) = &chunk {
+            assert_eq!(
+                chunks[0],
+                Chunk::StringPool(
+                    vec![
+                        "res/layout/main.xml".into(),
+                        "res/layout/title.xml".into(),
+                        "App".into(),
+                        "Title".into()
+                    ],
+                    vec![]
+                )
+            );
+        }
+        let attr = u32::from(table.entry_by_ref(Ref::parse("@attr/title")?)?.id());
+        let title = u32::from(table.entry_by_ref(Ref::parse("@string/title")?)?.id());
+        let chunks = match files[1].compile(&table)? {
+            Output::Xml(_, Chunk::Xml(chunks)) => chunks,
+            _ => unreachable!(),
+        };
+        assert_eq!(chunks[1], Chunk::XmlResourceMap(vec![attr]));
+        let value = chunks.iter().find_map(|chunk| match chunk {
+            Chunk::XmlStartElement(_, _, attrs) => Some(attrs[0].typed_value),
+            _ => None,
+        });
+        assert_eq!(value.map(|value| value.data), Some(title));
+        Ok(())
+    }
+
     #[test]
     fn test_compile_mipmap() -> Result<()> {
         crate::tests::init_logger();
-        let mipmap = compile_mipmap("com.example.helloworld", "icon")?;
+        let mut package = PackageBuilder::new("com.example.helloworld");
+        let mipmap = compile_mipmap(&mut package, "icon", 48)?;
+        let variants = mipmap.variants().collect::<Vec<_>>();
+        assert_eq!(variants.len(), 5);
+        assert_eq!(variants[0], ("res/mipmap-mdpi-v4/icon.png".into(), 48));
+        assert_eq!(variants[4], ("res/mipmap-xxxhdpi-v4/icon.png".into(), 192));
+        let mut table = Table::default();
+        let chunk = package.build(&mut table)?;
         let mut buf = vec![];
         let mut cursor = Cursor::new(&mut buf);
-        mipmap.chunk().write(&mut cursor)?;
+        chunk.write(&mut cursor)?;
         let mut cursor = Cursor::new(&buf);
-        let chunk = Chunk::parse(&mut cursor)?;
-        println!("{:#?}", mipmap.chunk());
+        let parsed = Chunk::parse(&mut cursor)?;
         println!("{:#?}", chunk);
-        assert_eq!(*mipmap.chunk(), chunk);
+        println!("{:#?}", parsed);
+        assert_eq!(chunk, parsed);
+        let entry = table.entry_by_ref(Ref::parse("@mipmap/icon")?)?;
+        assert_eq!(u32::from(entry.id()), 0x7f01_0000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_adaptive_icon() -> Result<()> {
+        let android = crate::tests::find_android_jar()?;
+        let mut table = Table::default();
+        table.import_apk(&android)?;
+        let mut package = PackageBuilder::new("com.example.helloworld");
+        compile_mipmap(&mut package, "icon", 48)?;
+        compile_mipmap(&mut package, "icon_foreground", 108)?;
+        let icon = compile_adaptive_icon(
+            &mut package,
+            "icon",
+            "@android:color/white",
+            "@mipmap/icon_foreground",
+            Some("@mipmap/icon_foreground"),
+        )?;
+        assert_eq!(icon.path(), "res/mipmap-anydpi-v26/icon.xml");
+        package.build(&mut table)?;
+        icon.compile(&table)?;
         Ok(())
     }
 
@@ -163,7 +666,21 @@ mod tests {
             ..Default::default()
         };
         manifest.application.activities.push(activity);
-        let _chunk = compile_manifest(&manifest, &table)?;
+        manifest.application.services.push(Service {
+            name: Some(".AudioService".into()),
+            foreground_service_type: Some("mediaPlayback|microphone".into()),
+            exported: Some(false),
+            ..Default::default()
+        });
+        let chunk = compile_manifest(&manifest, &table)?;
+        let decompiled = decompile_manifest(&chunk)?;
+        assert_eq!(decompiled.application.label.as_deref(), Some("helloworld"));
+        assert_eq!(decompiled.application.debuggable, Some(true));
+        assert_eq!(decompiled.application.activities.len(), 1);
+        let service = &decompiled.application.services[0];
+        assert_eq!(service.exported, Some(false));
+        // flags are decompiled as their value
+        assert!(service.foreground_service_type.is_some());
         Ok(())
     }
 }
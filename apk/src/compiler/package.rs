@@ -0,0 +1,531 @@
+use crate::compiler::attributes::compile_value;
+use crate::compiler::table::{Ref, Table};
+use crate::res::{
+    Chunk, ResAttributeType, ResTableConfig, ResTableEntry, ResTableHeader, ResTableMap,
+    ResTableMapEntry, ResTablePackageHeader, ResTableRef, ResTableTypeHeader,
+    ResTableTypeSpecHeader, ResTableValue, ResValue, ResValueType, ATTR_TYPE,
+};
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Cursor;
+
+/// Id of the package of the app.
+pub const PACKAGE_ID: u8 = 127;
+
+/// Format of resources that can contain any value.
+pub const ANY: u32 = ResAttributeType::Any as u32;
+
+type Types = BTreeMap<String, BTreeMap<String, Vec<(ResTableConfig, Value)>>>;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Value {
+    /// A value compiled according to `format`, a mask of [`ResAttributeType`]s.
+    Item { format: u32, value: String },
+    /// Path of a file in the apk.
+    File(String),
+    /// A style with an optional parent style reference and `(attr, value)`
+    /// items.
+    Style {
+        parent: Option<String>,
+        items: Vec<(String, String)>,
+    },
+    /// An array or plurals with `(key, value)` items, where the key is the
+    /// index of an array item or the quantity of a plural.
+    Bag {
+        format: u32,
+        items: Vec<(u32, String)>,
+    },
+    /// An attribute with a `format` and the `(name, value)` symbols of an
+    /// enum or flags attribute. The symbols are declared as ids.
+    Attr {
+        format: u32,
+        symbols: Vec<(String, u32)>,
+    },
+}
+
+/// Ids of the resources of a package, used to generate `R` classes.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Symbols {
+    /// `(type, name, id)` of each resource.
+    pub resources: Vec<(String, String, u32)>,
+    /// Styleables with the `(attr, id)` of their attributes ordered by id.
+    pub styleables: Vec<(String, Vec<(String, u32)>)>,
+}
+
+/// Collects the resources of a package and compiles them into a resource
+/// table. Type and entry ids are assigned in the order of their names.
+pub struct PackageBuilder {
+    name: String,
+    types: Types,
+    styleables: BTreeMap<String, Vec<String>>,
+    weak_attrs: BTreeSet<String>,
+}
+
+impl PackageBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            types: Default::default(),
+            styleables: Default::default(),
+            weak_attrs: Default::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn add(
+        &mut self,
+        ty: &str,
+        name: &str,
+        config: ResTableConfig,
+        value: Value,
+    ) -> Result<()> {
+        let configs = self
+            .types
+            .entry(ty.to_string())
+            .or_default()
+            .entry(name.to_string())
+            .or_default();
+        anyhow::ensure!(
+            configs.iter().all(|(c, _)| *c != config),
+            "duplicate resource @{}/{}",
+            ty,
+            name
+        );
+        configs.push((config, value));
+        Ok(())
+    }
+
+    pub fn contains(&self, ty: &str, name: &str) -> bool {
+        self.types
+            .get(ty)
+            .map(|entries| entries.contains_key(name))
+            .unwrap_or_default()
+    }
+
+    /// Declares an attribute and the ids of its symbols. An attribute without
+    /// a `format` is a weak declaration that accepts any value until the
+    /// attribute is declared with a format. Attributes may be declared more
+    /// than once, the first declaration with a format is used.
+    pub fn add_attr(
+        &mut self,
+        name: &str,
+        format: Option<u32>,
+        symbols: Vec<(String, u32)>,
+    ) -> Result<()> {
+        for (symbol, _) in &symbols {
+            if !self.contains("id", symbol) {
+                self.add(
+                    "id",
+                    symbol,
+                    Default::default(),
+                    Value::Item {
+                        format: ResAttributeType::Boolean as u32,
+                        value: "false".into(),
+                    },
+                )?;
+            }
+        }
+        let weak = format.is_none();
+        if self.contains("attr", name) && (weak || !self.weak_attrs.contains(name)) {
+            return Ok(());
+        }
+        let value = Value::Attr {
+            format: format.unwrap_or(ANY),
+            symbols,
+        };
+        self.types
+            .entry("attr".into())
+            .or_default()
+            .insert(name.to_string(), vec![(Default::default(), value)]);
+        if weak {
+            self.weak_attrs.insert(name.to_string());
+        } else {
+            self.weak_attrs.remove(name);
+        }
+        Ok(())
+    }
+
+    /// Adds a styleable with its attributes, `android:name` for framework
+    /// attributes. Styleables are only used to generate `R` classes.
+    pub fn add_styleable(&mut self, name: &str, attrs: Vec<String>) -> Result<()> {
+        anyhow::ensure!(
+            !self.styleables.contains_key(name),
+            "duplicate resource @styleable/{}",
+            name
+        );
+        self.styleables.insert(name.to_string(), attrs);
+        Ok(())
+    }
+
+    /// Adds the resources of a library package. Resources that are already
+    /// declared for the same configuration take precedence, styleables
+    /// declared by both packages contain the attributes of both.
+    pub fn merge(&mut self, library: PackageBuilder) {
+        for (ty, entries) in library.types {
+            let types = self.types.entry(ty.clone()).or_default();
+            for (name, variants) in entries {
+                if ty == "attr" {
+                    let weak = library.weak_attrs.contains(&name);
+                    if !types.contains_key(&name) || !weak && self.weak_attrs.contains(&name) {
+                        if weak {
+                            self.weak_attrs.insert(name.clone());
+                        } else {
+                            self.weak_attrs.remove(&name);
+                        }
+                        types.insert(name, variants);
+                    }
+                    continue;
+                }
+                let configs = types.entry(name).or_default();
+                for (config, value) in variants {
+                    if configs.iter().all(|(c, _)| *c != config) {
+                        configs.push((config, value));
+                    }
+                }
+            }
+        }
+        for (name, attrs) in library.styleables {
+            let styleable = self.styleables.entry(name).or_default();
+            for attr in attrs {
+                if !styleable.contains(&attr) {
+                    styleable.push(attr);
+                }
+            }
+        }
+    }
+
+    /// Returns the paths of the file resources.
+    pub fn files(&self) -> impl Iterator<Item = &str> + '_ {
+        self.types
+            .values()
+            .flat_map(|entries| entries.values())
+            .flatten()
+            .filter_map(|(_, value)| match value {
+                Value::File(path) => Some(path.as_str()),
+                _ => None,
+            })
+    }
+
+    /// Returns the ids of the resources and styleables. Framework attributes
+    /// of styleables are resolved using `table`.
+    pub fn symbols(&self, table: &Table) -> Result<Symbols> {
+        let mut resources = vec![];
+        for (i, (ty, entries)) in self.types.iter().enumerate() {
+            for (j, name) in entries.keys().enumerate() {
+                let id = ResTableRef::new(PACKAGE_ID, i as u8 + 1, j as u16);
+                resources.push((ty.clone(), name.clone(), u32::from(id)));
+            }
+        }
+        let attr_ids = resources
+            .iter()
+            .filter(|(ty, _, _)| ty == "attr")
+            .map(|(_, name, id)| (name.as_str(), *id))
+            .collect::<BTreeMap<_, _>>();
+        let mut styleables = Vec::with_capacity(self.styleables.len());
+        for (name, attrs) in &self.styleables {
+            let mut ids = Vec::with_capacity(attrs.len());
+            for attr in attrs {
+                let id = if let Some(attr) = attr.strip_prefix("android:") {
+                    u32::from(table.entry_by_ref(Ref::attr(attr))?.id())
+                } else {
+                    *attr_ids.get(attr.as_str()).with_context(|| {
+                        format!("styleable {} references unknown attr {}", name, attr)
+                    })?
+                };
+                ids.push((attr.clone(), id));
+            }
+            ids.sort_by_key(|(_, id)| *id);
+            styleables.push((name.clone(), ids));
+        }
+        Ok(Symbols {
+            resources,
+            styleables,
+        })
+    }
+
+    /// Compiles the resource table and imports the package into `table`.
+    /// References are resolved using `table`, which needs to contain the
+    /// android framework resources referenced by the package.
+    pub fn build(&self, table: &mut Table) -> Result<Chunk> {
+        let null = ResTableValue::Simple(ResValue {
+            size: 8,
+            res0: 0,
+            data_type: ResValueType::Null as u8,
+            data: 0,
+        });
+        // import the ids first, so that resources can reference each other
+        let skeleton = self.chunk(&mut |_, _, _| Ok(null.clone()))?;
+        table.import_chunk(&skeleton);
+        // and the attributes, so that styles and xml files can use them
+        let attrs = self.chunk(&mut |name, value, strings| match value {
+            Value::Attr { .. } => compile(table, &self.types, name, value, strings)
+                .with_context(|| format!("failed to compile resource {}", name)),
+            _ => Ok(null.clone()),
+        })?;
+        table.import_chunk(&attrs);
+        let chunk = self.chunk(&mut |name, value, strings| {
+            compile(table, &self.types, name, value, strings)
+                .with_context(|| format!("failed to compile resource {}", name))
+        })?;
+        table.import_chunk(&chunk);
+        Ok(chunk)
+    }
+
+    fn chunk(
+        &self,
+        compile: &mut dyn FnMut(&str, &Value, &mut StringPool) -> Result<ResTableValue>,
+    ) -> Result<Chunk> {
+        let mut strings = StringPool::default();
+        let mut keys = StringPool::default();
+        let mut chunks = vec![
+            Chunk::StringPool(self.types.keys().cloned().collect(), vec![]),
+            Chunk::Null,
+        ];
+        for (i, (ty, entries)) in self.types.iter().enumerate() {
+            let id = i as u8 + 1;
+            let mut configs: Vec<&ResTableConfig> = vec![];
+            let mut spec = Vec::with_capacity(entries.len());
+            for variants in entries.values() {
+                let mut mask = 0;
+                for (config, _) in variants {
+                    if !configs.contains(&config) {
+                        configs.push(config);
+                    }
+                    // the sdk version doesn't change at runtime
+                    mask |=
+                        config.diff(&ResTableConfig::default()) & !ResTableConfig::CONFIG_VERSION;
+                }
+                spec.push(mask);
+            }
+            chunks.push(Chunk::TableTypeSpec(
+                ResTableTypeSpecHeader {
+                    id,
+                    res0: 0,
+                    res1: 0,
+                    entry_count: entries.len() as u32,
+                },
+                spec,
+            ));
+            for config in configs {
+                let mut index = Vec::with_capacity(entries.len());
+                let mut table_entries = Vec::with_capacity(entries.len());
+                let mut offset = 0;
+                for (name, variants) in entries {
+                    let value = variants
+                        .iter()
+                        .find(|(c, _)| c == config)
+                        .map(|(_, value)| value);
+                    let value = if let Some(value) = value {
+                        compile(&format!("@{}/{}", ty, name), value, &mut strings)?
+                    } else {
+                        index.push(0xffff_ffff);
+                        table_entries.push(None);
+                        continue;
+                    };
+                    let (size, flags, len) = match &value {
+                        ResTableValue::Simple(_) => (8, 0, 16),
+                        ResTableValue::Complex(_, map) => (16, 1, 16 + 12 * map.len() as u32),
+                    };
+                    index.push(offset);
+                    offset += len;
+                    table_entries.push(Some(ResTableEntry {
+                        size,
+                        flags,
+                        key: keys.id(name),
+                        value,
+                    }));
+                }
+                chunks.push(Chunk::TableType(
+                    ResTableTypeHeader {
+                        id,
+                        res0: 0,
+                        res1: 0,
+                        entry_count: entries.len() as u32,
+                        entries_start: 84 + 4 * entries.len() as u32,
+                        config: config.clone(),
+                    },
+                    index,
+                    table_entries,
+                ));
+            }
+        }
+        let key_count = keys.strings.len() as u32;
+        chunks[1] = Chunk::StringPool(keys.strings, vec![]);
+        // the type strings follow the package header
+        let type_strings = 288;
+        let mut buf = vec![];
+        chunks[0].write(&mut Cursor::new(&mut buf))?;
+        let package = Chunk::TablePackage(
+            ResTablePackageHeader {
+                id: PACKAGE_ID as u32,
+                name: self.name.clone(),
+                type_strings,
+                last_public_type: self.types.len() as u32,
+                key_strings: type_strings + buf.len() as u32,
+                last_public_key: key_count,
+                type_id_offset: 0,
+            },
+            chunks,
+        );
+        Ok(Chunk::Table(
+            ResTableHeader { package_count: 1 },
+            vec![Chunk::StringPool(strings.strings, vec![]), package],
+        ))
+    }
+}
+
+/// Interns strings of a string pool.
+#[derive(Default)]
+pub struct StringPool {
+    strings: Vec<String>,
+    map: BTreeMap<String, u32>,
+}
+
+impl StringPool {
+    pub fn id(&mut self, s: &str) -> u32 {
+        if let Some(id) = self.map.get(s) {
+            return *id;
+        }
+        let id = self.strings.len() as u32;
+        self.strings.push(s.to_string());
+        self.map.insert(s.to_string(), id);
+        id
+    }
+
+    pub fn get(&self, id: u32) -> Option<&str> {
+        self.strings.get(id as usize).map(|s| s.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    pub fn into_strings(self) -> Vec<String> {
+        self.strings
+    }
+}
+
+fn compile(
+    table: &Table,
+    types: &Types,
+    name: &str,
+    value: &Value,
+    strings: &mut StringPool,
+) -> Result<ResTableValue> {
+    Ok(match value {
+        Value::Item { format, value } => {
+            ResTableValue::Simple(compile_value(table, None, *format, value, &mut |s| {
+                strings.id(s)
+            })?)
+        }
+        Value::File(path) => ResTableValue::Simple(ResValue {
+            size: 8,
+            res0: 0,
+            data_type: ResValueType::String as u8,
+            data: strings.id(path),
+        }),
+        Value::Style { parent, items } => {
+            let parent = match parent {
+                Some(parent) => Some(parent.clone()),
+                // `Theme.Child` implicitly inherits from `Theme` if it exists
+                None => name
+                    .rsplit_once('.')
+                    .map(|(parent, _)| parent.to_string())
+                    .filter(|parent| {
+                        let name = parent.trim_start_matches("@style/");
+                        types
+                            .get("style")
+                            .map(|styles| styles.contains_key(name))
+                            .unwrap_or_default()
+                    }),
+            };
+            let parent = match parent.as_deref() {
+                Some("") | None => 0,
+                Some(parent) => u32::from(table.entry_by_ref(Ref::parse(parent)?)?.id()),
+            };
+            let mut map = Vec::with_capacity(items.len());
+            for (attr, value) in items {
+                let (package, attr_name) = match attr.split_once(':') {
+                    Some((package, name)) => (Some(package), name),
+                    None => (None, attr.as_str()),
+                };
+                let entry = table.entry_by_ref(Ref::new(package, "attr", attr_name))?;
+                let format = entry
+                    .attribute_format()
+                    .with_context(|| format!("{} is not an attribute", attr))?;
+                let value =
+                    compile_value(table, Some(entry), format, value, &mut |s| strings.id(s))
+                        .with_context(|| {
+                            format!("invalid value for attribute {}: {}", attr, value)
+                        })?;
+                map.push(ResTableMap {
+                    name: entry.id().into(),
+                    value,
+                });
+            }
+            // items are looked up using a binary search
+            map.sort_by_key(|item| item.name);
+            ResTableValue::Complex(
+                ResTableMapEntry {
+                    parent,
+                    count: map.len() as u32,
+                },
+                map,
+            )
+        }
+        Value::Bag { format, items } => {
+            let mut map = Vec::with_capacity(items.len());
+            for (key, value) in items {
+                let value = compile_value(table, None, *format, value, &mut |s| strings.id(s))?;
+                map.push(ResTableMap { name: *key, value });
+            }
+            map.sort_by_key(|item| item.name);
+            ResTableValue::Complex(
+                ResTableMapEntry {
+                    parent: 0,
+                    count: map.len() as u32,
+                },
+                map,
+            )
+        }
+        Value::Attr { format, symbols } => {
+            let int = |data: u32, data_type: ResValueType| ResValue {
+                size: 8,
+                res0: 0,
+                data_type: data_type as u8,
+                data,
+            };
+            let mut map = vec![ResTableMap {
+                name: ATTR_TYPE,
+                value: int(*format, ResValueType::IntDec),
+            }];
+            let data_type = if format & ResAttributeType::Flags as u32 != 0 {
+                ResValueType::IntHex
+            } else {
+                ResValueType::IntDec
+            };
+            for (symbol, value) in symbols {
+                let id = table.entry_by_ref(Ref::new(None, "id", symbol))?.id();
+                map.push(ResTableMap {
+                    name: id.into(),
+                    value: int(*value, data_type),
+                });
+            }
+            map.sort_by_key(|item| item.name);
+            ResTableValue::Complex(
+                ResTableMapEntry {
+                    parent: 0,
+                    count: map.len() as u32,
+                },
+                map,
+            )
+        }
+    })
+}
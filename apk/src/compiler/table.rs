@@ -1,4 +1,4 @@
-use crate::res::{Chunk, ResAttributeType, ResTableEntry, ResTableRef, ResTableValue, ResValue};
+use crate::res::{Chunk, ResTableEntry, ResTableRef, ResTableValue, ResValue};
 use anyhow::{Context, Result};
 use std::io::Cursor;
 use std::path::Path;
@@ -10,18 +10,14 @@ pub struct Ref<'a> {
 }
 
 impl<'a> Ref<'a> {
-    pub fn attr(name: &'a str) -> Self {
-        Self {
-            package: Some("android"),
-            ty: "attr",
-            name,
-        }
+    pub fn new(package: Option<&'a str>, ty: &'a str, name: &'a str) -> Self {
+        Self { package, ty, name }
     }
 
-    pub fn id(name: &'a str) -> Self {
+    pub fn attr(name: &'a str) -> Self {
         Self {
             package: Some("android"),
-            ty: "id",
+            ty: "attr",
             name,
         }
     }
@@ -30,6 +26,8 @@ impl<'a> Ref<'a> {
         let s = s
             .strip_prefix('@')
             .with_context(|| format!("invalid reference {}: expected `@`", s))?;
+        // `@+id/name` declares the id it references
+        let s = s.strip_prefix('+').unwrap_or(s);
         let (descr, name) = s
             .split_once('/')
             .with_context(|| format!("invalid reference {}: expected `/`", s))?;
@@ -89,38 +87,44 @@ impl<'a> Package<'a> {
     }
 
     fn lookup_type(&self, id: u8) -> Result<Type<'a>> {
+        let mut configs = vec![];
         for chunk in self.chunks {
             if let Chunk::TableType(header, _offsets, entries) = chunk {
                 if header.id == id {
-                    return Ok(Type {
-                        package: self.id,
-                        id,
-                        entries,
-                    });
+                    configs.push(&entries[..]);
                 }
             }
         }
-        anyhow::bail!("failed to locate type {}", id);
+        anyhow::ensure!(!configs.is_empty(), "failed to locate type {}", id);
+        Ok(Type {
+            package: self.id,
+            id,
+            configs,
+        })
     }
 }
 
+/// A type with the entries of all its configurations. An entry doesn't need
+/// to be present in every configuration.
 struct Type<'a> {
     package: u8,
     id: u8,
-    entries: &'a [Option<ResTableEntry>],
+    configs: Vec<&'a [Option<ResTableEntry>]>,
 }
 
 impl<'a> Type<'a> {
     pub fn lookup_entry_id(&self, key: u32) -> Result<u16> {
         let id = self
-            .entries
+            .configs
             .iter()
-            .position(|entry| {
-                if let Some(entry) = entry {
-                    entry.key == key
-                } else {
-                    false
-                }
+            .find_map(|entries| {
+                entries.iter().position(|entry| {
+                    if let Some(entry) = entry {
+                        entry.key == key
+                    } else {
+                        false
+                    }
+                })
             })
             .with_context(|| format!("failed to lookup entry id {}", key))?;
         Ok(id as u16)
@@ -128,10 +132,9 @@ impl<'a> Type<'a> {
 
     pub fn lookup_entry(&self, id: u16) -> Result<Entry<'a>> {
         let entry = self
-            .entries
-            .get(id as usize)
-            .with_context(|| format!("failed to lookup entry {}", id))?
-            .as_ref()
+            .configs
+            .iter()
+            .find_map(|entries| entries.get(id as usize)?.as_ref())
             .with_context(|| format!("failed to lookup entry {}", id))?;
         let id = ResTableRef::new(self.package, self.id, id);
         Ok(Entry { id, entry })
@@ -144,29 +147,16 @@ pub struct Entry<'a> {
     entry: &'a ResTableEntry,
 }
 
-impl Entry<'_> {
+impl<'a> Entry<'a> {
     pub fn id(self) -> ResTableRef {
         self.id
     }
 
-    pub fn attribute_type(self) -> Option<ResAttributeType> {
+    /// Returns the raw format of an attribute, a mask of
+    /// [`ResAttributeType`](crate::res::ResAttributeType)s.
+    pub fn attribute_format(self) -> Option<u32> {
         if let ResTableValue::Complex(_, entries) = &self.entry.value {
-            let data = entries[0].value.data;
-            // TODO: android supports multiple types
-            if data == 0b110 {
-                return Some(ResAttributeType::Integer);
-            }
-            if data == 0b11 {
-                return Some(ResAttributeType::String);
-            }
-            if data == 0b111110 {
-                return Some(ResAttributeType::String);
-            }
-            if let Some(value) = ResAttributeType::from_u32(entries[0].value.data) {
-                Some(value)
-            } else {
-                panic!("attribute_type: 0x{:x}", data);
-            }
+            Some(entries[0].value.data)
         } else {
             None
         }
@@ -197,9 +187,18 @@ impl Table {
         Ok(())
     }
 
+    /// Imports the packages of a resource table. A package replaces a
+    /// previously imported package with the same id.
     pub fn import_chunk(&mut self, chunk: &Chunk) {
         if let Chunk::Table(_, packages) = chunk {
-            self.packages.extend_from_slice(packages);
+            for package in packages {
+                if let Chunk::TablePackage(header, _) = package {
+                    self.packages.retain(
+                        |chunk| !matches!(chunk, Chunk::TablePackage(h, _) if h.id == header.id),
+                    );
+                    self.packages.push(package.clone());
+                }
+            }
         }
     }
 
@@ -218,7 +217,7 @@ impl Table {
         }
     }
 
-    fn lookup_package(&self, id: u8) -> Result<Package> {
+    fn lookup_package(&self, id: u8) -> Result<Package<'_>> {
         for package in &self.packages {
             if let Chunk::TablePackage(header, chunks) = package {
                 if header.id == id as u32 {
@@ -229,7 +228,7 @@ impl Table {
         anyhow::bail!("failed to locate package {}", id);
     }
 
-    pub fn entry_by_ref(&self, r: Ref) -> Result<Entry> {
+    pub fn entry_by_ref(&self, r: Ref) -> Result<Entry<'_>> {
         let id = self.lookup_package_id(r.package)?;
         let package = self.lookup_package(id)?;
         let id = package.lookup_type_id(r.ty)?;
@@ -245,3 +244,193 @@ impl Table {
         ty.lookup_entry(r.entry())
     }*/
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::values::compile_values;
+    use crate::compiler::PackageBuilder;
+    use crate::res::{ResAttributeType, ResTableConfig, ResValueType};
+
+    #[test]
+    fn test_compile_values() -> Result<()> {
+        let mut package = PackageBuilder::new("com.example.helloworld");
+        compile_values(
+            &mut package,
+            r#"<?xml version="1.0" encoding="utf-8"?>
+            <resources>
+                <string name="app_name">Hello  World</string>
+                <string name="title">@string/app_name</string>
+                <color name="primary">#f00</color>
+                <dimen name="margin">1.5dp</dimen>
+                <bool name="enabled">true</bool>
+                <style name="Base" parent="" />
+                <style name="Base.Theme" />
+            </resources>"#,
+            &ResTableConfig::default(),
+        )?;
+        let mut table = Table::default();
+        let chunk = package.build(&mut table)?;
+        let mut buf = vec![];
+        chunk.write(&mut Cursor::new(&mut buf))?;
+        assert_eq!(Chunk::parse(&mut Cursor::new(&buf))?, chunk);
+
+        let value = |r: &str| -> Result<ResValue> {
+            match &table.entry_by_ref(Ref::parse(r)?)?.entry.value {
+                ResTableValue::Simple(value) => Ok(*value),
+                value => anyhow::bail!("unexpected value {:?}", value),
+            }
+        };
+        let app_name = table.entry_by_ref(Ref::parse("@string/app_name")?)?.id();
+        let title = value("@string/title")?;
+        assert_eq!(title.data_type, ResValueType::Reference as u8);
+        assert_eq!(title.data, u32::from(app_name));
+        assert_eq!(value("@color/primary")?.data, 0xffff_0000);
+        assert_eq!(value("@dimen/margin")?.data, 0x00c0_0021);
+        assert_eq!(value("@bool/enabled")?.data, 0xffff_ffff);
+        let base = table.entry_by_ref(Ref::parse("@style/Base")?)?.id();
+        match &table
+            .entry_by_ref(Ref::parse("@style/Base.Theme")?)?
+            .entry
+            .value
+        {
+            ResTableValue::Complex(entry, _) => assert_eq!(entry.parent, u32::from(base)),
+            value => anyhow::bail!("unexpected value {:?}", value),
+        }
+        if let Chunk::Table(_, chunks) = &chunk {
+            assert_eq!(
+                chunks[0],
+                Chunk::StringPool(vec!["Hello World".into()], vec![])
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_bags() -> Result<()> {
+        let mut package = PackageBuilder::new("com.example.helloworld");
+        compile_values(
+            &mut package,
+            r#"<?xml version="1.0" encoding="utf-8"?>
+            <resources>
+                <string name="app_name">Hello World</string>
+                <attr name="custom" format="string" />
+                <declare-styleable name="CustomView">
+                    <attr name="custom" />
+                </declare-styleable>
+                <string-array name="planets">
+                    <item>Mercury</item>
+                    <item>@string/app_name</item>
+                </string-array>
+                <integer-array name="sizes"><item>1</item><item>0x10</item></integer-array>
+                <plurals name="apples">
+                    <item quantity="other">apples</item>
+                    <item quantity="one">apple</item>
+                </plurals>
+            </resources>"#,
+            &ResTableConfig::default(),
+        )?;
+        let mut table = Table::default();
+        let chunk = package.build(&mut table)?;
+        let mut buf = vec![];
+        chunk.write(&mut Cursor::new(&mut buf))?;
+        assert_eq!(Chunk::parse(&mut Cursor::new(&buf))?, chunk);
+
+        let bag = |r: &str| -> Result<Vec<(u32, u32)>> {
+            match &table.entry_by_ref(Ref::parse(r)?)?.entry.value {
+                ResTableValue::Complex(_, map) => Ok(map
+                    .iter()
+                    .map(|item| (item.name, item.value.data))
+                    .collect()),
+                value => anyhow::bail!("unexpected value {:?}", value),
+            }
+        };
+        let app_name = table.entry_by_ref(Ref::parse("@string/app_name")?)?.id();
+        let planets = bag("@array/planets")?;
+        assert_eq!(planets.len(), 2);
+        assert_eq!(planets[1], (0x0200_0001, u32::from(app_name)));
+        assert_eq!(
+            bag("@array/sizes")?,
+            vec![(0x0200_0000, 1), (0x0200_0001, 16)]
+        );
+        let apples = bag("@plurals/apples")?;
+        assert_eq!(
+            apples.iter().map(|(key, _)| *key).collect::<Vec<_>>(),
+            vec![0x0100_0004, 0x0100_0006]
+        );
+        let custom = table.entry_by_ref(Ref::parse("@attr/custom")?)?;
+        assert_eq!(
+            custom.attribute_format(),
+            Some(ResAttributeType::String as u32)
+        );
+        let symbols = package.symbols(&table)?;
+        assert_eq!(
+            symbols.styleables,
+            [(
+                "CustomView".to_string(),
+                vec![("custom".to_string(), u32::from(custom.id()))]
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_attrs() -> Result<()> {
+        let mut package = PackageBuilder::new("com.example.helloworld");
+        compile_values(
+            &mut package,
+            r#"<?xml version="1.0" encoding="utf-8"?>
+            <resources>
+                <declare-styleable name="CustomView">
+                    <attr name="orientation" />
+                    <attr name="gravity">
+                        <flag name="top" value="0x30" />
+                        <flag name="left" value="0x03" />
+                    </attr>
+                </declare-styleable>
+                <attr name="orientation">
+                    <enum name="horizontal" value="0" />
+                    <enum name="vertical" value="1" />
+                </attr>
+                <style name="Custom">
+                    <item name="orientation">vertical</item>
+                    <item name="gravity">top|left</item>
+                </style>
+            </resources>"#,
+            &ResTableConfig::default(),
+        )?;
+        let mut table = Table::default();
+        package.build(&mut table)?;
+
+        let orientation = table.entry_by_ref(Ref::parse("@attr/orientation")?)?;
+        assert_eq!(
+            orientation.attribute_format(),
+            Some(ResAttributeType::Enum as u32)
+        );
+        let vertical = table.entry_by_ref(Ref::parse("@id/vertical")?)?.id();
+        assert_eq!(
+            orientation.lookup_value(vertical).map(|value| value.data),
+            Some(1)
+        );
+        let gravity = table.entry_by_ref(Ref::parse("@attr/gravity")?)?;
+        let style = match &table
+            .entry_by_ref(Ref::parse("@style/Custom")?)?
+            .entry
+            .value
+        {
+            ResTableValue::Complex(_, map) => map
+                .iter()
+                .map(|item| (item.name, item.value.data))
+                .collect::<Vec<_>>(),
+            value => anyhow::bail!("unexpected value {:?}", value),
+        };
+        assert_eq!(
+            style,
+            [
+                (u32::from(gravity.id()), 0x33),
+                (u32::from(orientation.id()), 1)
+            ]
+        );
+        Ok(())
+    }
+}
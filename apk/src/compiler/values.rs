@@ -0,0 +1,325 @@
+use crate::compiler::package::{PackageBuilder, Value, ANY};
+use crate::res::{ResAttributeType, ResTableConfig, ARRAY_ITEM, ATTR_OTHER};
+use anyhow::{Context, Result};
+use roxmltree::{Document, Node};
+
+/// Adds the resources declared in a values xml file to the package.
+pub fn compile_values(
+    package: &mut PackageBuilder,
+    xml: &str,
+    config: &ResTableConfig,
+) -> Result<()> {
+    let doc = Document::parse(xml)?;
+    let root = doc.root_element();
+    anyhow::ensure!(
+        root.tag_name().name() == "resources",
+        "expected <resources> found <{}>",
+        root.tag_name().name()
+    );
+    for node in root.children().filter(|node| node.is_element()) {
+        let tag = node.tag_name().name();
+        // ids are assigned in the order of the names, so `<public>` has no
+        // effect
+        if tag == "eat-comment" || tag == "public" {
+            continue;
+        }
+        let name = node
+            .attribute("name")
+            .with_context(|| format!("<{}> is missing a name", tag))?;
+        if tag == "attr" {
+            attr(package, node, name)?;
+            continue;
+        }
+        if tag == "declare-styleable" {
+            let mut attrs = vec![];
+            for child in node.children().filter(|node| node.is_element()) {
+                anyhow::ensure!(
+                    child.tag_name().name() == "attr",
+                    "unexpected <{}> in <declare-styleable>",
+                    child.tag_name().name()
+                );
+                let attr_name = child
+                    .attribute("name")
+                    .context("<attr> in <declare-styleable> is missing a name")?;
+                if !attr_name.starts_with("android:") {
+                    attr(package, child, attr_name)?;
+                }
+                attrs.push(attr_name.to_string());
+            }
+            package.add_styleable(name, attrs)?;
+            continue;
+        }
+        let reference = ResAttributeType::Reference as u32;
+        let (ty, value) = match tag {
+            "string" => (
+                "string",
+                string_value(node, ResAttributeType::String as u32),
+            ),
+            "color" => (
+                "color",
+                item(node, reference | ResAttributeType::Color as u32),
+            ),
+            "dimen" => (
+                "dimen",
+                item(node, reference | ResAttributeType::Dimension as u32),
+            ),
+            "bool" => (
+                "bool",
+                item(node, reference | ResAttributeType::Boolean as u32),
+            ),
+            "integer" => (
+                "integer",
+                item(node, reference | ResAttributeType::Integer as u32),
+            ),
+            "fraction" => (
+                "fraction",
+                item(node, reference | ResAttributeType::Fraction as u32),
+            ),
+            "style" => ("style", style(node)?),
+            "string-array" => (
+                "array",
+                array(node, reference | ResAttributeType::String as u32)?,
+            ),
+            "integer-array" => (
+                "array",
+                array(node, reference | ResAttributeType::Integer as u32)?,
+            ),
+            "array" => ("array", array(node, ANY)?),
+            "plurals" => ("plurals", plurals(node)?),
+            "item" => {
+                let ty = node
+                    .attribute("type")
+                    .with_context(|| format!("<item name=\"{}\"> is missing a type", name))?;
+                if ty == "id" && package.contains(ty, name) {
+                    // ids may also be declared using `@+id/name`
+                    continue;
+                }
+                let value = if ty == "id" {
+                    // ids don't have a value
+                    Value::Item {
+                        format: ResAttributeType::Boolean as u32,
+                        value: "false".into(),
+                    }
+                } else {
+                    let format = node
+                        .attribute("format")
+                        .map(parse_format)
+                        .unwrap_or(Ok(ANY))?;
+                    string_value(node, format)
+                };
+                (ty, value)
+            }
+            _ => anyhow::bail!("unsupported resource <{}>", tag),
+        };
+        package.add(ty, name, config.clone(), value)?;
+    }
+    Ok(())
+}
+
+fn text(node: Node) -> String {
+    node.descendants()
+        .filter(|node| node.is_text())
+        .filter_map(|node| node.text())
+        .collect()
+}
+
+fn item(node: Node, format: u32) -> Value {
+    Value::Item {
+        format,
+        value: text(node).trim().to_string(),
+    }
+}
+
+/// Strings are unescaped unless they are a reference.
+fn string_value(node: Node, format: u32) -> Value {
+    let text = text(node);
+    let trimmed = text.trim();
+    if trimmed.starts_with('@') || trimmed.starts_with('?') {
+        Value::Item {
+            format: format | ResAttributeType::Reference as u32,
+            value: trimmed.to_string(),
+        }
+    } else {
+        Value::Item {
+            format,
+            value: unescape(&text),
+        }
+    }
+}
+
+fn style(node: Node) -> Result<Value> {
+    let parent = node.attribute("parent").map(|parent| {
+        if parent.is_empty() || parent.starts_with('@') {
+            parent.to_string()
+        } else if let Some((package, name)) = parent.split_once(':') {
+            format!("@{}:style/{}", package, name)
+        } else {
+            format!("@style/{}", parent)
+        }
+    });
+    let mut items = vec![];
+    for item in node.children().filter(|node| node.is_element()) {
+        anyhow::ensure!(
+            item.tag_name().name() == "item",
+            "unexpected <{}> in <style>",
+            item.tag_name().name()
+        );
+        let name = item
+            .attribute("name")
+            .context("<item> in <style> is missing a name")?;
+        let value = match string_value(item, ANY) {
+            Value::Item { value, .. } => value,
+            _ => unreachable!(),
+        };
+        items.push((name.to_string(), value));
+    }
+    Ok(Value::Style { parent, items })
+}
+
+/// Declares an attribute with the `<enum>` or `<flag>` symbols of its
+/// values.
+fn attr(package: &mut PackageBuilder, node: Node, name: &str) -> Result<()> {
+    let mut format = node.attribute("format").map(parse_format).transpose()?;
+    let mut symbols = vec![];
+    for symbol in node.children().filter(|node| node.is_element()) {
+        let tag = symbol.tag_name().name();
+        let ty = match tag {
+            "enum" => ResAttributeType::Enum,
+            "flag" => ResAttributeType::Flags,
+            _ => anyhow::bail!("unexpected <{}> in <attr name=\"{}\">", tag, name),
+        };
+        format = Some(format.unwrap_or_default() | ty as u32);
+        let symbol_name = symbol
+            .attribute("name")
+            .with_context(|| format!("<{}> in <attr name=\"{}\"> is missing a name", tag, name))?;
+        let value = symbol.attribute("value").unwrap_or_default().trim();
+        let value = if let Some(hex) = value.strip_prefix("0x") {
+            u32::from_str_radix(hex, 16).ok()
+        } else {
+            value.parse::<i32>().ok().map(|value| value as u32)
+        }
+        .with_context(|| format!("invalid value of {} {}: {}", tag, symbol_name, value))?;
+        symbols.push((symbol_name.to_string(), value));
+    }
+    package.add_attr(name, format, symbols)
+}
+
+/// Returns the `<item>` children of an array or plurals.
+fn bag_items<'a, 'input>(node: Node<'a, 'input>) -> Result<Vec<Node<'a, 'input>>> {
+    let tag = node.tag_name().name();
+    node.children()
+        .filter(|node| node.is_element())
+        .map(|item| {
+            anyhow::ensure!(
+                item.tag_name().name() == "item",
+                "unexpected <{}> in <{}>",
+                item.tag_name().name(),
+                tag
+            );
+            Ok(item)
+        })
+        .collect()
+}
+
+fn bag_value(node: Node, format: u32) -> String {
+    match string_value(node, format) {
+        Value::Item { value, .. } => value,
+        _ => unreachable!(),
+    }
+}
+
+fn array(node: Node, format: u32) -> Result<Value> {
+    let items = bag_items(node)?
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| (ARRAY_ITEM + i as u32, bag_value(item, format)))
+        .collect();
+    Ok(Value::Bag { format, items })
+}
+
+/// Plural quantities in the order of their keys.
+const QUANTITIES: [&str; 6] = ["other", "zero", "one", "two", "few", "many"];
+
+fn plurals(node: Node) -> Result<Value> {
+    let format = ResAttributeType::Reference as u32 | ResAttributeType::String as u32;
+    let mut items = vec![];
+    for item in bag_items(node)? {
+        let quantity = item
+            .attribute("quantity")
+            .context("<item> in <plurals> is missing a quantity")?;
+        let index = QUANTITIES
+            .iter()
+            .position(|q| *q == quantity)
+            .with_context(|| format!("unknown plural quantity {}", quantity))?;
+        items.push((ATTR_OTHER + index as u32, bag_value(item, format)));
+    }
+    Ok(Value::Bag { format, items })
+}
+
+fn parse_format(format: &str) -> Result<u32> {
+    let mut mask = 0;
+    for ty in format.split('|') {
+        mask |= match ty.trim() {
+            "reference" => ResAttributeType::Reference,
+            "string" => ResAttributeType::String,
+            "integer" => ResAttributeType::Integer,
+            "boolean" => ResAttributeType::Boolean,
+            "color" => ResAttributeType::Color,
+            "float" => ResAttributeType::Float,
+            "dimension" => ResAttributeType::Dimension,
+            "fraction" => ResAttributeType::Fraction,
+            "enum" => ResAttributeType::Enum,
+            "flags" => ResAttributeType::Flags,
+            ty => anyhow::bail!("unknown format {}", ty),
+        } as u32;
+    }
+    Ok(mask)
+}
+
+/// Collapses whitespace outside of double quotes, removes the quotes and
+/// resolves `\` escapes like aapt does.
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut quoted = false;
+    let mut space = false;
+    let mut chars = s.trim().chars();
+    while let Some(c) = chars.next() {
+        if !quoted && c.is_whitespace() {
+            space = true;
+            continue;
+        }
+        if space {
+            out.push(' ');
+            space = false;
+        }
+        match c {
+            '"' => quoted = !quoted,
+            '\\' => match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some('u') => {
+                    let hex = chars.by_ref().take(4).collect::<String>();
+                    if let Some(c) = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                        out.push(c);
+                    }
+                }
+                Some(c) => out.push(c),
+                None => {}
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("  hello \n  world  "), "hello world");
+        assert_eq!(unescape("\"  quoted  \""), "  quoted  ");
+        assert_eq!(unescape(r"don\'t\nstop \@ é"), "don't\nstop @ é");
+    }
+}
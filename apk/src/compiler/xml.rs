@@ -1,13 +1,24 @@
-use crate::compiler::attributes::{StringPoolBuilder, Strings};
+use crate::compiler::attributes::{
+    attr_entry, compile_attr, compile_value, StringPoolBuilder, Strings,
+};
 use crate::compiler::table::Table;
+use crate::manifest::TOOLS_NS;
 use crate::res::{
-    Chunk, ResValue, ResValueType, ResXmlAttribute, ResXmlEndElement, ResXmlNamespace,
-    ResXmlNodeHeader, ResXmlStartElement,
+    Chunk, ResAttributeType, ResValue, ResValueType, ResXmlAttribute, ResXmlCdata,
+    ResXmlEndElement, ResXmlNamespace, ResXmlNodeHeader, ResXmlStartElement,
 };
-use anyhow::Result;
+use anyhow::{Context, Result};
 use roxmltree::{Document, Node, NodeType};
 use std::collections::BTreeMap;
 
+/// Formats values of attributes outside of the android namespace are parsed
+/// as. The raw string is kept as well.
+const PLAIN_FORMAT: u32 = ResAttributeType::Reference as u32
+    | ResAttributeType::Boolean as u32
+    | ResAttributeType::Integer as u32
+    | ResAttributeType::Color as u32
+    | ResAttributeType::String as u32;
+
 pub fn compile_xml(xml: &str, table: &Table) -> Result<Chunk> {
     let doc = Document::parse(xml)?;
     let root = doc.root_element();
@@ -16,7 +27,13 @@ pub fn compile_xml(xml: &str, table: &Table) -> Result<Chunk> {
     let strings = builder.build();
     let mut chunks = vec![Chunk::Null, Chunk::Null];
 
-    for ns in root.namespaces() {
+    // the tools namespace is only used at build time
+    let namespaces = root
+        .namespaces()
+        .iter()
+        .filter(|ns| ns.uri() != TOOLS_NS)
+        .collect::<Vec<_>>();
+    for ns in &namespaces {
         chunks.push(Chunk::XmlStartNamespace(
             ResXmlNodeHeader::default(),
             ResXmlNamespace {
@@ -26,7 +43,7 @@ pub fn compile_xml(xml: &str, table: &Table) -> Result<Chunk> {
         ));
     }
     compile_node(root, &strings, &mut chunks, table)?;
-    for ns in root.namespaces() {
+    for ns in &namespaces {
         chunks.push(Chunk::XmlEndNamespace(
             ResXmlNodeHeader::default(),
             ResXmlNamespace {
@@ -41,14 +58,26 @@ pub fn compile_xml(xml: &str, table: &Table) -> Result<Chunk> {
     Ok(Chunk::Xml(chunks))
 }
 
+/// Returns the text of a text node, whitespace only text is dropped.
+fn text<'a>(node: Node<'a, '_>) -> Option<&'a str> {
+    if node.node_type() != NodeType::Text {
+        return None;
+    }
+    Some(node.text()?.trim()).filter(|text| !text.is_empty())
+}
+
 fn build_string_pool<'a>(node: Node<'a, 'a>, builder: &mut StringPoolBuilder<'a>) -> Result<()> {
+    if let Some(text) = text(node) {
+        builder.add_string(text);
+        return Ok(());
+    }
     if node.node_type() != NodeType::Element {
         for node in node.children() {
             build_string_pool(node, builder)?;
         }
         return Ok(());
     }
-    for ns in node.namespaces() {
+    for ns in node.namespaces().iter().filter(|ns| ns.uri() != TOOLS_NS) {
         if let Some(prefix) = ns.name() {
             builder.add_string(prefix);
         }
@@ -59,6 +88,9 @@ fn build_string_pool<'a>(node: Node<'a, 'a>, builder: &mut StringPoolBuilder<'a>
     }
     builder.add_string(node.tag_name().name());
     for attr in node.attributes() {
+        if attr.namespace() == Some(TOOLS_NS) {
+            continue;
+        }
         builder.add_attribute(attr)?;
     }
     for node in node.children() {
@@ -73,6 +105,21 @@ fn compile_node(
     chunks: &mut Vec<Chunk>,
     table: &Table,
 ) -> Result<()> {
+    if let Some(text) = text(node) {
+        chunks.push(Chunk::XmlCdata(
+            ResXmlNodeHeader::default(),
+            ResXmlCdata {
+                data: strings.id(text),
+                typed_data: ResValue {
+                    size: 8,
+                    res0: 0,
+                    data_type: ResValueType::Null as u8,
+                    data: 0,
+                },
+            },
+        ));
+        return Ok(());
+    }
     if node.node_type() != NodeType::Element {
         for node in node.children() {
             compile_node(node, strings, chunks, table)?;
@@ -84,40 +131,57 @@ fn compile_node(
     let mut class_index = 0;
     let mut style_index = 0;
     let mut attrs = BTreeMap::new();
-    for (i, attr) in node.attributes().enumerate() {
+    let attributes = node
+        .attributes()
+        .filter(|attr| attr.namespace() != Some(TOOLS_NS));
+    for (i, attr) in attributes.enumerate() {
         match attr.name() {
             "id" => id_index = i as u16 + 1,
             "class" => class_index = i as u16 + 1,
             "style" => style_index = i as u16 + 1,
             _ => {}
         }
-        let value = if let Some("http://schemas.android.com/apk/res/android") = attr.namespace() {
-            super::attributes::compile_attr(table, attr.name(), attr.value(), strings)?
+        let entry = attr_entry(table, &attr)?;
+        let (value, raw_value) = if let Some(entry) = entry {
+            let value = compile_attr(table, entry, attr.name(), attr.value(), strings)?;
+            let raw_value = if value.data_type == ResValueType::String as u8 {
+                value.data as i32
+            } else {
+                -1
+            };
+            (value, raw_value)
         } else if attr.name() == "platformBuildVersionCode"
             || attr.name() == "platformBuildVersionName"
         {
-            ResValue {
+            let value = ResValue {
                 size: 8,
                 res0: 0,
                 data_type: ResValueType::IntDec as u8,
                 data: attr.value().parse()?,
-            }
+            };
+            (value, -1)
         } else {
-            ResValue {
-                size: 8,
-                res0: 0,
-                data_type: ResValueType::String as u8,
-                data: strings.id(attr.value()) as u32,
-            }
+            let value = compile_value(table, None, PLAIN_FORMAT, attr.value(), &mut |s| {
+                strings.id(s) as u32
+            })
+            .with_context(|| {
+                format!(
+                    "invalid value for attribute {}: {}",
+                    attr.name(),
+                    attr.value()
+                )
+            })?;
+            (value, strings.id(attr.value()))
         };
-        let raw_value = if value.data_type == ResValueType::String as u8 {
-            value.data as i32
-        } else {
-            -1
+        // attributes with the same name in different namespaces are distinct
+        // strings of the resource map
+        let name = match entry {
+            Some(entry) => strings.attribute(entry.id().into()),
+            None => strings.id(attr.name()),
         };
         let attr = ResXmlAttribute {
             namespace: attr.namespace().map(|ns| strings.id(ns)).unwrap_or(-1),
-            name: strings.id(attr.name()),
+            name,
             raw_value,
             typed_value: value,
         };
@@ -0,0 +1,266 @@
+use crate::sign::{content_digest, signature_algorithm, verify_signature};
+use anyhow::Result;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use rasn_pkix::Certificate;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use xcommon::Signer;
+
+const V4_VERSION: u32 = 2;
+const HASH_ALGORITHM_SHA256: u32 = 1;
+const LOG2_BLOCK_SIZE: u8 = 12;
+const BLOCK_SIZE: usize = 1 << LOG2_BLOCK_SIZE;
+const DIGEST_SIZE: usize = 32;
+
+/// Path of the v4 signature of an apk: `<name>.apk.idsig`.
+pub fn idsig_path(apk: &Path) -> PathBuf {
+    let mut path = apk.as_os_str().to_owned();
+    path.push(".idsig");
+    path.into()
+}
+
+/// Writes an APK Signature Scheme v4 signature next to an apk signed with
+/// v2/v3. The signature covers the fs-verity merkle tree root of the apk
+/// and the v2/v3 content digest.
+pub fn sign(path: &Path, signer: &Signer) -> Result<()> {
+    let apk_digest = content_digest(path)?;
+    let file_size = std::fs::metadata(path)?.len();
+    let (tree, root_hash) = merkle_tree(&mut BufReader::new(File::open(path)?))?;
+    let certificate = rasn::der::encode(signer.cert()).map_err(|err| anyhow::anyhow!("{}", err))?;
+    let mut signature = V4Signature {
+        root_hash: root_hash.to_vec(),
+        apk_digest: apk_digest.to_vec(),
+        certificate,
+        public_key: signer.pubkey().to_vec(),
+        algorithm: signature_algorithm(signer),
+        signature: vec![],
+        tree,
+    };
+    signature.signature = signer.sign(&signature.signed_data(file_size)?);
+    let mut w = BufWriter::new(File::create(idsig_path(path))?);
+    signature.write(&mut w)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Verifies the v4 signature of an apk against the apk contents and
+/// returns the signer certificate.
+pub fn verify(path: &Path) -> Result<Certificate> {
+    let idsig = idsig_path(path);
+    let signature = V4Signature::read(&mut BufReader::new(File::open(&idsig)?))?;
+    anyhow::ensure!(
+        signature.apk_digest == content_digest(path)?,
+        "v4: apk digest doesn't match v2/v3 digest"
+    );
+    let (tree, root_hash) = merkle_tree(&mut BufReader::new(File::open(path)?))?;
+    anyhow::ensure!(
+        signature.root_hash == root_hash && signature.tree == tree,
+        "v4: merkle tree mismatch"
+    );
+    let file_size = std::fs::metadata(path)?.len();
+    verify_signature(
+        signature.algorithm,
+        &signature.public_key,
+        &signature.signed_data(file_size)?,
+        &signature.signature,
+    )
+    .map_err(|err| anyhow::anyhow!("v4: {}", err))?;
+    rasn::der::decode(&signature.certificate).map_err(|err| anyhow::anyhow!("{}", err))
+}
+
+/// Computes the fs-verity merkle tree using 4k blocks and SHA-256 without a
+/// salt. Returns the tree with the top level first and the root hash.
+fn merkle_tree(r: &mut impl Read) -> Result<(Vec<u8>, [u8; DIGEST_SIZE])> {
+    let mut levels = vec![];
+    let mut level = vec![];
+    let mut block = Vec::with_capacity(BLOCK_SIZE);
+    loop {
+        block.clear();
+        r.take(BLOCK_SIZE as u64).read_to_end(&mut block)?;
+        if block.is_empty() {
+            break;
+        }
+        block.resize(BLOCK_SIZE, 0);
+        level.extend_from_slice(&Sha256::digest(&block));
+    }
+    pad_to_block(&mut level);
+    while level.len() > BLOCK_SIZE {
+        let mut next = Vec::with_capacity(level.len() / BLOCK_SIZE * DIGEST_SIZE);
+        for block in level.chunks(BLOCK_SIZE) {
+            next.extend_from_slice(&Sha256::digest(block));
+        }
+        pad_to_block(&mut next);
+        levels.push(level);
+        level = next;
+    }
+    let root_hash = Sha256::digest(&level).into();
+    levels.push(level);
+    let tree = levels.into_iter().rev().flatten().collect();
+    Ok((tree, root_hash))
+}
+
+fn pad_to_block(level: &mut Vec<u8>) {
+    let len = level.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+    level.resize(len.max(BLOCK_SIZE), 0);
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+struct V4Signature {
+    root_hash: Vec<u8>,
+    apk_digest: Vec<u8>,
+    certificate: Vec<u8>,
+    public_key: Vec<u8>,
+    algorithm: u32,
+    signature: Vec<u8>,
+    tree: Vec<u8>,
+}
+
+impl V4Signature {
+    fn signed_data(&self, file_size: u64) -> Result<Vec<u8>> {
+        let size = 4
+            + 8
+            + 4
+            + 1
+            + 4
+            + 4
+            + self.root_hash.len()
+            + 4
+            + self.apk_digest.len()
+            + 4
+            + self.certificate.len()
+            + 4;
+        let mut buf = Vec::with_capacity(size);
+        buf.write_u32::<LittleEndian>(size as u32)?;
+        buf.write_u64::<LittleEndian>(file_size)?;
+        buf.write_u32::<LittleEndian>(HASH_ALGORITHM_SHA256)?;
+        buf.write_u8(LOG2_BLOCK_SIZE)?;
+        write_bytes(&mut buf, &[])?;
+        write_bytes(&mut buf, &self.root_hash)?;
+        write_bytes(&mut buf, &self.apk_digest)?;
+        write_bytes(&mut buf, &self.certificate)?;
+        write_bytes(&mut buf, &[])?;
+        Ok(buf)
+    }
+
+    fn read(r: &mut impl Read) -> Result<Self> {
+        let version = r.read_u32::<LittleEndian>()?;
+        anyhow::ensure!(version == V4_VERSION, "unsupported v4 version {}", version);
+        let hashing_info = read_bytes(r)?;
+        let mut h = &hashing_info[..];
+        let hash_algorithm = h.read_u32::<LittleEndian>()?;
+        let log2_block_size = h.read_u8()?;
+        anyhow::ensure!(
+            hash_algorithm == HASH_ALGORITHM_SHA256 && log2_block_size == LOG2_BLOCK_SIZE,
+            "unsupported v4 hashing info"
+        );
+        let salt = read_bytes(&mut h)?;
+        anyhow::ensure!(salt.is_empty(), "unsupported v4 salt");
+        let root_hash = read_bytes(&mut h)?;
+        let signing_info = read_bytes(r)?;
+        let mut s = &signing_info[..];
+        let apk_digest = read_bytes(&mut s)?;
+        let certificate = read_bytes(&mut s)?;
+        let _additional_data = read_bytes(&mut s)?;
+        let public_key = read_bytes(&mut s)?;
+        let algorithm = s.read_u32::<LittleEndian>()?;
+        let signature = read_bytes(&mut s)?;
+        let tree = read_bytes(r)?;
+        Ok(Self {
+            root_hash,
+            apk_digest,
+            certificate,
+            public_key,
+            algorithm,
+            signature,
+            tree,
+        })
+    }
+
+    fn write(&self, w: &mut impl Write) -> Result<()> {
+        w.write_u32::<LittleEndian>(V4_VERSION)?;
+        let mut hashing_info = vec![];
+        hashing_info.write_u32::<LittleEndian>(HASH_ALGORITHM_SHA256)?;
+        hashing_info.write_u8(LOG2_BLOCK_SIZE)?;
+        write_bytes(&mut hashing_info, &[])?;
+        write_bytes(&mut hashing_info, &self.root_hash)?;
+        write_bytes(w, &hashing_info)?;
+        let mut signing_info = vec![];
+        write_bytes(&mut signing_info, &self.apk_digest)?;
+        write_bytes(&mut signing_info, &self.certificate)?;
+        write_bytes(&mut signing_info, &[])?;
+        write_bytes(&mut signing_info, &self.public_key)?;
+        signing_info.write_u32::<LittleEndian>(self.algorithm)?;
+        write_bytes(&mut signing_info, &self.signature)?;
+        write_bytes(w, &signing_info)?;
+        write_bytes(w, &self.tree)?;
+        Ok(())
+    }
+}
+
+fn read_bytes(r: &mut impl Read) -> Result<Vec<u8>> {
+    let len = r.read_u32::<LittleEndian>()?;
+    let mut buf = vec![0; len as usize];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn write_bytes(w: &mut impl Write, bytes: &[u8]) -> Result<()> {
+    w.write_u32::<LittleEndian>(bytes.len() as u32)?;
+    w.write_all(bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use xcommon::{Zip, ZipFileOptions};
+
+    #[test]
+    fn merkle_tree_levels() -> Result<()> {
+        // a single block only needs one level
+        let (tree, root_hash) = merkle_tree(&mut Cursor::new(vec![1; 100]))?;
+        assert_eq!(tree.len(), BLOCK_SIZE);
+        let mut block = vec![1; 100];
+        block.resize(BLOCK_SIZE, 0);
+        assert_eq!(&tree[..DIGEST_SIZE], &Sha256::digest(&block)[..]);
+        assert_eq!(root_hash, <[u8; 32]>::from(Sha256::digest(&tree)));
+
+        // 129 blocks need 2 blocks of hashes and a level on top
+        let (tree, root_hash) = merkle_tree(&mut Cursor::new(vec![1; BLOCK_SIZE * 129]))?;
+        assert_eq!(tree.len(), 3 * BLOCK_SIZE);
+        assert_eq!(
+            root_hash,
+            <[u8; 32]>::from(Sha256::digest(&tree[..BLOCK_SIZE]))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sign_verify_idsig() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("sign_verify_idsig.apk");
+        let mut zip = Zip::new(&path, true)?;
+        zip.create_file(
+            Path::new("AndroidManifest.xml"),
+            ZipFileOptions::Compressed,
+            b"manifest",
+        )?;
+        zip.finish()?;
+        let signer = crate::sign::debug_signer()?;
+        crate::sign::sign(&path, Some(signer.clone()), None)?;
+        sign(&path, &signer)?;
+        verify(&path)?;
+
+        // the signature no longer matches after the apk changed
+        crate::sign::sign(&path, Some(signer), None)?;
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(b"x")?;
+        assert!(verify(&path).is_err());
+        Ok(())
+    }
+}
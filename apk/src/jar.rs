@@ -0,0 +1,739 @@
+use crate::sign::{
+    key_info, verify_signature, KeyAlgorithm, ECDSA_SHA2_256, RSA_PKCS1V15_SHA2_256,
+};
+use anyhow::{Context, Result};
+use rasn::prelude::*;
+use rasn_cms::{
+    AlgorithmIdentifier, CertificateChoices, ContentInfo, EncapsulatedContentInfo,
+    IssuerAndSerialNumber, SignedData, SignerIdentifier, SignerInfo, CONTENT_DATA,
+    CONTENT_SIGNED_DATA,
+};
+use rasn_pkix::Certificate;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::{PaddingScheme, PublicKey, RsaPublicKey};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use xcommon::{SignatureAlgorithm, Signer, Zip, ZipFileOptions};
+use zip::ZipArchive;
+
+const MANIFEST: &str = "META-INF/MANIFEST.MF";
+const SIGNATURE_FILE: &str = "META-INF/CERT.SF";
+const CREATED_BY: &str = "1.0 (xbuild)";
+const MAX_LINE_LENGTH: usize = 72;
+
+/// Digest algorithm of a JAR signature.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JarDigest {
+    /// SHA-1 with SHA1withRSA signatures, android verifies nothing else
+    /// before API level 18.
+    Sha1,
+    Sha256,
+}
+
+impl JarDigest {
+    /// Returns the strongest digest supported by API level `min_sdk`.
+    pub fn for_min_sdk(min_sdk: u32) -> Self {
+        if min_sdk < 18 {
+            Self::Sha1
+        } else {
+            Self::Sha256
+        }
+    }
+
+    /// Name of the digest attributes.
+    fn attr(self) -> &'static str {
+        match self {
+            Self::Sha1 => "SHA1-Digest",
+            Self::Sha256 => "SHA-256-Digest",
+        }
+    }
+
+    fn oid(self) -> ConstOid {
+        match self {
+            Self::Sha1 => Oid::ISO_IDENTIFIED_ORGANISATION_OIW_SECSIG_ALGORITHM_SHA1,
+            Self::Sha256 => {
+                Oid::JOINT_ISO_ITU_T_COUNTRY_US_ORGANIZATION_GOV_CSOR_NIST_ALGORITHMS_HASH_SHA256
+            }
+        }
+    }
+
+    fn from_oid(oid: &Oid) -> Result<Self> {
+        [Self::Sha1, Self::Sha256]
+            .into_iter()
+            .find(|digest| *digest.oid() == *oid)
+            .with_context(|| format!("unsupported digest algorithm {:?}", oid))
+    }
+
+    fn digest(self, data: &[u8]) -> String {
+        match self {
+            Self::Sha1 => base64::encode(Sha1::digest(data)),
+            Self::Sha256 => base64::encode(Sha256::digest(data)),
+        }
+    }
+
+    fn digest_reader(self, r: &mut impl Read) -> Result<String> {
+        Ok(match self {
+            Self::Sha1 => {
+                let mut hasher = Sha1::new();
+                std::io::copy(r, &mut hasher)?;
+                base64::encode(hasher.finalize())
+            }
+            Self::Sha256 => {
+                let mut hasher = Sha256::new();
+                std::io::copy(r, &mut hasher)?;
+                base64::encode(hasher.finalize())
+            }
+        })
+    }
+}
+
+/// Signs a zip archive using JAR signing, also known as APK Signature
+/// Scheme v1. Adds `META-INF/MANIFEST.MF`, `META-INF/CERT.SF` and
+/// `META-INF/CERT.RSA` or `META-INF/CERT.EC` to the archive.
+///
+/// Apks supporting API levels before 18 need [`JarDigest::Sha1`] and an RSA
+/// key, android verifies SHA-256 digests and ECDSA signatures since API level
+/// 18.
+///
+/// When `apk` is set the signature file announces that the archive is also
+/// signed with v2/v3, to protect against stripping those signatures. In this
+/// case the APK signing block must be written after the JAR signature.
+pub fn sign(path: &Path, signer: &Signer, apk: bool, digest: JarDigest) -> Result<()> {
+    anyhow::ensure!(
+        digest != JarDigest::Sha1 || signer.algorithm() == SignatureAlgorithm::RsaPkcs1v15Sha256,
+        "ECDSA keys can only sign apks with a minSdkVersion of 18 or higher, use an RSA key \
+         or raise the minSdkVersion"
+    );
+    let mut entries = vec![];
+    {
+        let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+        for i in 0..archive.len() {
+            let mut f = archive.by_index(i)?;
+            if f.is_dir() {
+                continue;
+            }
+            anyhow::ensure!(
+                !is_signature_file(f.name()),
+                "{} is already JAR signed",
+                path.display()
+            );
+            let name = f.name().to_string();
+            entries.push((name, digest.digest_reader(&mut f)?));
+        }
+    }
+
+    let mut manifest = vec![];
+    write_attr(&mut manifest, "Manifest-Version", "1.0");
+    write_attr(&mut manifest, "Created-By", CREATED_BY);
+    manifest.extend_from_slice(b"\r\n");
+    let main_attributes_digest = digest.digest(&manifest);
+
+    let mut sections = vec![];
+    for (name, entry_digest) in &entries {
+        let mut section = vec![];
+        write_attr(&mut section, "Name", name);
+        write_attr(&mut section, digest.attr(), entry_digest);
+        section.extend_from_slice(b"\r\n");
+        manifest.extend_from_slice(&section);
+        sections.push((name, digest.digest(&section)));
+    }
+
+    let mut signature_file = vec![];
+    write_attr(&mut signature_file, "Signature-Version", "1.0");
+    write_attr(&mut signature_file, "Created-By", CREATED_BY);
+    write_attr(
+        &mut signature_file,
+        &format!("{}-Manifest", digest.attr()),
+        &digest.digest(&manifest),
+    );
+    write_attr(
+        &mut signature_file,
+        &format!("{}-Manifest-Main-Attributes", digest.attr()),
+        &main_attributes_digest,
+    );
+    if apk {
+        write_attr(&mut signature_file, "X-Android-APK-Signed", "2, 3");
+    }
+    signature_file.extend_from_slice(b"\r\n");
+    for (name, section_digest) in sections {
+        write_attr(&mut signature_file, "Name", name);
+        write_attr(&mut signature_file, digest.attr(), &section_digest);
+        signature_file.extend_from_slice(b"\r\n");
+    }
+
+    let signature_block = signature_block(signer, &signature_file, digest)?;
+
+    let mut zip = Zip::append(path, true)?;
+    zip.create_file(Path::new(MANIFEST), ZipFileOptions::Compressed, &manifest)?;
+    zip.create_file(
+        Path::new(SIGNATURE_FILE),
+        ZipFileOptions::Compressed,
+        &signature_file,
+    )?;
+    zip.create_file(
+        Path::new(signature_block_file(signer)),
+        ZipFileOptions::Compressed,
+        &signature_block,
+    )?;
+    zip.finish()?;
+    Ok(())
+}
+
+/// Result of verifying a JAR signature.
+#[derive(Clone, Debug)]
+pub struct JarSignature {
+    pub certificates: Vec<Certificate>,
+    /// Signature schemes announced by `X-Android-APK-Signed`, a v1 signature
+    /// announcing v2 or v3 is invalid when the apk lacks them.
+    pub apk_signed: Vec<u32>,
+}
+
+/// Verifies the JAR signature of a zip archive. Returns `None` if the
+/// archive is not JAR signed. SHA-1 and SHA-256 digests are supported.
+pub fn verify(path: &Path) -> Result<Option<JarSignature>> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    let names = archive
+        .file_names()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    if !names.iter().any(|name| name == MANIFEST) {
+        return Ok(None);
+    }
+    let mut read = |name: &str| -> Result<Vec<u8>> {
+        let mut buf = vec![];
+        archive.by_name(name)?.read_to_end(&mut buf)?;
+        Ok(buf)
+    };
+    let manifest = read(MANIFEST)?;
+    let sections = parse_sections(&manifest)?;
+    let entries = sections
+        .iter()
+        .skip(1)
+        .filter_map(|section| Some((section.get("Name")?, section)))
+        .collect::<HashMap<_, _>>();
+
+    let signature_files = names
+        .iter()
+        .filter(|name| {
+            name.strip_prefix("META-INF/")
+                .map(|name| !name.contains('/') && name.ends_with(".SF"))
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    anyhow::ensure!(!signature_files.is_empty(), "v1: no signature file found");
+    let mut signature = JarSignature {
+        certificates: vec![],
+        apk_signed: vec![],
+    };
+    for signature_file in signature_files {
+        let stem = signature_file.strip_suffix(".SF").unwrap();
+        let block = [".RSA", ".EC", ".DSA"]
+            .iter()
+            .map(|ext| format!("{}{}", stem, ext))
+            .find(|block| names.contains(block))
+            .with_context(|| format!("v1: no signature block for {}", signature_file))?;
+        let data = read(signature_file)?;
+        let certificate = verify_signature_block(&read(&block)?, &data)
+            .map_err(|err| anyhow::anyhow!("v1: {}: {}", block, err))?;
+        signature.certificates.push(certificate);
+
+        let signature_sections = parse_sections(&data)?;
+        let main = &signature_sections[0];
+        if let Some(schemes) = main.get("X-Android-APK-Signed") {
+            for scheme in schemes.split(',') {
+                signature.apk_signed.push(scheme.trim().parse()?);
+            }
+        }
+        let digest = [JarDigest::Sha256, JarDigest::Sha1]
+            .into_iter()
+            .find(|digest| main.get(&format!("{}-Manifest", digest.attr())).is_some())
+            .context("v1: no supported manifest digest")?;
+        let manifest_digest = main.get(&format!("{}-Manifest", digest.attr()));
+        if manifest_digest != Some(digest.digest(&manifest).as_str()) {
+            // fall back to the digests of the main attributes and of the
+            // individual sections, each of which needs to be signed
+            anyhow::ensure!(
+                main.get(&format!("{}-Manifest-Main-Attributes", digest.attr()))
+                    == Some(digest.digest(sections[0].bytes).as_str()),
+                "v1: manifest main attributes digest mismatch"
+            );
+            let mut signed = HashSet::new();
+            for section in signature_sections.iter().skip(1) {
+                let name = section.get("Name").context("v1: section without name")?;
+                let entry = entries
+                    .get(name)
+                    .with_context(|| format!("v1: {} is not in the manifest", name))?;
+                anyhow::ensure!(
+                    section.get(digest.attr()) == Some(digest.digest(entry.bytes).as_str()),
+                    "v1: manifest section digest mismatch for {}",
+                    name
+                );
+                signed.insert(name);
+            }
+            for name in entries.keys() {
+                anyhow::ensure!(
+                    signed.contains(name),
+                    "v1: {} is not signed by {}",
+                    name,
+                    signature_file
+                );
+            }
+        }
+    }
+
+    for name in &names {
+        if name.ends_with('/') || is_signature_file(name) {
+            continue;
+        }
+        let entry = entries
+            .get(name.as_str())
+            .with_context(|| format!("v1: {} is not in the manifest", name))?;
+        let (digest, expected) = [JarDigest::Sha256, JarDigest::Sha1]
+            .into_iter()
+            .find_map(|digest| Some((digest, entry.get(digest.attr())?)))
+            .with_context(|| format!("v1: no supported digest for {}", name))?;
+        anyhow::ensure!(
+            expected == digest.digest_reader(&mut archive.by_name(name)?)?,
+            "v1: digest mismatch for {}",
+            name
+        );
+    }
+    for name in entries.keys() {
+        anyhow::ensure!(
+            names.iter().any(|entry| entry == name),
+            "v1: {} is in the manifest but missing from the archive",
+            name
+        );
+    }
+    Ok(Some(signature))
+}
+
+/// Verifies a detached PKCS#7 signature of `data` and returns the
+/// certificate of the signer.
+fn verify_signature_block(block: &[u8], data: &[u8]) -> Result<Certificate> {
+    let info = rasn::der::decode::<ContentInfo>(block).map_err(|err| anyhow::anyhow!("{}", err))?;
+    let signed_data = rasn::der::decode::<SignedData>(info.content.as_bytes())
+        .map_err(|err| anyhow::anyhow!("{}", err))?;
+    let signer_info = signed_data
+        .signer_infos
+        .iter()
+        .next()
+        .context("no signer info")?;
+    anyhow::ensure!(
+        signer_info.signed_attrs.is_none(),
+        "signed attributes are not supported"
+    );
+    let digest = JarDigest::from_oid(&signer_info.digest_algorithm.algorithm)?;
+    let sid = if let SignerIdentifier::IssuerAndSerialNumber(sid) = &signer_info.sid {
+        sid
+    } else {
+        anyhow::bail!("unsupported signer identifier");
+    };
+    let certificate = signed_data
+        .certificates
+        .iter()
+        .flatten()
+        .find_map(|cert| match cert {
+            CertificateChoices::Certificate(cert)
+                if cert.tbs_certificate.issuer == sid.issuer
+                    && cert.tbs_certificate.serial_number == sid.serial_number =>
+            {
+                Some(cert.as_ref().clone())
+            }
+            _ => None,
+        })
+        .context("signer certificate not found")?;
+    let public_key = rasn::der::encode(&certificate.tbs_certificate.subject_public_key_info)
+        .map_err(|err| anyhow::anyhow!("{}", err))?;
+    let algorithm = match key_info(&public_key)?.0 {
+        KeyAlgorithm::Rsa => RSA_PKCS1V15_SHA2_256,
+        KeyAlgorithm::Ec => ECDSA_SHA2_256,
+    };
+    if digest == JarDigest::Sha1 {
+        anyhow::ensure!(
+            algorithm == RSA_PKCS1V15_SHA2_256,
+            "ECDSA with SHA-1 is not supported"
+        );
+        let pubkey = RsaPublicKey::from_public_key_der(&public_key)?;
+        let padding = PaddingScheme::new_pkcs1v15_sign::<Sha1>();
+        pubkey.verify(padding, &Sha1::digest(data), &signer_info.signature)?;
+    } else {
+        verify_signature(algorithm, &public_key, data, &signer_info.signature)?;
+    }
+    Ok(certificate)
+}
+
+/// A section of a manifest or signature file. `bytes` includes the
+/// terminating empty line and is what section digests are computed over.
+struct Section<'a> {
+    bytes: &'a [u8],
+    attrs: Vec<(String, String)>,
+}
+
+impl Section<'_> {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Splits a manifest into sections separated by empty lines, joining
+/// continuation lines. Lines are wrapped at byte boundaries, so values are
+/// only decoded once all their continuation lines are joined.
+fn parse_sections(data: &[u8]) -> Result<Vec<Section<'_>>> {
+    let mut sections = vec![];
+    let mut attrs: Vec<(String, Vec<u8>)> = vec![];
+    let mut start = 0;
+    let mut pos = 0;
+    while pos < data.len() {
+        let end = data[pos..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map(|i| pos + i + 1)
+            .unwrap_or(data.len());
+        let mut line = &data[pos..end];
+        while let [rest @ .., b'\r' | b'\n'] = line {
+            line = rest;
+        }
+        if line.is_empty() {
+            if !attrs.is_empty() {
+                sections.push(Section {
+                    bytes: &data[start..end],
+                    attrs: decode_attrs(std::mem::take(&mut attrs))?,
+                });
+            }
+            start = end;
+        } else if let Some(line) = line.strip_prefix(b" ") {
+            let (_, value) = attrs.last_mut().context("invalid continuation line")?;
+            value.extend_from_slice(line);
+        } else {
+            let sep = line.windows(2).position(|w| w == b": ").with_context(|| {
+                format!("invalid manifest line `{}`", String::from_utf8_lossy(line))
+            })?;
+            let name = std::str::from_utf8(&line[..sep])?;
+            attrs.push((name.to_string(), line[sep + 2..].to_vec()));
+        }
+        pos = end;
+    }
+    if !attrs.is_empty() {
+        sections.push(Section {
+            bytes: &data[start..],
+            attrs: decode_attrs(attrs)?,
+        });
+    }
+    anyhow::ensure!(!sections.is_empty(), "empty manifest");
+    Ok(sections)
+}
+
+fn decode_attrs(attrs: Vec<(String, Vec<u8>)>) -> Result<Vec<(String, String)>> {
+    attrs
+        .into_iter()
+        .map(|(name, value)| Ok((name, String::from_utf8(value)?)))
+        .collect()
+}
+
+fn signature_block_file(signer: &Signer) -> &'static str {
+    match signer.algorithm() {
+        SignatureAlgorithm::RsaPkcs1v15Sha256 => "META-INF/CERT.RSA",
+        SignatureAlgorithm::EcdsaSha256 => "META-INF/CERT.EC",
+    }
+}
+
+fn is_signature_file(name: &str) -> bool {
+    let name = if let Some(name) = name.strip_prefix("META-INF/") {
+        name
+    } else {
+        return false;
+    };
+    name == "MANIFEST.MF"
+        || name.starts_with("SIG-")
+        || [".SF", ".RSA", ".DSA", ".EC"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+}
+
+/// Writes a manifest attribute. Lines are limited to 72 bytes, longer
+/// values continue on the next line prefixed by a space.
+fn write_attr(w: &mut Vec<u8>, name: &str, value: &str) {
+    let line = format!("{}: {}", name, value);
+    let mut line = line.as_bytes();
+    let mut max = MAX_LINE_LENGTH;
+    while line.len() > max {
+        w.extend_from_slice(&line[..max]);
+        w.extend_from_slice(b"\r\n ");
+        line = &line[max..];
+        max = MAX_LINE_LENGTH - 1;
+    }
+    w.extend_from_slice(line);
+    w.extend_from_slice(b"\r\n");
+}
+
+/// Creates a detached PKCS#7 signature of the signature file.
+#[allow(clippy::mutable_key_type)]
+fn signature_block(signer: &Signer, signature_file: &[u8], digest: JarDigest) -> Result<Vec<u8>> {
+    let cert = signer.cert();
+    let digest_algorithm = AlgorithmIdentifier {
+        algorithm: digest.oid().into(),
+        parameters: Some(Any::new(vec![5, 0])),
+    };
+    let signature = match digest {
+        JarDigest::Sha1 => signer.sign_sha1(signature_file)?,
+        JarDigest::Sha256 => signer.sign(signature_file),
+    };
+    let signer_info = SignerInfo {
+        version: 1.into(),
+        sid: SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+            issuer: cert.tbs_certificate.issuer.clone(),
+            serial_number: cert.tbs_certificate.serial_number.clone(),
+        }),
+        digest_algorithm: digest_algorithm.clone(),
+        signed_attrs: None,
+        signature_algorithm: signer.algorithm().pkcs7_algorithm_identifier(),
+        signature: OctetString::from(signature),
+        unsigned_attrs: None,
+    };
+    let signed_data = SignedData {
+        version: 1.into(),
+        digest_algorithms: {
+            let mut digest_algorithms = SetOf::default();
+            digest_algorithms.insert(digest_algorithm);
+            digest_algorithms
+        },
+        encap_content_info: EncapsulatedContentInfo {
+            content_type: CONTENT_DATA.into(),
+            content: None,
+        },
+        certificates: Some({
+            let mut certificates = SetOf::default();
+            certificates.insert(CertificateChoices::Certificate(Box::new(cert.clone())));
+            certificates
+        }),
+        crls: None,
+        signer_infos: {
+            let mut signer_infos = SetOf::default();
+            signer_infos.insert(signer_info);
+            signer_infos
+        },
+    };
+    let content_info = ContentInfo {
+        content_type: CONTENT_SIGNED_DATA.into(),
+        content: Any::new(
+            rasn::der::encode(&signed_data).map_err(|err| anyhow::anyhow!("{}", err))?,
+        ),
+    };
+    rasn::der::encode(&content_info).map_err(|err| anyhow::anyhow!("{}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::path::PathBuf;
+
+    const DEBUG_PEM: &str = include_str!("../assets/debug.pem");
+    const P256_PEM: &str = include_str!("../../xcommon/assets/test-p256.pem");
+
+    fn read_file(path: &Path, name: &str) -> Result<Vec<u8>> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let mut f = archive.by_name(name)?;
+        let mut buf = vec![];
+        f.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    #[test]
+    fn attr_line_wrapping() {
+        let mut buf = vec![];
+        let value = "a".repeat(100);
+        write_attr(&mut buf, "Name", &value);
+        let lines = buf
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), MAX_LINE_LENGTH + 1);
+        assert_eq!(lines[1][0], b' ');
+    }
+
+    #[test]
+    fn sign_jar() -> Result<()> {
+        for pem in [DEBUG_PEM, P256_PEM] {
+            sign_jar_with(&Signer::new(pem)?)?;
+        }
+        Ok(())
+    }
+
+    fn sign_jar_with(signer: &Signer) -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("sign_jar.aab");
+        let mut zip = Zip::new(&path, true)?;
+        zip.create_file(
+            Path::new("base/manifest/AndroidManifest.xml"),
+            ZipFileOptions::Compressed,
+            b"manifest",
+        )?;
+        zip.finish()?;
+        sign(&path, signer, false, JarDigest::Sha256)?;
+
+        let manifest = read_file(&path, MANIFEST)?;
+        let digest = base64::encode(Sha256::digest(b"manifest"));
+        let manifest = String::from_utf8(manifest)?;
+        assert!(manifest.contains("Name: base/manifest/AndroidManifest.xml\r\n"));
+        assert!(manifest.contains(&format!("SHA-256-Digest: {}\r\n", digest)));
+
+        let signature_file = read_file(&path, SIGNATURE_FILE)?;
+        let signature_block = read_file(&path, signature_block_file(signer))?;
+        let info = rasn::der::decode::<ContentInfo>(&signature_block)
+            .map_err(|err| anyhow::anyhow!("{}", err))?;
+        let signed_data = rasn::der::decode::<SignedData>(info.content.as_bytes())
+            .map_err(|err| anyhow::anyhow!("{}", err))?;
+        let signer_info = signed_data.signer_infos.into_iter().next().unwrap();
+        crate::sign::verify_signature(
+            crate::sign::signature_algorithm(signer),
+            signer.pubkey(),
+            &signature_file,
+            &signer_info.signature,
+        )?;
+
+        let signature = verify(&path)?.unwrap();
+        assert_eq!(signature.certificates, vec![signer.cert().clone()]);
+        assert!(signature.apk_signed.is_empty());
+
+        assert!(sign(&path, signer, false, JarDigest::Sha256).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sign_jar_sha1() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        assert_eq!(JarDigest::for_min_sdk(17), JarDigest::Sha1);
+        assert_eq!(JarDigest::for_min_sdk(18), JarDigest::Sha256);
+        let path = tmp.path().join("sign_jar_sha1.apk");
+        let mut zip = Zip::new(&path, true)?;
+        zip.create_file(Path::new("classes.dex"), ZipFileOptions::Compressed, b"dex")?;
+        zip.finish()?;
+        let err = sign(&path, &Signer::new(P256_PEM)?, true, JarDigest::Sha1).unwrap_err();
+        assert!(err.to_string().contains("minSdkVersion of 18"), "{}", err);
+
+        let signer = Signer::new(DEBUG_PEM)?;
+        sign(&path, &signer, true, JarDigest::Sha1)?;
+        let manifest = String::from_utf8(read_file(&path, MANIFEST)?)?;
+        let digest = base64::encode(Sha1::digest(b"dex"));
+        assert!(manifest.contains(&format!("SHA1-Digest: {}\r\n", digest)));
+        let signature_file = String::from_utf8(read_file(&path, SIGNATURE_FILE)?)?;
+        assert!(signature_file.contains("SHA1-Digest-Manifest: "));
+        assert!(!signature_file.contains("SHA-256"));
+        let signature = verify(&path)?.unwrap();
+        assert_eq!(signature.certificates, vec![signer.cert().clone()]);
+        Ok(())
+    }
+
+    #[test]
+    fn verify_jar() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("verify_jar.apk");
+        let mut zip = Zip::new(&path, true)?;
+        zip.create_file(Path::new("classes.dex"), ZipFileOptions::Compressed, b"dex")?;
+        zip.finish()?;
+        assert!(verify(&path)?.is_none());
+        sign(&path, &Signer::new(DEBUG_PEM)?, true, JarDigest::Sha256)?;
+        assert_eq!(verify(&path)?.unwrap().apk_signed, [2, 3]);
+
+        let mut zip = Zip::append(&path, true)?;
+        zip.create_file(Path::new("extra.txt"), ZipFileOptions::Compressed, b"extra")?;
+        zip.finish()?;
+        let err = verify(&path).unwrap_err().to_string();
+        assert_eq!(err, "v1: extra.txt is not in the manifest");
+        Ok(())
+    }
+
+    /// Copies a signed archive, replacing the manifest and adding `extra`.
+    fn tamper(path: &Path, manifest: &[u8], extra: Option<(&str, &[u8])>) -> Result<PathBuf> {
+        let out = path.with_extension("tampered.apk");
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let mut zip = Zip::new(&out, true)?;
+        for i in 0..archive.len() {
+            let mut f = archive.by_index(i)?;
+            let mut buf = vec![];
+            f.read_to_end(&mut buf)?;
+            let data = if f.name() == MANIFEST { manifest } else { &buf };
+            zip.create_file(Path::new(f.name()), ZipFileOptions::Compressed, data)?;
+        }
+        if let Some((name, data)) = extra {
+            zip.create_file(Path::new(name), ZipFileOptions::Compressed, data)?;
+        }
+        zip.finish()?;
+        Ok(out)
+    }
+
+    #[test]
+    fn verify_jar_injected_entry() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("verify_jar_injected.apk");
+        let mut zip = Zip::new(&path, true)?;
+        zip.create_file(Path::new("classes.dex"), ZipFileOptions::Compressed, b"dex")?;
+        zip.finish()?;
+        sign(&path, &Signer::new(DEBUG_PEM)?, true, JarDigest::Sha256)?;
+        let manifest = read_file(&path, MANIFEST)?;
+
+        // an entry added together with its manifest section is not signed
+        let mut injected = manifest.clone();
+        write_attr(&mut injected, "Name", "extra.txt");
+        write_attr(
+            &mut injected,
+            "SHA-256-Digest",
+            &JarDigest::Sha256.digest(b"extra"),
+        );
+        injected.extend_from_slice(b"\r\n");
+        let out = tamper(&path, &injected, Some(("extra.txt", b"extra")))?;
+        let err = verify(&out).unwrap_err().to_string();
+        assert_eq!(err, "v1: extra.txt is not signed by META-INF/CERT.SF");
+
+        // the main attributes are covered by their own digest
+        let mut edited = b"Manifest-Version: 1.0\r\nCreated-By: someone else\r\n\r\n".to_vec();
+        let main_len = parse_sections(&manifest)?[0].bytes.len();
+        edited.extend_from_slice(&manifest[main_len..]);
+        let out = tamper(&path, &edited, None)?;
+        let err = verify(&out).unwrap_err().to_string();
+        assert_eq!(err, "v1: manifest main attributes digest mismatch");
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_sections() -> Result<()> {
+        let manifest = b"Manifest-Version: 1.0\r\n\r\nName: a\r\n b\r\nSHA-256-Digest: x\r\n\r\n";
+        let sections = parse_sections(manifest)?;
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].bytes, b"Manifest-Version: 1.0\r\n\r\n");
+        assert_eq!(sections[1].get("Name"), Some("ab"));
+        assert_eq!(sections[1].get("SHA-256-Digest"), Some("x"));
+        Ok(())
+    }
+
+    #[test]
+    fn sign_verify_non_ascii_name() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("non_ascii_name.apk");
+        // wraps the `Name` line in the middle of a multibyte character
+        let name = format!("assets/{}.txt", "ä".repeat(40));
+        let mut zip = Zip::new(&path, true)?;
+        zip.create_file(Path::new(&name), ZipFileOptions::Compressed, b"asset")?;
+        zip.finish()?;
+        let signer = Signer::new(DEBUG_PEM)?;
+        sign(&path, &signer, false, JarDigest::Sha256)?;
+
+        let manifest = read_file(&path, MANIFEST)?;
+        assert!(std::str::from_utf8(&manifest).is_err());
+        let sections = parse_sections(&manifest)?;
+        assert_eq!(sections[1].get("Name"), Some(name.as_str()));
+        let signature = verify(&path)?.unwrap();
+        assert_eq!(signature.certificates, vec![signer.cert().clone()]);
+        Ok(())
+    }
+}
@@ -1,93 +1,197 @@
-use crate::compiler::Table;
+use crate::compiler::Output;
+use crate::jar::JarDigest;
 use crate::res::Chunk;
 use anyhow::{Context, Result};
+use std::collections::BTreeMap;
 use std::io::Cursor;
 use std::path::{Path, PathBuf};
-use xcommon::{Scaler, ScalerOpts, Zip, ZipFileOptions};
+use xcommon::{Scaler, Zip, ZipFileOptions};
 
+mod aab;
+mod align;
+mod apks;
+pub mod arsc;
 mod compiler;
+mod idsig;
+mod jar;
+mod lineage;
 pub mod manifest;
+mod merge;
+mod pb;
 pub mod res;
+mod rjar;
 mod sign;
+mod split;
 mod utils;
 
+pub use crate::aab::Aab;
+pub use crate::compiler::{Symbols, XmlElement};
+pub use crate::lineage::{Lineage, LineageNode};
 pub use crate::manifest::AndroidManifest;
+pub use crate::merge::ManifestMerger;
+pub use crate::rjar::write_r_jar;
+pub use crate::sign::{
+    KeyAlgorithm, Rotation, SchemeVerification, SignerCertificate, Verification, VerificationReport,
+};
 pub use crate::utils::{Target, VersionCode};
-pub use xcommon::{Certificate, Signer};
+pub use xcommon::{Certificate, Signer, ZipEntry};
 pub use zip;
 
 pub struct Apk {
     manifest: AndroidManifest,
     path: PathBuf,
     zip: Zip,
+    rotation: Option<Rotation>,
+    idsig: bool,
+    icon_background: IconBackground,
+    compress: bool,
+    split: bool,
+    splits: BTreeMap<String, Zip>,
+    page_size: Option<u16>,
+}
+
+/// Background layer of an adaptive launcher icon.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IconBackground {
+    /// A color like `#ffffff`.
+    Color(String),
+    /// A png, webp or svg image, raster images of at least 512x512 px.
+    Image(PathBuf),
+}
+
+impl Default for IconBackground {
+    fn default() -> Self {
+        Self::Color("#ffffff".into())
+    }
 }
 
 impl Apk {
     pub fn new(path: PathBuf, manifest: AndroidManifest, compress: bool) -> Result<Self> {
         let zip = Zip::new(&path, compress)?;
+        // splits of a previous build would be installed with the new apk
+        for split in crate::split::splits(&path)? {
+            std::fs::remove_file(&split)?;
+            std::fs::remove_file(Self::idsig_path(&split)).ok();
+        }
         Ok(Self {
             manifest,
             path,
             zip,
+            rotation: None,
+            idsig: false,
+            icon_background: Default::default(),
+            compress,
+            split: false,
+            splits: Default::default(),
+            page_size: None,
         })
     }
 
-    pub fn add_res(&mut self, icon: Option<&Path>, android: &Path) -> Result<()> {
-        let mut buf = vec![];
-        let mut table = Table::default();
-        table.import_apk(android)?;
-        if let Some(path) = icon {
-            let mut scaler = Scaler::open(path)?;
-            scaler.optimize();
-            let package = if let Some(package) = self.manifest.package.as_ref() {
-                package
-            } else {
-                anyhow::bail!("missing manifest.package");
-            };
-            let mipmap = crate::compiler::compile_mipmap(package, "icon")?;
-
-            let mut cursor = Cursor::new(&mut buf);
-            mipmap.chunk().write(&mut cursor)?;
-            self.zip.create_file(
-                Path::new("resources.arsc"),
-                ZipFileOptions::Aligned(4),
-                &buf,
-            )?;
+    /// Moves the libraries of each abi and the resources of each density
+    /// into config splits named `<name>.config.<abi|density>.apk`, which are
+    /// installed together with the base apk. Must be set before calling
+    /// [`Apk::add_res`].
+    pub fn set_split(&mut self, split: bool) {
+        self.split = split;
+    }
 
-            for (name, size) in mipmap.variants() {
-                buf.clear();
-                let mut cursor = Cursor::new(&mut buf);
-                scaler.write(&mut cursor, ScalerOpts::new(size))?;
-                self.zip
-                    .create_file(name.as_ref(), ZipFileOptions::Aligned(4), &buf)?;
-            }
+    /// Stores native libraries uncompressed and aligned to `page_size`, which
+    /// is either 4096 or 16384, and sets `android:extractNativeLibs="false"`
+    /// so that they are mapped directly from the apk. The `LOAD` segments of
+    /// the libraries must be aligned to `page_size` as well. Must be set
+    /// before calling [`Apk::add_res`].
+    pub fn set_page_size(&mut self, page_size: u16) -> Result<()> {
+        anyhow::ensure!(
+            crate::align::PAGE_SIZES.contains(&page_size),
+            "invalid page size {}, expected one of {:?}",
+            page_size,
+            crate::align::PAGE_SIZES
+        );
+        self.page_size = Some(page_size);
+        self.manifest.application.extract_native_libs = Some(false);
+        Ok(())
+    }
 
-            table.import_chunk(mipmap.chunk());
-            self.manifest.application.icon = Some("@mipmap/icon".into());
-        }
-        let manifest = crate::compiler::compile_manifest(&self.manifest, &table)?;
-        buf.clear();
-        let mut cursor = Cursor::new(&mut buf);
-        manifest.write(&mut cursor)?;
-        self.zip.create_file(
-            Path::new("AndroidManifest.xml"),
-            ZipFileOptions::Compressed,
-            &buf,
+    /// Signs the apk with a rotated key using APK Signature Scheme v3.
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.rotation = Some(rotation);
+    }
+
+    /// Background of the adaptive launcher icon, defaults to white. Must be
+    /// set before calling [`Apk::add_res`].
+    pub fn set_icon_background(&mut self, background: IconBackground) {
+        self.icon_background = background;
+    }
+
+    /// Writes an APK Signature Scheme v4 signature to `<name>.apk.idsig`
+    /// which is required for incremental installs.
+    pub fn set_idsig(&mut self, idsig: bool) {
+        self.idsig = idsig;
+    }
+
+    /// Compiles the `res` directory, the `res` directories of `libraries`
+    /// and the icon into `resources.arsc`, adds the resource files and
+    /// compiles the manifest. References to resources in the manifest are
+    /// resolved using the compiled resources and the android framework
+    /// resources in `android`. Returns the symbols for the `R` classes.
+    pub fn add_res(
+        &mut self,
+        res: Option<&Path>,
+        libraries: &[PathBuf],
+        icon: Option<&Scaler>,
+        android: &Path,
+    ) -> Result<Symbols> {
+        let (outputs, symbols) = crate::compiler::compile_resources(
+            &mut self.manifest,
+            &self.icon_background,
+            res,
+            libraries,
+            icon,
+            android,
         )?;
-        Ok(())
+        if !self.split {
+            write_outputs(&mut self.zip, outputs)?;
+            return Ok(symbols);
+        }
+        let (base, splits) = crate::split::split_outputs(outputs)?;
+        write_outputs(&mut self.zip, base)?;
+        for (split, outputs) in splits {
+            write_outputs(self.split_zip(split)?, outputs)?;
+        }
+        Ok(symbols)
+    }
+
+    fn split_zip(&mut self, split: String) -> Result<&mut Zip> {
+        if !self.splits.contains_key(&split) {
+            let path = crate::split::split_path(&self.path, &split);
+            let zip = Zip::new(&path, self.compress)?;
+            self.splits.insert(split.clone(), zip);
+        }
+        Ok(self.splits.get_mut(&split).unwrap())
     }
 
     pub fn add_asset(&mut self, asset: &Path, opts: ZipFileOptions) -> Result<()> {
+        self.add_asset_with(asset, |_| opts)
+    }
+
+    /// Adds an asset with the options returned by `opts` for the path of
+    /// each file relative to the asset directory, or the file name of an
+    /// asset file.
+    pub fn add_asset_with(
+        &mut self,
+        asset: &Path,
+        opts: impl Fn(&Path) -> ZipFileOptions,
+    ) -> Result<()> {
         let file_name = asset
             .file_name()
             .context("Asset must have file_name component")?;
         let dest = Path::new("assets").join(file_name);
         if asset.is_dir() {
             tracing::info!("Embedding asset directory `{}`", asset.display());
-            self.zip.add_directory(asset, &dest, opts)
+            self.zip.add_directory_with(asset, &dest, opts)
         } else {
             tracing::info!("Embedding asset file `{}`", asset.display());
-            self.zip.add_file(asset, &dest, opts)
+            self.zip.add_file(asset, &dest, opts(Path::new(file_name)))
         }
         .with_context(|| format!("While embedding asset `{}`", asset.display()))
     }
@@ -98,107 +202,207 @@ impl Apk {
         Ok(())
     }
 
+    /// Adds the dex files of a multidex app as `classes.dex`, `classes2.dex`,
+    /// ... in order.
+    pub fn add_dexes(&mut self, dexes: &[PathBuf]) -> Result<()> {
+        for (i, dex) in dexes.iter().enumerate() {
+            self.zip
+                .add_file(dex, Path::new(&dex_name(i)), ZipFileOptions::Compressed)?;
+        }
+        Ok(())
+    }
+
     pub fn add_lib(&mut self, target: Target, path: &Path) -> Result<()> {
         let name = path.file_name().context("invalid path")?;
-        self.zip.add_file(
+        let opts = lib_options(path, self.page_size)?;
+        let zip = if self.split {
+            self.split_zip(crate::split::abi_split(target))?
+        } else {
+            &mut self.zip
+        };
+        zip.add_file(
             path,
             &Path::new("lib").join(target.as_str()).join(name),
-            ZipFileOptions::Compressed,
+            opts,
         )
     }
 
     pub fn finish(self, signer: Option<Signer>) -> Result<()> {
         self.zip.finish()?;
-        crate::sign::sign(&self.path, signer)?;
+        let signer = signer.map(Ok).unwrap_or_else(crate::sign::debug_signer)?;
+        let min_sdk = self.manifest.sdk.min_sdk_version.unwrap_or(1);
+        let rotation = self.rotation.as_ref();
+        sign_apk(&self.path, &signer, min_sdk, rotation, self.idsig)?;
+        if self.splits.is_empty() {
+            return Ok(());
+        }
+        for (split, mut zip) in self.splits {
+            let manifest = crate::split::split_manifest(&self.manifest, &split)?;
+            write_outputs(
+                &mut zip,
+                vec![Output::Xml("AndroidManifest.xml".into(), manifest)],
+            )?;
+            zip.finish()?;
+            let path = crate::split::split_path(&self.path, &split);
+            sign_apk(&path, &signer, min_sdk, rotation, self.idsig)?;
+        }
         Ok(())
     }
 
-    pub fn sign(path: &Path, signer: Option<Signer>) -> Result<()> {
-        crate::sign::sign(path, signer)
+    pub fn sign(path: &Path, signer: Option<Signer>, rotation: Option<&Rotation>) -> Result<()> {
+        crate::sign::sign(path, signer, rotation)
+    }
+
+    /// JAR signs a zip archive like an android app bundle. Apks are signed
+    /// by [`Apk::finish`].
+    pub fn sign_jar(path: &Path, signer: Option<Signer>) -> Result<()> {
+        let signer = signer.map(Ok).unwrap_or_else(crate::sign::debug_signer)?;
+        crate::jar::sign(path, &signer, false, JarDigest::Sha256)
+    }
+
+    /// Builds a signed apk set from an android app bundle, see
+    /// [`Aab`].
+    pub fn build_apks(aab: &Path, apks: &Path, signer: Option<Signer>) -> Result<()> {
+        crate::apks::build_apks(aab, apks, signer)
+    }
+
+    /// Extracts the master and config splits of an apk set built with
+    /// [`Apk::build_apks`] into `dir`. Returns the path of the base apk, the
+    /// splits are returned by [`Apk::splits`].
+    pub fn extract_apks(apks: &Path, dir: &Path) -> Result<PathBuf> {
+        crate::apks::extract_apks(apks, dir)
+    }
+
+    /// Returns the config splits of the apk at `path` built with
+    /// [`Apk::set_split`].
+    pub fn splits(path: &Path) -> Result<Vec<PathBuf>> {
+        crate::split::splits(path)
+    }
+
+    /// Returns the entries of the apk at `path` that aren't aligned like
+    /// `zipalign -c -p`. Uncompressed entries must start at a 4 byte
+    /// boundary and uncompressed libraries at a `page_size` boundary.
+    pub fn check_alignment(path: &Path, page_size: u16) -> Result<Vec<ZipEntry>> {
+        crate::align::check_zip(path, page_size)
     }
 
-    pub fn verify(path: &Path) -> Result<Vec<Certificate>> {
+    pub fn idsig_path(path: &Path) -> PathBuf {
+        crate::idsig::idsig_path(path)
+    }
+
+    pub fn verify(path: &Path) -> Result<Verification> {
         crate::sign::verify(path)
     }
 
-    pub fn entry_point(path: &Path) -> Result<EntryPoint> {
+    /// Verifies the v1, v2, v3 and v4 signatures of an apk and reports the
+    /// result of each scheme, the signer certificates and the lineage.
+    pub fn verify_report(path: &Path) -> Result<VerificationReport> {
+        crate::sign::verify_report(path)
+    }
+
+    /// Verifies the APK Signature Scheme v4 signature in `<name>.apk.idsig`.
+    pub fn verify_idsig(path: &Path) -> Result<()> {
+        crate::idsig::verify(path)?;
+        Ok(())
+    }
+
+    /// Decompiles the binary `AndroidManifest.xml` of an apk.
+    pub fn manifest_xml(path: &Path) -> Result<XmlElement> {
         let manifest = xcommon::extract_zip_file(path, "AndroidManifest.xml")?;
-        let chunks = if let Chunk::Xml(chunks) = Chunk::parse(&mut Cursor::new(manifest))? {
-            chunks
-        } else {
-            anyhow::bail!("invalid manifest 0");
-        };
-        let strings = if let Chunk::StringPool(strings, _) = &chunks[0] {
-            strings
-        } else {
-            anyhow::bail!("invalid manifest 1");
-        };
-        let mut manifest = None;
-        let mut package = None;
-        let mut activity = None;
-        let mut name = None;
-        for (i, s) in strings.iter().enumerate() {
-            match s.as_str() {
-                "manifest" => {
-                    manifest = Some(i as i32);
-                }
-                "package" => {
-                    package = Some(i as i32);
-                }
-                "activity" => {
-                    activity = Some(i as i32);
-                }
-                "name" => {
-                    name = Some(i as i32);
-                }
-                _ => {}
+        let chunk = Chunk::parse(&mut Cursor::new(manifest))?;
+        crate::compiler::decompile_xml(&chunk)
+    }
+
+    /// Reads the manifest of an apk.
+    pub fn manifest(path: &Path) -> Result<AndroidManifest> {
+        let manifest = xcommon::extract_zip_file(path, "AndroidManifest.xml")?;
+        let chunk = Chunk::parse(&mut Cursor::new(manifest))?;
+        crate::compiler::decompile_manifest(&chunk)
+    }
+
+    /// Returns the package and the activity or activity alias launched from
+    /// the launcher.
+    pub fn entry_point(path: &Path) -> Result<EntryPoint> {
+        let manifest = Self::manifest_xml(path)?;
+        let package = manifest
+            .attribute("package")
+            .context("manifest is missing a package")?;
+        let activity = crate::compiler::launcher_activity(&manifest)
+            .context("manifest doesn't contain an activity")?;
+        Ok(EntryPoint {
+            package: package.to_string(),
+            activity: activity.to_string(),
+        })
+    }
+}
+
+fn write_outputs(zip: &mut Zip, outputs: Vec<Output>) -> Result<()> {
+    let mut buf = vec![];
+    for output in outputs {
+        buf.clear();
+        let (path, opts) = match &output {
+            Output::Table(chunk) => {
+                chunk.write(&mut Cursor::new(&mut buf))?;
+                ("resources.arsc", ZipFileOptions::Aligned(4))
             }
-        }
-        let (manifest, package, activity, name) =
-            if let (Some(manifest), Some(package), Some(activity), Some(name)) =
-                (manifest, package, activity, name)
-            {
-                (manifest, package, activity, name)
-            } else {
-                anyhow::bail!("invalid manifest 2");
-            };
-        let mut package_value = None;
-        let mut name_value = None;
-        for chunk in &chunks[2..] {
-            if let Chunk::XmlStartElement(_, el, attrs) = chunk {
-                match el.name {
-                    x if x == manifest => {
-                        package_value = attrs
-                            .iter()
-                            .find(|attr| attr.name == package)
-                            .map(|attr| attr.raw_value);
-                    }
-                    x if x == activity => {
-                        // should really select the activity with the intent-filter
-                        // `android.intent.action.MAIN`. for now we settle for the
-                        // first activity
-                        if name_value.is_some() {
-                            continue;
-                        }
-                        name_value = attrs
-                            .iter()
-                            .find(|attr| attr.name == name)
-                            .map(|attr| attr.raw_value);
-                    }
-                    _ => {}
-                }
+            Output::Xml(path, chunk) => {
+                chunk.write(&mut Cursor::new(&mut buf))?;
+                (path.as_str(), ZipFileOptions::Compressed)
             }
-        }
-        let entry = if let (Some(package_value), Some(name_value)) = (package_value, name_value) {
-            EntryPoint {
-                package: strings[package_value as usize].clone(),
-                activity: strings[name_value as usize].clone(),
+            Output::File(path, bytes) => {
+                buf.extend_from_slice(bytes);
+                (path.as_str(), ZipFileOptions::Aligned(4))
             }
-        } else {
-            anyhow::bail!("invalid manifest 3");
         };
-        Ok(entry)
+        zip.create_file(path.as_ref(), opts, &buf)?;
+    }
+    Ok(())
+}
+
+/// Libraries are compressed unless they are mapped from the apk, in which
+/// case their segments need to be page aligned.
+fn lib_options(path: &Path, page_size: Option<u16>) -> Result<ZipFileOptions> {
+    if let Some(page_size) = page_size {
+        crate::align::check_lib(path, page_size)?;
+        Ok(ZipFileOptions::Aligned(page_size))
+    } else {
+        Ok(ZipFileOptions::Compressed)
+    }
+}
+
+/// Name of the `i`th dex file, the primary one doesn't have a number.
+fn dex_name(i: usize) -> String {
+    if i == 0 {
+        "classes.dex".into()
+    } else {
+        format!("classes{}.dex", i + 1)
+    }
+}
+
+fn sign_apk(
+    path: &Path,
+    signer: &Signer,
+    min_sdk: u32,
+    rotation: Option<&Rotation>,
+    idsig: bool,
+) -> Result<()> {
+    anyhow::ensure!(
+        min_sdk >= 28 || rotation.is_none_or(|rotation| rotation.original.is_some()),
+        "signing with a lineage for min_sdk {} requires the original signer",
+        min_sdk
+    );
+    // devices older than android 7 only verify JAR signatures
+    if min_sdk < 24 {
+        let original = rotation
+            .and_then(|rotation| rotation.original.as_ref())
+            .unwrap_or(signer);
+        crate::jar::sign(path, original, true, JarDigest::for_min_sdk(min_sdk))?;
+    }
+    crate::sign::sign(path, Some(signer.clone()), rotation)?;
+    if idsig {
+        crate::idsig::sign(path, signer)?;
     }
+    Ok(())
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -237,6 +441,58 @@ pub(crate) mod tests {
         Ok(jar.unwrap())
     }
 
+    #[test]
+    fn test_dex_name() {
+        assert_eq!(dex_name(0), "classes.dex");
+        assert_eq!(dex_name(1), "classes2.dex");
+        assert_eq!(dex_name(9), "classes10.dex");
+    }
+
+    #[test]
+    fn test_incompressible_assets_aligned() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let dir = tmp.path().join("sounds");
+        std::fs::create_dir_all(&dir)?;
+        let noise = include_bytes!("../../xcommon/assets/noise.bin");
+        // names of different lengths so that not every entry is aligned by chance
+        for name in ["a.ogg", "bb.ogg", "ccc.ogg", "dddd.ogg"] {
+            std::fs::write(dir.join(name), noise)?;
+        }
+        let path = tmp.path().join("incompressible_assets.apk");
+        let mut apk = Apk::new(path.clone(), AndroidManifest::default(), true)?;
+        apk.add_asset(
+            &dir,
+            ZipFileOptions::Deflate {
+                level: Some(9),
+                store_if_incompressible: true,
+            },
+        )?;
+        apk.finish(None)?;
+        let misaligned = Apk::check_alignment(&path, 4096)?;
+        assert!(misaligned.is_empty(), "{:?}", misaligned);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_rotation_requires_original() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("rotation.apk");
+        let mut zip = Zip::new(&path, true)?;
+        zip.create_file(Path::new("classes.dex"), ZipFileOptions::Compressed, b"dex")?;
+        zip.finish()?;
+        let original = Signer::new(include_str!("../assets/debug.pem"))?;
+        let signer = Signer::new(include_str!("../../xcommon/assets/test.pem"))?;
+        let mut lineage = Lineage::new(&original)?;
+        lineage.rotate(&original, &signer)?;
+        let rotation = Rotation {
+            lineage,
+            original: None,
+        };
+        let err = sign_apk(&path, &signer, 21, Some(&rotation), false).unwrap_err();
+        assert!(err.to_string().contains("original signer"), "{}", err);
+        Ok(())
+    }
+
     pub fn android_jar(platform: u16) -> Result<PathBuf> {
         let home = std::env::var("ANDROID_HOME")?;
         let android = Path::new(&home)
@@ -0,0 +1,219 @@
+use crate::sign::{signature_algorithm, verify_signature};
+use anyhow::Result;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use rasn_pkix::Certificate;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use xcommon::Signer;
+
+const LINEAGE_VERSION: u32 = 1;
+
+/// Default capabilities granted to a previous signing certificate: installed
+/// data, shared uid, permission and auth. Rollback is not granted.
+const DEFAULT_CAPABILITIES: u32 = 0b1_0111;
+
+/// Signing certificate lineage used by APK Signature Scheme v3 to prove
+/// that a signing key was rotated from a previous one (proof-of-rotation).
+///
+/// The first node is the original signing certificate, every following node
+/// is signed by the key of its predecessor.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Lineage {
+    nodes: Vec<LineageNode>,
+}
+
+/// A certificate in a [`Lineage`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LineageNode {
+    /// DER encoded signing certificate.
+    pub certificate: Vec<u8>,
+    /// Signature algorithm the previous signer used to sign this node.
+    pub parent_algorithm: u32,
+    /// Capabilities granted to this certificate.
+    pub flags: u32,
+    /// Signature algorithm this signer uses to sign the next node.
+    pub algorithm: u32,
+    /// Signature of the previous signer over this node.
+    pub signature: Vec<u8>,
+}
+
+impl LineageNode {
+    fn signed_data(&self) -> Result<Vec<u8>> {
+        let mut buf = vec![];
+        buf.write_u32::<LittleEndian>(self.certificate.len() as u32)?;
+        buf.write_all(&self.certificate)?;
+        buf.write_u32::<LittleEndian>(self.parent_algorithm)?;
+        Ok(buf)
+    }
+
+    pub fn certificate(&self) -> Result<Certificate> {
+        rasn::der::decode(&self.certificate).map_err(|err| anyhow::anyhow!("{}", err))
+    }
+}
+
+impl Lineage {
+    /// Creates a new lineage starting with the certificate of `signer`.
+    pub fn new(signer: &Signer) -> Result<Self> {
+        let certificate =
+            rasn::der::encode(signer.cert()).map_err(|err| anyhow::anyhow!("{}", err))?;
+        Ok(Self {
+            nodes: vec![LineageNode {
+                certificate,
+                parent_algorithm: 0,
+                flags: DEFAULT_CAPABILITIES,
+                algorithm: 0,
+                signature: vec![],
+            }],
+        })
+    }
+
+    /// Rotates the signing key from `current` to `next`. `current` must be
+    /// the last certificate in the lineage.
+    pub fn rotate(&mut self, current: &Signer, next: &Signer) -> Result<()> {
+        let current_cert =
+            rasn::der::encode(current.cert()).map_err(|err| anyhow::anyhow!("{}", err))?;
+        let last = self.nodes.last_mut().expect("lineage is never empty");
+        anyhow::ensure!(
+            last.certificate == current_cert,
+            "current signer is not the last certificate in the lineage"
+        );
+        let certificate =
+            rasn::der::encode(next.cert()).map_err(|err| anyhow::anyhow!("{}", err))?;
+        anyhow::ensure!(
+            !self.contains(&certificate),
+            "certificate is already part of the lineage"
+        );
+        let last = self.nodes.last_mut().unwrap();
+        last.algorithm = signature_algorithm(current);
+        let mut node = LineageNode {
+            certificate,
+            parent_algorithm: last.algorithm,
+            flags: DEFAULT_CAPABILITIES,
+            algorithm: 0,
+            signature: vec![],
+        };
+        node.signature = current.sign(&node.signed_data()?);
+        self.nodes.push(node);
+        Ok(())
+    }
+
+    pub fn nodes(&self) -> &[LineageNode] {
+        &self.nodes
+    }
+
+    /// DER encoded certificate of the original signer.
+    pub fn first(&self) -> &[u8] {
+        &self.nodes[0].certificate
+    }
+
+    /// DER encoded certificate of the current signer.
+    pub fn last(&self) -> &[u8] {
+        &self.nodes[self.nodes.len() - 1].certificate
+    }
+
+    pub fn contains(&self, certificate: &[u8]) -> bool {
+        self.nodes
+            .iter()
+            .any(|node| node.certificate == certificate)
+    }
+
+    /// Checks that every node is signed by its predecessor.
+    pub fn verify(&self) -> Result<()> {
+        for (i, pair) in self.nodes.windows(2).enumerate() {
+            let (parent, node) = (&pair[0], &pair[1]);
+            anyhow::ensure!(
+                parent.algorithm == node.parent_algorithm,
+                "signature algorithm mismatch for certificate #{} in lineage",
+                i + 1
+            );
+            let public_key = rasn::der::encode(
+                &parent
+                    .certificate()?
+                    .tbs_certificate
+                    .subject_public_key_info,
+            )
+            .map_err(|err| anyhow::anyhow!("{}", err))?;
+            verify_signature(
+                node.parent_algorithm,
+                &public_key,
+                &node.signed_data()?,
+                &node.signature,
+            )
+            .map_err(|err| {
+                anyhow::anyhow!(
+                    "invalid signature for certificate #{} in lineage: {}",
+                    i + 1,
+                    err
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn read(r: &mut impl Read) -> Result<Self> {
+        let version = r.read_u32::<LittleEndian>()?;
+        anyhow::ensure!(
+            version == LINEAGE_VERSION,
+            "unsupported lineage version {}",
+            version
+        );
+        let mut nodes = vec![];
+        loop {
+            let length = match r.read_u32::<LittleEndian>() {
+                Ok(length) => length,
+                Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            };
+            let mut node = vec![0; length as usize];
+            r.read_exact(&mut node)?;
+            let mut r = Cursor::new(&node[..]);
+            let _signed_data_size = r.read_u32::<LittleEndian>()?;
+            let certificate_size = r.read_u32::<LittleEndian>()?;
+            let mut certificate = vec![0; certificate_size as usize];
+            r.read_exact(&mut certificate)?;
+            let parent_algorithm = r.read_u32::<LittleEndian>()?;
+            let flags = r.read_u32::<LittleEndian>()?;
+            let algorithm = r.read_u32::<LittleEndian>()?;
+            let signature_size = r.read_u32::<LittleEndian>()?;
+            let mut signature = vec![0; signature_size as usize];
+            r.read_exact(&mut signature)?;
+            nodes.push(LineageNode {
+                certificate,
+                parent_algorithm,
+                flags,
+                algorithm,
+                signature,
+            });
+        }
+        anyhow::ensure!(!nodes.is_empty(), "lineage contains no certificates");
+        Ok(Self { nodes })
+    }
+
+    pub fn write(&self, w: &mut impl Write) -> Result<()> {
+        w.write_u32::<LittleEndian>(LINEAGE_VERSION)?;
+        for node in &self.nodes {
+            let signed_data = node.signed_data()?;
+            w.write_u32::<LittleEndian>(
+                signed_data.len() as u32 + node.signature.len() as u32 + 16,
+            )?;
+            w.write_u32::<LittleEndian>(signed_data.len() as u32)?;
+            w.write_all(&signed_data)?;
+            w.write_u32::<LittleEndian>(node.flags)?;
+            w.write_u32::<LittleEndian>(node.algorithm)?;
+            w.write_u32::<LittleEndian>(node.signature.len() as u32)?;
+            w.write_all(&node.signature)?;
+        }
+        Ok(())
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        Self::read(&mut Cursor::new(bytes))
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.write(&mut buf).unwrap();
+        buf
+    }
+}
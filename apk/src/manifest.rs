@@ -1,7 +1,17 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize, Serializer};
 
+/// Namespace of the `android:` attributes.
+pub(crate) const ANDROID_NS: &str = "http://schemas.android.com/apk/res/android";
+
+/// Namespace of the `tools:` attributes, which are only used at build time.
+pub(crate) const TOOLS_NS: &str = "http://schemas.android.com/tools";
+
 /// Android [manifest element](https://developer.android.com/guide/topics/manifest/manifest-element), containing an [`Application`] element.
+///
+/// The `tools_node`, `tools_replace` and `tools_remove` fields of the
+/// elements are the `tools:` markers controlling how library manifests are
+/// merged into the element, see [`ManifestMerger`](crate::ManifestMerger).
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename = "manifest")]
 #[serde(deny_unknown_fields)]
@@ -9,7 +19,12 @@ pub struct AndroidManifest {
     #[serde(rename(serialize = "xmlns:android"))]
     #[serde(default = "default_namespace")]
     ns_android: String,
+    #[serde(rename(serialize = "xmlns:tools"))]
+    #[serde(default = "default_tools_namespace")]
+    ns_tools: String,
     pub package: Option<String>,
+    /// Name of a split apk, like `config.arm64_v8a`.
+    pub split: Option<String>,
     #[serde(rename(serialize = "android:versionCode"))]
     pub version_code: Option<u32>,
     #[serde(rename(serialize = "android:versionName"))]
@@ -31,6 +46,12 @@ pub struct AndroidManifest {
     #[serde(rename(serialize = "uses-permission"))]
     #[serde(default)]
     pub uses_permission: Vec<Permission>,
+    #[serde(rename(serialize = "uses-permission-sdk-23"))]
+    #[serde(default)]
+    pub uses_permission_sdk_23: Vec<Permission>,
+    #[serde(default)]
+    pub permission: Vec<PermissionDeclaration>,
+    pub queries: Option<Queries>,
     #[serde(default)]
     pub application: Application,
 }
@@ -39,12 +60,17 @@ impl Default for AndroidManifest {
     fn default() -> Self {
         Self {
             ns_android: default_namespace(),
+            ns_tools: default_tools_namespace(),
             package: Default::default(),
+            split: Default::default(),
             version_code: Default::default(),
             version_name: Default::default(),
             sdk: Default::default(),
             uses_feature: Default::default(),
             uses_permission: Default::default(),
+            uses_permission_sdk_23: Default::default(),
+            permission: Default::default(),
+            queries: Default::default(),
             application: Default::default(),
             compile_sdk_version: Default::default(),
             compile_sdk_version_codename: Default::default(),
@@ -60,7 +86,7 @@ impl std::fmt::Display for AndroidManifest {
     }
 }
 
-/// Android [application element](https://developer.android.com/guide/topics/manifest/application-element), containing an [`Activity`] element.
+/// Android [application element](https://developer.android.com/guide/topics/manifest/application-element), containing the components of the app.
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Application {
@@ -76,12 +102,39 @@ pub struct Application {
     pub label: Option<String>,
     #[serde(rename(serialize = "android:appComponentFactory"))]
     pub app_component_factory: Option<String>,
+    /// Set to `false` to load native libraries directly from the apk, which
+    /// requires them to be stored uncompressed and page aligned.
+    #[serde(rename(serialize = "android:extractNativeLibs"))]
+    pub extract_native_libs: Option<bool>,
+    #[serde(rename(serialize = "tools:node"))]
+    pub tools_node: Option<String>,
+    #[serde(rename(serialize = "tools:replace"))]
+    pub tools_replace: Option<String>,
+    #[serde(rename(serialize = "tools:remove"))]
+    pub tools_remove: Option<String>,
     #[serde(rename(serialize = "meta-data"))]
     #[serde(default)]
     pub meta_data: Vec<MetaData>,
     #[serde(rename(serialize = "activity"))]
     #[serde(default)]
     pub activities: Vec<Activity>,
+    /// Aliases need to be declared after their target activity.
+    #[serde(rename(serialize = "activity-alias"))]
+    #[serde(default)]
+    pub activity_aliases: Vec<ActivityAlias>,
+    #[serde(rename(serialize = "service"))]
+    #[serde(default)]
+    pub services: Vec<Service>,
+    #[serde(rename(serialize = "receiver"))]
+    #[serde(default)]
+    pub receivers: Vec<Receiver>,
+    #[serde(rename(serialize = "provider"))]
+    #[serde(default)]
+    pub providers: Vec<Provider>,
+    #[serde(rename(serialize = "uses-library"))]
+    #[serde(default)]
+    pub uses_library: Vec<UsesLibrary>,
+    pub profileable: Option<Profileable>,
 }
 
 /// Android [activity element](https://developer.android.com/guide/topics/manifest/activity-element).
@@ -104,6 +157,12 @@ pub struct Activity {
     pub exported: Option<bool>,
     #[serde(rename(serialize = "android:hardwareAccelerated"))]
     pub hardware_accelerated: Option<bool>,
+    #[serde(rename(serialize = "tools:node"))]
+    pub tools_node: Option<String>,
+    #[serde(rename(serialize = "tools:replace"))]
+    pub tools_replace: Option<String>,
+    #[serde(rename(serialize = "tools:remove"))]
+    pub tools_remove: Option<String>,
     #[serde(rename(serialize = "meta-data"))]
     #[serde(default)]
     pub meta_data: Vec<MetaData>,
@@ -115,6 +174,227 @@ pub struct Activity {
     pub color_mode: Option<String>,
 }
 
+/// Android [activity-alias element](https://developer.android.com/guide/topics/manifest/activity-alias-element).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ActivityAlias {
+    #[serde(rename(serialize = "android:name"))]
+    pub name: Option<String>,
+    #[serde(rename(serialize = "android:targetActivity"))]
+    pub target_activity: Option<String>,
+    #[serde(rename(serialize = "android:label"))]
+    pub label: Option<String>,
+    #[serde(rename(serialize = "android:icon"))]
+    pub icon: Option<String>,
+    #[serde(rename(serialize = "android:enabled"))]
+    pub enabled: Option<bool>,
+    #[serde(rename(serialize = "android:exported"))]
+    pub exported: Option<bool>,
+    #[serde(rename(serialize = "tools:node"))]
+    pub tools_node: Option<String>,
+    #[serde(rename(serialize = "tools:replace"))]
+    pub tools_replace: Option<String>,
+    #[serde(rename(serialize = "tools:remove"))]
+    pub tools_remove: Option<String>,
+    #[serde(rename(serialize = "meta-data"))]
+    #[serde(default)]
+    pub meta_data: Vec<MetaData>,
+    #[serde(rename(serialize = "intent-filter"))]
+    #[serde(default)]
+    pub intent_filters: Vec<IntentFilter>,
+}
+
+/// Android [service element](https://developer.android.com/guide/topics/manifest/service-element).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Service {
+    #[serde(rename(serialize = "android:name"))]
+    pub name: Option<String>,
+    #[serde(rename(serialize = "android:label"))]
+    pub label: Option<String>,
+    #[serde(rename(serialize = "android:icon"))]
+    pub icon: Option<String>,
+    #[serde(rename(serialize = "android:enabled"))]
+    pub enabled: Option<bool>,
+    #[serde(rename(serialize = "android:exported"))]
+    pub exported: Option<bool>,
+    /// Types of a foreground service separated by `|`, like
+    /// `mediaPlayback|location`. Required by Android 14 for foreground
+    /// services.
+    #[serde(rename(serialize = "android:foregroundServiceType"))]
+    pub foreground_service_type: Option<String>,
+    #[serde(rename(serialize = "android:permission"))]
+    pub permission: Option<String>,
+    #[serde(rename(serialize = "android:process"))]
+    pub process: Option<String>,
+    #[serde(rename(serialize = "android:stopWithTask"))]
+    pub stop_with_task: Option<bool>,
+    #[serde(rename(serialize = "tools:node"))]
+    pub tools_node: Option<String>,
+    #[serde(rename(serialize = "tools:replace"))]
+    pub tools_replace: Option<String>,
+    #[serde(rename(serialize = "tools:remove"))]
+    pub tools_remove: Option<String>,
+    #[serde(rename(serialize = "meta-data"))]
+    #[serde(default)]
+    pub meta_data: Vec<MetaData>,
+    #[serde(rename(serialize = "intent-filter"))]
+    #[serde(default)]
+    pub intent_filters: Vec<IntentFilter>,
+}
+
+/// Android [receiver element](https://developer.android.com/guide/topics/manifest/receiver-element).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Receiver {
+    #[serde(rename(serialize = "android:name"))]
+    pub name: Option<String>,
+    #[serde(rename(serialize = "android:label"))]
+    pub label: Option<String>,
+    #[serde(rename(serialize = "android:enabled"))]
+    pub enabled: Option<bool>,
+    #[serde(rename(serialize = "android:exported"))]
+    pub exported: Option<bool>,
+    #[serde(rename(serialize = "android:permission"))]
+    pub permission: Option<String>,
+    #[serde(rename(serialize = "android:process"))]
+    pub process: Option<String>,
+    #[serde(rename(serialize = "tools:node"))]
+    pub tools_node: Option<String>,
+    #[serde(rename(serialize = "tools:replace"))]
+    pub tools_replace: Option<String>,
+    #[serde(rename(serialize = "tools:remove"))]
+    pub tools_remove: Option<String>,
+    #[serde(rename(serialize = "meta-data"))]
+    #[serde(default)]
+    pub meta_data: Vec<MetaData>,
+    #[serde(rename(serialize = "intent-filter"))]
+    #[serde(default)]
+    pub intent_filters: Vec<IntentFilter>,
+}
+
+/// Android [provider element](https://developer.android.com/guide/topics/manifest/provider-element).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Provider {
+    #[serde(rename(serialize = "android:name"))]
+    pub name: Option<String>,
+    /// Authorities separated by `;`, like `com.example.fileprovider`.
+    #[serde(rename(serialize = "android:authorities"))]
+    pub authorities: Option<String>,
+    #[serde(rename(serialize = "android:enabled"))]
+    pub enabled: Option<bool>,
+    #[serde(rename(serialize = "android:exported"))]
+    pub exported: Option<bool>,
+    #[serde(rename(serialize = "android:grantUriPermissions"))]
+    pub grant_uri_permissions: Option<bool>,
+    #[serde(rename(serialize = "android:permission"))]
+    pub permission: Option<String>,
+    #[serde(rename(serialize = "android:readPermission"))]
+    pub read_permission: Option<String>,
+    #[serde(rename(serialize = "android:writePermission"))]
+    pub write_permission: Option<String>,
+    #[serde(rename(serialize = "android:process"))]
+    pub process: Option<String>,
+    #[serde(rename(serialize = "tools:node"))]
+    pub tools_node: Option<String>,
+    #[serde(rename(serialize = "tools:replace"))]
+    pub tools_replace: Option<String>,
+    #[serde(rename(serialize = "tools:remove"))]
+    pub tools_remove: Option<String>,
+    /// A `FileProvider` needs a `android.support.FILE_PROVIDER_PATHS`
+    /// entry referencing its paths, like `@xml/file_paths`.
+    #[serde(rename(serialize = "meta-data"))]
+    #[serde(default)]
+    pub meta_data: Vec<MetaData>,
+}
+
+/// Android [uses-library element](https://developer.android.com/guide/topics/manifest/uses-library-element).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct UsesLibrary {
+    #[serde(rename(serialize = "android:name"))]
+    pub name: String,
+    #[serde(rename(serialize = "android:required"))]
+    pub required: Option<bool>,
+    #[serde(rename(serialize = "tools:node"))]
+    pub tools_node: Option<String>,
+    #[serde(rename(serialize = "tools:replace"))]
+    pub tools_replace: Option<String>,
+    #[serde(rename(serialize = "tools:remove"))]
+    pub tools_remove: Option<String>,
+}
+
+/// Android [profileable element](https://developer.android.com/guide/topics/manifest/profileable-element).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profileable {
+    #[serde(rename(serialize = "android:shell"))]
+    pub shell: Option<bool>,
+    #[serde(rename(serialize = "android:enabled"))]
+    pub enabled: Option<bool>,
+}
+
+/// Android [queries element](https://developer.android.com/guide/topics/manifest/queries-element),
+/// declaring the other apps the app interacts with.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Queries {
+    /// Serialize package names wrapped in `<package android:name="..." />`
+    #[serde(serialize_with = "serialize_packages")]
+    #[serde(rename(serialize = "package"))]
+    #[serde(default)]
+    pub packages: Vec<String>,
+    #[serde(rename(serialize = "intent"))]
+    #[serde(default)]
+    pub intents: Vec<IntentFilter>,
+    /// Serialize authorities wrapped in `<provider android:authorities="..." />`
+    #[serde(serialize_with = "serialize_providers")]
+    #[serde(rename(serialize = "provider"))]
+    #[serde(default)]
+    pub providers: Vec<String>,
+}
+
+fn serialize_packages<S>(packages: &[String], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    use serde::ser::SerializeSeq;
+
+    #[derive(Serialize)]
+    struct Package {
+        #[serde(rename = "android:name")]
+        name: String,
+    }
+    let mut seq = serializer.serialize_seq(Some(packages.len()))?;
+    for package in packages {
+        seq.serialize_element(&Package {
+            name: package.clone(),
+        })?;
+    }
+    seq.end()
+}
+
+fn serialize_providers<S>(providers: &[String], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    use serde::ser::SerializeSeq;
+
+    #[derive(Serialize)]
+    struct Provider {
+        #[serde(rename = "android:authorities")]
+        authorities: String,
+    }
+    let mut seq = serializer.serialize_seq(Some(providers.len()))?;
+    for provider in providers {
+        seq.serialize_element(&Provider {
+            authorities: provider.clone(),
+        })?;
+    }
+    seq.end()
+}
+
 /// Android [intent filter element](https://developer.android.com/guide/topics/manifest/intent-filter-element).
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -201,7 +481,19 @@ pub struct MetaData {
     #[serde(rename(serialize = "android:name"))]
     pub name: String,
     #[serde(rename(serialize = "android:value"))]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
     pub value: String,
+    /// Reference to a resource like `@xml/file_paths`, used instead of a
+    /// value.
+    #[serde(rename(serialize = "android:resource"))]
+    pub resource: Option<String>,
+    #[serde(rename(serialize = "tools:node"))]
+    pub tools_node: Option<String>,
+    #[serde(rename(serialize = "tools:replace"))]
+    pub tools_replace: Option<String>,
+    #[serde(rename(serialize = "tools:remove"))]
+    pub tools_remove: Option<String>,
 }
 
 /// Android [uses-feature element](https://developer.android.com/guide/topics/manifest/uses-feature-element).
@@ -221,12 +513,18 @@ pub struct Feature {
     ///   for available levels and the respective Vulkan features required/provided.
     ///
     /// - `name="android.hardware.vulkan.version"`: Represents the value of Vulkan's `VkPhysicalDeviceProperties::apiVersion`. See the [Android documentation](https://developer.android.com/reference/android/content/pm/PackageManager#FEATURE_VULKAN_HARDWARE_VERSION)
-    ///    for available levels and the respective Vulkan features required/provided.
+    ///   for available levels and the respective Vulkan features required/provided.
     #[serde(rename(serialize = "android:version"))]
     pub version: Option<u32>,
     #[serde(rename(serialize = "android:glEsVersion"))]
     #[serde(serialize_with = "serialize_opengles_version")]
     pub opengles_version: Option<(u8, u8)>,
+    #[serde(rename(serialize = "tools:node"))]
+    pub tools_node: Option<String>,
+    #[serde(rename(serialize = "tools:replace"))]
+    pub tools_replace: Option<String>,
+    #[serde(rename(serialize = "tools:remove"))]
+    pub tools_remove: Option<String>,
 }
 
 fn serialize_opengles_version<S>(
@@ -246,13 +544,44 @@ where
 }
 
 /// Android [uses-permission element](https://developer.android.com/guide/topics/manifest/uses-permission-element).
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Permission {
     #[serde(rename(serialize = "android:name"))]
     pub name: String,
     #[serde(rename(serialize = "android:maxSdkVersion"))]
     pub max_sdk_version: Option<u32>,
+    #[serde(rename(serialize = "tools:node"))]
+    pub tools_node: Option<String>,
+    #[serde(rename(serialize = "tools:replace"))]
+    pub tools_replace: Option<String>,
+    #[serde(rename(serialize = "tools:remove"))]
+    pub tools_remove: Option<String>,
+}
+
+/// Android [permission element](https://developer.android.com/guide/topics/manifest/permission-element),
+/// declaring a permission that protects components of the app.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PermissionDeclaration {
+    #[serde(rename(serialize = "android:name"))]
+    pub name: String,
+    #[serde(rename(serialize = "android:protectionLevel"))]
+    pub protection_level: Option<String>,
+    #[serde(rename(serialize = "android:permissionGroup"))]
+    pub permission_group: Option<String>,
+    #[serde(rename(serialize = "android:label"))]
+    pub label: Option<String>,
+    #[serde(rename(serialize = "android:description"))]
+    pub description: Option<String>,
+    #[serde(rename(serialize = "android:icon"))]
+    pub icon: Option<String>,
+    #[serde(rename(serialize = "tools:node"))]
+    pub tools_node: Option<String>,
+    #[serde(rename(serialize = "tools:replace"))]
+    pub tools_replace: Option<String>,
+    #[serde(rename(serialize = "tools:remove"))]
+    pub tools_remove: Option<String>,
 }
 
 /// Android [uses-sdk element](https://developer.android.com/guide/topics/manifest/uses-sdk-element).
@@ -268,5 +597,203 @@ pub struct Sdk {
 }
 
 fn default_namespace() -> String {
-    "http://schemas.android.com/apk/res/android".to_string()
+    ANDROID_NS.to_string()
+}
+
+fn default_tools_namespace() -> String {
+    TOOLS_NS.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_components() {
+        let mut manifest = AndroidManifest {
+            queries: Some(Queries {
+                packages: vec!["com.example.other".into()],
+                intents: vec![IntentFilter {
+                    actions: vec!["android.intent.action.SEND".into()],
+                    data: vec![IntentFilterData {
+                        mime_type: Some("image/*".into()),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                providers: vec![],
+            }),
+            ..Default::default()
+        };
+        let app = &mut manifest.application;
+        app.services.push(Service {
+            name: Some(".AudioService".into()),
+            foreground_service_type: Some("mediaPlayback".into()),
+            exported: Some(false),
+            ..Default::default()
+        });
+        app.providers.push(Provider {
+            name: Some("androidx.core.content.FileProvider".into()),
+            authorities: Some("com.example.fileprovider".into()),
+            meta_data: vec![MetaData {
+                name: "android.support.FILE_PROVIDER_PATHS".into(),
+                resource: Some("@xml/file_paths".into()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        app.profileable = Some(Profileable {
+            shell: Some(true),
+            ..Default::default()
+        });
+        let xml = manifest.to_string();
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+        let element = |name| {
+            doc.descendants()
+                .find(|node| node.has_tag_name(name))
+                .unwrap()
+        };
+        fn android<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+            node.attribute((ANDROID_NS, name))
+        }
+        let package = element("package");
+        assert_eq!(package.parent().unwrap().tag_name().name(), "queries");
+        assert_eq!(android(package, "name"), Some("com.example.other"));
+        assert_eq!(android(element("data"), "mimeType"), Some("image/*"));
+        let service = element("service");
+        assert_eq!(
+            android(service, "foregroundServiceType"),
+            Some("mediaPlayback")
+        );
+        assert_eq!(android(service, "exported"), Some("false"));
+        let meta_data = element("meta-data");
+        assert_eq!(meta_data.parent().unwrap().tag_name().name(), "provider");
+        assert_eq!(android(meta_data, "resource"), Some("@xml/file_paths"));
+        assert_eq!(android(meta_data, "value"), None);
+        assert_eq!(android(element("profileable"), "shell"), Some("true"));
+        assert!(!doc.descendants().any(|node| node.has_tag_name("receiver")));
+    }
+
+    #[test]
+    fn test_component_order() {
+        let mut manifest = AndroidManifest {
+            queries: Some(Queries {
+                providers: vec!["com.example.other.provider".into()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let app = &mut manifest.application;
+        app.activities.push(Activity {
+            name: Some(".MainActivity".into()),
+            ..Default::default()
+        });
+        app.activity_aliases.push(ActivityAlias {
+            name: Some(".Launcher".into()),
+            target_activity: Some(".MainActivity".into()),
+            enabled: Some(true),
+            ..Default::default()
+        });
+        app.services.push(Service {
+            name: Some(".SyncService".into()),
+            ..Default::default()
+        });
+        app.receivers.push(Receiver {
+            name: Some(".BootReceiver".into()),
+            exported: Some(true),
+            intent_filters: vec![IntentFilter {
+                actions: vec!["android.intent.action.BOOT_COMPLETED".into()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        app.providers.push(Provider {
+            name: Some(".DataProvider".into()),
+            authorities: Some("com.example.data".into()),
+            grant_uri_permissions: Some(true),
+            ..Default::default()
+        });
+        app.uses_library.push(UsesLibrary {
+            name: "org.apache.http.legacy".into(),
+            required: Some(false),
+            ..Default::default()
+        });
+        app.profileable = Some(Profileable {
+            shell: Some(true),
+            enabled: Some(true),
+        });
+        let xml = manifest.to_string();
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+        let android =
+            |node: roxmltree::Node, name| node.attribute((ANDROID_NS, name)).map(str::to_string);
+        let application = doc
+            .descendants()
+            .find(|node| node.has_tag_name("application"))
+            .unwrap();
+        let children = application
+            .children()
+            .filter(|node| node.is_element())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            children
+                .iter()
+                .map(|node| node.tag_name().name())
+                .collect::<Vec<_>>(),
+            [
+                "activity",
+                "activity-alias",
+                "service",
+                "receiver",
+                "provider",
+                "uses-library",
+                "profileable"
+            ]
+        );
+        let alias = children[1];
+        assert_eq!(
+            android(alias, "targetActivity").as_deref(),
+            Some(".MainActivity")
+        );
+        assert_eq!(android(alias, "enabled").as_deref(), Some("true"));
+        let receiver = children[3];
+        assert_eq!(android(receiver, "name").as_deref(), Some(".BootReceiver"));
+        assert_eq!(android(receiver, "exported").as_deref(), Some("true"));
+        let action = receiver
+            .descendants()
+            .find(|node| node.has_tag_name("action"))
+            .unwrap();
+        assert_eq!(
+            android(action, "name").as_deref(),
+            Some("android.intent.action.BOOT_COMPLETED")
+        );
+        let provider = children[4];
+        assert_eq!(
+            android(provider, "authorities").as_deref(),
+            Some("com.example.data")
+        );
+        assert_eq!(
+            android(provider, "grantUriPermissions").as_deref(),
+            Some("true")
+        );
+        let library = children[5];
+        assert_eq!(
+            android(library, "name").as_deref(),
+            Some("org.apache.http.legacy")
+        );
+        assert_eq!(android(library, "required").as_deref(), Some("false"));
+        assert_eq!(android(children[6], "enabled").as_deref(), Some("true"));
+        let queries = doc
+            .descendants()
+            .find(|node| node.has_tag_name("queries"))
+            .unwrap();
+        let provider = queries.first_element_child().unwrap();
+        assert_eq!(provider.tag_name().name(), "provider");
+        assert_eq!(
+            android(provider, "authorities").as_deref(),
+            Some("com.example.other.provider")
+        );
+        assert!(!queries
+            .descendants()
+            .any(|node| node.has_tag_name("package")));
+    }
 }
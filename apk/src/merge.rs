@@ -0,0 +1,702 @@
+//! Merges library manifests into the app manifest following the rules of
+//! the android gradle plugin's manifest merger. Elements are matched by
+//! their name and key attribute, attributes of matching elements are
+//! merged and conflicting values are reported unless the higher priority
+//! element resolves them with `tools:replace`. The app manifest has the
+//! highest priority, followed by the libraries in the order they are
+//! merged. Its markers are set with the `tools_*` fields of the manifest
+//! model.
+
+use crate::compiler::XmlElement;
+use crate::manifest::{AndroidManifest, ANDROID_NS, TOOLS_NS};
+use anyhow::{Context, Result};
+use roxmltree::{Document, Node as XmlNode};
+use std::path::Path;
+
+/// Name of the app manifest in conflict reports.
+const APP: &str = "the app manifest";
+
+/// Index of the app manifest in the sources.
+const APP_SOURCE: usize = 0;
+
+/// Merge behaviour of an element set with `tools:node`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Marker {
+    Merge,
+    MergeOnlyAttributes,
+    Replace,
+    Remove,
+    RemoveAll,
+    Strict,
+}
+
+impl std::str::FromStr for Marker {
+    type Err = anyhow::Error;
+
+    fn from_str(marker: &str) -> Result<Self> {
+        Ok(match marker {
+            "merge" => Self::Merge,
+            "merge-only-attributes" => Self::MergeOnlyAttributes,
+            "replace" => Self::Replace,
+            "remove" => Self::Remove,
+            "removeAll" => Self::RemoveAll,
+            "strict" => Self::Strict,
+            _ => anyhow::bail!("invalid tools:node=\"{}\"", marker),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Attribute {
+    name: String,
+    value: String,
+    /// Index of the manifest the value comes from.
+    source: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    name: String,
+    attributes: Vec<Attribute>,
+    children: Vec<Node>,
+    marker: Marker,
+    /// Attributes set with `tools:replace`.
+    replace: Vec<String>,
+    /// Attributes set with `tools:remove`.
+    remove: Vec<String>,
+    source: usize,
+}
+
+impl Node {
+    fn parse(node: XmlNode, source: usize) -> Result<Self> {
+        let mut element = Self {
+            name: node.tag_name().name().to_string(),
+            attributes: vec![],
+            children: vec![],
+            marker: Marker::Merge,
+            replace: vec![],
+            remove: vec![],
+            source,
+        };
+        let list = |value: &str| {
+            value
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .collect::<Vec<_>>()
+        };
+        for attr in node.attributes() {
+            let name = match attr.namespace() {
+                Some(TOOLS_NS) => {
+                    match attr.name() {
+                        "node" => element.marker = attr.value().parse()?,
+                        "replace" => element.replace = list(attr.value()),
+                        "remove" => element.remove = list(attr.value()),
+                        _ => {}
+                    }
+                    continue;
+                }
+                Some(ANDROID_NS) => format!("android:{}", attr.name()),
+                Some(ns) => match node.lookup_prefix(ns) {
+                    Some(prefix) => format!("{}:{}", prefix, attr.name()),
+                    None => attr.name().to_string(),
+                },
+                None => attr.name().to_string(),
+            };
+            element.attributes.push(Attribute {
+                name,
+                value: attr.value().to_string(),
+                source,
+            });
+        }
+        for child in node.children().filter(|child| child.is_element()) {
+            element.children.push(Self::parse(child, source)?);
+        }
+        Ok(element)
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attr| attr.name == name)
+            .map(|attr| attr.value.as_str())
+    }
+
+    /// Key identifying an element among its siblings with the same name.
+    /// Elements without a key attribute like `<application>` exist once,
+    /// intent filters have no key and are matched by their content.
+    fn key(&self) -> Option<&str> {
+        match self.name.as_str() {
+            "intent-filter" | "intent" | "data" => None,
+            _ => Some(
+                ["android:name", "android:authorities", "android:glEsVersion"]
+                    .iter()
+                    .find_map(|name| self.attribute(name))
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self.key().filter(|key| !key.is_empty()) {
+            Some(key) => format!("<{} {}>", self.name, key),
+            None => format!("<{}>", self.name),
+        }
+    }
+
+    /// Compares elements ignoring where they come from.
+    fn same(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.attributes.len() == other.attributes.len()
+            && self
+                .attributes
+                .iter()
+                .all(|attr| other.attribute(&attr.name) == Some(&attr.value))
+            && self.children.len() == other.children.len()
+            && self
+                .children
+                .iter()
+                .zip(&other.children)
+                .all(|(a, b)| a.same(b))
+    }
+
+    fn visit_attributes(
+        &mut self,
+        f: &mut impl FnMut(&str, &mut Attribute) -> Result<()>,
+    ) -> Result<()> {
+        for attr in &mut self.attributes {
+            f(&self.name, attr)?;
+        }
+        for child in &mut self.children {
+            child.visit_attributes(f)?;
+        }
+        Ok(())
+    }
+
+    /// Merges the lower priority element `lower` into this element.
+    fn merge(&mut self, lower: Node, sources: &[String]) -> Result<()> {
+        match self.marker {
+            Marker::Remove | Marker::RemoveAll | Marker::Replace => return Ok(()),
+            Marker::Strict => {
+                anyhow::ensure!(
+                    self.same(&lower),
+                    "{} from {} is declared differently in {}, but is marked with tools:node=\"strict\"",
+                    self.describe(),
+                    sources[self.source],
+                    sources[lower.source]
+                );
+                return Ok(());
+            }
+            Marker::Merge | Marker::MergeOnlyAttributes => {}
+        }
+        for attr in lower.attributes {
+            if self.remove.contains(&attr.name) {
+                continue;
+            }
+            let existing =
+                if let Some(existing) = self.attributes.iter().find(|a| a.name == attr.name) {
+                    existing
+                } else {
+                    self.attributes.push(attr);
+                    continue;
+                };
+            if existing.value == attr.value || self.replace.contains(&attr.name) {
+                continue;
+            }
+            anyhow::bail!(
+                "Attribute {}@{} value=({}) from {} is also present at {} value=({}). \
+                 Add tools:replace=\"{}\" to the {} element of {} to override it.",
+                self.describe(),
+                attr.name,
+                existing.value,
+                sources[existing.source],
+                sources[attr.source],
+                attr.value,
+                attr.name,
+                self.describe(),
+                sources[existing.source],
+            );
+        }
+        if self.marker == Marker::MergeOnlyAttributes {
+            return Ok(());
+        }
+        self.merge_children(lower.children, sources)
+    }
+
+    fn merge_children(&mut self, children: Vec<Node>, sources: &[String]) -> Result<()> {
+        for child in children {
+            let removed = self
+                .children
+                .iter()
+                .any(|c| c.name == child.name && c.marker == Marker::RemoveAll);
+            if removed {
+                continue;
+            }
+            if child.key().is_none() {
+                if !self.children.iter().any(|c| c.same(&child)) {
+                    self.children.push(child);
+                }
+                continue;
+            }
+            let existing = self
+                .children
+                .iter_mut()
+                .find(|c| c.name == child.name && c.key() == child.key());
+            if let Some(existing) = existing {
+                existing.merge(child, sources)?;
+            } else {
+                self.children.push(child);
+            }
+        }
+        Ok(())
+    }
+
+    /// Collects the elements and attributes of this element which are
+    /// missing in `kept`, the element after converting the merged manifest
+    /// to the manifest model. Elements are matched by name and position.
+    fn dropped(&self, kept: &Node, sources: &[String], dropped: &mut Vec<String>) {
+        for attr in &self.attributes {
+            if !self.remove.contains(&attr.name) && kept.attribute(&attr.name).is_none() {
+                dropped.push(format!(
+                    "attribute {}@{} from {}",
+                    self.describe(),
+                    attr.name,
+                    sources[attr.source]
+                ));
+            }
+        }
+        let mut seen: Vec<&str> = vec![];
+        for child in &self.children {
+            if matches!(child.marker, Marker::Remove | Marker::RemoveAll) {
+                continue;
+            }
+            let index = seen.iter().filter(|name| **name == child.name).count();
+            seen.push(&child.name);
+            match kept
+                .children
+                .iter()
+                .filter(|c| c.name == child.name)
+                .nth(index)
+            {
+                Some(kept) => child.dropped(kept, sources, dropped),
+                None => dropped.push(format!(
+                    "element {} from {}",
+                    child.describe(),
+                    sources[child.source]
+                )),
+            }
+        }
+    }
+
+    /// Drops removed elements and tools markers.
+    fn into_element(self) -> Option<XmlElement> {
+        if matches!(self.marker, Marker::Remove | Marker::RemoveAll) {
+            return None;
+        }
+        Some(XmlElement {
+            name: self.name,
+            attributes: self
+                .attributes
+                .into_iter()
+                .filter(|attr| !self.remove.contains(&attr.name))
+                .map(|attr| (attr.name, attr.value))
+                .collect(),
+            children: self
+                .children
+                .into_iter()
+                .filter_map(Node::into_element)
+                .collect(),
+            text: String::new(),
+        })
+    }
+}
+
+/// Merges the manifests of libraries, like the `AndroidManifest.xml` of
+/// an AAR, into the app manifest. Libraries are merged in priority order.
+///
+/// `${applicationId}` and `${packageName}` placeholders are replaced with
+/// the app package, other placeholders are kept with a warning. Relative
+/// class names of library components are resolved with the library
+/// package. Elements and attributes which aren't part of
+/// [`AndroidManifest`] are dropped with a warning when finishing.
+pub struct ManifestMerger {
+    root: Node,
+    package: String,
+    min_sdk: u32,
+    sources: Vec<String>,
+}
+
+impl ManifestMerger {
+    pub fn new(manifest: &AndroidManifest) -> Result<Self> {
+        let package = manifest
+            .package
+            .clone()
+            .context("the app manifest has no package")?;
+        let xml = quick_xml::se::to_string(manifest)?;
+        let doc = Document::parse(&xml)?;
+        let root = Node::parse(doc.root_element(), APP_SOURCE)?;
+        Ok(Self {
+            root,
+            package,
+            min_sdk: manifest.sdk.min_sdk_version.unwrap_or(1),
+            sources: vec![APP.into()],
+        })
+    }
+
+    /// Merges the library manifest at `path`.
+    pub fn merge_file(&mut self, path: &Path) -> Result<()> {
+        let xml = std::fs::read_to_string(path)?;
+        self.merge(&path.display().to_string(), &xml)
+            .with_context(|| format!("failed to merge {}", path.display()))
+    }
+
+    /// Merges the library manifest `xml`. Conflicts are reported as coming
+    /// from `source`.
+    pub fn merge(&mut self, source: &str, xml: &str) -> Result<()> {
+        let doc = Document::parse(xml)?;
+        let index = self.sources.len();
+        let mut library = Node::parse(doc.root_element(), index)?;
+        anyhow::ensure!(
+            library.name == "manifest",
+            "expected <manifest> found <{}>",
+            library.name
+        );
+        let library_package = library.attribute("package").unwrap_or_default().to_string();
+        let package = &self.package;
+        library.visit_attributes(&mut |element, attr| {
+            attr.value = replace_placeholders(&attr.value, package)?;
+            let is_class = attr.name == "android:name"
+                && matches!(
+                    element,
+                    "application"
+                        | "activity"
+                        | "activity-alias"
+                        | "service"
+                        | "receiver"
+                        | "provider"
+                )
+                || attr.name == "android:targetActivity";
+            if is_class && !library_package.is_empty() {
+                attr.value = resolve_class(&library_package, &attr.value);
+            }
+            Ok(())
+        })?;
+        if let Some(sdk) = library.children.iter().find(|c| c.name == "uses-sdk") {
+            let min_sdk = sdk
+                .attribute("android:minSdkVersion")
+                .map(|v| v.parse::<u32>())
+                .transpose()
+                .context("invalid minSdkVersion")?
+                .unwrap_or(1);
+            anyhow::ensure!(
+                min_sdk <= self.min_sdk,
+                "{} requires minSdkVersion {}, but the app declares {}",
+                source,
+                min_sdk,
+                self.min_sdk
+            );
+        }
+        library.children.retain(|c| c.name != "uses-sdk");
+        self.sources.push(source.to_string());
+        self.root.merge_children(library.children, &self.sources)
+    }
+
+    pub fn finish(self) -> Result<AndroidManifest> {
+        let (manifest, dropped) = self.convert()?;
+        for dropped in dropped {
+            tracing::warn!("dropping unsupported manifest {}", dropped);
+        }
+        Ok(manifest)
+    }
+
+    /// Converts the merged manifest to the manifest model and returns the
+    /// elements and attributes the model doesn't support.
+    fn convert(self) -> Result<(AndroidManifest, Vec<String>)> {
+        let root = self
+            .root
+            .clone()
+            .into_element()
+            .context("the app manifest was removed")?;
+        let manifest = crate::compiler::manifest_from_element(&root)?;
+        let xml = quick_xml::se::to_string(&manifest)?;
+        let doc = Document::parse(&xml)?;
+        let kept = Node::parse(doc.root_element(), APP_SOURCE)?;
+        let mut dropped = vec![];
+        self.root.dropped(&kept, &self.sources, &mut dropped);
+        Ok((manifest, dropped))
+    }
+}
+
+fn replace_placeholders(value: &str, package: &str) -> Result<String> {
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..]
+            .find('}')
+            .with_context(|| format!("unterminated placeholder in {}", value))?;
+        let placeholder = &rest[start + 2..start + end];
+        result.push_str(&rest[..start]);
+        match placeholder {
+            "applicationId" | "packageName" => result.push_str(package),
+            _ => {
+                tracing::warn!("unknown placeholder ${{{}}} in {}", placeholder, value);
+                result.push_str(&rest[start..start + end + 1]);
+            }
+        }
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Resolves class names relative to the package of the library like
+/// `.Service` or `Service`.
+fn resolve_class(package: &str, name: &str) -> String {
+    if name.starts_with('.') {
+        format!("{}{}", package, name)
+    } else if !name.contains('.') {
+        format!("{}.{}", package, name)
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{Activity, MetaData, Permission, Provider};
+
+    fn app() -> AndroidManifest {
+        let mut manifest = AndroidManifest::default();
+        manifest.package = Some("com.example.app".into());
+        manifest.sdk.min_sdk_version = Some(21);
+        manifest.uses_permission.push(Permission {
+            name: "android.permission.INTERNET".into(),
+            ..Default::default()
+        });
+        manifest.application.label = Some("app".into());
+        manifest.application.tools_replace = Some("android:label".into());
+        manifest.application.activities.push(Activity {
+            name: Some("android.app.NativeActivity".into()),
+            ..Default::default()
+        });
+        manifest.application.providers.push(Provider {
+            name: Some("androidx.startup.InitializationProvider".into()),
+            authorities: Some("com.example.app.androidx-startup".into()),
+            meta_data: vec![MetaData {
+                name: "com.example.app.Initializer".into(),
+                value: "androidx.startup".into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        manifest
+    }
+
+    const CORE: &str = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android"
+        package="androidx.core">
+        <uses-sdk android:minSdkVersion="19"/>
+        <uses-permission android:name="android.permission.INTERNET"/>
+        <uses-permission android:name="${applicationId}.DYNAMIC_RECEIVER_NOT_EXPORTED_PERMISSION"/>
+        <application android:appComponentFactory="androidx.core.app.CoreComponentFactory"
+            android:label="core">
+            <service android:name=".Service" android:exported="false"/>
+        </application>
+    </manifest>"#;
+
+    const EMOJI: &str = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android"
+        xmlns:tools="http://schemas.android.com/tools" package="androidx.emoji2">
+        <application>
+            <provider android:name="androidx.startup.InitializationProvider"
+                android:authorities="${applicationId}.androidx-startup"
+                android:exported="false" tools:node="merge">
+                <meta-data android:name="androidx.emoji2.text.EmojiCompatInitializer"
+                    android:value="androidx.startup"/>
+            </provider>
+            <service android:name="androidx.core.Service" android:exported="true"/>
+        </application>
+    </manifest>"#;
+
+    #[test]
+    fn test_merge() -> Result<()> {
+        let mut merger = ManifestMerger::new(&app())?;
+        merger.merge("androidx.core", CORE)?;
+        let manifest = merger.finish()?;
+        assert_eq!(manifest.package.as_deref(), Some("com.example.app"));
+        assert_eq!(manifest.sdk.min_sdk_version, Some(21));
+        let permissions = manifest
+            .uses_permission
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            permissions,
+            [
+                "android.permission.INTERNET",
+                "com.example.app.DYNAMIC_RECEIVER_NOT_EXPORTED_PERMISSION"
+            ]
+        );
+        let app = &manifest.application;
+        // the app manifest takes precedence
+        assert_eq!(app.label.as_deref(), Some("app"));
+        assert_eq!(
+            app.app_component_factory.as_deref(),
+            Some("androidx.core.app.CoreComponentFactory")
+        );
+        assert_eq!(app.activities.len(), 1);
+        assert_eq!(
+            app.services[0].name.as_deref(),
+            Some("androidx.core.Service")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_markers() -> Result<()> {
+        let mut merger = ManifestMerger::new(&app())?;
+        merger.merge("androidx.emoji2", EMOJI)?;
+        let manifest = merger.finish()?;
+        let provider = &manifest.application.providers[0];
+        assert_eq!(manifest.application.providers.len(), 1);
+        assert_eq!(provider.exported, Some(false));
+        assert_eq!(provider.meta_data.len(), 2);
+
+        let mut merger = ManifestMerger::new(&app())?;
+        merger.merge("androidx.core", CORE)?;
+        let err = merger.merge("androidx.emoji2", EMOJI).unwrap_err();
+        let err = err.to_string();
+        assert!(err.contains("android:exported"), "{}", err);
+        assert!(err.contains("androidx.core"), "{}", err);
+        assert!(err.contains("tools:replace"), "{}", err);
+
+        let remove = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android"
+            xmlns:tools="http://schemas.android.com/tools" package="com.example.lib">
+            <uses-permission android:name="android.permission.CAMERA" tools:node="remove"/>
+            <application>
+                <service android:name="androidx.core.Service" tools:replace="android:exported"
+                    android:exported="true"/>
+            </application>
+        </manifest>"#;
+        let camera = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android"
+            package="com.example.camera">
+            <uses-permission android:name="android.permission.CAMERA"/>
+        </manifest>"#;
+        let mut merger = ManifestMerger::new(&app())?;
+        merger.merge("lib", remove)?;
+        merger.merge("androidx.core", CORE)?;
+        merger.merge("camera", camera)?;
+        let manifest = merger.finish()?;
+        assert!(!manifest
+            .uses_permission
+            .iter()
+            .any(|p| p.name == "android.permission.CAMERA"));
+        assert_eq!(manifest.application.services[0].exported, Some(true));
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_app_markers() -> Result<()> {
+        let mut manifest = app();
+        manifest.application.tools_replace = None;
+        let mut merger = ManifestMerger::new(&manifest)?;
+        let err = merger.merge("androidx.core", CORE).unwrap_err().to_string();
+        assert!(err.contains("android:label"), "{}", err);
+        assert!(err.contains("tools:replace"), "{}", err);
+
+        let camera = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android"
+            package="com.example.camera">
+            <uses-permission android:name="android.permission.CAMERA"/>
+            <uses-permission android:name="android.permission.RECORD_AUDIO"/>
+        </manifest>"#;
+        let mut manifest = app();
+        manifest.uses_permission.push(Permission {
+            name: "android.permission.CAMERA".into(),
+            tools_node: Some("remove".into()),
+            ..Default::default()
+        });
+        let mut merger = ManifestMerger::new(&manifest)?;
+        merger.merge("camera", camera)?;
+        let manifest = merger.finish()?;
+        let permissions = manifest
+            .uses_permission
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            permissions,
+            [
+                "android.permission.INTERNET",
+                "android.permission.RECORD_AUDIO"
+            ]
+        );
+        assert!(manifest.application.tools_replace.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_dropped() -> Result<()> {
+        let lib = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android"
+            package="androidx.core">
+            <permission android:name="${applicationId}.DYNAMIC_RECEIVER_NOT_EXPORTED_PERMISSION"
+                android:protectionLevel="signature"/>
+            <uses-permission-sdk-23 android:name="android.permission.CAMERA"/>
+            <supports-screens android:smallScreens="true"/>
+            <application android:largeHeap="true">
+                <service android:name=".Service" android:exported="false"/>
+            </application>
+        </manifest>"#;
+        let mut merger = ManifestMerger::new(&app())?;
+        merger.merge("androidx.core", lib)?;
+        let (manifest, dropped) = merger.convert()?;
+        assert_eq!(
+            manifest.permission[0].name,
+            "com.example.app.DYNAMIC_RECEIVER_NOT_EXPORTED_PERMISSION"
+        );
+        assert_eq!(
+            manifest.permission[0].protection_level.as_deref(),
+            Some("signature")
+        );
+        assert_eq!(
+            manifest.uses_permission_sdk_23[0].name,
+            "android.permission.CAMERA"
+        );
+        assert_eq!(
+            dropped,
+            [
+                "attribute <application>@android:largeHeap from androidx.core",
+                "element <supports-screens> from androidx.core",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_min_sdk() -> Result<()> {
+        let lib = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android"
+            package="com.example.lib">
+            <uses-sdk android:minSdkVersion="26"/>
+        </manifest>"#;
+        let mut merger = ManifestMerger::new(&app())?;
+        let err = merger.merge("lib", lib).unwrap_err().to_string();
+        assert!(err.starts_with("lib requires minSdkVersion 26"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_placeholders() -> Result<()> {
+        assert_eq!(
+            replace_placeholders("${applicationId}.provider", "com.example")?,
+            "com.example.provider"
+        );
+        assert_eq!(
+            replace_placeholders("${unknown}.${packageName}", "com.example")?,
+            "${unknown}.com.example"
+        );
+        assert_eq!(resolve_class("com.lib", ".Service"), "com.lib.Service");
+        assert_eq!(resolve_class("com.lib", "Service"), "com.lib.Service");
+        assert_eq!(
+            resolve_class("com.lib", "com.other.Service"),
+            "com.other.Service"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+//! Minimal protocol buffers encoding and decoding, used for the aapt2 and
+//! bundletool formats of android app bundles.
+
+use anyhow::{Context, Result};
+
+const VARINT: u32 = 0;
+const FIXED64: u32 = 1;
+const LEN: u32 = 2;
+const FIXED32: u32 = 5;
+
+/// An encoded message. Fields are written in the order they are added.
+/// Default values are written as well, which is required for fields of a
+/// `oneof`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.varint((field << 3 | wire_type) as u64);
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    pub fn uint(&mut self, field: u32, value: u64) -> &mut Self {
+        self.key(field, VARINT);
+        self.varint(value);
+        self
+    }
+
+    /// Negative numbers are sign extended to 10 bytes.
+    pub fn int(&mut self, field: u32, value: i64) -> &mut Self {
+        self.uint(field, value as u64)
+    }
+
+    pub fn bool(&mut self, field: u32, value: bool) -> &mut Self {
+        self.uint(field, value as u64)
+    }
+
+    pub fn float(&mut self, field: u32, value: f32) -> &mut Self {
+        self.key(field, FIXED32);
+        self.buf.extend_from_slice(&value.to_bits().to_le_bytes());
+        self
+    }
+
+    pub fn bytes(&mut self, field: u32, value: &[u8]) -> &mut Self {
+        self.key(field, LEN);
+        self.varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    pub fn string(&mut self, field: u32, value: &str) -> &mut Self {
+        self.bytes(field, value.as_bytes())
+    }
+
+    pub fn message(&mut self, field: u32, value: &Message) -> &mut Self {
+        self.bytes(field, &value.buf)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+/// A decoded field.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Field<'a> {
+    Varint(u64),
+    Fixed32(u32),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+}
+
+/// A decoded message. Missing fields have their default value, for
+/// repeated fields the last value is returned unless all values are
+/// requested.
+#[derive(Clone, Debug, Default)]
+pub struct Reader<'a> {
+    fields: Vec<(u32, Field<'a>)>,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(mut buf: &'a [u8]) -> Result<Self> {
+        let mut fields = vec![];
+        while !buf.is_empty() {
+            let key = read_varint(&mut buf)?;
+            let field = (key >> 3) as u32;
+            let value = match key as u32 & 0x7 {
+                VARINT => Field::Varint(read_varint(&mut buf)?),
+                FIXED64 => Field::Fixed64(u64::from_le_bytes(read_bytes(&mut buf, 8)?.try_into()?)),
+                LEN => {
+                    let len = read_varint(&mut buf)? as usize;
+                    Field::Bytes(read_bytes(&mut buf, len)?)
+                }
+                FIXED32 => Field::Fixed32(u32::from_le_bytes(read_bytes(&mut buf, 4)?.try_into()?)),
+                wire_type => anyhow::bail!("unsupported wire type {}", wire_type),
+            };
+            fields.push((field, value));
+        }
+        Ok(Self { fields })
+    }
+
+    fn get(&self, field: u32) -> Option<Field<'a>> {
+        self.fields
+            .iter()
+            .rev()
+            .find(|(f, _)| *f == field)
+            .map(|(_, value)| *value)
+    }
+
+    fn get_bytes(&self, field: u32) -> Result<Option<&'a [u8]>> {
+        match self.get(field) {
+            Some(Field::Bytes(bytes)) => Ok(Some(bytes)),
+            Some(_) => anyhow::bail!("field {} is not length delimited", field),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns `true` if the field is present, which is needed to tell
+    /// which field of a `oneof` is set.
+    pub fn has(&self, field: u32) -> bool {
+        self.get(field).is_some()
+    }
+
+    pub fn uint(&self, field: u32) -> u64 {
+        match self.get(field) {
+            Some(Field::Varint(value) | Field::Fixed64(value)) => value,
+            Some(Field::Fixed32(value)) => value as u64,
+            _ => 0,
+        }
+    }
+
+    pub fn int(&self, field: u32) -> i64 {
+        self.uint(field) as i64
+    }
+
+    pub fn bool(&self, field: u32) -> bool {
+        self.uint(field) != 0
+    }
+
+    pub fn float(&self, field: u32) -> f32 {
+        f32::from_bits(self.uint(field) as u32)
+    }
+
+    pub fn bytes(&self, field: u32) -> Result<&'a [u8]> {
+        Ok(self.get_bytes(field)?.unwrap_or_default())
+    }
+
+    pub fn string(&self, field: u32) -> Result<&'a str> {
+        std::str::from_utf8(self.bytes(field)?)
+            .with_context(|| format!("field {} is not a string", field))
+    }
+
+    pub fn message(&self, field: u32) -> Result<Option<Reader<'a>>> {
+        self.get_bytes(field)?.map(Reader::new).transpose()
+    }
+
+    /// Returns all values of a repeated message field.
+    pub fn messages(&self, field: u32) -> Result<Vec<Reader<'a>>> {
+        self.fields
+            .iter()
+            .filter(|(f, _)| *f == field)
+            .map(|(_, value)| match value {
+                Field::Bytes(bytes) => Reader::new(bytes),
+                _ => anyhow::bail!("field {} is not length delimited", field),
+            })
+            .collect()
+    }
+}
+
+fn read_varint(buf: &mut &[u8]) -> Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = buf.split_first().context("unexpected end of message")?;
+        *buf = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    anyhow::bail!("varint is too long")
+}
+
+fn read_bytes<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    anyhow::ensure!(buf.len() >= len, "unexpected end of message");
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let mut inner = Message::new();
+        inner.string(2, "testing");
+        let mut msg = Message::new();
+        msg.uint(1, 150)
+            .message(3, &inner)
+            .bool(4, false)
+            .float(5, 1.0);
+        assert_eq!(
+            msg.as_bytes(),
+            [
+                0x08, 0x96, 0x01, 0x1a, 0x09, 0x12, 0x07, b't', b'e', b's', b't', b'i', b'n', b'g',
+                0x20, 0x00, 0x2d, 0x00, 0x00, 0x80, 0x3f
+            ]
+        );
+        let mut msg = Message::new();
+        msg.int(1, -1);
+        assert_eq!(msg.as_bytes().len(), 11);
+    }
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        let mut inner = Message::new();
+        inner.string(2, "testing");
+        let mut msg = Message::new();
+        msg.uint(1, 150)
+            .message(3, &inner)
+            .message(3, &Message::new())
+            .float(5, 1.5)
+            .int(6, -2);
+        let reader = Reader::new(msg.as_bytes())?;
+        assert_eq!(reader.uint(1), 150);
+        assert_eq!(reader.float(5), 1.5);
+        assert_eq!(reader.int(6), -2);
+        assert!(!reader.has(2));
+        assert_eq!(reader.string(2)?, "");
+        let messages = reader.messages(3)?;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].string(2)?, "testing");
+        assert!(reader.message(3)?.is_some());
+        assert!(Reader::new(&[0x0a, 0x05, 0x00]).is_err());
+        Ok(())
+    }
+}
@@ -13,7 +13,7 @@ pub enum ChunkType {
     XmlEndNamespace = 0x0101,
     XmlStartElement = 0x0102,
     XmlEndElement = 0x0103,
-    //XmlCdata = 0x0104,
+    XmlCdata = 0x0104,
     //XmlLastChunk = 0x017f,
     XmlResourceMap = 0x0180,
     TablePackage = 0x0200,
@@ -33,7 +33,7 @@ impl ChunkType {
             ty if ty == ChunkType::XmlEndNamespace as u16 => ChunkType::XmlEndNamespace,
             ty if ty == ChunkType::XmlStartElement as u16 => ChunkType::XmlStartElement,
             ty if ty == ChunkType::XmlEndElement as u16 => ChunkType::XmlEndElement,
-            //ty if ty == ChunkType::XmlCdata as u16 => ChunkType::XmlCdata,
+            ty if ty == ChunkType::XmlCdata as u16 => ChunkType::XmlCdata,
             //ty if ty == ChunkType::XmlLastChunk as u16 => ChunkType::XmlLastChunk,
             ty if ty == ChunkType::XmlResourceMap as u16 => ChunkType::XmlResourceMap,
             ty if ty == ChunkType::TablePackage as u16 => ChunkType::TablePackage,
@@ -317,6 +317,27 @@ impl ResXmlEndElement {
     }
 }
 
+/// Text of an element. `data` is the index of the text in the string pool.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ResXmlCdata {
+    pub data: i32,
+    pub typed_data: ResValue,
+}
+
+impl ResXmlCdata {
+    pub fn read(r: &mut impl Read) -> Result<Self> {
+        let data = r.read_i32::<LittleEndian>()?;
+        let typed_data = ResValue::read(r)?;
+        Ok(Self { data, typed_data })
+    }
+
+    pub fn write(&self, w: &mut impl Write) -> Result<()> {
+        w.write_i32::<LittleEndian>(self.data)?;
+        self.typed_data.write(w)?;
+        Ok(())
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct ResTableRef(u32);
 
@@ -517,79 +538,762 @@ impl ResTableTypeHeader {
     }
 }
 
+/// Describes a particular resource configuration, the qualifiers of a
+/// resource directory like `values-de-rDE-night-v21`.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ResTableConfig {
+    /// Size of the configuration in bytes. Fields after `size` are zero.
     pub size: u32,
-    pub imsi: u32,
-    pub locale: u32,
-    pub screen_type: ScreenType,
-    pub input: u32,
-    pub screen_size: u32,
-    pub version: u32,
-    pub unknown: Vec<u8>,
+    /// Mobile country code.
+    pub mcc: u16,
+    /// Mobile network code.
+    pub mnc: u16,
+    /// ISO-639 language code, three letter codes are packed into two bytes.
+    pub language: [u8; 2],
+    /// ISO-3166 region code or UN M.49 area code, packed like `language`.
+    pub country: [u8; 2],
+    pub orientation: u8,
+    pub touchscreen: u8,
+    pub density: u16,
+    pub keyboard: u8,
+    pub navigation: u8,
+    pub input_flags: u8,
+    pub screen_width: u16,
+    pub screen_height: u16,
+    pub sdk_version: u16,
+    /// Must be 0.
+    pub minor_version: u16,
+    pub screen_layout: u8,
+    pub ui_mode: u8,
+    pub smallest_screen_width_dp: u16,
+    pub screen_width_dp: u16,
+    pub screen_height_dp: u16,
+    /// ISO-15924 script code.
+    pub locale_script: [u8; 4],
+    /// BCP-47 variant subtag.
+    pub locale_variant: [u8; 8],
+    pub screen_layout2: u8,
+    pub color_mode: u8,
+    pub locale_script_was_computed: bool,
+    /// BCP-47 unicode extension for the numbering system.
+    pub locale_numbering_system: [u8; 8],
 }
 
 impl ResTableConfig {
+    /// Size of the fields known to this implementation, including the 3
+    /// bytes of padding after `locale_numbering_system`.
+    const KNOWN_SIZE: usize = 64;
+
+    pub const ORIENTATION_PORT: u8 = 1;
+    pub const ORIENTATION_LAND: u8 = 2;
+    pub const ORIENTATION_SQUARE: u8 = 3;
+
+    pub const TOUCHSCREEN_NOTOUCH: u8 = 1;
+    pub const TOUCHSCREEN_STYLUS: u8 = 2;
+    pub const TOUCHSCREEN_FINGER: u8 = 3;
+
+    pub const DENSITY_LOW: u16 = 120;
+    pub const DENSITY_MEDIUM: u16 = 160;
+    pub const DENSITY_TV: u16 = 213;
+    pub const DENSITY_HIGH: u16 = 240;
+    pub const DENSITY_XHIGH: u16 = 320;
+    pub const DENSITY_XXHIGH: u16 = 480;
+    pub const DENSITY_XXXHIGH: u16 = 640;
+    pub const DENSITY_ANY: u16 = 0xfffe;
+    pub const DENSITY_NONE: u16 = 0xffff;
+
+    pub const KEYBOARD_NOKEYS: u8 = 1;
+    pub const KEYBOARD_QWERTY: u8 = 2;
+    pub const KEYBOARD_12KEY: u8 = 3;
+
+    pub const NAVIGATION_NONAV: u8 = 1;
+    pub const NAVIGATION_DPAD: u8 = 2;
+    pub const NAVIGATION_TRACKBALL: u8 = 3;
+    pub const NAVIGATION_WHEEL: u8 = 4;
+
+    pub const MASK_KEYSHIDDEN: u8 = 0x03;
+    pub const KEYSHIDDEN_NO: u8 = 1;
+    pub const KEYSHIDDEN_YES: u8 = 2;
+    pub const KEYSHIDDEN_SOFT: u8 = 3;
+    pub const MASK_NAVHIDDEN: u8 = 0x0c;
+    pub const NAVHIDDEN_NO: u8 = 1 << 2;
+    pub const NAVHIDDEN_YES: u8 = 2 << 2;
+
+    pub const MASK_SCREENSIZE: u8 = 0x0f;
+    pub const SCREENSIZE_SMALL: u8 = 1;
+    pub const SCREENSIZE_NORMAL: u8 = 2;
+    pub const SCREENSIZE_LARGE: u8 = 3;
+    pub const SCREENSIZE_XLARGE: u8 = 4;
+    pub const MASK_SCREENLONG: u8 = 0x30;
+    pub const SCREENLONG_NO: u8 = 1 << 4;
+    pub const SCREENLONG_YES: u8 = 2 << 4;
+    pub const MASK_LAYOUTDIR: u8 = 0xc0;
+    pub const LAYOUTDIR_LTR: u8 = 1 << 6;
+    pub const LAYOUTDIR_RTL: u8 = 2 << 6;
+
+    pub const MASK_UI_MODE_TYPE: u8 = 0x0f;
+    pub const UI_MODE_TYPE_NORMAL: u8 = 1;
+    pub const UI_MODE_TYPE_DESK: u8 = 2;
+    pub const UI_MODE_TYPE_CAR: u8 = 3;
+    pub const UI_MODE_TYPE_TELEVISION: u8 = 4;
+    pub const UI_MODE_TYPE_APPLIANCE: u8 = 5;
+    pub const UI_MODE_TYPE_WATCH: u8 = 6;
+    pub const UI_MODE_TYPE_VR_HEADSET: u8 = 7;
+    pub const MASK_UI_MODE_NIGHT: u8 = 0x30;
+    pub const UI_MODE_NIGHT_NO: u8 = 1 << 4;
+    pub const UI_MODE_NIGHT_YES: u8 = 2 << 4;
+
+    pub const MASK_SCREENROUND: u8 = 0x03;
+    pub const SCREENROUND_NO: u8 = 1;
+    pub const SCREENROUND_YES: u8 = 2;
+
+    pub const MASK_WIDE_COLOR_GAMUT: u8 = 0x03;
+    pub const WIDE_COLOR_GAMUT_NO: u8 = 1;
+    pub const WIDE_COLOR_GAMUT_YES: u8 = 2;
+    pub const MASK_HDR: u8 = 0x0c;
+    pub const HDR_NO: u8 = 1 << 2;
+    pub const HDR_YES: u8 = 2 << 2;
+
+    pub const CONFIG_MCC: u32 = 0x0001;
+    pub const CONFIG_MNC: u32 = 0x0002;
+    pub const CONFIG_LOCALE: u32 = 0x0004;
+    pub const CONFIG_TOUCHSCREEN: u32 = 0x0008;
+    pub const CONFIG_KEYBOARD: u32 = 0x0010;
+    pub const CONFIG_KEYBOARD_HIDDEN: u32 = 0x0020;
+    pub const CONFIG_NAVIGATION: u32 = 0x0040;
+    pub const CONFIG_ORIENTATION: u32 = 0x0080;
+    pub const CONFIG_DENSITY: u32 = 0x0100;
+    pub const CONFIG_SCREEN_SIZE: u32 = 0x0200;
+    pub const CONFIG_VERSION: u32 = 0x0400;
+    pub const CONFIG_SCREEN_LAYOUT: u32 = 0x0800;
+    pub const CONFIG_UI_MODE: u32 = 0x1000;
+    pub const CONFIG_SMALLEST_SCREEN_SIZE: u32 = 0x2000;
+    pub const CONFIG_LAYOUTDIR: u32 = 0x4000;
+    pub const CONFIG_SCREEN_ROUND: u32 = 0x8000;
+    pub const CONFIG_COLOR_MODE: u32 = 0x10000;
+
     pub fn read(r: &mut impl Read) -> Result<Self> {
         let size = r.read_u32::<LittleEndian>()?;
-        let imsi = r.read_u32::<LittleEndian>()?;
-        let locale = r.read_u32::<LittleEndian>()?;
-        let screen_type = ScreenType::read(r)?;
-        let input = r.read_u32::<LittleEndian>()?;
-        let screen_size = r.read_u32::<LittleEndian>()?;
-        let version = r.read_u32::<LittleEndian>()?;
-        let unknown_len = size as usize - 28;
-        let mut unknown = vec![0; unknown_len];
-        r.read_exact(&mut unknown)?;
+        anyhow::ensure!(size >= 28, "invalid config size {}", size);
+        let mut buf = vec![0; size as usize - 4];
+        r.read_exact(&mut buf)?;
+        // older configs are shorter, newer configs may have unknown fields
+        buf.resize(Self::KNOWN_SIZE - 4, 0);
+        let r = &mut &buf[..];
+        let mcc = r.read_u16::<LittleEndian>()?;
+        let mnc = r.read_u16::<LittleEndian>()?;
+        let mut language = [0; 2];
+        r.read_exact(&mut language)?;
+        let mut country = [0; 2];
+        r.read_exact(&mut country)?;
+        let orientation = r.read_u8()?;
+        let touchscreen = r.read_u8()?;
+        let density = r.read_u16::<LittleEndian>()?;
+        let keyboard = r.read_u8()?;
+        let navigation = r.read_u8()?;
+        let input_flags = r.read_u8()?;
+        let _input_pad = r.read_u8()?;
+        let screen_width = r.read_u16::<LittleEndian>()?;
+        let screen_height = r.read_u16::<LittleEndian>()?;
+        let sdk_version = r.read_u16::<LittleEndian>()?;
+        let minor_version = r.read_u16::<LittleEndian>()?;
+        let screen_layout = r.read_u8()?;
+        let ui_mode = r.read_u8()?;
+        let smallest_screen_width_dp = r.read_u16::<LittleEndian>()?;
+        let screen_width_dp = r.read_u16::<LittleEndian>()?;
+        let screen_height_dp = r.read_u16::<LittleEndian>()?;
+        let mut locale_script = [0; 4];
+        r.read_exact(&mut locale_script)?;
+        let mut locale_variant = [0; 8];
+        r.read_exact(&mut locale_variant)?;
+        let screen_layout2 = r.read_u8()?;
+        let color_mode = r.read_u8()?;
+        let _screen_config_pad2 = r.read_u16::<LittleEndian>()?;
+        let locale_script_was_computed = r.read_u8()? != 0;
+        let mut locale_numbering_system = [0; 8];
+        r.read_exact(&mut locale_numbering_system)?;
         Ok(Self {
             size,
-            imsi,
-            locale,
-            screen_type,
-            input,
-            screen_size,
-            version,
-            unknown,
+            mcc,
+            mnc,
+            language,
+            country,
+            orientation,
+            touchscreen,
+            density,
+            keyboard,
+            navigation,
+            input_flags,
+            screen_width,
+            screen_height,
+            sdk_version,
+            minor_version,
+            screen_layout,
+            ui_mode,
+            smallest_screen_width_dp,
+            screen_width_dp,
+            screen_height_dp,
+            locale_script,
+            locale_variant,
+            screen_layout2,
+            color_mode,
+            locale_script_was_computed,
+            locale_numbering_system,
         })
     }
 
     pub fn write(&self, w: &mut impl Write) -> Result<()> {
+        let mut buf = Vec::with_capacity(Self::KNOWN_SIZE);
+        buf.write_u16::<LittleEndian>(self.mcc)?;
+        buf.write_u16::<LittleEndian>(self.mnc)?;
+        buf.write_all(&self.language)?;
+        buf.write_all(&self.country)?;
+        buf.write_u8(self.orientation)?;
+        buf.write_u8(self.touchscreen)?;
+        buf.write_u16::<LittleEndian>(self.density)?;
+        buf.write_u8(self.keyboard)?;
+        buf.write_u8(self.navigation)?;
+        buf.write_u8(self.input_flags)?;
+        buf.write_u8(0)?;
+        buf.write_u16::<LittleEndian>(self.screen_width)?;
+        buf.write_u16::<LittleEndian>(self.screen_height)?;
+        buf.write_u16::<LittleEndian>(self.sdk_version)?;
+        buf.write_u16::<LittleEndian>(self.minor_version)?;
+        buf.write_u8(self.screen_layout)?;
+        buf.write_u8(self.ui_mode)?;
+        buf.write_u16::<LittleEndian>(self.smallest_screen_width_dp)?;
+        buf.write_u16::<LittleEndian>(self.screen_width_dp)?;
+        buf.write_u16::<LittleEndian>(self.screen_height_dp)?;
+        buf.write_all(&self.locale_script)?;
+        buf.write_all(&self.locale_variant)?;
+        buf.write_u8(self.screen_layout2)?;
+        buf.write_u8(self.color_mode)?;
+        buf.write_u16::<LittleEndian>(0)?;
+        buf.write_u8(self.locale_script_was_computed as u8)?;
+        buf.write_all(&self.locale_numbering_system)?;
+        buf.resize(self.size as usize - 4, 0);
         w.write_u32::<LittleEndian>(self.size)?;
-        w.write_u32::<LittleEndian>(self.imsi)?;
-        w.write_u32::<LittleEndian>(self.locale)?;
-        self.screen_type.write(w)?;
-        w.write_u32::<LittleEndian>(self.input)?;
-        w.write_u32::<LittleEndian>(self.screen_size)?;
-        w.write_u32::<LittleEndian>(self.version)?;
-        w.write_all(&self.unknown)?;
+        w.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// Returns a mask of the `CONFIG_*` flags that differ between two
+    /// configurations.
+    pub fn diff(&self, other: &Self) -> u32 {
+        let mut diff = 0;
+        if self.mcc != other.mcc {
+            diff |= Self::CONFIG_MCC;
+        }
+        if self.mnc != other.mnc {
+            diff |= Self::CONFIG_MNC;
+        }
+        if self.language != other.language
+            || self.country != other.country
+            || self.locale_script != other.locale_script
+            || self.locale_variant != other.locale_variant
+            || self.locale_numbering_system != other.locale_numbering_system
+        {
+            diff |= Self::CONFIG_LOCALE;
+        }
+        if self.orientation != other.orientation {
+            diff |= Self::CONFIG_ORIENTATION;
+        }
+        if self.density != other.density {
+            diff |= Self::CONFIG_DENSITY;
+        }
+        if self.touchscreen != other.touchscreen {
+            diff |= Self::CONFIG_TOUCHSCREEN;
+        }
+        if (self.input_flags ^ other.input_flags) & (Self::MASK_KEYSHIDDEN | Self::MASK_NAVHIDDEN)
+            != 0
+        {
+            diff |= Self::CONFIG_KEYBOARD_HIDDEN;
+        }
+        if self.keyboard != other.keyboard {
+            diff |= Self::CONFIG_KEYBOARD;
+        }
+        if self.navigation != other.navigation {
+            diff |= Self::CONFIG_NAVIGATION;
+        }
+        if self.screen_width != other.screen_width
+            || self.screen_height != other.screen_height
+            || self.screen_width_dp != other.screen_width_dp
+            || self.screen_height_dp != other.screen_height_dp
+        {
+            diff |= Self::CONFIG_SCREEN_SIZE;
+        }
+        if self.sdk_version != other.sdk_version || self.minor_version != other.minor_version {
+            diff |= Self::CONFIG_VERSION;
+        }
+        if (self.screen_layout ^ other.screen_layout) & Self::MASK_LAYOUTDIR != 0 {
+            diff |= Self::CONFIG_LAYOUTDIR;
+        }
+        if (self.screen_layout ^ other.screen_layout) & !Self::MASK_LAYOUTDIR != 0 {
+            diff |= Self::CONFIG_SCREEN_LAYOUT;
+        }
+        if self.screen_layout2 != other.screen_layout2 {
+            diff |= Self::CONFIG_SCREEN_ROUND;
+        }
+        if self.color_mode != other.color_mode {
+            diff |= Self::CONFIG_COLOR_MODE;
+        }
+        if self.ui_mode != other.ui_mode {
+            diff |= Self::CONFIG_UI_MODE;
+        }
+        if self.smallest_screen_width_dp != other.smallest_screen_width_dp {
+            diff |= Self::CONFIG_SMALLEST_SCREEN_SIZE;
+        }
+        diff
+    }
+
+    /// Parses the qualifiers of a resource directory, like `de-rDE-night` in
+    /// `values-de-rDE-night`. Qualifiers that require a newer sdk version
+    /// than specified raise the sdk version like aapt does.
+    pub fn from_qualifiers(qualifiers: &str) -> Result<Self> {
+        let mut config = Self::default();
+        if qualifiers.is_empty() {
+            return Ok(config);
+        }
+        let mut parts = qualifiers.split('-').peekable();
+        while let Some(part) = parts.next() {
+            let lower = part.to_ascii_lowercase();
+            let number = |prefix: &str, suffix: &str| -> Option<u16> {
+                lower
+                    .strip_prefix(prefix)?
+                    .strip_suffix(suffix)?
+                    .parse()
+                    .ok()
+            };
+            let density = match lower.as_str() {
+                "ldpi" => Some(Self::DENSITY_LOW),
+                "mdpi" => Some(Self::DENSITY_MEDIUM),
+                "tvdpi" => Some(Self::DENSITY_TV),
+                "hdpi" => Some(Self::DENSITY_HIGH),
+                "xhdpi" => Some(Self::DENSITY_XHIGH),
+                "xxhdpi" => Some(Self::DENSITY_XXHIGH),
+                "xxxhdpi" => Some(Self::DENSITY_XXXHIGH),
+                "anydpi" => Some(Self::DENSITY_ANY),
+                "nodpi" => Some(Self::DENSITY_NONE),
+                _ => number("", "dpi"),
+            };
+            if let Some(density) = density {
+                config.density = density;
+                continue;
+            }
+            match lower.as_str() {
+                "ldltr" => config.screen_layout |= Self::LAYOUTDIR_LTR,
+                "ldrtl" => config.screen_layout |= Self::LAYOUTDIR_RTL,
+                "small" => config.screen_layout |= Self::SCREENSIZE_SMALL,
+                "normal" => config.screen_layout |= Self::SCREENSIZE_NORMAL,
+                "large" => config.screen_layout |= Self::SCREENSIZE_LARGE,
+                "xlarge" => config.screen_layout |= Self::SCREENSIZE_XLARGE,
+                "long" => config.screen_layout |= Self::SCREENLONG_YES,
+                "notlong" => config.screen_layout |= Self::SCREENLONG_NO,
+                "round" => config.screen_layout2 |= Self::SCREENROUND_YES,
+                "notround" => config.screen_layout2 |= Self::SCREENROUND_NO,
+                "widecg" => config.color_mode |= Self::WIDE_COLOR_GAMUT_YES,
+                "nowidecg" => config.color_mode |= Self::WIDE_COLOR_GAMUT_NO,
+                "highdr" => config.color_mode |= Self::HDR_YES,
+                "lowdr" => config.color_mode |= Self::HDR_NO,
+                "port" => config.orientation = Self::ORIENTATION_PORT,
+                "land" => config.orientation = Self::ORIENTATION_LAND,
+                "square" => config.orientation = Self::ORIENTATION_SQUARE,
+                "desk" => config.ui_mode |= Self::UI_MODE_TYPE_DESK,
+                "car" => config.ui_mode |= Self::UI_MODE_TYPE_CAR,
+                "television" => config.ui_mode |= Self::UI_MODE_TYPE_TELEVISION,
+                "appliance" => config.ui_mode |= Self::UI_MODE_TYPE_APPLIANCE,
+                "watch" => config.ui_mode |= Self::UI_MODE_TYPE_WATCH,
+                "vrheadset" => config.ui_mode |= Self::UI_MODE_TYPE_VR_HEADSET,
+                "night" => config.ui_mode |= Self::UI_MODE_NIGHT_YES,
+                "notnight" => config.ui_mode |= Self::UI_MODE_NIGHT_NO,
+                "notouch" => config.touchscreen = Self::TOUCHSCREEN_NOTOUCH,
+                "stylus" => config.touchscreen = Self::TOUCHSCREEN_STYLUS,
+                "finger" => config.touchscreen = Self::TOUCHSCREEN_FINGER,
+                "keysexposed" => config.input_flags |= Self::KEYSHIDDEN_NO,
+                "keyshidden" => config.input_flags |= Self::KEYSHIDDEN_YES,
+                "keyssoft" => config.input_flags |= Self::KEYSHIDDEN_SOFT,
+                "nokeys" => config.keyboard = Self::KEYBOARD_NOKEYS,
+                "qwerty" => config.keyboard = Self::KEYBOARD_QWERTY,
+                "12key" => config.keyboard = Self::KEYBOARD_12KEY,
+                "navexposed" => config.input_flags |= Self::NAVHIDDEN_NO,
+                "navhidden" => config.input_flags |= Self::NAVHIDDEN_YES,
+                "nonav" => config.navigation = Self::NAVIGATION_NONAV,
+                "dpad" => config.navigation = Self::NAVIGATION_DPAD,
+                "trackball" => config.navigation = Self::NAVIGATION_TRACKBALL,
+                "wheel" => config.navigation = Self::NAVIGATION_WHEEL,
+                _ => {
+                    if let Some(mcc) = number("mcc", "") {
+                        config.mcc = mcc;
+                    } else if let Some(mnc) = number("mnc", "") {
+                        config.mnc = if mnc == 0 { 0xffff } else { mnc };
+                    } else if let Some(dp) = number("sw", "dp") {
+                        config.smallest_screen_width_dp = dp;
+                    } else if let Some(dp) = number("w", "dp") {
+                        config.screen_width_dp = dp;
+                    } else if let Some(dp) = number("h", "dp") {
+                        config.screen_height_dp = dp;
+                    } else if let Some(version) = number("v", "") {
+                        config.sdk_version = version;
+                    } else if let Some(tags) = lower.strip_prefix("b+") {
+                        config.set_bcp47(tags)?;
+                    } else if (2..=3).contains(&part.len())
+                        && part.chars().all(|c| c.is_ascii_lowercase())
+                        && config.language == [0; 2]
+                    {
+                        config.language = pack_language_or_region(part, b'a');
+                        // `round` and friends also start with an `r`
+                        if let Some(region) = parts
+                            .peek()
+                            .and_then(|part| part.strip_prefix('r'))
+                            .filter(|region| is_region(region))
+                        {
+                            config.country =
+                                pack_language_or_region(&region.to_ascii_uppercase(), b'0');
+                            parts.next();
+                        }
+                    } else {
+                        anyhow::bail!("invalid qualifier {} in {}", part, qualifiers);
+                    }
+                }
+            }
+        }
+        config.sdk_version = config.sdk_version.max(config.min_sdk_version());
+        Ok(config)
+    }
+
+    /// Parses a `b+` locale like `b+sr+Latn+RS`.
+    fn set_bcp47(&mut self, qualifier: &str) -> Result<()> {
+        let mut tags = qualifier.split('+');
+        let language = tags.next().unwrap_or_default();
+        anyhow::ensure!(
+            (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_lowercase()),
+            "invalid language {} in qualifier b+{}",
+            language,
+            qualifier
+        );
+        self.language = pack_language_or_region(language, b'a');
+        for tag in tags {
+            if tag.len() == 4 && tag.chars().all(|c| c.is_ascii_alphabetic()) {
+                let mut script = [0; 4];
+                for (i, c) in tag.bytes().enumerate() {
+                    script[i] = if i == 0 {
+                        c.to_ascii_uppercase()
+                    } else {
+                        c.to_ascii_lowercase()
+                    };
+                }
+                self.locale_script = script;
+            } else if is_region(tag) {
+                self.country = pack_language_or_region(&tag.to_ascii_uppercase(), b'0');
+            } else if (5..=8).contains(&tag.len()) {
+                self.locale_variant = [0; 8];
+                self.locale_variant[..tag.len()].copy_from_slice(tag.as_bytes());
+            } else {
+                anyhow::bail!("invalid locale subtag {} in qualifier b+{}", tag, qualifier);
+            }
+        }
         Ok(())
     }
+
+    /// The sdk version required by the qualifiers.
+    fn min_sdk_version(&self) -> u16 {
+        if self.color_mode != 0 {
+            26
+        } else if self.screen_layout2 & Self::MASK_SCREENROUND != 0 {
+            23
+        } else if self.density == Self::DENSITY_ANY
+            || self.locale_script != [0; 4]
+            || self.locale_variant != [0; 8]
+        {
+            21
+        } else if self.screen_layout & Self::MASK_LAYOUTDIR != 0 {
+            17
+        } else if self.smallest_screen_width_dp != 0
+            || self.screen_width_dp != 0
+            || self.screen_height_dp != 0
+        {
+            13
+        } else if self.ui_mode != 0 {
+            8
+        } else if self.screen_layout != 0 || self.density != 0 {
+            4
+        } else {
+            0
+        }
+    }
+
+    /// The language like `en` or `fil`.
+    pub fn language(&self) -> Option<String> {
+        unpack_language_or_region(self.language, b'a')
+    }
+
+    /// The region like `US` or `419`.
+    pub fn region(&self) -> Option<String> {
+        unpack_language_or_region(self.country, b'0')
+    }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub struct ScreenType {
-    pub orientation: u8,
-    pub touchscreen: u8,
-    pub density: u16,
+/// Adds the name of a qualifier value.
+fn push_qualifier(parts: &mut Vec<String>, value: u8, names: &[(u8, &str)]) {
+    if let Some((_, name)) = names.iter().find(|(v, _)| *v == value) {
+        parts.push(name.to_string());
+    }
 }
 
-impl ScreenType {
-    pub fn read(r: &mut impl Read) -> Result<Self> {
-        let orientation = r.read_u8()?;
-        let touchscreen = r.read_u8()?;
-        let density = r.read_u16::<LittleEndian>()?;
-        Ok(Self {
-            orientation,
-            touchscreen,
-            density,
-        })
+fn is_region(region: &str) -> bool {
+    (region.len() == 2 && region.chars().all(|c| c.is_ascii_alphabetic()))
+        || (region.len() == 3 && region.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Two letter codes are stored as is, three letter codes are packed into
+/// 15 bits relative to `base` with the high bit set.
+fn pack_language_or_region(s: &str, base: u8) -> [u8; 2] {
+    let s = s.as_bytes();
+    if s.len() == 2 {
+        [s[0], s[1]]
+    } else {
+        let first = (s[0] - base) & 0x7f;
+        let second = (s[1] - base) & 0x7f;
+        let third = (s[2] - base) & 0x7f;
+        [0x80 | (third << 2) | (second >> 3), (second << 5) | first]
+    }
+}
+
+fn unpack_language_or_region(packed: [u8; 2], base: u8) -> Option<String> {
+    if packed[0] == 0 {
+        return None;
     }
+    let bytes = if packed[0] & 0x80 != 0 {
+        let first = packed[1] & 0x1f;
+        let second = ((packed[1] & 0xe0) >> 5) | ((packed[0] & 0x03) << 3);
+        let third = (packed[0] & 0x7c) >> 2;
+        vec![first + base, second + base, third + base]
+    } else {
+        packed.to_vec()
+    };
+    String::from_utf8(bytes).ok()
+}
 
-    pub fn write(&self, w: &mut impl Write) -> Result<()> {
-        w.write_u8(self.orientation)?;
-        w.write_u8(self.touchscreen)?;
-        w.write_u16::<LittleEndian>(self.density)?;
-        Ok(())
+impl std::fmt::Display for ResTableConfig {
+    /// Formats the configuration as resource directory qualifiers.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut parts: Vec<String> = vec![];
+        if self.mcc != 0 {
+            parts.push(format!("mcc{}", self.mcc));
+        }
+        if self.mnc != 0 {
+            parts.push(format!(
+                "mnc{}",
+                if self.mnc == 0xffff { 0 } else { self.mnc }
+            ));
+        }
+        if let Some(language) = self.language() {
+            let script = std::str::from_utf8(&self.locale_script)
+                .unwrap_or_default()
+                .trim_end_matches('\0');
+            let variant = std::str::from_utf8(&self.locale_variant)
+                .unwrap_or_default()
+                .trim_end_matches('\0');
+            if script.is_empty() && variant.is_empty() {
+                match self.region() {
+                    Some(region) => parts.push(format!("{}-r{}", language, region)),
+                    None => parts.push(language),
+                }
+            } else {
+                let mut locale = format!("b+{}", language);
+                let region = self.region().unwrap_or_default();
+                for tag in [script, &region, variant] {
+                    if !tag.is_empty() {
+                        locale.push('+');
+                        locale.push_str(tag);
+                    }
+                }
+                parts.push(locale);
+            }
+        }
+        push_qualifier(
+            &mut parts,
+            self.screen_layout & Self::MASK_LAYOUTDIR,
+            &[
+                (Self::LAYOUTDIR_LTR, "ldltr"),
+                (Self::LAYOUTDIR_RTL, "ldrtl"),
+            ],
+        );
+        if self.smallest_screen_width_dp != 0 {
+            parts.push(format!("sw{}dp", self.smallest_screen_width_dp));
+        }
+        if self.screen_width_dp != 0 {
+            parts.push(format!("w{}dp", self.screen_width_dp));
+        }
+        if self.screen_height_dp != 0 {
+            parts.push(format!("h{}dp", self.screen_height_dp));
+        }
+        push_qualifier(
+            &mut parts,
+            self.screen_layout & Self::MASK_SCREENSIZE,
+            &[
+                (Self::SCREENSIZE_SMALL, "small"),
+                (Self::SCREENSIZE_NORMAL, "normal"),
+                (Self::SCREENSIZE_LARGE, "large"),
+                (Self::SCREENSIZE_XLARGE, "xlarge"),
+            ],
+        );
+        push_qualifier(
+            &mut parts,
+            self.screen_layout & Self::MASK_SCREENLONG,
+            &[
+                (Self::SCREENLONG_YES, "long"),
+                (Self::SCREENLONG_NO, "notlong"),
+            ],
+        );
+        push_qualifier(
+            &mut parts,
+            self.screen_layout2 & Self::MASK_SCREENROUND,
+            &[
+                (Self::SCREENROUND_YES, "round"),
+                (Self::SCREENROUND_NO, "notround"),
+            ],
+        );
+        push_qualifier(
+            &mut parts,
+            self.color_mode & Self::MASK_WIDE_COLOR_GAMUT,
+            &[
+                (Self::WIDE_COLOR_GAMUT_YES, "widecg"),
+                (Self::WIDE_COLOR_GAMUT_NO, "nowidecg"),
+            ],
+        );
+        push_qualifier(
+            &mut parts,
+            self.color_mode & Self::MASK_HDR,
+            &[(Self::HDR_YES, "highdr"), (Self::HDR_NO, "lowdr")],
+        );
+        push_qualifier(
+            &mut parts,
+            self.orientation,
+            &[
+                (Self::ORIENTATION_PORT, "port"),
+                (Self::ORIENTATION_LAND, "land"),
+                (Self::ORIENTATION_SQUARE, "square"),
+            ],
+        );
+        push_qualifier(
+            &mut parts,
+            self.ui_mode & Self::MASK_UI_MODE_TYPE,
+            &[
+                (Self::UI_MODE_TYPE_DESK, "desk"),
+                (Self::UI_MODE_TYPE_CAR, "car"),
+                (Self::UI_MODE_TYPE_TELEVISION, "television"),
+                (Self::UI_MODE_TYPE_APPLIANCE, "appliance"),
+                (Self::UI_MODE_TYPE_WATCH, "watch"),
+                (Self::UI_MODE_TYPE_VR_HEADSET, "vrheadset"),
+            ],
+        );
+        push_qualifier(
+            &mut parts,
+            self.ui_mode & Self::MASK_UI_MODE_NIGHT,
+            &[
+                (Self::UI_MODE_NIGHT_YES, "night"),
+                (Self::UI_MODE_NIGHT_NO, "notnight"),
+            ],
+        );
+        match self.density {
+            0 => {}
+            Self::DENSITY_LOW => parts.push("ldpi".into()),
+            Self::DENSITY_MEDIUM => parts.push("mdpi".into()),
+            Self::DENSITY_TV => parts.push("tvdpi".into()),
+            Self::DENSITY_HIGH => parts.push("hdpi".into()),
+            Self::DENSITY_XHIGH => parts.push("xhdpi".into()),
+            Self::DENSITY_XXHIGH => parts.push("xxhdpi".into()),
+            Self::DENSITY_XXXHIGH => parts.push("xxxhdpi".into()),
+            Self::DENSITY_ANY => parts.push("anydpi".into()),
+            Self::DENSITY_NONE => parts.push("nodpi".into()),
+            density => parts.push(format!("{}dpi", density)),
+        }
+        push_qualifier(
+            &mut parts,
+            self.touchscreen,
+            &[
+                (Self::TOUCHSCREEN_NOTOUCH, "notouch"),
+                (Self::TOUCHSCREEN_STYLUS, "stylus"),
+                (Self::TOUCHSCREEN_FINGER, "finger"),
+            ],
+        );
+        push_qualifier(
+            &mut parts,
+            self.input_flags & Self::MASK_KEYSHIDDEN,
+            &[
+                (Self::KEYSHIDDEN_NO, "keysexposed"),
+                (Self::KEYSHIDDEN_YES, "keyshidden"),
+                (Self::KEYSHIDDEN_SOFT, "keyssoft"),
+            ],
+        );
+        push_qualifier(
+            &mut parts,
+            self.keyboard,
+            &[
+                (Self::KEYBOARD_NOKEYS, "nokeys"),
+                (Self::KEYBOARD_QWERTY, "qwerty"),
+                (Self::KEYBOARD_12KEY, "12key"),
+            ],
+        );
+        push_qualifier(
+            &mut parts,
+            self.input_flags & Self::MASK_NAVHIDDEN,
+            &[
+                (Self::NAVHIDDEN_NO, "navexposed"),
+                (Self::NAVHIDDEN_YES, "navhidden"),
+            ],
+        );
+        push_qualifier(
+            &mut parts,
+            self.navigation,
+            &[
+                (Self::NAVIGATION_NONAV, "nonav"),
+                (Self::NAVIGATION_DPAD, "dpad"),
+                (Self::NAVIGATION_TRACKBALL, "trackball"),
+                (Self::NAVIGATION_WHEEL, "wheel"),
+            ],
+        );
+        if self.screen_width != 0 && self.screen_height != 0 {
+            parts.push(format!("{}x{}", self.screen_width, self.screen_height));
+        }
+        if self.sdk_version != 0 {
+            parts.push(format!("v{}", self.sdk_version));
+        }
+        write!(f, "{}", parts.join("-"))
+    }
+}
+
+impl Default for ResTableConfig {
+    fn default() -> Self {
+        Self {
+            size: 64,
+            mcc: 0,
+            mnc: 0,
+            language: [0; 2],
+            country: [0; 2],
+            orientation: 0,
+            touchscreen: 0,
+            density: 0,
+            keyboard: 0,
+            navigation: 0,
+            input_flags: 0,
+            screen_width: 0,
+            screen_height: 0,
+            sdk_version: 0,
+            minor_version: 0,
+            screen_layout: 0,
+            ui_mode: 0,
+            smallest_screen_width_dp: 0,
+            screen_width_dp: 0,
+            screen_height_dp: 0,
+            locale_script: [0; 4],
+            locale_variant: [0; 8],
+            screen_layout2: 0,
+            color_mode: 0,
+            locale_script_was_computed: false,
+            locale_numbering_system: [0; 8],
+        }
     }
 }
 
@@ -801,6 +1505,15 @@ impl ResTableMapEntry {
     }
 }
 
+/// Keys of the items of attribute and plural bags.
+pub const ATTR_TYPE: u32 = 0x0100_0000;
+pub const ATTR_MIN: u32 = 0x0100_0001;
+pub const ATTR_MAX: u32 = 0x0100_0002;
+pub const ATTR_OTHER: u32 = 0x0100_0004;
+pub const ATTR_MANY: u32 = 0x0100_0009;
+/// Key of the first item of an array bag.
+pub const ARRAY_ITEM: u32 = 0x0200_0000;
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct ResTableMap {
     pub name: u32,
@@ -861,6 +1574,7 @@ pub enum Chunk {
     XmlEndNamespace(ResXmlNodeHeader, ResXmlNamespace),
     XmlStartElement(ResXmlNodeHeader, ResXmlStartElement, Vec<ResXmlAttribute>),
     XmlEndElement(ResXmlNodeHeader, ResXmlEndElement),
+    XmlCdata(ResXmlNodeHeader, ResXmlCdata),
     XmlResourceMap(Vec<u32>),
     TablePackage(ResTablePackageHeader, Vec<Chunk>),
     TableType(ResTableTypeHeader, Vec<u32>, Vec<Option<ResTableEntry>>),
@@ -897,13 +1611,13 @@ impl Chunk {
                     if string_pool_header.is_utf8() {
                         let charsh = r.read_u8()? as u16;
                         let _chars = if charsh > 0x7f {
-                            charsh & 0x7f | r.read_u8()? as u16
+                            (charsh & 0x7f) << 8 | r.read_u8()? as u16
                         } else {
                             charsh
                         };
                         let bytesh = r.read_u8()? as u16;
                         let bytes = if bytesh > 0x7f {
-                            bytesh & 0x7f | r.read_u8()? as u16
+                            (bytesh & 0x7f) << 8 | r.read_u8()? as u16
                         } else {
                             bytesh
                         };
@@ -919,7 +1633,7 @@ impl Chunk {
                     } else {
                         let charsh = r.read_u16::<LittleEndian>()? as u32;
                         let chars = if charsh > 0x7fff {
-                            charsh & 0x7fff | r.read_u16::<LittleEndian>()? as u32
+                            (charsh & 0x7fff) << 16 | r.read_u16::<LittleEndian>()? as u32
                         } else {
                             charsh
                         };
@@ -1001,6 +1715,12 @@ impl Chunk {
                 let end_element = ResXmlEndElement::read(r)?;
                 Ok(Chunk::XmlEndElement(node_header, end_element))
             }
+            Some(ChunkType::XmlCdata) => {
+                tracing::trace!("xml cdata");
+                let node_header = ResXmlNodeHeader::read(r)?;
+                let cdata = ResXmlCdata::read(r)?;
+                Ok(Chunk::XmlCdata(node_header, cdata))
+            }
             Some(ChunkType::XmlResourceMap) => {
                 tracing::trace!("xml resource map");
                 let mut resource_map =
@@ -1109,10 +1829,9 @@ impl Chunk {
                 let strings_start = w.stream_position()?;
                 for string in strings {
                     indices.push(w.stream_position()? - strings_start);
-                    assert!(string.len() < 0x7f);
-                    let chars = string.chars().count();
-                    w.write_u8(chars as u8)?;
-                    w.write_u8(string.len() as u8)?;
+                    anyhow::ensure!(string.len() <= 0x7fff, "string too long");
+                    write_utf8_len(w, string.encode_utf16().count())?;
+                    write_utf8_len(w, string.len())?;
                     w.write_all(string.as_bytes())?;
                     w.write_u8(0)?;
                 }
@@ -1191,6 +1910,13 @@ impl Chunk {
                 end_element.write(w)?;
                 chunk.end_chunk(w)?;
             }
+            Chunk::XmlCdata(node_header, cdata) => {
+                let mut chunk = ChunkWriter::start_chunk(ChunkType::XmlCdata, w)?;
+                node_header.write(w)?;
+                chunk.end_header(w)?;
+                cdata.write(w)?;
+                chunk.end_chunk(w)?;
+            }
             Chunk::XmlResourceMap(resource_map) => {
                 let mut chunk = ChunkWriter::start_chunk(ChunkType::XmlResourceMap, w)?;
                 chunk.end_header(w)?;
@@ -1252,6 +1978,16 @@ impl Chunk {
     }
 }
 
+/// Lengths in utf8 string pools are encoded in one byte or in two bytes
+/// with the high bit of the first byte set.
+fn write_utf8_len(w: &mut impl Write, len: usize) -> Result<()> {
+    if len > 0x7f {
+        w.write_u8((len >> 8) as u8 | 0x80)?;
+    }
+    w.write_u8(len as u8)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1281,4 +2017,71 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_string_pool_roundtrip() -> Result<()> {
+        let chunk = Chunk::StringPool(vec!["short".into(), "é".repeat(200)], vec![]);
+        let mut buf = vec![];
+        chunk.write(&mut Cursor::new(&mut buf))?;
+        assert_eq!(Chunk::parse(&mut Cursor::new(&buf))?, chunk);
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_qualifiers() -> Result<()> {
+        for (qualifiers, expected) in [
+            ("", ""),
+            ("de-rDE-night", "de-rDE-night-v8"),
+            ("fil-rPH", "fil-rPH"),
+            ("es-r419", "es-r419"),
+            ("b+sr+Latn", "b+sr+Latn-v21"),
+            ("mcc310-mnc004-en-rUS", "mcc310-mnc4-en-rUS"),
+            ("ldrtl-sw600dp-land", "ldrtl-sw600dp-land-v17"),
+            ("w820dp-h720dp-large-long", "w820dp-h720dp-large-long-v13"),
+            ("hdpi", "hdpi-v4"),
+            ("watch-round-v26", "round-watch-v26"),
+            (
+                "finger-keyshidden-qwerty-navhidden-dpad",
+                "finger-keyshidden-qwerty-navhidden-dpad",
+            ),
+        ] {
+            let config = ResTableConfig::from_qualifiers(qualifiers)?;
+            assert_eq!(config.to_string(), expected);
+            assert_eq!(ResTableConfig::from_qualifiers(expected)?, config);
+        }
+        let config = ResTableConfig::from_qualifiers("fil-rPH")?;
+        assert_eq!(config.language().as_deref(), Some("fil"));
+        assert_eq!(config.region().as_deref(), Some("PH"));
+        assert!(ResTableConfig::from_qualifiers("de-foo").is_err());
+        let config = ResTableConfig::from_qualifiers("en-round")?;
+        assert_eq!(config.language().as_deref(), Some("en"));
+        assert_eq!(config.region(), None);
+        assert_eq!(config.to_string(), "en-round-v23");
+        assert!(ResTableConfig::from_qualifiers("en-r1").is_err());
+        let err = ResTableConfig::from_qualifiers("b+12a").unwrap_err();
+        assert_eq!(err.to_string(), "invalid language 12a in qualifier b+12a");
+        assert!(ResTableConfig::from_qualifiers("b+en+1x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_roundtrip() -> Result<()> {
+        let mut config = ResTableConfig::from_qualifiers("b+sr+Latn+RS-night-xhdpi")?;
+        config.locale_script_was_computed = true;
+        for size in [64, 68] {
+            config.size = size;
+            let mut buf = vec![];
+            config.write(&mut buf)?;
+            assert_eq!(buf.len(), size as usize);
+            assert_eq!(ResTableConfig::read(&mut &buf[..])?, config);
+        }
+        // configs written by older tools are shorter
+        let mut buf = vec![];
+        config.write(&mut buf)?;
+        buf[..4].copy_from_slice(&36u32.to_le_bytes());
+        let old = ResTableConfig::read(&mut &buf[..36])?;
+        assert_eq!(old.density, ResTableConfig::DENSITY_XHIGH);
+        assert_eq!(old.locale_script, [0; 4]);
+        Ok(())
+    }
 }
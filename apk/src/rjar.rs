@@ -0,0 +1,429 @@
+//! Writes the `R` classes of packages to a jar like the `R.jar` of the
+//! android gradle plugin, so that no java compiler is required.
+
+use crate::compiler::Symbols;
+use anyhow::{Context, Result};
+use byteorder::{BigEndian, WriteBytesExt};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use xcommon::{Zip, ZipFileOptions};
+
+const ACC_PUBLIC: u16 = 0x0001;
+const ACC_STATIC: u16 = 0x0008;
+const ACC_FINAL: u16 = 0x0010;
+const ACC_SUPER: u16 = 0x0020;
+
+/// Java 8, which doesn't require stack map frames.
+const CLASS_VERSION: u16 = 52;
+
+/// A static field of an `R` class.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Field {
+    Int(String, u32),
+    IntArray(String, Vec<u32>),
+}
+
+impl Field {
+    fn name(&self) -> &str {
+        match self {
+            Self::Int(name, _) | Self::IntArray(name, _) => name,
+        }
+    }
+}
+
+/// Writes an `R` class for each package to the jar at `path`. The class of a
+/// package with an `R.txt` only contains the symbols listed in the `R.txt`,
+/// the values are taken from `symbols`.
+pub fn write_r_jar(
+    path: &Path,
+    symbols: &Symbols,
+    packages: &[(String, Option<&Path>)],
+) -> Result<()> {
+    let fields = fields(symbols);
+    let mut zip = Zip::new(path, true)?;
+    for (package, r_txt) in packages {
+        let fields = if let Some(r_txt) = r_txt {
+            let listed = parse_r_txt(&std::fs::read_to_string(r_txt)?)
+                .with_context(|| format!("invalid R.txt `{}`", r_txt.display()))?;
+            filter_fields(&fields, &listed)
+                .with_context(|| format!("failed to generate the R class of {}", package))?
+        } else {
+            fields.clone()
+        };
+        let outer = format!("{}/R", package.replace('.', "/"));
+        let inner = fields
+            .keys()
+            .map(|ty| (format!("{}${}", outer, ty), ty.clone()))
+            .collect::<Vec<_>>();
+        for ((class, ty), fields) in inner.iter().zip(fields.values()) {
+            let bytes = class_file(class, fields, &outer, &[(class.clone(), ty.clone())])?;
+            zip.create_file(
+                Path::new(&format!("{}.class", class)),
+                ZipFileOptions::Compressed,
+                &bytes,
+            )?;
+        }
+        let bytes = class_file(&outer, &[], &outer, &inner)?;
+        zip.create_file(
+            Path::new(&format!("{}.class", outer)),
+            ZipFileOptions::Compressed,
+            &bytes,
+        )?;
+    }
+    zip.finish()
+}
+
+/// Returns the field name of a resource, `Theme.AppCompat` is accessed as
+/// `R.style.Theme_AppCompat`.
+fn field_name(name: &str) -> String {
+    name.replace(['.', ':', '-'], "_")
+}
+
+/// Returns the fields of each type. Styleables are an array of the attribute
+/// ids and an index field for each attribute.
+fn fields(symbols: &Symbols) -> BTreeMap<String, Vec<Field>> {
+    let mut fields: BTreeMap<String, Vec<Field>> = BTreeMap::new();
+    for (ty, name, id) in &symbols.resources {
+        fields
+            .entry(ty.clone())
+            .or_default()
+            .push(Field::Int(field_name(name), *id));
+    }
+    for (name, attrs) in &symbols.styleables {
+        let styleable = fields.entry("styleable".into()).or_default();
+        let name = field_name(name);
+        styleable.push(Field::IntArray(
+            name.clone(),
+            attrs.iter().map(|(_, id)| *id).collect(),
+        ));
+        for (i, (attr, _)) in attrs.iter().enumerate() {
+            styleable.push(Field::Int(
+                format!("{}_{}", name, field_name(attr)),
+                i as u32,
+            ));
+        }
+    }
+    fields
+}
+
+/// Returns the `(type, name)` of the fields declared in an `R.txt`, with
+/// lines like `int string app_name 0x7f0f001b` or
+/// `int[] styleable ActionBar { 0x7f030031, 0x7f030032 }`.
+fn parse_r_txt(r_txt: &str) -> Result<BTreeSet<(String, String)>> {
+    let mut fields = BTreeSet::new();
+    for line in r_txt.lines().filter(|line| !line.trim().is_empty()) {
+        let mut parts = line.split_whitespace();
+        let (kind, ty, name) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(kind), Some(ty), Some(name)) => (kind, ty, name),
+            _ => anyhow::bail!("invalid line `{}`", line),
+        };
+        anyhow::ensure!(
+            kind == "int" || kind == "int[]",
+            "invalid field type `{}`",
+            kind
+        );
+        fields.insert((ty.to_string(), name.to_string()));
+    }
+    Ok(fields)
+}
+
+fn filter_fields(
+    fields: &BTreeMap<String, Vec<Field>>,
+    listed: &BTreeSet<(String, String)>,
+) -> Result<BTreeMap<String, Vec<Field>>> {
+    let mut filtered: BTreeMap<String, Vec<Field>> = BTreeMap::new();
+    let mut found = 0;
+    for (ty, fields) in fields {
+        for field in fields {
+            if listed.contains(&(ty.clone(), field.name().to_string())) {
+                filtered.entry(ty.clone()).or_default().push(field.clone());
+                found += 1;
+            }
+        }
+    }
+    if found < listed.len() {
+        let (ty, name) = listed
+            .iter()
+            .find(|(ty, name)| {
+                !filtered
+                    .get(ty)
+                    .map(|fields| fields.iter().any(|field| field.name() == name))
+                    .unwrap_or_default()
+            })
+            .unwrap();
+        anyhow::bail!("missing resource for R.{}.{}", ty, name);
+    }
+    Ok(filtered)
+}
+
+/// The constant pool of a class file. Equal constants are only added once.
+struct ConstantPool {
+    bytes: Vec<u8>,
+    count: u16,
+    constants: HashMap<Vec<u8>, u16>,
+}
+
+impl ConstantPool {
+    fn new() -> Self {
+        Self {
+            bytes: vec![],
+            // the first index is unused
+            count: 1,
+            constants: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, constant: Vec<u8>) -> Result<u16> {
+        if let Some(index) = self.constants.get(&constant) {
+            return Ok(*index);
+        }
+        anyhow::ensure!(self.count < u16::MAX, "too many constants");
+        let index = self.count;
+        self.count += 1;
+        self.bytes.extend_from_slice(&constant);
+        self.constants.insert(constant, index);
+        Ok(index)
+    }
+
+    /// Names are ascii, so modified utf-8 is the same as utf-8.
+    fn utf8(&mut self, s: &str) -> Result<u16> {
+        let mut constant = vec![1];
+        constant.write_u16::<BigEndian>(s.len().try_into()?)?;
+        constant.extend_from_slice(s.as_bytes());
+        self.add(constant)
+    }
+
+    fn integer(&mut self, value: u32) -> Result<u16> {
+        let mut constant = vec![3];
+        constant.write_u32::<BigEndian>(value)?;
+        self.add(constant)
+    }
+
+    fn class(&mut self, name: &str) -> Result<u16> {
+        let name = self.utf8(name)?;
+        let mut constant = vec![7];
+        constant.write_u16::<BigEndian>(name)?;
+        self.add(constant)
+    }
+
+    fn field_ref(&mut self, class: &str, name: &str, descriptor: &str) -> Result<u16> {
+        let class = self.class(class)?;
+        let name = self.utf8(name)?;
+        let descriptor = self.utf8(descriptor)?;
+        let mut name_and_type = vec![12];
+        name_and_type.write_u16::<BigEndian>(name)?;
+        name_and_type.write_u16::<BigEndian>(descriptor)?;
+        let name_and_type = self.add(name_and_type)?;
+        let mut constant = vec![9];
+        constant.write_u16::<BigEndian>(class)?;
+        constant.write_u16::<BigEndian>(name_and_type)?;
+        self.add(constant)
+    }
+}
+
+/// Pushes an int constant onto the operand stack.
+fn push_int(code: &mut Vec<u8>, pool: &mut ConstantPool, value: u32) -> Result<()> {
+    match value as i32 {
+        value @ -1..=5 => code.push((0x03 + value) as u8),
+        value @ -128..=127 => code.extend_from_slice(&[0x10, value as u8]),
+        value @ -32768..=32767 => {
+            code.push(0x11);
+            code.write_i16::<BigEndian>(value as i16)?;
+        }
+        _ => {
+            // ldc_w
+            code.push(0x13);
+            code.write_u16::<BigEndian>(pool.integer(value)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns a `public final class` with `public static final` fields. Int
+/// fields are constants, arrays are initialized in the static initializer.
+/// `inner_classes` are the `(class, name)` of the inner classes of `outer`
+/// declared or referenced by the class.
+fn class_file(
+    class: &str,
+    fields: &[Field],
+    outer: &str,
+    inner_classes: &[(String, String)],
+) -> Result<Vec<u8>> {
+    let mut pool = ConstantPool::new();
+    let this_class = pool.class(class)?;
+    let super_class = pool.class("java/lang/Object")?;
+    let field_access = ACC_PUBLIC | ACC_STATIC | ACC_FINAL;
+
+    let mut field_bytes = vec![];
+    let mut clinit = vec![];
+    for field in fields {
+        field_bytes.write_u16::<BigEndian>(field_access)?;
+        field_bytes.write_u16::<BigEndian>(pool.utf8(field.name())?)?;
+        match field {
+            Field::Int(_, value) => {
+                field_bytes.write_u16::<BigEndian>(pool.utf8("I")?)?;
+                field_bytes.write_u16::<BigEndian>(1)?;
+                field_bytes.write_u16::<BigEndian>(pool.utf8("ConstantValue")?)?;
+                field_bytes.write_u32::<BigEndian>(2)?;
+                field_bytes.write_u16::<BigEndian>(pool.integer(*value)?)?;
+            }
+            Field::IntArray(name, values) => {
+                field_bytes.write_u16::<BigEndian>(pool.utf8("[I")?)?;
+                field_bytes.write_u16::<BigEndian>(0)?;
+                push_int(&mut clinit, &mut pool, values.len() as u32)?;
+                // newarray int
+                clinit.extend_from_slice(&[0xbc, 10]);
+                for (i, value) in values.iter().enumerate() {
+                    // dup
+                    clinit.push(0x59);
+                    push_int(&mut clinit, &mut pool, i as u32)?;
+                    push_int(&mut clinit, &mut pool, *value)?;
+                    // iastore
+                    clinit.push(0x4f);
+                }
+                // putstatic
+                clinit.push(0xb3);
+                clinit.write_u16::<BigEndian>(pool.field_ref(class, name, "[I")?)?;
+            }
+        }
+    }
+
+    let mut method_bytes = vec![];
+    let methods_count = if clinit.is_empty() {
+        0
+    } else {
+        // return
+        clinit.push(0xb1);
+        anyhow::ensure!(
+            clinit.len() <= u16::MAX as usize,
+            "static initializer of {} is too large",
+            class
+        );
+        method_bytes.write_u16::<BigEndian>(ACC_STATIC)?;
+        method_bytes.write_u16::<BigEndian>(pool.utf8("<clinit>")?)?;
+        method_bytes.write_u16::<BigEndian>(pool.utf8("()V")?)?;
+        method_bytes.write_u16::<BigEndian>(1)?;
+        method_bytes.write_u16::<BigEndian>(pool.utf8("Code")?)?;
+        method_bytes.write_u32::<BigEndian>(12 + clinit.len() as u32)?;
+        // the array, its copy, the index and the value
+        method_bytes.write_u16::<BigEndian>(4)?;
+        method_bytes.write_u16::<BigEndian>(0)?;
+        method_bytes.write_u32::<BigEndian>(clinit.len() as u32)?;
+        method_bytes.extend_from_slice(&clinit);
+        // no exception table and attributes
+        method_bytes.write_u16::<BigEndian>(0)?;
+        method_bytes.write_u16::<BigEndian>(0)?;
+        1
+    };
+
+    // the `InnerClasses` attribute makes `R.string` resolvable by compilers
+    let mut attribute_bytes = vec![];
+    attribute_bytes.write_u16::<BigEndian>(pool.utf8("InnerClasses")?)?;
+    attribute_bytes.write_u32::<BigEndian>(2 + 8 * inner_classes.len() as u32)?;
+    attribute_bytes.write_u16::<BigEndian>(inner_classes.len() as u16)?;
+    let outer = pool.class(outer)?;
+    for (inner, name) in inner_classes {
+        attribute_bytes.write_u16::<BigEndian>(pool.class(inner)?)?;
+        attribute_bytes.write_u16::<BigEndian>(outer)?;
+        attribute_bytes.write_u16::<BigEndian>(pool.utf8(name)?)?;
+        attribute_bytes.write_u16::<BigEndian>(ACC_PUBLIC | ACC_STATIC | ACC_FINAL)?;
+    }
+
+    let mut bytes = vec![];
+    bytes.write_u32::<BigEndian>(0xcafe_babe)?;
+    bytes.write_u16::<BigEndian>(0)?;
+    bytes.write_u16::<BigEndian>(CLASS_VERSION)?;
+    bytes.write_u16::<BigEndian>(pool.count)?;
+    bytes.extend_from_slice(&pool.bytes);
+    bytes.write_u16::<BigEndian>(ACC_PUBLIC | ACC_FINAL | ACC_SUPER)?;
+    bytes.write_u16::<BigEndian>(this_class)?;
+    bytes.write_u16::<BigEndian>(super_class)?;
+    bytes.write_u16::<BigEndian>(0)?;
+    bytes.write_u16::<BigEndian>(fields.len().try_into()?)?;
+    bytes.extend_from_slice(&field_bytes);
+    bytes.write_u16::<BigEndian>(methods_count)?;
+    bytes.extend_from_slice(&method_bytes);
+    bytes.write_u16::<BigEndian>(1)?;
+    bytes.extend_from_slice(&attribute_bytes);
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbols() -> Symbols {
+        Symbols {
+            resources: vec![
+                ("attr".into(), "colorPrimary".into(), 0x7f01_0000),
+                ("string".into(), "app_name".into(), 0x7f02_0000),
+                ("style".into(), "Theme.App".into(), 0x7f03_0000),
+            ],
+            styleables: vec![(
+                "View".into(),
+                vec![
+                    ("android:text".into(), 0x0101_014f),
+                    ("colorPrimary".into(), 0x7f01_0000),
+                ],
+            )],
+        }
+    }
+
+    #[test]
+    fn test_fields() {
+        let fields = fields(&symbols());
+        assert_eq!(
+            fields["style"],
+            [Field::Int("Theme_App".into(), 0x7f03_0000)]
+        );
+        assert_eq!(
+            fields["styleable"],
+            [
+                Field::IntArray("View".into(), vec![0x0101_014f, 0x7f01_0000]),
+                Field::Int("View_android_text".into(), 0),
+                Field::Int("View_colorPrimary".into(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filter_fields() -> Result<()> {
+        let fields = fields(&symbols());
+        let listed = parse_r_txt(
+            "int attr colorPrimary 0x7f040001\n\
+             int[] styleable View { 0x0101014f, 0x7f040001 }\n\
+             int styleable View_android_text 0\n\
+             int styleable View_colorPrimary 1\n",
+        )?;
+        let filtered = filter_fields(&fields, &listed)?;
+        assert_eq!(filtered.keys().collect::<Vec<_>>(), ["attr", "styleable"]);
+        assert_eq!(filtered["styleable"], fields["styleable"]);
+        let listed = parse_r_txt("int string missing 0x7f0f0001")?;
+        assert!(filter_fields(&fields, &listed).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_class_file() -> Result<()> {
+        let fields = fields(&symbols());
+        let class = "com/example/R$styleable";
+        let bytes = class_file(
+            class,
+            &fields["styleable"],
+            "com/example/R",
+            &[(class.into(), "styleable".into())],
+        )?;
+        assert_eq!(bytes[..4], [0xca, 0xfe, 0xba, 0xbe]);
+        // a constant larger than a short is loaded with `ldc_w`
+        let mut pool = ConstantPool::new();
+        let mut code = vec![];
+        for value in [1, 100, 1000, 0x7f01_0000, u32::MAX] {
+            push_int(&mut code, &mut pool, value)?;
+        }
+        assert_eq!(
+            code,
+            [0x04, 0x10, 100, 0x11, 0x03, 0xe8, 0x13, 0x00, 0x01, 0x02]
+        );
+        Ok(())
+    }
+}
@@ -1,13 +1,15 @@
+use crate::lineage::Lineage;
 use anyhow::Result;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use p256::ecdsa::signature::hazmat::PrehashVerifier;
 use rasn_pkix::Certificate;
-use rsa::pkcs8::{DecodePublicKey, EncodePublicKey};
-use rsa::{PaddingScheme, PublicKey, RsaPublicKey};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::{PaddingScheme, PublicKey, PublicKeyParts, RsaPublicKey};
 use sha2::{Digest as _, Sha256};
-use std::fs::File;
-use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
 use std::path::Path;
-use xcommon::{Signer, ZipInfo};
+use xcommon::{SignatureAlgorithm, Signer, ZipInfo};
 
 const DEBUG_PEM: &str = include_str!("../assets/debug.pem");
 
@@ -15,22 +17,251 @@ const APK_SIGNING_BLOCK_MAGIC: &[u8] = b"APK Sig Block 42";
 const APK_SIGNING_BLOCK_V2_ID: u32 = 0x7109871a;
 const APK_SIGNING_BLOCK_V3_ID: u32 = 0xf05368c0;
 const APK_SIGNING_BLOCK_V4_ID: u32 = 0x42726577;
-const RSA_PKCS1V15_SHA2_256: u32 = 0x0103;
+pub(crate) const RSA_PKCS1V15_SHA2_256: u32 = 0x0103;
+pub(crate) const ECDSA_SHA2_256: u32 = 0x0201;
+const PROOF_OF_ROTATION_ATTR_ID: u32 = 0x3ba06f8c;
+const STRIPPING_PROTECTION_ATTR_ID: u32 = 0xbeeff00d;
+const V3_MIN_SDK_VERSION: u32 = 28;
+const V3_MAX_SDK_VERSION: u32 = i32::MAX as u32;
 const MAX_CHUNK_SIZE: usize = 1024 * 1024;
 
-pub fn verify(path: &Path) -> Result<Vec<Certificate>> {
-    let f = File::open(path)?;
-    let mut r = BufReader::new(f);
-    let sblock = parse_apk_signing_block(&mut r)?;
-    let mut sblockv2 = None;
+/// Key rotation settings for APK Signature Scheme v3.
+#[derive(Clone, Debug)]
+pub struct Rotation {
+    /// Lineage from the original signing certificate to the current one.
+    pub lineage: Lineage,
+    /// Signer of the first certificate in the lineage. When present it is
+    /// used for the v2 signature, so that devices older than Android 9 keep
+    /// accepting updates. Apks with a `minSdkVersion` below 28 require it.
+    pub original: Option<Signer>,
+}
+
+/// Result of verifying the signatures of an apk.
+#[derive(Clone, Debug, Default)]
+pub struct Verification {
+    /// Certificates of the v3 signers or the v2 signers if there is no v3 block.
+    pub certificates: Vec<Certificate>,
+    /// Proof-of-rotation lineage of the v3 signer.
+    pub lineage: Option<Lineage>,
+}
+
+/// Algorithm of a signing key.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeyAlgorithm {
+    Rsa,
+    Ec,
+}
+
+impl std::fmt::Display for KeyAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Rsa => write!(f, "RSA"),
+            Self::Ec => write!(f, "EC"),
+        }
+    }
+}
+
+/// Certificate of a signer with its digest and key details.
+#[derive(Clone, Debug)]
+pub struct SignerCertificate {
+    pub certificate: Certificate,
+    /// SHA-256 digest of the DER encoded certificate.
+    pub sha256: [u8; 32],
+    pub key_algorithm: KeyAlgorithm,
+    /// Key size in bits.
+    pub key_size: usize,
+}
+
+impl SignerCertificate {
+    pub fn new(certificate: Certificate) -> Result<Self> {
+        let der = rasn::der::encode(&certificate).map_err(|err| anyhow::anyhow!("{}", err))?;
+        let public_key = rasn::der::encode(&certificate.tbs_certificate.subject_public_key_info)
+            .map_err(|err| anyhow::anyhow!("{}", err))?;
+        let (key_algorithm, key_size) = key_info(&public_key)?;
+        Ok(Self {
+            certificate,
+            sha256: Sha256::digest(der).into(),
+            key_algorithm,
+            key_size,
+        })
+    }
+
+    /// Lowercase hex encoded SHA-256 digest, as printed by `apksigner`.
+    pub fn sha256_hex(&self) -> String {
+        hex(&self.sha256)
+    }
+}
+
+/// Result of verifying one signature scheme.
+#[derive(Clone, Debug, Default)]
+pub enum SchemeVerification {
+    /// The apk isn't signed with this scheme.
+    #[default]
+    Missing,
+    /// The signature is valid, contains the signer certificates.
+    Verified(Vec<SignerCertificate>),
+    /// The signature is invalid.
+    Failed(String),
+}
+
+impl SchemeVerification {
+    fn new(result: Result<Vec<Certificate>>) -> Self {
+        let result = result.and_then(|certificates| {
+            certificates
+                .into_iter()
+                .map(SignerCertificate::new)
+                .collect::<Result<Vec<_>>>()
+        });
+        match result {
+            Ok(signers) => Self::Verified(signers),
+            Err(err) => Self::Failed(err.to_string()),
+        }
+    }
+
+    pub fn is_verified(&self) -> bool {
+        matches!(self, Self::Verified(_))
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self, Self::Failed(_))
+    }
+
+    fn signers(&self) -> Option<&[SignerCertificate]> {
+        if let Self::Verified(signers) = self {
+            Some(signers)
+        } else {
+            None
+        }
+    }
+}
+
+/// Report of all signature schemes of an apk, like `apksigner verify
+/// --print-certs`.
+#[derive(Clone, Debug, Default)]
+pub struct VerificationReport {
+    /// JAR signing.
+    pub v1: SchemeVerification,
+    /// APK Signature Scheme v2.
+    pub v2: SchemeVerification,
+    /// APK Signature Scheme v3.
+    pub v3: SchemeVerification,
+    /// APK Signature Scheme v4 in `<name>.apk.idsig`.
+    pub v4: SchemeVerification,
+    /// Proof-of-rotation lineage of the v3 signer.
+    pub lineage: Option<Lineage>,
+}
+
+impl VerificationReport {
+    /// Returns true if the apk is signed with v1, v2 or v3 and none of the
+    /// present signatures failed to verify.
+    pub fn is_verified(&self) -> bool {
+        let schemes = [&self.v1, &self.v2, &self.v3, &self.v4];
+        schemes[..3].iter().any(|scheme| scheme.is_verified())
+            && !schemes.iter().any(|scheme| scheme.is_failed())
+    }
+
+    /// Signers android uses to identify the app, the v3 signers or the v2
+    /// signers or the v1 signers.
+    pub fn signers(&self) -> &[SignerCertificate] {
+        [&self.v3, &self.v2, &self.v1]
+            .into_iter()
+            .find_map(|scheme| scheme.signers())
+            .unwrap_or_default()
+    }
+
+    /// Returns true if any signer uses the debug certificate that apks are
+    /// signed with when no signer is configured.
+    pub fn is_debug_signed(&self) -> bool {
+        let debug = debug_signer().ok().and_then(|signer| {
+            rasn::der::encode(signer.cert())
+                .ok()
+                .map(|der| <[u8; 32]>::from(Sha256::digest(der)))
+        });
+        [&self.v1, &self.v2, &self.v3, &self.v4]
+            .iter()
+            .filter_map(|scheme| scheme.signers())
+            .flatten()
+            .any(|signer| Some(signer.sha256) == debug)
+    }
+}
+
+impl std::fmt::Display for VerificationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let schemes = [
+            ("v1", "JAR signing", &self.v1),
+            ("v2", "APK Signature Scheme v2", &self.v2),
+            ("v3", "APK Signature Scheme v3", &self.v3),
+            ("v4", "APK Signature Scheme v4", &self.v4),
+        ];
+        for (name, description, scheme) in schemes {
+            writeln!(
+                f,
+                "Verified using {} scheme ({}): {}",
+                name,
+                description,
+                scheme.is_verified()
+            )?;
+        }
+        for (i, signer) in self.signers().iter().enumerate() {
+            let i = i + 1;
+            writeln!(
+                f,
+                "Signer #{} certificate SHA-256 digest: {}",
+                i,
+                signer.sha256_hex()
+            )?;
+            writeln!(f, "Signer #{} key algorithm: {}", i, signer.key_algorithm)?;
+            writeln!(f, "Signer #{} key size (bits): {}", i, signer.key_size)?;
+        }
+        if let Some(lineage) = &self.lineage {
+            for (i, node) in lineage.nodes().iter().enumerate() {
+                writeln!(
+                    f,
+                    "Lineage #{} certificate SHA-256 digest: {}",
+                    i + 1,
+                    hex(&Sha256::digest(&node.certificate))
+                )?;
+            }
+        }
+        for (name, _, scheme) in schemes {
+            if let SchemeVerification::Failed(err) = scheme {
+                writeln!(f, "ERROR ({}): {}", name, err)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Returns the algorithm and size in bits of a DER encoded public key.
+pub(crate) fn key_info(public_key: &[u8]) -> Result<(KeyAlgorithm, usize)> {
+    if let Ok(pubkey) = RsaPublicKey::from_public_key_der(public_key) {
+        Ok((KeyAlgorithm::Rsa, pubkey.n().bits()))
+    } else if p256::PublicKey::from_public_key_der(public_key).is_ok() {
+        Ok((KeyAlgorithm::Ec, 256))
+    } else if p384::PublicKey::from_public_key_der(public_key).is_ok() {
+        Ok((KeyAlgorithm::Ec, 384))
+    } else {
+        anyhow::bail!("unsupported public key")
+    }
+}
+
+/// Finds the v2 and v3 blocks in the APK signing block.
+fn signature_scheme_blocks(sblock: &ApkSignatureBlock) -> (Option<u64>, Option<u64>) {
+    let mut v2 = None;
+    let mut v3 = None;
     for block in &sblock.blocks {
         match block.id {
             APK_SIGNING_BLOCK_V2_ID => {
                 tracing::debug!("v2 signing block");
-                sblockv2 = Some(*block);
+                v2 = Some(block.start);
             }
             APK_SIGNING_BLOCK_V3_ID => {
                 tracing::debug!("v3 signing block");
+                v3 = Some(block.start);
             }
             APK_SIGNING_BLOCK_V4_ID => {
                 tracing::debug!("v4 signing block");
@@ -40,80 +271,241 @@ pub fn verify(path: &Path) -> Result<Vec<Certificate>> {
             }
         }
     }
-    let block = if let Some(block) = sblockv2 {
-        r.seek(SeekFrom::Start(block.start))?;
-        ApkSignatureBlockV2::read(&mut r)?
-    } else {
-        anyhow::bail!("no signing block v2 found");
-    };
-    let zip_hash = compute_digest(&mut r, sblock.sb_start, sblock.cd_start, sblock.cde_start)?;
+    (v2, v3)
+}
+
+fn verify_v2<R: Read + Seek>(
+    r: &mut R,
+    start: u64,
+    zip_hash: [u8; 32],
+    has_v3: bool,
+) -> Result<Vec<Certificate>> {
+    r.seek(SeekFrom::Start(start))?;
+    let block = ApkSignatureSchemeBlock::read(r, false)?;
     let mut certificates = vec![];
-    for signer in &block.signers {
-        anyhow::ensure!(
-            !signer.signatures.is_empty(),
-            "found no signatures in v2 block"
-        );
-        for sig in &signer.signatures {
-            anyhow::ensure!(
-                sig.algorithm == RSA_PKCS1V15_SHA2_256,
-                "found unsupported signature algorithm 0x{:x}",
-                sig.algorithm
-            );
-            let pubkey = RsaPublicKey::from_public_key_der(&signer.public_key)?;
-            let digest = Sha256::digest(&signer.signed_data);
-            let padding = PaddingScheme::new_pkcs1v15_sign::<sha2::Sha256>();
-            pubkey.verify(padding, &digest, &sig.signature)?;
+    for signed_data in block.verify(zip_hash, "v2")? {
+        for (id, value) in &signed_data.additional_attributes {
+            if *id == STRIPPING_PROTECTION_ATTR_ID {
+                anyhow::ensure!(
+                    value[..] != 3u32.to_le_bytes() || has_v3,
+                    "v2 signature requires a v3 signature which was stripped"
+                );
+            } else {
+                tracing::debug!("v2: additional attribute: 0x{:x} {:?}", id, value);
+            }
         }
-        let mut r = Cursor::new(&signer.signed_data[..]);
-        let signed_data = SignedData::read(&mut r)?;
-        anyhow::ensure!(
-            !signed_data.digests.is_empty(),
-            "found no digests in v2 block"
-        );
-        for digest in &signed_data.digests {
+        certificates.extend(signed_data.decode_certificates()?);
+    }
+    Ok(certificates)
+}
+
+fn verify_v3<R: Read + Seek>(
+    r: &mut R,
+    start: u64,
+    zip_hash: [u8; 32],
+) -> Result<(Vec<Certificate>, Option<Lineage>)> {
+    r.seek(SeekFrom::Start(start))?;
+    let block = ApkSignatureSchemeBlock::read(r, true)?;
+    let mut certificates = vec![];
+    let mut lineage = None;
+    for signed_data in block.verify(zip_hash, "v3")? {
+        for (id, value) in &signed_data.additional_attributes {
+            if *id == PROOF_OF_ROTATION_ATTR_ID {
+                let proof = Lineage::read(&mut Cursor::new(value))?;
+                proof.verify()?;
+                anyhow::ensure!(
+                    signed_data.certificates.first().map(|c| &c[..]) == Some(proof.last()),
+                    "v3 signer is not the last certificate in the lineage"
+                );
+                lineage = Some(proof);
+            } else {
+                tracing::debug!("v3: additional attribute: 0x{:x} {:?}", id, value);
+            }
+        }
+        certificates.extend(signed_data.decode_certificates()?);
+    }
+    Ok((certificates, lineage))
+}
+
+pub fn verify(path: &Path) -> Result<Verification> {
+    let f = File::open(path)?;
+    let mut r = BufReader::new(f);
+    let sblock = parse_apk_signing_block(&mut r)?;
+    let (sblockv2, sblockv3) = signature_scheme_blocks(&sblock);
+    anyhow::ensure!(
+        sblockv2.is_some() || sblockv3.is_some(),
+        "no signing block v2 or v3 found"
+    );
+    let zip_hash = compute_digest(&mut r, sblock.sb_start, sblock.cd_start, sblock.cde_start)?;
+    let mut verification = Verification::default();
+    if let Some(start) = sblockv2 {
+        verification.certificates = verify_v2(&mut r, start, zip_hash, sblockv3.is_some())?;
+    }
+    if let Some(start) = sblockv3 {
+        let (certificates, lineage) = verify_v3(&mut r, start, zip_hash)?;
+        verification.certificates = certificates;
+        verification.lineage = lineage;
+    }
+    Ok(verification)
+}
+
+/// Verifies all signature schemes of an apk. Unlike [`verify`] an invalid
+/// signature doesn't return an error but is recorded in the report.
+pub fn verify_report(path: &Path) -> Result<VerificationReport> {
+    let mut report = VerificationReport::default();
+    let mut r = BufReader::new(File::open(path)?);
+    let sblock = parse_apk_signing_block(&mut r)?;
+    let (sblockv2, sblockv3) = signature_scheme_blocks(&sblock);
+    if sblockv2.is_some() || sblockv3.is_some() {
+        let zip_hash = compute_digest(&mut r, sblock.sb_start, sblock.cd_start, sblock.cde_start)?;
+        if let Some(start) = sblockv2 {
+            report.v2 =
+                SchemeVerification::new(verify_v2(&mut r, start, zip_hash, sblockv3.is_some()));
+        }
+        if let Some(start) = sblockv3 {
+            let result = verify_v3(&mut r, start, zip_hash).map(|(certificates, lineage)| {
+                report.lineage = lineage;
+                certificates
+            });
+            report.v3 = SchemeVerification::new(result);
+        }
+    }
+
+    let v1 = crate::jar::verify(path).transpose().map(|result| {
+        let signature = result?;
+        for (scheme, verification) in [(2, &report.v2), (3, &report.v3)] {
             anyhow::ensure!(
-                digest.algorithm == RSA_PKCS1V15_SHA2_256,
-                "found unsupported digest algorithm 0x{:x}",
-                digest.algorithm
+                !signature.apk_signed.contains(&scheme)
+                    || !matches!(verification, SchemeVerification::Missing),
+                "v1: signature announces v{} which was stripped",
+                scheme
             );
+        }
+        Ok(signature.certificates)
+    });
+    if let Some(v1) = v1 {
+        report.v1 = SchemeVerification::new(v1);
+    }
+    if let (Some(v1), Some(v2)) = (report.v1.signers(), report.v2.signers()) {
+        if v1.iter().map(|s| s.sha256).ne(v2.iter().map(|s| s.sha256)) {
+            report.v1 = SchemeVerification::Failed("v1 and v2 signers don't match".into());
+        }
+    }
+
+    if crate::idsig::idsig_path(path).exists() {
+        let expected = report
+            .v3
+            .signers()
+            .or_else(|| report.v2.signers())
+            .and_then(|signers| signers.first())
+            .map(|signer| signer.sha256);
+        let v4 = crate::idsig::verify(path).and_then(|certificate| {
+            let der = rasn::der::encode(&certificate).map_err(|err| anyhow::anyhow!("{}", err))?;
             anyhow::ensure!(
-                digest.digest == zip_hash,
-                "computed hash doesn't match signed hash."
+                Some(<[u8; 32]>::from(Sha256::digest(der))) == expected,
+                "v4: signer doesn't match the v2/v3 signer"
             );
+            Ok(vec![certificate])
+        });
+        report.v4 = SchemeVerification::new(v4);
+    }
+    Ok(report)
+}
+
+pub(crate) fn verify_signature(
+    algorithm: u32,
+    public_key: &[u8],
+    data: &[u8],
+    signature: &[u8],
+) -> Result<()> {
+    let digest = Sha256::digest(data);
+    match algorithm {
+        RSA_PKCS1V15_SHA2_256 => {
+            let pubkey = RsaPublicKey::from_public_key_der(public_key)?;
+            let padding = PaddingScheme::new_pkcs1v15_sign::<sha2::Sha256>();
+            pubkey.verify(padding, &digest, signature)?;
         }
-        for cert in &signed_data.certificates {
-            let cert =
-                rasn::der::decode::<Certificate>(cert).map_err(|err| anyhow::anyhow!("{}", err))?;
-            certificates.push(cert);
-        }
-        for attr in &signed_data.additional_attributes {
-            tracing::debug!("v2: additional attribute: 0x{:x} {:?}", attr.0, &attr.1);
+        ECDSA_SHA2_256 => {
+            if let Ok(pubkey) = p256::ecdsa::VerifyingKey::from_public_key_der(public_key) {
+                let signature = p256::ecdsa::Signature::from_der(signature)?;
+                pubkey.verify_prehash(&digest, &signature)?;
+            } else {
+                let pubkey = p384::ecdsa::VerifyingKey::from_public_key_der(public_key)?;
+                let signature = p384::ecdsa::Signature::from_der(signature)?;
+                pubkey.verify_prehash(&digest, &signature)?;
+            }
         }
+        _ => anyhow::bail!("found unsupported signature algorithm 0x{:x}", algorithm),
     }
-    Ok(certificates)
+    Ok(())
+}
+
+/// Returns the signature algorithm id of a signer.
+pub(crate) fn signature_algorithm(signer: &Signer) -> u32 {
+    match signer.algorithm() {
+        SignatureAlgorithm::RsaPkcs1v15Sha256 => RSA_PKCS1V15_SHA2_256,
+        SignatureAlgorithm::EcdsaSha256 => ECDSA_SHA2_256,
+    }
+}
+
+pub(crate) fn debug_signer() -> Result<Signer> {
+    Signer::new(DEBUG_PEM)
 }
 
-pub fn sign(path: &Path, signer: Option<Signer>) -> Result<()> {
-    let signer = signer.map(Ok).unwrap_or_else(|| Signer::new(DEBUG_PEM))?;
-    let apk = std::fs::read(path)?;
-    let mut r = Cursor::new(&apk);
+pub fn sign(path: &Path, signer: Option<Signer>, rotation: Option<&Rotation>) -> Result<()> {
+    let signer = signer.map(Ok).unwrap_or_else(debug_signer)?;
+    if let Some(rotation) = rotation {
+        let cert = rasn::der::encode(signer.cert()).map_err(|err| anyhow::anyhow!("{}", err))?;
+        anyhow::ensure!(
+            rotation.lineage.last() == cert,
+            "signer is not the last certificate in the lineage"
+        );
+        if let Some(original) = rotation.original.as_ref() {
+            let cert =
+                rasn::der::encode(original.cert()).map_err(|err| anyhow::anyhow!("{}", err))?;
+            anyhow::ensure!(
+                rotation.lineage.first() == cert,
+                "original signer is not the first certificate in the lineage"
+            );
+        }
+    }
+    // the contents are hashed from disk and left in place, only the signing
+    // block, the central directory and the end of central directory are
+    // rewritten.
+    let mut f = OpenOptions::new().read(true).write(true).open(path)?;
+    let mut r = BufReader::new(&mut f);
     let block = parse_apk_signing_block(&mut r)?;
     let zip_hash = compute_digest(&mut r, block.sb_start, block.cd_start, block.cde_start)?;
+    let mut cd = Vec::with_capacity((block.cde_start - block.cd_start) as usize + 22);
+    r.seek(SeekFrom::Start(block.cd_start))?;
+    r.read_to_end(&mut cd)?;
+    drop(r);
     let mut nblock = vec![];
     let mut w = Cursor::new(&mut nblock);
-    write_apk_signing_block(&mut w, zip_hash, &signer)?;
-    let mut f = File::create(path)?;
-    f.write_all(&apk[..(block.sb_start as usize)])?;
-    f.write_all(&nblock)?;
-    let cd_start = f.stream_position()?;
-    f.write_all(&apk[(block.cd_start as usize)..(block.cde_start as usize)])?;
-    let cde_start = f.stream_position()?;
-    f.write_all(&apk[(block.cde_start as usize)..])?;
-    f.seek(SeekFrom::Start(cde_start + 16))?;
-    f.write_u32::<LittleEndian>(cd_start as u32)?;
+    write_apk_signing_block(&mut w, zip_hash, &signer, rotation)?;
+    let cd_start = block.sb_start + nblock.len() as u64;
+    let cd_offset = u32::try_from(cd_start).map_err(|_| {
+        anyhow::anyhow!("signed apk is larger than 4 GiB, which android doesn't support")
+    })?;
+    let cde_offset = (block.cde_start - block.cd_start) as usize;
+    cd[cde_offset + 16..cde_offset + 20].copy_from_slice(&cd_offset.to_le_bytes());
+    f.seek(SeekFrom::Start(block.sb_start))?;
+    let mut w = BufWriter::new(&mut f);
+    w.write_all(&nblock)?;
+    w.write_all(&cd)?;
+    w.flush()?;
+    drop(w);
+    f.set_len(cd_start + cd.len() as u64)?;
     Ok(())
 }
 
+/// Computes the v2/v3 content digest of an apk.
+pub(crate) fn content_digest(path: &Path) -> Result<[u8; 32]> {
+    let mut r = BufReader::new(File::open(path)?);
+    let block = parse_apk_signing_block(&mut r)?;
+    compute_digest(&mut r, block.sb_start, block.cd_start, block.cde_start)
+}
+
 fn compute_digest<R: Read + Seek>(
     r: &mut R,
     sb_start: u64,
@@ -169,7 +561,7 @@ fn hash_chunk<R: Read + Seek>(
     let end = std::cmp::min(*pos + MAX_CHUNK_SIZE as u64, size);
     let len = (end - *pos) as usize;
     buffer.resize(len, 0);
-    r.read_exact(buffer).unwrap();
+    r.read_exact(buffer)?;
     hasher.update([0xa5]);
     hasher.update((len as u32).to_le_bytes());
     hasher.update(buffer);
@@ -185,9 +577,9 @@ struct Digest {
 }
 
 impl Digest {
-    fn new(hash: [u8; 32]) -> Self {
+    fn new(algorithm: u32, hash: [u8; 32]) -> Self {
         Self {
-            algorithm: RSA_PKCS1V15_SHA2_256,
+            algorithm,
             digest: hash.to_vec(),
         }
     }
@@ -218,21 +610,31 @@ impl Digest {
 struct SignedData {
     pub digests: Vec<Digest>,
     pub certificates: Vec<Vec<u8>>,
+    /// Supported sdk versions, only present in v3.
+    pub sdk_range: Option<(u32, u32)>,
     pub additional_attributes: Vec<(u32, Vec<u8>)>,
 }
 
 impl SignedData {
     fn new(hash: [u8; 32], signer: &Signer) -> Result<Self> {
         Ok(Self {
-            digests: vec![Digest::new(hash)],
+            digests: vec![Digest::new(signature_algorithm(signer), hash)],
             certificates: vec![
                 rasn::der::encode(signer.cert()).map_err(|err| anyhow::anyhow!("{}", err))?
             ],
+            sdk_range: None,
             additional_attributes: vec![],
         })
     }
 
-    fn read(r: &mut impl Read) -> Result<Self> {
+    fn decode_certificates(&self) -> Result<Vec<Certificate>> {
+        self.certificates
+            .iter()
+            .map(|cert| rasn::der::decode(cert).map_err(|err| anyhow::anyhow!("{}", err)))
+            .collect()
+    }
+
+    fn read(r: &mut impl Read, v3: bool) -> Result<Self> {
         let mut signed_data = SignedData::default();
         let mut remaining_digests_size = r.read_u32::<LittleEndian>()?;
         while remaining_digests_size > 0 {
@@ -248,6 +650,11 @@ impl SignedData {
             signed_data.certificates.push(cert);
             remaining_certificates_size -= length + 4;
         }
+        if v3 {
+            let min_sdk = r.read_u32::<LittleEndian>()?;
+            let max_sdk = r.read_u32::<LittleEndian>()?;
+            signed_data.sdk_range = Some((min_sdk, max_sdk));
+        }
         let mut remaining_additional_attributes_size = r.read_u32::<LittleEndian>()?;
         while remaining_additional_attributes_size > 0 {
             let length = r.read_u32::<LittleEndian>()?;
@@ -270,6 +677,10 @@ impl SignedData {
             w.write_u32::<LittleEndian>(cert.len() as u32)?;
             w.write_all(cert)?;
         }
+        if let Some((min_sdk, max_sdk)) = self.sdk_range {
+            w.write_u32::<LittleEndian>(min_sdk)?;
+            w.write_u32::<LittleEndian>(max_sdk)?;
+        }
         w.write_u32::<LittleEndian>(
             self.additional_attributes
                 .iter()
@@ -285,14 +696,17 @@ impl SignedData {
     }
 }
 
+/// Signature scheme v2 or v3 block. v3 adds the supported sdk range to the
+/// signer and to the signed data.
 #[derive(Debug)]
-struct ApkSignatureBlockV2 {
+struct ApkSignatureSchemeBlock {
     pub signers: Vec<ApkSigner>,
 }
 
 #[derive(Debug)]
 struct ApkSigner {
     pub signed_data: Vec<u8>,
+    pub sdk_range: Option<(u32, u32)>,
     pub signatures: Vec<ApkSignature>,
     pub public_key: Vec<u8>,
 }
@@ -303,24 +717,102 @@ struct ApkSignature {
     pub signature: Vec<u8>,
 }
 
-impl ApkSignatureBlockV2 {
-    fn new(hash: [u8; 32], signer: &Signer) -> Result<Self> {
-        let mut signed_data = vec![];
-        SignedData::new(hash, signer)?.write(&mut signed_data)?;
-        let signature = signer.sign(&signed_data);
+impl ApkSignatureSchemeBlock {
+    fn v2(hash: [u8; 32], signer: &Signer) -> Result<Self> {
+        let mut signed_data = SignedData::new(hash, signer)?;
+        signed_data
+            .additional_attributes
+            .push((STRIPPING_PROTECTION_ATTR_ID, 3u32.to_le_bytes().to_vec()));
+        Self::new(signed_data, signer)
+    }
+
+    fn v3(hash: [u8; 32], signer: &Signer, lineage: Option<&Lineage>) -> Result<Self> {
+        let mut signed_data = SignedData::new(hash, signer)?;
+        signed_data.sdk_range = Some((V3_MIN_SDK_VERSION, V3_MAX_SDK_VERSION));
+        if let Some(lineage) = lineage {
+            signed_data
+                .additional_attributes
+                .push((PROOF_OF_ROTATION_ATTR_ID, lineage.to_vec()));
+        }
+        Self::new(signed_data, signer)
+    }
+
+    fn new(signed_data: SignedData, signer: &Signer) -> Result<Self> {
+        let sdk_range = signed_data.sdk_range;
+        let mut buf = vec![];
+        signed_data.write(&mut buf)?;
+        let signature = signer.sign(&buf);
         Ok(Self {
             signers: vec![ApkSigner {
-                signed_data,
+                signed_data: buf,
+                sdk_range,
                 signatures: vec![ApkSignature {
-                    algorithm: RSA_PKCS1V15_SHA2_256,
+                    algorithm: signature_algorithm(signer),
                     signature,
                 }],
-                public_key: signer.pubkey().to_public_key_der()?.as_ref().to_vec(),
+                public_key: signer.pubkey().to_vec(),
             }],
         })
     }
 
-    fn read(r: &mut impl Read) -> Result<Self> {
+    /// Verifies the signatures and digests of all signers and returns
+    /// their signed data.
+    fn verify(&self, zip_hash: [u8; 32], scheme: &str) -> Result<Vec<SignedData>> {
+        anyhow::ensure!(!self.signers.is_empty(), "{}: no signers found", scheme);
+        let mut result = Vec::with_capacity(self.signers.len());
+        for signer in &self.signers {
+            anyhow::ensure!(
+                !signer.signatures.is_empty(),
+                "{}: signer without signatures",
+                scheme
+            );
+            for sig in &signer.signatures {
+                verify_signature(
+                    sig.algorithm,
+                    &signer.public_key,
+                    &signer.signed_data,
+                    &sig.signature,
+                )
+                .map_err(|err| anyhow::anyhow!("{}: {}", scheme, err))?;
+            }
+            let signed_data = SignedData::read(
+                &mut Cursor::new(&signer.signed_data),
+                signer.sdk_range.is_some(),
+            )?;
+            anyhow::ensure!(
+                signed_data.sdk_range == signer.sdk_range,
+                "{}: sdk versions of signer and signed data don't match",
+                scheme
+            );
+            anyhow::ensure!(!signed_data.digests.is_empty(), "{}: no digests", scheme);
+            for digest in &signed_data.digests {
+                // both algorithms use the chunked SHA-256 content digest
+                anyhow::ensure!(
+                    [RSA_PKCS1V15_SHA2_256, ECDSA_SHA2_256].contains(&digest.algorithm),
+                    "{}: found unsupported digest algorithm 0x{:x}",
+                    scheme,
+                    digest.algorithm
+                );
+                anyhow::ensure!(digest.digest == zip_hash, "{}: digest mismatch", scheme);
+            }
+            let cert = signed_data
+                .decode_certificates()?
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("{}: no certificates", scheme))?;
+            let public_key = rasn::der::encode(&cert.tbs_certificate.subject_public_key_info)
+                .map_err(|err| anyhow::anyhow!("{}", err))?;
+            anyhow::ensure!(
+                public_key == signer.public_key,
+                "{}: public key doesn't match certificate",
+                scheme
+            );
+            result.push(signed_data);
+        }
+        Ok(result)
+    }
+
+    fn read(r: &mut impl Read, v3: bool) -> Result<Self> {
         let mut signers = vec![];
         let mut remaining_size = r.read_u32::<LittleEndian>()? as u64;
         while remaining_size > 0 {
@@ -330,6 +822,14 @@ impl ApkSignatureBlockV2 {
             let mut signed_data = vec![0; signed_data_size as _];
             r.read_exact(&mut signed_data)?;
 
+            let sdk_range = if v3 {
+                let min_sdk = r.read_u32::<LittleEndian>()?;
+                let max_sdk = r.read_u32::<LittleEndian>()?;
+                Some((min_sdk, max_sdk))
+            } else {
+                None
+            };
+
             let mut signatures = vec![];
             let mut remaining_signature_size = r.read_u32::<LittleEndian>()?;
             while remaining_signature_size > 0 {
@@ -351,12 +851,13 @@ impl ApkSignatureBlockV2 {
 
             signers.push(ApkSigner {
                 signed_data,
+                sdk_range,
                 signatures,
                 public_key,
             });
             remaining_size -= signer_size as u64 + 4;
         }
-        Ok(ApkSignatureBlockV2 { signers })
+        Ok(Self { signers })
     }
 
     fn write(&self, w: &mut impl Write) -> Result<()> {
@@ -365,6 +866,10 @@ impl ApkSignatureBlockV2 {
             let mut signer_buffer = vec![];
             signer_buffer.write_u32::<LittleEndian>(signer.signed_data.len() as u32)?;
             signer_buffer.write_all(&signer.signed_data)?;
+            if let Some((min_sdk, max_sdk)) = signer.sdk_range {
+                signer_buffer.write_u32::<LittleEndian>(min_sdk)?;
+                signer_buffer.write_u32::<LittleEndian>(max_sdk)?;
+            }
             let mut sig_buffer = vec![];
             for sig in &signer.signatures {
                 sig_buffer.write_u32::<LittleEndian>(sig.signature.len() as u32 + 8)?;
@@ -403,14 +908,30 @@ fn write_apk_signing_block<W: Write + Seek>(
     w: &mut W,
     hash: [u8; 32],
     signer: &Signer,
+    rotation: Option<&Rotation>,
 ) -> Result<()> {
-    let mut buf = vec![];
-    ApkSignatureBlockV2::new(hash, signer)?.write(&mut buf)?;
-    let size = buf.len() as u64 + 36;
+    // devices older than android 9 only know about v2, so keep signing it
+    // with the original key when rotating.
+    let v2_signer = rotation
+        .and_then(|rotation| rotation.original.as_ref())
+        .unwrap_or(signer);
+    let mut v2 = vec![];
+    ApkSignatureSchemeBlock::v2(hash, v2_signer)?.write(&mut v2)?;
+    let mut v3 = vec![];
+    ApkSignatureSchemeBlock::v3(hash, signer, rotation.map(|rotation| &rotation.lineage))?
+        .write(&mut v3)?;
+    let pairs = [(APK_SIGNING_BLOCK_V2_ID, v2), (APK_SIGNING_BLOCK_V3_ID, v3)];
+    let size = pairs
+        .iter()
+        .map(|(_, value)| value.len() as u64 + 12)
+        .sum::<u64>()
+        + 24;
     w.write_u64::<LittleEndian>(size)?;
-    w.write_u64::<LittleEndian>(buf.len() as u64 + 4)?;
-    w.write_u32::<LittleEndian>(APK_SIGNING_BLOCK_V2_ID)?;
-    w.write_all(&buf)?;
+    for (id, value) in &pairs {
+        w.write_u64::<LittleEndian>(value.len() as u64 + 4)?;
+        w.write_u32::<LittleEndian>(*id)?;
+        w.write_all(value)?;
+    }
     w.write_u64::<LittleEndian>(size)?;
     w.write_all(APK_SIGNING_BLOCK_MAGIC)?;
     Ok(())
@@ -418,6 +939,12 @@ fn write_apk_signing_block<W: Write + Seek>(
 
 fn parse_apk_signing_block<R: Read + Seek>(r: &mut R) -> Result<ApkSignatureBlock> {
     let info = ZipInfo::new(r)?;
+    // android rejects apks with a zip64 end of central directory
+    anyhow::ensure!(
+        !info.is_zip64(),
+        "zip64 apks are not supported by android, apks must be smaller than 4 GiB \
+         and contain fewer than 65536 entries"
+    );
     let mut block = ApkSignatureBlock {
         cde_start: info.cde_start,
         cd_start: info.cd_start,
@@ -445,3 +972,211 @@ fn parse_apk_signing_block<R: Read + Seek>(r: &mut R) -> Result<ApkSignatureBloc
     }
     Ok(block)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use xcommon::{Zip, ZipFileOptions};
+
+    const TEST_PEM: &str = include_str!("../../xcommon/assets/test.pem");
+    const P256_PEM: &str = include_str!("../../xcommon/assets/test-p256.pem");
+    const P384_PEM: &str = include_str!("../../xcommon/assets/test-p384.pem");
+
+    fn create_apk(dir: &Path, name: &str) -> Result<PathBuf> {
+        let path = dir.join(name);
+        let mut zip = Zip::new(&path, true)?;
+        zip.create_file(
+            Path::new("AndroidManifest.xml"),
+            ZipFileOptions::Compressed,
+            b"manifest",
+        )?;
+        zip.create_file(
+            Path::new("resources.arsc"),
+            ZipFileOptions::Aligned(4),
+            b"resources",
+        )?;
+        zip.finish()?;
+        Ok(path)
+    }
+
+    #[test]
+    fn sign_verify() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = create_apk(tmp.path(), "sign_verify.apk")?;
+        sign(&path, None, None)?;
+        let verification = verify(&path)?;
+        let signer = Signer::new(DEBUG_PEM)?;
+        assert_eq!(verification.certificates, vec![signer.cert().clone()]);
+        assert!(verification.lineage.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn sign_verify_ecdsa() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        for pem in [P256_PEM, P384_PEM] {
+            let path = create_apk(tmp.path(), "sign_verify_ecdsa.apk")?;
+            let signer = Signer::new(pem)?;
+            sign(&path, Some(signer.clone()), None)?;
+            let verification = verify(&path)?;
+            assert_eq!(verification.certificates, vec![signer.cert().clone()]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn sign_verify_jar_signed() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = create_apk(tmp.path(), "sign_verify_jar_signed.apk")?;
+        let signer = Signer::new(DEBUG_PEM)?;
+        crate::jar::sign(&path, &signer, true, crate::jar::JarDigest::Sha256)?;
+        sign(&path, Some(signer.clone()), None)?;
+        let verification = verify(&path)?;
+        assert_eq!(verification.certificates, vec![signer.cert().clone()]);
+        Ok(())
+    }
+
+    #[test]
+    fn sign_verify_rotation() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = create_apk(tmp.path(), "sign_verify_rotation.apk")?;
+        let original = Signer::new(DEBUG_PEM)?;
+        let signer = Signer::new(TEST_PEM)?;
+        let mut lineage = Lineage::new(&original)?;
+        lineage.rotate(&original, &signer)?;
+        let rotation = Rotation {
+            lineage: lineage.clone(),
+            original: Some(original),
+        };
+        sign(&path, Some(signer.clone()), Some(&rotation))?;
+        let verification = verify(&path)?;
+        assert_eq!(verification.certificates, vec![signer.cert().clone()]);
+        assert_eq!(verification.lineage, Some(lineage));
+        Ok(())
+    }
+
+    #[test]
+    fn sign_verify_rotation_to_ecdsa() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = create_apk(tmp.path(), "sign_verify_rotation_to_ecdsa.apk")?;
+        let original = Signer::new(DEBUG_PEM)?;
+        let signer = Signer::new(P256_PEM)?;
+        let mut lineage = Lineage::new(&original)?;
+        lineage.rotate(&original, &signer)?;
+        let rotation = Rotation {
+            lineage: lineage.clone(),
+            original: Some(original),
+        };
+        sign(&path, Some(signer.clone()), Some(&rotation))?;
+        let verification = verify(&path)?;
+        assert_eq!(verification.certificates, vec![signer.cert().clone()]);
+        assert_eq!(verification.lineage, Some(lineage));
+        Ok(())
+    }
+
+    #[test]
+    fn verify_report_schemes() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = create_apk(tmp.path(), "verify_report_schemes.apk")?;
+        let report = verify_report(&path)?;
+        assert!(!report.is_verified());
+        assert!(report.signers().is_empty());
+
+        let signer = debug_signer()?;
+        crate::jar::sign(&path, &signer, true, crate::jar::JarDigest::Sha256)?;
+        let report = verify_report(&path)?;
+        assert!(report.v1.is_failed());
+        assert!(!report.is_verified());
+
+        sign(&path, Some(signer), None)?;
+        crate::idsig::sign(&path, &debug_signer()?)?;
+        let report = verify_report(&path)?;
+        for scheme in [&report.v1, &report.v2, &report.v3, &report.v4] {
+            assert!(scheme.is_verified(), "{:?}", scheme);
+        }
+        assert!(report.is_verified());
+        assert!(report.is_debug_signed());
+        let signers = report.signers();
+        assert_eq!(signers.len(), 1);
+        assert_eq!(signers[0].key_algorithm, KeyAlgorithm::Rsa);
+        assert_eq!(signers[0].key_size, 2048);
+        assert!(report.to_string().contains(&format!(
+            "Signer #1 certificate SHA-256 digest: {}",
+            signers[0].sha256_hex()
+        )));
+        std::fs::remove_file(crate::idsig::idsig_path(&path))?;
+        Ok(())
+    }
+
+    #[test]
+    fn verify_report_rotation() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = create_apk(tmp.path(), "verify_report_rotation.apk")?;
+        let original = Signer::new(DEBUG_PEM)?;
+        let signer = Signer::new(P256_PEM)?;
+        let mut lineage = Lineage::new(&original)?;
+        lineage.rotate(&original, &signer)?;
+        let rotation = Rotation {
+            lineage: lineage.clone(),
+            original: Some(original),
+        };
+        sign(&path, Some(signer), Some(&rotation))?;
+        let report = verify_report(&path)?;
+        assert!(report.is_verified());
+        assert!(report.v1.signers().is_none());
+        assert_eq!(report.signers()[0].key_algorithm, KeyAlgorithm::Ec);
+        assert_eq!(report.signers()[0].key_size, 256);
+        assert_eq!(report.lineage, Some(lineage));
+        assert!(report
+            .to_string()
+            .contains("Lineage #2 certificate SHA-256 digest"));
+        Ok(())
+    }
+
+    #[test]
+    fn resign() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = create_apk(tmp.path(), "resign.apk")?;
+        sign(&path, None, None)?;
+        let len = std::fs::metadata(&path)?.len();
+        // the ecdsa signing block is smaller, the file must be truncated
+        let signer = Signer::new(P256_PEM)?;
+        sign(&path, Some(signer.clone()), None)?;
+        assert!(std::fs::metadata(&path)?.len() < len);
+        let verification = verify(&path)?;
+        assert_eq!(verification.certificates, vec![signer.cert().clone()]);
+        sign(&path, None, None)?;
+        assert_eq!(std::fs::metadata(&path)?.len(), len);
+        verify(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn reject_zip64() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("reject_zip64.apk");
+        let mut zip = Zip::new(&path, false)?;
+        for i in 0..=u16::MAX as usize {
+            zip.create_file(Path::new(&i.to_string()), ZipFileOptions::Unaligned, b"")?;
+        }
+        zip.finish()?;
+        let err = sign(&path, None, None).unwrap_err().to_string();
+        assert!(err.starts_with("zip64 apks are not supported"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_signer_not_in_lineage() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = create_apk(tmp.path(), "reject_signer_not_in_lineage.apk")?;
+        let original = Signer::new(DEBUG_PEM)?;
+        let rotation = Rotation {
+            lineage: Lineage::new(&original)?,
+            original: None,
+        };
+        let signer = Signer::new(TEST_PEM)?;
+        assert!(sign(&path, Some(signer), Some(&rotation)).is_err());
+        Ok(())
+    }
+}
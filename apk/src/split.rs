@@ -0,0 +1,310 @@
+use crate::aab::xml_from_proto;
+use crate::compiler::Output;
+use crate::manifest::{AndroidManifest, ANDROID_NS};
+use crate::pb::Message;
+use crate::res::{Chunk, ResTableConfig};
+use crate::Target;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const ATTR_HAS_CODE: u32 = 0x0101_000c;
+const ATTR_VERSION_CODE: u32 = 0x0101_021b;
+const ATTR_VERSION_NAME: u32 = 0x0101_021c;
+
+/// Name of the config split containing the libraries of `target`.
+pub fn abi_split(target: Target) -> String {
+    format!("config.{}", target.as_str().replace('-', "_"))
+}
+
+/// Name of the config split containing the resources of `density`. Resources
+/// without a density or for any density stay in the base apk.
+pub fn density_split(density: u16) -> Option<String> {
+    match density {
+        0 | ResTableConfig::DENSITY_ANY | ResTableConfig::DENSITY_NONE => None,
+        density => {
+            let config = ResTableConfig {
+                density,
+                ..Default::default()
+            };
+            Some(format!("config.{}", config))
+        }
+    }
+}
+
+/// Path of a split apk next to the base apk, `<name>.config.xhdpi.apk`.
+pub fn split_path(path: &Path, split: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap().to_str().unwrap();
+    path.with_file_name(format!("{}.{}.apk", stem, split))
+}
+
+/// Returns the split apks of the base apk at `path`.
+pub fn splits(path: &Path) -> Result<Vec<PathBuf>> {
+    let prefix = split_path(path, "config.");
+    let prefix = prefix.file_name().unwrap().to_str().unwrap();
+    let prefix = prefix.strip_suffix(".apk").unwrap();
+    let mut splits = vec![];
+    if let Some(dir) = path.parent().filter(|dir| dir.exists()) {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let file_name = path.file_name().unwrap().to_str().unwrap_or_default();
+            if file_name.starts_with(prefix) && file_name.ends_with(".apk") {
+                splits.push(path);
+            }
+        }
+    }
+    splits.sort();
+    Ok(splits)
+}
+
+/// Binary manifest of a config split of the app described by `manifest`.
+/// Written as an `aapt.pb.XmlNode` with the resource ids of the attributes,
+/// so that the android framework resources aren't required.
+pub fn split_manifest(manifest: &AndroidManifest, split: &str) -> Result<Chunk> {
+    let attr = |ns: &str, name: &str, value: &str, id: u32| {
+        let mut attr = Message::new();
+        if !ns.is_empty() {
+            attr.string(1, ns);
+        }
+        attr.string(2, name).string(3, value);
+        if id != 0 {
+            attr.uint(5, id as u64);
+        }
+        attr
+    };
+    let mut root = Message::new();
+    root.message(1, Message::new().string(1, "android").string(2, ANDROID_NS))
+        .string(3, "manifest");
+    let package = manifest
+        .package
+        .as_deref()
+        .context("missing manifest.package")?;
+    root.message(4, &attr("", "package", package, 0));
+    if let Some(version_code) = manifest.version_code {
+        let mut version_code_attr = attr(
+            ANDROID_NS,
+            "versionCode",
+            &version_code.to_string(),
+            ATTR_VERSION_CODE,
+        );
+        // int_decimal_value
+        let prim = Message::new().uint(6, version_code as u64).clone();
+        version_code_attr.message(6, Message::new().message(7, &prim));
+        root.message(4, &version_code_attr);
+    }
+    if let Some(version_name) = &manifest.version_name {
+        root.message(
+            4,
+            &attr(ANDROID_NS, "versionName", version_name, ATTR_VERSION_NAME),
+        );
+    }
+    root.message(4, &attr("", "split", split, 0));
+    let mut has_code = attr(ANDROID_NS, "hasCode", "false", ATTR_HAS_CODE);
+    // boolean_value
+    let prim = Message::new().bool(8, false).clone();
+    has_code.message(6, Message::new().message(7, &prim));
+    let mut application = Message::new();
+    application.string(3, "application").message(4, &has_code);
+    root.message(5, Message::new().message(1, &application));
+    xml_from_proto(Message::new().message(1, &root).as_bytes())
+}
+
+/// Returns the configuration of a resource file from the qualifiers of its
+/// directory, like `res/mipmap-xhdpi-v4/icon.png`.
+pub fn path_config(path: &str) -> Result<ResTableConfig> {
+    let dir = path
+        .strip_prefix("res/")
+        .and_then(|path| path.split_once('/'))
+        .map(|(dir, _)| dir);
+    if let Some((_, qualifiers)) = dir.and_then(|dir| dir.split_once('-')) {
+        ResTableConfig::from_qualifiers(qualifiers)
+    } else {
+        Ok(Default::default())
+    }
+}
+
+/// Returns the density split a compiled resource belongs to.
+fn output_split(output: &Output) -> Result<Option<String>> {
+    let path = match output {
+        Output::Table(_) => return Ok(None),
+        Output::Xml(path, _) | Output::File(path, _) => path,
+    };
+    Ok(density_split(path_config(path)?.density))
+}
+
+/// Moves the types of density specific configurations of a resource table
+/// into a table per density split. The split tables keep the string pools
+/// and the type specs of the types they contain so that resource ids
+/// match the base table.
+fn split_table(chunk: &Chunk) -> Result<(Chunk, BTreeMap<String, Chunk>)> {
+    let (header, chunks) = if let Chunk::Table(header, chunks) = chunk {
+        (header, chunks)
+    } else {
+        anyhow::bail!("expected a resource table");
+    };
+    let string_pools = |chunks: &[Chunk]| {
+        chunks
+            .iter()
+            .filter(|chunk| matches!(chunk, Chunk::StringPool(_, _)))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let mut base = vec![];
+    let mut splits: BTreeMap<String, Vec<Chunk>> = BTreeMap::new();
+    for chunk in chunks {
+        let (package_header, package_chunks) = if let Chunk::TablePackage(header, chunks) = chunk {
+            (header, chunks)
+        } else {
+            base.push(chunk.clone());
+            continue;
+        };
+        let mut base_package = vec![];
+        let mut split_packages: BTreeMap<String, Vec<Chunk>> = BTreeMap::new();
+        let mut spec = None;
+        for chunk in package_chunks {
+            let split = match chunk {
+                Chunk::TableTypeSpec(_, _) => {
+                    spec = Some(chunk);
+                    None
+                }
+                Chunk::TableType(header, _, _) => density_split(header.config.density),
+                _ => None,
+            };
+            if let Some(split) = split {
+                let package = split_packages
+                    .entry(split)
+                    .or_insert_with(|| string_pools(package_chunks));
+                if let Some(spec) = spec.filter(|spec| !package.contains(spec)) {
+                    package.push(spec.clone());
+                }
+                package.push(chunk.clone());
+            } else {
+                base_package.push(chunk.clone());
+            }
+        }
+        for (split, package) in split_packages {
+            splits
+                .entry(split)
+                .or_insert_with(|| string_pools(chunks))
+                .push(Chunk::TablePackage(package_header.clone(), package));
+        }
+        base.push(Chunk::TablePackage(package_header.clone(), base_package));
+    }
+    let splits = splits
+        .into_iter()
+        .map(|(split, chunks)| (split, Chunk::Table(*header, chunks)))
+        .collect();
+    Ok((Chunk::Table(*header, base), splits))
+}
+
+/// Compiled resources of each split.
+pub type SplitOutputs = BTreeMap<String, Vec<Output>>;
+
+/// Splits the compiled resources into the outputs of the base apk and the
+/// outputs of each density split.
+pub fn split_outputs(outputs: Vec<Output>) -> Result<(Vec<Output>, SplitOutputs)> {
+    let mut base = vec![];
+    let mut splits = SplitOutputs::new();
+    for output in outputs {
+        if let Output::Table(chunk) = &output {
+            let (table, split_tables) = split_table(chunk)?;
+            base.push(Output::Table(table));
+            for (split, table) in split_tables {
+                splits.entry(split).or_default().push(Output::Table(table));
+            }
+        } else if let Some(split) = output_split(&output)? {
+            splits.entry(split).or_default().push(output);
+        } else {
+            base.push(output);
+        }
+    }
+    Ok((base, splits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arsc::ResourceTable;
+    use crate::compiler::{compile_mipmap, PackageBuilder, Table, Value};
+    use crate::res::ResAttributeType;
+
+    #[test]
+    fn test_split_names() {
+        assert_eq!(abi_split(Target::Arm64V8a), "config.arm64_v8a");
+        assert_eq!(density_split(320).as_deref(), Some("config.xhdpi"));
+        assert_eq!(density_split(ResTableConfig::DENSITY_ANY), None);
+        assert_eq!(
+            split_path(Path::new("out/app.apk"), "config.x86_64"),
+            Path::new("out/app.config.x86_64.apk")
+        );
+    }
+
+    #[test]
+    fn test_split_manifest() -> Result<()> {
+        let mut manifest = AndroidManifest::default();
+        manifest.package = Some("com.example.helloworld".into());
+        manifest.version_code = Some(3);
+        manifest.version_name = Some("1.0.2".into());
+        let split =
+            crate::compiler::decompile_manifest(&split_manifest(&manifest, "config.xhdpi")?)?;
+        assert_eq!(split.package, manifest.package);
+        assert_eq!(split.version_code, Some(3));
+        assert_eq!(split.version_name.as_deref(), Some("1.0.2"));
+        assert_eq!(split.split.as_deref(), Some("config.xhdpi"));
+        assert_eq!(split.application.has_code, Some(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_outputs() -> Result<()> {
+        let mut package = PackageBuilder::new("com.example.helloworld");
+        package.add(
+            "string",
+            "app_name",
+            Default::default(),
+            Value::Item {
+                format: ResAttributeType::String as u32,
+                value: "Hello".into(),
+            },
+        )?;
+        let mipmap = compile_mipmap(&mut package, "icon", 48)?;
+        let mut outputs = vec![Output::Table(package.build(&mut Table::default())?)];
+        for (path, _) in mipmap.variants() {
+            outputs.push(Output::File(path, vec![]));
+        }
+        let (base, splits) = split_outputs(outputs)?;
+
+        assert_eq!(base.len(), 1);
+        let base = if let Output::Table(chunk) = &base[0] {
+            ResourceTable::from_chunk(chunk)?
+        } else {
+            unreachable!();
+        };
+        assert!(base.entry_by_name("@string/app_name").is_some());
+        assert!(base.entry_by_name("@mipmap/icon").is_none());
+
+        assert_eq!(
+            splits.keys().collect::<Vec<_>>(),
+            [
+                "config.hdpi",
+                "config.mdpi",
+                "config.xhdpi",
+                "config.xxhdpi",
+                "config.xxxhdpi"
+            ]
+        );
+        let xhdpi = &splits["config.xhdpi"];
+        assert_eq!(xhdpi.len(), 2);
+        let table = if let Output::Table(chunk) = &xhdpi[0] {
+            ResourceTable::from_chunk(chunk)?
+        } else {
+            unreachable!();
+        };
+        assert!(table.entry_by_name("@string/app_name").is_none());
+        let icon = table.entry_by_name("@mipmap/icon").unwrap();
+        assert_eq!(icon.values.len(), 1);
+        assert_eq!(icon.values[0].0.density, 320);
+        assert!(matches!(&xhdpi[1], Output::File(path, _) if path.starts_with("res/mipmap-xhdpi")));
+        Ok(())
+    }
+}
@@ -6,7 +6,6 @@ use apple_codesign::notarization::{
 use apple_codesign::stapling::Stapler;
 use apple_codesign::{BundleSigner, CodeSignatureFlags, SettingsScope, SigningSettings};
 use icns::{IconFamily, Image};
-use pkcs8::EncodePrivateKey;
 use plist::Value;
 use rasn_cms::{ContentInfo, SignedData};
 use std::fs::{File, OpenOptions};
@@ -81,8 +80,7 @@ impl AppBundle {
         }
     }
 
-    pub fn add_icon(&mut self, path: &Path) -> Result<()> {
-        let scaler = Scaler::open(path)?;
+    pub fn add_icon(&mut self, scaler: &Scaler) -> Result<()> {
         let sizes = if self.ios() {
             &IOS_ICON_SIZES[..]
         } else {
@@ -217,8 +215,8 @@ impl AppBundle {
             let mut signing_settings = SigningSettings::default();
             let cert =
                 CapturedX509Certificate::from_der(rasn::der::encode(signer.cert()).unwrap())?;
-            let secret = signer.key().to_pkcs8_der().unwrap();
-            let key = InMemorySigningKeyPair::from_pkcs8_der(secret.as_bytes())?;
+            let secret = signer.key().to_pkcs8_der()?;
+            let key = InMemorySigningKeyPair::from_pkcs8_der(&secret)?;
             signing_settings.set_signing_key(&key, cert);
             signing_settings.chain_apple_certificates();
             signing_settings
@@ -248,8 +246,8 @@ impl AppBundle {
         let mut f = OpenOptions::new().read(true).write(true).open(path)?;
         let mut signing_settings = SigningSettings::default();
         let cert = CapturedX509Certificate::from_der(rasn::der::encode(signer.cert()).unwrap())?;
-        let secret = signer.key().to_pkcs8_der().unwrap();
-        let key = InMemorySigningKeyPair::from_pkcs8_der(secret.as_bytes())?;
+        let secret = signer.key().to_pkcs8_der()?;
+        let key = InMemorySigningKeyPair::from_pkcs8_der(&secret)?;
         signing_settings.set_signing_key(&key, cert);
         signing_settings.chain_apple_certificates();
         signing_settings
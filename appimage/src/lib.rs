@@ -1,11 +1,11 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Write};
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use xcommon::Signer;
+use xcommon::{Scaler, ScalerOpts, Signer};
 
 static RUNTIME: &[u8] = include_bytes!("../assets/runtime-x86_64");
 
@@ -45,14 +45,11 @@ impl AppImage {
         Ok(())
     }
 
-    pub fn add_icon(&self, path: &Path) -> Result<()> {
-        let ext = path
-            .extension()
-            .map(|ext| ext.to_str())
-            .unwrap_or_default()
-            .context("unsupported extension")?;
-        let name = format!("{}.{}", self.name, ext);
-        self.add_file(path, Path::new(&name))?;
+    pub fn add_icon(&self, scaler: &Scaler) -> Result<()> {
+        let name = format!("{}.png", self.name);
+        let mut icon = BufWriter::new(File::create(self.appdir.join(&name))?);
+        scaler.write(&mut icon, ScalerOpts::new(512))?;
+        icon.flush()?;
         #[cfg(unix)]
         std::os::unix::fs::symlink(name, self.appdir.join(".DirIcon"))?;
         Ok(())
@@ -3,7 +3,6 @@ use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::io::Read;
 use std::path::Path;
-use zip::read::ZipFile;
 
 pub struct BlockMapBuilder {
     block_map: AppxBlockMap,
@@ -20,22 +19,24 @@ impl Default for BlockMapBuilder {
 }
 
 impl BlockMapBuilder {
-    pub fn add(&mut self, f: &mut ZipFile) -> Result<()> {
-        let name = Path::new(f.name())
+    /// Adds a file with the uncompressed contents `r`. `lfh_size` is the
+    /// size of its local file header in the package.
+    pub fn add(&mut self, name: &str, size: u64, lfh_size: u16, mut r: impl Read) -> Result<()> {
+        let name = Path::new(name)
             .iter()
             .map(|seg| seg.to_str().unwrap())
             .collect::<Vec<_>>()
             .join("\\");
-        let size = f.size();
         let mut file = File {
-            lfh_size: 30 + name.len() as u16,
+            lfh_size,
             name,
             size,
             ..Default::default()
         };
         loop {
             self.buf.clear();
-            f.take(self.buf.capacity() as u64)
+            (&mut r)
+                .take(self.buf.capacity() as u64)
                 .read_to_end(&mut self.buf)?;
             file.blocks.push(Block::new(&self.buf));
             if self.buf.len() != self.buf.capacity() {
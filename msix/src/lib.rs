@@ -2,13 +2,14 @@ use crate::block_map::BlockMapBuilder;
 use crate::content_types::ContentTypesBuilder;
 use crate::p7x::Digests;
 use anyhow::Result;
+use flate2::read::DeflateDecoder;
 use serde::Serialize;
 use sha2::{Digest, Sha256};
 use std::fs::File;
-use std::io::{BufReader, Cursor, Read, Seek};
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use xcommon::{Scaler, ScalerOptsBuilder, Signer, Zip, ZipFileOptions, ZipInfo};
-use zip::ZipArchive;
+use zip::{CompressionMethod, ZipArchive};
 
 mod block_map;
 mod content_types;
@@ -48,8 +49,8 @@ impl Msix {
         })
     }
 
-    pub fn add_icon(&mut self, path: &Path) -> Result<()> {
-        let mut scaler = Scaler::open(path)?;
+    pub fn add_icon(&mut self, scaler: &Scaler) -> Result<()> {
+        let mut scaler = scaler.clone();
         scaler.optimize();
         let images = Path::new("Images");
         let mut buf = vec![];
@@ -98,15 +99,53 @@ impl Msix {
             .unwrap_or_else(|| Signer::new(DEBUG_PEM))
             .unwrap();
 
-        // add content types and block map
+        // the block map and the digest of the local file headers and data
+        // are computed in a single pass over the package
+        let mut entries = vec![];
         let mut zip = ZipArchive::new(BufReader::new(File::open(path)?))?;
+        for i in 0..zip.len() {
+            let file = zip.by_index_raw(i)?;
+            entries.push(Entry {
+                name: file.name().to_string(),
+                header_start: file.header_start(),
+                data_start: file.data_start(),
+                compressed_size: file.compressed_size(),
+                size: file.size(),
+                compression: file.compression(),
+            });
+        }
+        drop(zip);
+        entries.sort_by_key(|entry| entry.header_start);
+        let mut f = File::open(path)?;
+        let cd_start = ZipInfo::new(&mut f)?.cd_start;
+        f.rewind()?;
+        let mut r = HashReader::new(BufReader::new(f));
         let mut content_types = ContentTypesBuilder::default();
         let mut block_map = BlockMapBuilder::default();
-        for i in 0..zip.len() {
-            let mut file = zip.by_index(i)?;
-            content_types.add(file.name().as_ref());
-            block_map.add(&mut file)?;
+        for entry in &entries {
+            content_types.add(entry.name.as_ref());
+            r.skip_to(entry.data_start)?;
+            let lfh_size = u16::try_from(entry.data_start - entry.header_start)?;
+            let data = (&mut r).take(entry.compressed_size);
+            match entry.compression {
+                CompressionMethod::Stored => {
+                    block_map.add(&entry.name, entry.size, lfh_size, data)?
+                }
+                CompressionMethod::Deflated => {
+                    block_map.add(&entry.name, entry.size, lfh_size, DeflateDecoder::new(data))?
+                }
+                method => anyhow::bail!(
+                    "unsupported compression method {} of `{}`",
+                    method,
+                    entry.name
+                ),
+            }
+            r.skip_to(entry.data_start + entry.compressed_size)?;
         }
+        r.skip_to(cd_start)?;
+        let mut hasher = r.hasher;
+
+        // add content types and block map
         let content_types = to_xml(&content_types.finish(), true);
         let axct = Sha256::digest(&content_types);
         let block_map = to_xml(&block_map.finish(), false);
@@ -124,15 +163,13 @@ impl Msix {
         )?;
         zip.finish()?;
 
-        // compute zip hashes
+        // compute zip hashes, the appended entries start at the old central
+        // directory
         let mut r = BufReader::new(File::open(path)?);
         let info = ZipInfo::new(&mut r)?;
-        r.rewind()?;
-        let mut hasher = Sha256::new();
-        let mut pc = (&mut r).take(info.cd_start);
-        std::io::copy(&mut pc, &mut hasher)?;
+        r.seek(SeekFrom::Start(cd_start))?;
+        std::io::copy(&mut (&mut r).take(info.cd_start - cd_start), &mut hasher)?;
         let axpc = hasher.finalize_reset();
-        hasher.reset();
         std::io::copy(&mut r, &mut hasher)?;
         let axcd = hasher.finalize();
         let digests = Digests {
@@ -156,6 +193,50 @@ impl Msix {
     }
 }
 
+/// Location of a zip entry in the package.
+struct Entry {
+    name: String,
+    header_start: u64,
+    data_start: u64,
+    compressed_size: u64,
+    size: u64,
+    compression: CompressionMethod,
+}
+
+/// Reader that hashes everything read from it.
+struct HashReader<R> {
+    inner: R,
+    hasher: Sha256,
+    pos: u64,
+}
+
+impl<R: Read> HashReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            pos: 0,
+        }
+    }
+
+    /// Reads and hashes everything up to `pos`.
+    fn skip_to(&mut self, pos: u64) -> Result<()> {
+        anyhow::ensure!(pos >= self.pos, "overlapping zip entries");
+        std::io::copy(&mut self.take(pos - self.pos), &mut std::io::sink())?;
+        anyhow::ensure!(self.pos == pos, "unexpected end of zip");
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
 fn to_xml<T: Serialize>(xml: &T, standalone: bool) -> Vec<u8> {
     let mut buf = vec![];
     let standalone = if standalone { "yes" } else { "no" };
@@ -169,3 +250,50 @@ fn to_xml<T: Serialize>(xml: &T, standalone: bool) -> Vec<u8> {
     quick_xml::se::to_writer(&mut buf, xml).unwrap();
     buf
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest<'a>(p7x: &'a [u8], tag: &[u8]) -> &'a [u8] {
+        let pos = p7x.windows(4).position(|w| w == tag).unwrap() + 4;
+        &p7x[pos..pos + 32]
+    }
+
+    #[test]
+    fn sign_streaming() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("sign_streaming.msix");
+        let large = (0..200_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut zip = Zip::new(&path, true)?;
+        zip.create_file("large.bin".as_ref(), ZipFileOptions::Compressed, &large)?;
+        zip.create_file("stored.bin".as_ref(), ZipFileOptions::Unaligned, &large)?;
+        zip.create_file("a/b.txt".as_ref(), ZipFileOptions::Compressed, b"hello")?;
+        zip.finish()?;
+        Msix::sign(&path, None, true)?;
+
+        // the block map matches the one computed from the decompressed entries
+        let mut zip = ZipArchive::new(File::open(&path)?)?;
+        let mut expected = BlockMapBuilder::default();
+        for name in ["large.bin", "stored.bin", "a/b.txt"] {
+            let file = zip.by_name(name)?;
+            let lfh_size = (file.data_start() - file.header_start()) as u16;
+            let size = file.size();
+            expected.add(name, size, lfh_size, file)?;
+        }
+        let mut block_map = vec![];
+        zip.by_name("AppxBlockMap.xml")?
+            .read_to_end(&mut block_map)?;
+        assert_eq!(block_map, to_xml(&expected.finish(), false));
+
+        // the signature covers everything up to the signature entry
+        let sig_start = zip.by_name("AppxSignature.p7x")?.header_start();
+        let mut p7x = vec![];
+        zip.by_name("AppxSignature.p7x")?.read_to_end(&mut p7x)?;
+        let package = std::fs::read(&path)?;
+        let axpc = Sha256::digest(&package[..sig_start as usize]);
+        assert_eq!(digest(&p7x, b"AXPC"), &axpc[..]);
+        assert_eq!(digest(&p7x, b"AXBM"), &Sha256::digest(&block_map)[..]);
+        Ok(())
+    }
+}
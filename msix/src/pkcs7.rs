@@ -54,10 +54,7 @@ pub fn build_pkcs7(signer: &Signer, encap_content_info: EncapsulatedContentInfo)
             });
             signed_attrs
         }),
-        signature_algorithm: AlgorithmIdentifier {
-            algorithm: Oid::ISO_MEMBER_BODY_US_RSADSI_PKCS1.into(),
-            parameters: Some(Any::new(vec![5, 0])),
-        },
+        signature_algorithm: signer.algorithm().pkcs7_algorithm_identifier(),
         signature: OctetString::from(signature.to_vec()),
         unsigned_attrs: Some({
             // TODO: 1.3.6.1.4.1.311.3.3.1 timestamp? optional?
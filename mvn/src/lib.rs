@@ -1,6 +1,6 @@
 use crate::metadata::Metadata;
 use crate::package::Artifact;
-use crate::pom::{Dependency, Pom};
+use crate::pom::Pom;
 use anyhow::{Context, Result};
 use pubgrub::error::PubGrubError;
 use pubgrub::range::Range;
@@ -18,6 +18,7 @@ mod pom;
 mod range;
 
 pub use package::{Package, Version};
+pub use pom::Dependency;
 
 pub trait Download {
     fn download(&self, url: &str, dest: &Path) -> Result<()>;
@@ -221,6 +221,7 @@ pub enum ResourceValueType {
     Utf8Path,
 }
 
+#[allow(dead_code)]
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct CandidateSet {
     pub resource_map_item: u32,
@@ -228,6 +229,7 @@ pub struct CandidateSet {
     pub candidates: Vec<Candidate>,
 }
 
+#[allow(dead_code)]
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct Candidate {
     pub qualifier_set: u16,
@@ -3,7 +3,7 @@ use crate::download::DownloadManager;
 use crate::task::TaskRunner;
 use crate::{BuildEnv, Format, Opt, Platform};
 use anyhow::{ensure, Context, Result};
-use apk::Apk;
+use apk::{Aab, Apk, IconBackground, ManifestMerger};
 use appbundle::AppBundle;
 use appimage::AppImage;
 use msix::Msix;
@@ -29,8 +29,8 @@ pub fn build(env: &BuildEnv) -> Result<()> {
     let bin_target = env.target().platform() != Platform::Android;
     let has_lib = env.root_dir().join("src").join("lib.rs").exists();
     if bin_target || has_lib {
-        if env.target().platform() == Platform::Android && env.config().android().gradle {
-            crate::gradle::prepare(env)?;
+        if env.target().platform() == Platform::Android {
+            crate::wry::prepare(env)?;
         }
         for target in env.target().compile_targets() {
             let arch_dir = platform_dir.join(target.arch().to_string());
@@ -44,6 +44,7 @@ pub fn build(env: &BuildEnv) -> Result<()> {
     }
 
     runner.start_task(format!("Create {}", env.target().format()));
+    let icon = env.icon_scaler()?;
     match env.target().platform() {
         Platform::Linux => {
             let target = env.target().compile_targets().next().unwrap();
@@ -52,7 +53,7 @@ pub fn build(env: &BuildEnv) -> Result<()> {
             let appimage = AppImage::new(&arch_dir, env.name().to_string())?;
             appimage.add_apprun()?;
             appimage.add_desktop()?;
-            if let Some(icon) = env.icon() {
+            if let Some(icon) = &icon {
                 appimage.add_icon(icon)?;
             }
 
@@ -194,26 +195,92 @@ pub fn build(env: &BuildEnv) -> Result<()> {
                 crate::gradle::build(env, libraries, &out)?;
                 runner.end_verbose_task();
                 return Ok(());
+            }
+            let deps = crate::dex::dependencies(env, &manager)?;
+            libraries.extend(deps.libraries.iter().cloned());
+            let mut manifest = env.config().android().manifest.clone();
+            if !deps.manifests.is_empty() {
+                let mut merger = ManifestMerger::new(&manifest)?;
+                for path in &deps.manifests {
+                    merger.merge_file(path)?;
+                }
+                manifest = merger.finish()?;
+            }
+            if crate::dex::needs_dex(env) {
+                manifest.application.has_code = Some(true);
+            }
+            let res = env
+                .config()
+                .android()
+                .res
+                .as_ref()
+                .map(|res| env.cargo().package_root().join(res));
+            let icon_background =
+                env.config()
+                    .android()
+                    .icon_background
+                    .as_ref()
+                    .map(|background| {
+                        if background.starts_with('#') {
+                            IconBackground::Color(background.clone())
+                        } else {
+                            IconBackground::Image(env.cargo().package_root().join(background))
+                        }
+                    });
+            if env.target().format() == Format::Aab {
+                let mut aab = Aab::new(out, manifest, env.target().opt() != Opt::Debug)?;
+                if let Some(background) = icon_background {
+                    aab.set_icon_background(background);
+                }
+                if let Some(page_size) = env.config().android().page_size {
+                    aab.set_page_size(page_size)?;
+                }
+                let symbols =
+                    aab.add_res(res.as_deref(), &deps.res, icon.as_ref(), &env.android_jar())?;
+
+                for asset in &env.config().android().assets {
+                    let path = env.cargo().package_root().join(asset.path());
+
+                    if !asset.optional() || path.exists() {
+                        aab.add_asset_with(&path, |file| asset.zip_file_options(file))?
+                    }
+                }
+
+                aab.add_dexes(&crate::dex::build(env, &deps, &symbols)?)?;
+                for (target, lib) in libraries {
+                    aab.add_lib(target, &lib)?;
+                }
+                aab.finish(env.target().signer().cloned())?;
             } else {
-                let mut apk = Apk::new(
-                    out,
-                    env.config().android().manifest.clone(),
-                    env.target().opt() != Opt::Debug,
-                )?;
-                apk.add_res(env.icon(), &env.android_jar())?;
+                let mut apk = Apk::new(out, manifest, env.target().opt() != Opt::Debug)?;
+                if let Some(background) = icon_background {
+                    apk.set_icon_background(background);
+                }
+                apk.set_split(env.target().split());
+                if let Some(page_size) = env.config().android().page_size {
+                    apk.set_page_size(page_size)?;
+                }
+                let symbols =
+                    apk.add_res(res.as_deref(), &deps.res, icon.as_ref(), &env.android_jar())?;
 
                 for asset in &env.config().android().assets {
                     let path = env.cargo().package_root().join(asset.path());
 
                     if !asset.optional() || path.exists() {
-                        apk.add_asset(&path, asset.alignment().to_zip_file_options())?
+                        apk.add_asset_with(&path, |file| asset.zip_file_options(file))?
                     }
                 }
 
+                apk.add_dexes(&crate::dex::build(env, &deps, &symbols)?)?;
                 for (target, lib) in libraries {
                     apk.add_lib(target, &lib)?;
                 }
 
+                if let Some(rotation) = env.target().rotation() {
+                    apk.set_rotation(rotation.clone());
+                }
+                // enables `adb install --incremental` when deploying to a device
+                apk.set_idsig(env.target().device().is_some());
                 apk.finish(env.target().signer().cloned())?;
             }
         }
@@ -222,7 +289,7 @@ pub fn build(env: &BuildEnv) -> Result<()> {
             let arch_dir = platform_dir.join(target.arch().to_string());
 
             let mut app = AppBundle::new(&arch_dir, env.config().macos().info.clone())?;
-            if let Some(icon) = env.icon() {
+            if let Some(icon) = &icon {
                 app.add_icon(icon)?;
             }
 
@@ -254,7 +321,7 @@ pub fn build(env: &BuildEnv) -> Result<()> {
             let arch_dir = platform_dir.join(target.arch().to_string());
             std::fs::create_dir_all(&arch_dir)?;
             let mut app = AppBundle::new(&arch_dir, env.config().ios().info.clone())?;
-            if let Some(icon) = env.icon() {
+            if let Some(icon) = &icon {
                 app.add_icon(icon)?;
             }
             let main = env.cargo_artefact(&arch_dir.join("cargo"), target, CrateType::Bin)?;
@@ -269,11 +336,14 @@ pub fn build(env: &BuildEnv) -> Result<()> {
             if env.target().format() == Format::Ipa {
                 let app = arch_dir.join(format!("{}.app", env.name()));
                 let out = arch_dir.join(format!("{}.ipa", env.name()));
-                let mut ipa = Zip::new(&out, false)?;
+                let compression = env.config().ios().ipa_compression;
+                let mut ipa = Zip::new(&out, compression.is_some())?;
                 ipa.add_directory(
                     &app,
                     &Path::new("Payload").join(format!("{}.app", env.name())),
-                    ZipFileOptions::Compressed,
+                    compression
+                        .map(|opts| opts.to_zip_file_options())
+                        .unwrap_or(ZipFileOptions::Compressed),
                 )?;
                 ipa.finish()?;
             }
@@ -294,7 +364,7 @@ pub fn build(env: &BuildEnv) -> Result<()> {
                         env.config().windows().manifest.clone(),
                         target.opt() != Opt::Debug,
                     )?;
-                    if let Some(icon) = env.icon() {
+                    if let Some(icon) = &icon {
                         msix.add_icon(icon)?;
                     }
                     // TODO: *.pri
@@ -1,9 +1,12 @@
 use crate::cargo::CrateType;
 use crate::devices::Device;
-use crate::{BuildEnv, CompileTarget, Platform};
-use anyhow::Result;
+use crate::{BuildEnv, CompileTarget, Format, Platform};
+use anyhow::{Context, Result};
+use apk::arsc::ResourceTable;
+use apk::{Apk, Lineage};
 use app_store_connect::UnifiedApiKey;
 use std::path::Path;
+use xcommon::Signer;
 
 mod build;
 mod doctor;
@@ -27,7 +30,12 @@ pub fn devices() -> Result<()> {
 }
 
 pub fn run(env: &BuildEnv) -> Result<()> {
-    let out = env.executable();
+    let mut out = env.executable();
+    if env.target().format() == Format::Aab {
+        let apks = out.with_extension("apks");
+        Apk::build_apks(&out, &apks, env.target().signer().cloned())?;
+        out = apks;
+    }
     if let Some(device) = env.target().device() {
         device.run(env, &out)?;
     } else {
@@ -63,6 +71,89 @@ pub fn lldb(env: &BuildEnv) -> Result<()> {
     Ok(())
 }
 
+pub fn rotate_key(lineage: &Path, old_pem: &Path, new_pem: &Path) -> Result<()> {
+    let current = Signer::from_path(old_pem)?;
+    let next = Signer::from_path(new_pem)?;
+    let mut chain = if lineage.exists() {
+        Lineage::from_path(lineage)?
+    } else {
+        Lineage::new(&current)?
+    };
+    chain.rotate(&current, &next)?;
+    std::fs::write(lineage, chain.to_vec())?;
+    Ok(())
+}
+
+pub fn dump_resources(apk: &Path, resource: Option<&str>) -> Result<()> {
+    let table = ResourceTable::from_apk(apk)?;
+    let resource = if let Some(resource) = resource {
+        resource
+    } else {
+        print!("{}", table);
+        return Ok(());
+    };
+    let entry = if let Some(id) = resource.strip_prefix("0x") {
+        table.entry(u32::from_str_radix(id, 16)?.into())
+    } else {
+        table.entry_by_name(resource)
+    };
+    let entry = entry.with_context(|| format!("{} not found in {}", resource, apk.display()))?;
+    print!("{}", table.dump_entry(entry));
+    Ok(())
+}
+
+pub fn build_apks(aab: &Path, apks: &Path, pem: Option<&Path>) -> Result<()> {
+    let signer = pem.map(Signer::from_path).transpose()?;
+    Apk::build_apks(aab, apks, signer)
+}
+
+pub fn check_alignment(apk: &Path, page_size: u16) -> Result<()> {
+    let misaligned = Apk::check_alignment(apk, page_size)?;
+    for entry in &misaligned {
+        println!(
+            "{} (offset {}) is not aligned",
+            entry.name, entry.data_start
+        );
+    }
+    anyhow::ensure!(
+        misaligned.is_empty(),
+        "{} entries of {} are not aligned",
+        misaligned.len(),
+        apk.display()
+    );
+    Ok(())
+}
+
+pub fn verify(apk: &Path, sha256: Option<&str>, allow_debug: bool) -> Result<()> {
+    let report = Apk::verify_report(apk)?;
+    print!("{}", report);
+    anyhow::ensure!(
+        report.is_verified(),
+        "{} is not signed correctly",
+        apk.display()
+    );
+    if let Some(sha256) = sha256 {
+        let sha256 = sha256.replace(':', "").to_lowercase();
+        anyhow::ensure!(
+            report
+                .signers()
+                .iter()
+                .any(|signer| signer.sha256_hex() == sha256),
+            "{} is not signed by the certificate {}",
+            apk.display(),
+            sha256
+        );
+    } else if report.is_debug_signed() {
+        anyhow::ensure!(
+            allow_debug,
+            "{} is signed with the debug certificate, pass --allow-debug to accept it",
+            apk.display()
+        );
+        tracing::warn!("{} is signed with the debug certificate", apk.display());
+    }
+    Ok(())
+}
+
 pub fn create_apple_api_key(
     issuer_id: &str,
     key_id: &str,
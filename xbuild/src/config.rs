@@ -27,6 +27,21 @@ impl Config {
         }
         let contents = std::fs::read_to_string(path.as_ref())?;
         let config: RawConfig = serde_yaml::from_str(&contents)?;
+        if let Some(android) = &config.android {
+            for asset in &android.assets {
+                asset.validate()?;
+            }
+        }
+        if let Some(DeflateOptions {
+            level: Some(level), ..
+        }) = config.ios.as_ref().and_then(|ios| ios.ipa_compression)
+        {
+            anyhow::ensure!(
+                level <= 9,
+                "invalid ipa compression level {}, expected 0 to 9",
+                level
+            );
+        }
         Ok(Self {
             generic: config.generic.unwrap_or_default(),
             android: config.android.unwrap_or_default(),
@@ -58,6 +73,17 @@ impl Config {
         self.select_generic(platform, |g| g.icon.as_deref())
     }
 
+    pub fn icon_overrides(&self, platform: Platform) -> &[PathBuf] {
+        self.select_generic(platform, |g| {
+            Some(&g.icon_overrides[..]).filter(|overrides| !overrides.is_empty())
+        })
+        .unwrap_or_default()
+    }
+
+    pub fn icon_letterbox(&self, platform: Platform) -> Option<&str> {
+        self.select_generic(platform, |g| g.icon_letterbox.as_deref())
+    }
+
     pub fn runtime_libs(&self, platform: Platform) -> Vec<PathBuf> {
         let generic = match platform {
             Platform::Android => &self.android.generic,
@@ -208,6 +234,7 @@ impl Config {
             activity.meta_data.push(MetaData {
                 name: "android.app.lib_name".into(),
                 value: manifest_package.name.replace('-', "_"),
+                ..Default::default()
             });
         }
         activity.intent_filters.push(IntentFilter {
@@ -324,6 +351,25 @@ pub enum UnalignedCompressed {
     Compressed,
 }
 
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeflateOptions {
+    /// Compression level from 0 to 9, defaults to 6
+    pub level: Option<u8>,
+    /// Store the file 4-byte aligned instead if compressing doesn't make it smaller
+    #[serde(default)]
+    pub store_if_incompressible: bool,
+}
+
+impl DeflateOptions {
+    pub fn to_zip_file_options(self) -> ZipFileOptions {
+        ZipFileOptions::Deflate {
+            level: self.level,
+            store_if_incompressible: self.store_if_incompressible,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Deserialize)]
 #[serde(untagged)]
 pub enum ZipAlignmentOptions {
@@ -331,6 +377,8 @@ pub enum ZipAlignmentOptions {
     Aligned(u16),
     /// Used to wrap a tagged enum with an untagged alignment value
     UnalignedCompressed(UnalignedCompressed),
+    /// Compress this file with the given options
+    Deflate(DeflateOptions),
 }
 
 impl Default for ZipAlignmentOptions {
@@ -347,10 +395,30 @@ impl ZipAlignmentOptions {
             Self::UnalignedCompressed(UnalignedCompressed::Compressed) => {
                 ZipFileOptions::Compressed
             }
+            Self::Deflate(opts) => opts.to_zip_file_options(),
         }
     }
 }
 
+/// Alignment of the files of an asset directory matching a glob.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssetRule {
+    /// Glob like `*.ogg` matched against the path relative to the asset
+    /// directory
+    #[serde(deserialize_with = "deserialize_glob")]
+    pub glob: glob::Pattern,
+    pub alignment: ZipAlignmentOptions,
+}
+
+fn deserialize_glob<'de, D>(deserializer: D) -> std::result::Result<glob::Pattern, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let glob = String::deserialize(deserializer)?;
+    glob::Pattern::new(&glob).map_err(serde::de::Error::custom)
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(untagged, deny_unknown_fields)]
 pub enum AssetPath {
@@ -361,6 +429,10 @@ pub enum AssetPath {
         optional: bool,
         #[serde(default)]
         alignment: ZipAlignmentOptions,
+        /// Overrides the alignment of the files matching a glob, the first
+        /// matching rule applies
+        #[serde(default)]
+        rules: Vec<AssetRule>,
     },
 }
 
@@ -388,6 +460,49 @@ impl AssetPath {
             AssetPath::Extended { alignment, .. } => *alignment,
         }
     }
+
+    /// Rejects compression levels the zip writer doesn't support.
+    fn validate(&self) -> Result<()> {
+        if let AssetPath::Extended {
+            path,
+            alignment,
+            rules,
+            ..
+        } = self
+        {
+            let alignments = std::iter::once(alignment).chain(rules.iter().map(|r| &r.alignment));
+            for alignment in alignments {
+                if let ZipAlignmentOptions::Deflate(DeflateOptions {
+                    level: Some(level), ..
+                }) = alignment
+                {
+                    anyhow::ensure!(
+                        *level <= 9,
+                        "invalid compression level {} for asset `{}`, expected 0 to 9",
+                        level,
+                        path.display()
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the zip options of the file at `path`, relative to the asset
+    /// directory.
+    pub fn zip_file_options(&self, path: &Path) -> ZipFileOptions {
+        match self {
+            AssetPath::Path(_) => self.alignment(),
+            AssetPath::Extended {
+                alignment, rules, ..
+            } => rules
+                .iter()
+                .find(|rule| rule.glob.matches_path(path))
+                .map(|rule| rule.alignment)
+                .unwrap_or(*alignment),
+        }
+        .to_zip_file_options()
+    }
 }
 
 #[derive(Deserialize)]
@@ -405,7 +520,16 @@ struct RawConfig {
 #[derive(Clone, Debug, Default, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct GenericConfig {
+    /// A png, webp or svg icon. Raster icons must be at least 512 px wide or
+    /// high. Svg icons can't contain `<text>`, convert text to paths.
     icon: Option<PathBuf>,
+    /// Hand-tuned square icons used instead of scaling `icon` for their
+    /// size, like a 16x16 png
+    #[serde(default)]
+    icon_overrides: Vec<PathBuf>,
+    /// Color like `#ffffff` filling the sides of a non-square `icon`, they
+    /// are transparent by default
+    icon_letterbox: Option<String>,
     #[serde(default)]
     runtime_libs: Vec<PathBuf>,
 }
@@ -428,6 +552,11 @@ pub struct AndroidConfig {
     generic: GenericConfig,
     #[serde(default)]
     pub manifest: AndroidManifest,
+    /// Maven dependencies like `androidx.appcompat:appcompat:1.4.1`. Without
+    /// gradle they are compiled to dex with d8 together with the `kotlin/`
+    /// sources, and the manifests, resources and native libraries of AARs are
+    /// included. The `R` classes of AARs are generated from the compiled
+    /// resources.
     #[serde(default)]
     pub dependencies: Vec<String>,
     #[serde(default)]
@@ -436,6 +565,18 @@ pub struct AndroidConfig {
     pub wry: bool,
     #[serde(default)]
     pub assets: Vec<AssetPath>,
+    /// Resource directory. The resources in `values` are compiled into
+    /// `resources.arsc`, other files like `xml/file_paths.xml` are added as
+    /// file resources. All of them can be referenced from the manifest.
+    pub res: Option<PathBuf>,
+    /// Background of the adaptive launcher icon, either a color like
+    /// `#ffffff` or the path to a square image.
+    pub icon_background: Option<String>,
+    /// Stores native libraries uncompressed and aligned to this page size,
+    /// either 4096 or 16384, so they are loaded directly from the apk
+    /// instead of being extracted on install. Libraries are linked with a
+    /// matching `max-page-size`.
+    pub page_size: Option<u16>,
     /// Debug configuration for `x run`
     #[serde(default)]
     pub debug: AndroidDebugConfig,
@@ -448,6 +589,9 @@ pub struct IosConfig {
     generic: GenericConfig,
     pub assets_car: Option<PathBuf>,
     pub info: InfoPlist,
+    /// Deflates the files of an `ipa` with these options, they are stored
+    /// uncompressed when unset
+    pub ipa_compression: Option<DeflateOptions>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
@@ -472,3 +616,44 @@ pub struct WindowsConfig {
     generic: GenericConfig,
     pub manifest: AppxManifest,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asset_compression_level() -> Result<()> {
+        let asset: AssetPath = serde_yaml::from_str(
+            "path: sounds\nalignment:\n  level: 9\nrules:\n  - glob: '*.ogg'\n    alignment:\n      level: 12\n",
+        )?;
+        let err = asset.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid compression level 12 for asset `sounds`, expected 0 to 9"
+        );
+        let asset: AssetPath = serde_yaml::from_str("path: sounds\nalignment:\n  level: 9\n")?;
+        asset.validate()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipa_compression_level() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("manifest.yaml");
+        std::fs::write(
+            &path,
+            "ios:\n  info: {}\n  ipa_compression:\n    level: 10\n",
+        )?;
+        let err = Config::parse(&path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid ipa compression level 10, expected 0 to 9"
+        );
+        std::fs::write(
+            &path,
+            "ios:\n  info: {}\n  ipa_compression:\n    level: 9\n",
+        )?;
+        Config::parse(&path)?;
+        Ok(())
+    }
+}
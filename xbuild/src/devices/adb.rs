@@ -2,6 +2,7 @@ use crate::config::AndroidDebugConfig;
 use crate::devices::{Backend, Device};
 use crate::{Arch, Platform};
 use anyhow::{Context, Result};
+use apk::res::ResTableConfig;
 use apk::Apk;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
@@ -75,7 +76,105 @@ impl Adb {
         Ok(std::str::from_utf8(&output.stdout)?.trim().to_string())
     }
 
+    /// Streams the apk to the device when a v4 signature is available.
+    /// Requires android 11.
+    fn install_incremental(&self, device: &str, path: &Path) -> Result<bool> {
+        if !Apk::idsig_path(path).exists() {
+            return Ok(false);
+        }
+        let sdk: u32 = self.getprop(device, "ro.build.version.sdk")?.parse()?;
+        if sdk < 30 {
+            return Ok(false);
+        }
+        let status = self
+            .adb(device)
+            .arg("install")
+            .arg("--incremental")
+            .arg(path)
+            .status()?;
+        if !status.success() {
+            tracing::warn!("adb install --incremental failed, falling back to pm install");
+        }
+        Ok(status.success())
+    }
+
+    /// Selects the abi split of the preferred abi of the device, the
+    /// density split closest to the density of the device and the language
+    /// split of the device locale.
+    fn select_splits(&self, device: &str, splits: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let mut abis = vec![];
+        let mut densities = vec![];
+        let mut languages = vec![];
+        for path in splits {
+            let name = path.file_stem().unwrap().to_str().unwrap();
+            let split = name
+                .rsplit_once(".config.")
+                .map(|(_, split)| split)
+                .unwrap();
+            match ResTableConfig::from_qualifiers(split) {
+                Ok(config) if config.density != 0 => densities.push((config.density, path)),
+                Ok(config) if config.language().is_some() => {
+                    languages.push((config.language().unwrap(), path))
+                }
+                _ => abis.push((split.replace('_', "-"), path)),
+            }
+        }
+        let mut selected = vec![];
+        if !abis.is_empty() {
+            let abilist = self.getprop(device, "ro.product.cpu.abilist")?;
+            let abi = abilist
+                .split(',')
+                .find_map(|abi| abis.iter().find(|(split, _)| split == abi))
+                .with_context(|| format!("no split for the abis of the device: {}", abilist))?;
+            selected.push(abi.1.clone());
+        }
+        if !densities.is_empty() {
+            let density: u16 = self.getprop(device, "ro.sf.lcd_density")?.parse()?;
+            densities.sort();
+            let split = densities
+                .iter()
+                .find(|(split, _)| *split >= density)
+                .or_else(|| densities.last())
+                .unwrap();
+            selected.push(split.1.clone());
+        }
+        if !languages.is_empty() {
+            let mut locale = self.getprop(device, "persist.sys.locale")?;
+            if locale.is_empty() {
+                locale = self.getprop(device, "ro.product.locale")?;
+            }
+            let language = locale.split('-').next().unwrap_or_default();
+            if let Some((_, path)) = languages.iter().find(|(split, _)| split == language) {
+                selected.push((*path).clone());
+            }
+        }
+        Ok(selected)
+    }
+
+    fn install_multiple(&self, device: &str, path: &Path, splits: &[PathBuf]) -> Result<()> {
+        let splits = self.select_splits(device, splits)?;
+        let status = self
+            .adb(device)
+            .arg("install-multiple")
+            .arg(path)
+            .args(&splits)
+            .status()?;
+        anyhow::ensure!(
+            status.success(),
+            "adb install-multiple exited with code {:?}",
+            status.code()
+        );
+        Ok(())
+    }
+
     fn install(&self, device: &str, path: &Path) -> Result<()> {
+        let splits = Apk::splits(path)?;
+        if !splits.is_empty() {
+            return self.install_multiple(device, path, &splits);
+        }
+        if self.install_incremental(device, path)? {
+            return Ok(());
+        }
         let file_name = path.file_name().unwrap().to_str().unwrap();
         self.push(device, path)?;
         let status = self
@@ -343,6 +442,13 @@ impl Adb {
         debug_config: &AndroidDebugConfig,
         debug: bool,
     ) -> Result<()> {
+        let base;
+        let path = if path.extension() == Some("apks".as_ref()) {
+            base = Apk::extract_apks(path, &path.with_extension(""))?;
+            &base
+        } else {
+            path
+        };
         let entry_point = Apk::entry_point(path)?;
         let package = &entry_point.package;
         let activity = &entry_point.activity;
@@ -0,0 +1,396 @@
+//! Compiles `kotlin/` sources and the `android.dependencies` to dex without
+//! gradle. The resources of AARs are compiled with the app resources and
+//! their `R` classes are generated from the compiled resources.
+
+use crate::download::DownloadManager;
+use crate::{task, BuildEnv, Opt};
+use anyhow::{Context, Result};
+use apk::{Symbols, Target};
+use mvn::{Dependency, Maven, Package, Version};
+use quick_xml::events::Event;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+/// Version of the build tools d8 is taken from.
+pub const BUILD_TOOLS_VERSION: &str = "34.0.0";
+
+const REPOSITORIES: [&str; 2] = ["https://maven.google.com", "https://repo1.maven.org/maven2"];
+
+/// The unpacked `android.dependencies`.
+#[derive(Default)]
+pub struct Dependencies {
+    /// Jars to be compiled to dex.
+    jars: Vec<PathBuf>,
+    /// Manifests of the AARs, to be merged into the app manifest.
+    pub manifests: Vec<PathBuf>,
+    /// Native libraries of the AARs.
+    pub libraries: Vec<(Target, PathBuf)>,
+    /// `res` directories of the AARs, to be compiled with the app resources.
+    pub res: Vec<PathBuf>,
+    /// Packages of the AARs with the `R.txt` listing the fields of their `R`
+    /// class.
+    r_classes: Vec<(String, Option<PathBuf>)>,
+}
+
+/// Returns the directories containing kotlin sources.
+fn kotlin_sources(env: &BuildEnv) -> Vec<PathBuf> {
+    [
+        env.cargo().package_root().join("kotlin"),
+        env.platform_dir().join("wry"),
+    ]
+    .into_iter()
+    .filter(|dir| dir.exists())
+    .collect()
+}
+
+/// Returns true if the app has kotlin sources or dependencies to be dexed.
+pub fn needs_dex(env: &BuildEnv) -> bool {
+    !env.config().android().dependencies.is_empty() || !kotlin_sources(env).is_empty()
+}
+
+/// Resolves the `android.dependencies` and returns the paths of the `.aar`
+/// and `.jar` artifacts.
+fn resolve(env: &BuildEnv, manager: &DownloadManager) -> Result<Vec<PathBuf>> {
+    let mut maven = Maven::new(env.cache_dir().join("maven"), manager)?;
+    for repo in REPOSITORIES {
+        maven.add_repository(repo);
+    }
+    let deps = env
+        .config()
+        .android()
+        .dependencies
+        .iter()
+        .map(|dep| {
+            Dependency::from_str(dep).with_context(|| format!("invalid dependency `{}`", dep))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let root = Package::new("xbuild", env.name());
+    let version = Version::from_str("0.0.0")?;
+    maven.add_package(root.clone(), version.clone(), deps);
+    Ok(maven
+        .resolve(root, version)?
+        .into_iter()
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(OsStr::to_str),
+                Some("aar" | "jar")
+            )
+        })
+        .collect())
+}
+
+/// Returns the files in `dir` with extension `ext`.
+fn files(dir: &Path, ext: &str) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    if dir.exists() {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension() == Some(OsStr::new(ext)) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Returns the `package` of a manifest.
+fn manifest_package(manifest: &Path) -> Result<String> {
+    let xml = std::fs::read_to_string(manifest)?;
+    let mut reader = quick_xml::Reader::from_str(&xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"manifest" => {
+                let package = e
+                    .try_get_attribute("package")?
+                    .context("missing manifest.package")?;
+                return Ok(package.unescape_value()?.into_owned());
+            }
+            Event::Eof => anyhow::bail!("missing <manifest>"),
+            _ => {}
+        }
+    }
+}
+
+/// Unpacks an AAR into `cache` and collects its jars, manifest, resources and
+/// the native libraries of `abis`.
+fn unpack_aar(cache: &Path, aar: &Path, abis: &[Target], deps: &mut Dependencies) -> Result<()> {
+    let dir = cache.join(aar.file_stem().context("invalid path")?);
+    if !dir.exists() {
+        xcommon::extract_zip(aar, &dir)
+            .with_context(|| format!("failed to extract `{}`", aar.display()))?;
+    }
+    let classes = dir.join("classes.jar");
+    if classes.exists() {
+        deps.jars.push(classes);
+    }
+    deps.jars.extend(files(&dir.join("libs"), "jar")?);
+    for abi in abis {
+        for lib in files(&dir.join("jni").join(abi.as_str()), "so")? {
+            deps.libraries.push((*abi, lib));
+        }
+    }
+    let res = dir.join("res");
+    let has_res = res.exists() && std::fs::read_dir(&res)?.next().is_some();
+    if has_res {
+        deps.res.push(res);
+    }
+    let manifest = dir.join("AndroidManifest.xml");
+    if !manifest.exists() {
+        anyhow::ensure!(
+            !has_res,
+            "`{}` has resources but no manifest",
+            aar.display()
+        );
+        return Ok(());
+    }
+    let r_txt = dir.join("R.txt");
+    if has_res || r_txt.exists() {
+        let package = manifest_package(&manifest)
+            .with_context(|| format!("invalid manifest `{}`", manifest.display()))?;
+        deps.r_classes
+            .push((package, Some(r_txt).filter(|r_txt| r_txt.exists())));
+    }
+    deps.manifests.push(manifest);
+    Ok(())
+}
+
+/// Compiles the kotlin sources into a jar. The kotlin runtime is included
+/// unless a dependency already provides it.
+fn kotlinc(
+    env: &BuildEnv,
+    kotlinc: &Path,
+    srcs: &[PathBuf],
+    jars: &[PathBuf],
+    out: &Path,
+) -> Result<()> {
+    let classpath = std::env::join_paths(std::iter::once(env.android_jar()).chain(jars.to_vec()))?;
+    let has_stdlib = jars.iter().any(|jar| {
+        jar.file_name()
+            .and_then(OsStr::to_str)
+            .unwrap_or_default()
+            .starts_with("kotlin-stdlib")
+    });
+    let mut cmd = Command::new(kotlinc);
+    cmd.arg("-classpath").arg(classpath).arg("-d").arg(out);
+    if !has_stdlib {
+        cmd.arg("-include-runtime");
+    }
+    cmd.args(srcs);
+    task::run(cmd, env.verbose()).context("failed to run kotlinc")
+}
+
+/// Compiles jars to dex with d8. d8 writes additional `classesN.dex` files
+/// when the methods don't fit a single dex, which requires a `minSdk` of 21
+/// or higher.
+fn d8(env: &BuildEnv, jars: &[PathBuf], out: &Path) -> Result<Vec<PathBuf>> {
+    if out.exists() {
+        std::fs::remove_dir_all(out)?;
+    }
+    std::fs::create_dir_all(out)?;
+    let min_sdk = env
+        .config()
+        .android()
+        .manifest
+        .sdk
+        .min_sdk_version
+        .context("missing minSdkVersion")?;
+    let mut cmd = Command::new("java");
+    cmd.arg("-cp")
+        .arg(env.android_d8())
+        .arg("com.android.tools.r8.D8")
+        .arg(if env.target().opt() == Opt::Debug {
+            "--debug"
+        } else {
+            "--release"
+        })
+        .arg("--min-api")
+        .arg(min_sdk.to_string())
+        .arg("--lib")
+        .arg(env.android_jar())
+        .arg("--output")
+        .arg(out)
+        .args(jars);
+    task::run(cmd, env.verbose()).context("failed to run d8")?;
+    dex_files(out)
+}
+
+/// Returns the dex files written by d8 in the order `classes.dex`,
+/// `classes2.dex`, ..., `classes10.dex`.
+fn dex_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut dexes = files(dir, "dex")?;
+    for dex in &dexes {
+        anyhow::ensure!(
+            dex_index(dex).is_some(),
+            "unexpected dex file `{}`",
+            dex.display()
+        );
+    }
+    dexes.sort_by_key(|dex| dex_index(dex));
+    Ok(dexes)
+}
+
+/// Returns `N` of `classesN.dex`, `classes.dex` is the first.
+fn dex_index(dex: &Path) -> Option<u32> {
+    let n = dex.file_stem()?.to_str()?.strip_prefix("classes")?;
+    if n.is_empty() {
+        Some(1)
+    } else {
+        n.parse().ok().filter(|n| *n > 1)
+    }
+}
+
+/// Resolves the dependencies and unpacks the AARs.
+pub fn dependencies(env: &BuildEnv, manager: &DownloadManager) -> Result<Dependencies> {
+    let mut deps = Dependencies::default();
+    if env.config().android().dependencies.is_empty() {
+        return Ok(deps);
+    }
+    let aar_cache = env.cache_dir().join("aar");
+    let abis = env
+        .target()
+        .compile_targets()
+        .map(|target| target.android_abi())
+        .collect::<Vec<_>>();
+    for artifact in resolve(env, manager)? {
+        if artifact.extension() == Some(OsStr::new("aar")) {
+            unpack_aar(&aar_cache, &artifact, &abis, &mut deps)?;
+        } else {
+            deps.jars.push(artifact);
+        }
+    }
+    Ok(deps)
+}
+
+/// Generates the `R` classes of the app and the AARs from the `symbols` of
+/// the compiled resources and compiles them together with the dependencies
+/// and the kotlin sources to dex. Returns the dex files.
+pub fn build(env: &BuildEnv, deps: &Dependencies, symbols: &Symbols) -> Result<Vec<PathBuf>> {
+    if !needs_dex(env) {
+        return Ok(vec![]);
+    }
+    let srcs = kotlin_sources(env);
+    let kotlinc_path = if srcs.is_empty() {
+        None
+    } else {
+        Some(which::which("kotlinc").context(
+            "kotlinc is required to compile the `kotlin/` sources, install the kotlin \
+             command line compiler and add it to PATH",
+        )?)
+    };
+    let java_dir = env.platform_dir().join("java");
+    std::fs::create_dir_all(&java_dir)?;
+    let mut jars = deps.jars.clone();
+    let mut packages = vec![];
+    if let Some(package) = &env.config().android().manifest.package {
+        packages.push((package.clone(), None));
+    }
+    for (package, r_txt) in &deps.r_classes {
+        if packages.iter().all(|(p, _)| p != package) {
+            packages.push((package.clone(), r_txt.as_deref()));
+        }
+    }
+    if !packages.is_empty() {
+        let r_jar = java_dir.join("R.jar");
+        apk::write_r_jar(&r_jar, symbols, &packages)?;
+        jars.push(r_jar);
+    }
+    let mut inputs = jars.clone();
+    if let Some(kotlinc_path) = kotlinc_path {
+        let classes = java_dir.join("classes.jar");
+        kotlinc(env, &kotlinc_path, &srcs, &jars, &classes)?;
+        inputs.push(classes);
+    }
+    d8(env, &inputs, &java_dir.join("dex"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcommon::{Zip, ZipFileOptions};
+
+    #[test]
+    fn test_dex_files() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let dir = tmp.path();
+        for name in [
+            "classes10.dex",
+            "classes2.dex",
+            "classes.dex",
+            "classes3.dex",
+        ] {
+            std::fs::write(dir.join(name), b"dex")?;
+        }
+        std::fs::write(dir.join("mapping.txt"), b"")?;
+        let names = dex_files(dir)?
+            .iter()
+            .map(|dex| dex.file_name().unwrap().to_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "classes.dex",
+                "classes2.dex",
+                "classes3.dex",
+                "classes10.dex"
+            ]
+        );
+        std::fs::write(dir.join("other.dex"), b"dex")?;
+        assert!(dex_files(dir).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_aar() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let dir = tmp.path();
+        let aar = dir.join("core-1.0.0.aar");
+        let mut zip = Zip::new(&aar, true)?;
+        zip.create_file(
+            Path::new("AndroidManifest.xml"),
+            ZipFileOptions::Compressed,
+            br#"<?xml version="1.0" encoding="utf-8"?>
+            <manifest xmlns:android="http://schemas.android.com/apk/res/android"
+                package="androidx.core"/>"#,
+        )?;
+        for name in [
+            "classes.jar",
+            "libs/b.jar",
+            "libs/a.jar",
+            "libs/readme.txt",
+            "jni/arm64-v8a/libcore.so",
+            "jni/x86_64/libcore.so",
+            "res/values/values.xml",
+            "R.txt",
+        ] {
+            zip.create_file(Path::new(name), ZipFileOptions::Compressed, b"")?;
+        }
+        zip.finish()?;
+
+        let cache = dir.join("cache");
+        let mut deps = Dependencies::default();
+        unpack_aar(&cache, &aar, &[Target::Arm64V8a], &mut deps)?;
+        let unpacked = cache.join("core-1.0.0");
+        assert_eq!(
+            deps.jars,
+            [
+                unpacked.join("classes.jar"),
+                unpacked.join("libs/a.jar"),
+                unpacked.join("libs/b.jar"),
+            ]
+        );
+        assert_eq!(deps.manifests, [unpacked.join("AndroidManifest.xml")]);
+        assert_eq!(
+            deps.libraries,
+            [(Target::Arm64V8a, unpacked.join("jni/arm64-v8a/libcore.so"))]
+        );
+        assert_eq!(deps.res, [unpacked.join("res")]);
+        assert_eq!(
+            deps.r_classes,
+            [("androidx.core".to_string(), Some(unpacked.join("R.txt")))]
+        );
+        Ok(())
+    }
+}
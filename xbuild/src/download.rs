@@ -142,6 +142,9 @@ impl<'a> DownloadManager<'a> {
             Platform::Android => {
                 self.android_ndk()?;
                 self.android_jar()?;
+                if !self.env().config().android().gradle && crate::dex::needs_dex(self.env()) {
+                    self.android_d8()?;
+                }
             }
             Platform::Ios => {
                 self.ios_sdk()?;
@@ -253,6 +256,20 @@ impl DownloadManager<'_> {
         Ok(())
     }
 
+    pub fn android_d8(&self) -> Result<()> {
+        let dir = self.env.android_sdk();
+        if !self.env.android_d8().exists() {
+            let package = format!("build-tools;{}", crate::dex::BUILD_TOOLS_VERSION);
+            android_sdkmanager::download_and_extract_packages(
+                dir.to_str().unwrap(),
+                android_sdkmanager::HostOs::Linux,
+                &[&package],
+                Some(&[android_sdkmanager::MatchType::EntireName("d8.jar")]),
+            )
+        }
+        Ok(())
+    }
+
     pub fn windows_sdk(&self) -> Result<()> {
         let output = self.env.windows_sdk();
         let mut item = WorkItem::xbuild_release(output, "Windows.sdk.tar.zst");
@@ -1,6 +1,6 @@
 use crate::{task, BuildEnv, Format, Opt};
 use anyhow::{Context, Result};
-use apk::Target;
+use apk::{Apk, Target};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
@@ -9,30 +9,6 @@ static GRADLE_PROPERTIES: &[u8] = include_bytes!("./gradle.properties");
 static SETTINGS_GRADLE: &[u8] = include_bytes!("./settings.gradle");
 static IC_LAUNCHER: &[u8] = include_bytes!("./ic_launcher.xml");
 
-pub fn prepare(env: &BuildEnv) -> Result<()> {
-    let config = env.config().android();
-    if config.wry {
-        let package = config.manifest.package.as_ref().unwrap();
-        let wry = env.platform_dir().join("wry");
-        std::fs::create_dir_all(&wry)?;
-        if !env.cargo().package_root().join("kotlin").exists() {
-            let main_activity = format!(
-                r#"
-                    package {}
-                    class MainActivity : TauriActivity()
-                "#,
-                package,
-            );
-            std::fs::write(wry.join("MainActivity.kt"), main_activity)?;
-        }
-        let (package, name) = package.rsplit_once('.').unwrap();
-        std::env::set_var("WRY_ANDROID_REVERSED_DOMAIN", package);
-        std::env::set_var("WRY_ANDROID_APP_NAME_SNAKE_CASE", name);
-        std::env::set_var("WRY_ANDROID_KOTLIN_FILES_OUT_DIR", wry);
-    }
-    Ok(())
-}
-
 pub fn build(env: &BuildEnv, libraries: Vec<(Target, PathBuf)>, out: &Path) -> Result<()> {
     let platform_dir = env.platform_dir();
     let gradle = platform_dir.join("gradle");
@@ -96,8 +72,7 @@ pub fn build(env: &BuildEnv, libraries: Vec<(Target, PathBuf)>, out: &Path) -> R
         dependencies = dependencies,
     );
 
-    if let Some(icon_path) = env.icon.as_ref() {
-        let mut scaler = xcommon::Scaler::open(icon_path)?;
+    if let Some(mut scaler) = env.icon_scaler()? {
         scaler.optimize();
         let anydpi = res.join("mipmap-anydpi-v26");
         std::fs::create_dir_all(&anydpi)?;
@@ -181,5 +156,9 @@ pub fn build(env: &BuildEnv, libraries: Vec<(Target, PathBuf)>, out: &Path) -> R
             _ => unreachable!(),
         });
     std::fs::copy(output, out)?;
+    // gradle only signs debug bundles, play requires release bundles to be JAR signed
+    if (format, opt) == (Format::Aab, Opt::Release) {
+        Apk::sign_jar(out, env.target().signer().cloned())?;
+    }
     Ok(())
 }
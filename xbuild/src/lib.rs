@@ -1,10 +1,11 @@
 use crate::cargo::{Cargo, CargoBuild, CrateType};
 use crate::config::Config;
 use crate::devices::Device;
-use anyhow::Result;
+use anyhow::{Context, Result};
+use apk::{Lineage, Rotation};
 use clap::{Parser, ValueEnum};
 use std::path::{Path, PathBuf};
-use xcommon::Signer;
+use xcommon::{Scaler, Signer};
 
 #[macro_export]
 macro_rules! exe {
@@ -21,9 +22,11 @@ pub mod cargo;
 pub mod command;
 mod config;
 mod devices;
+mod dex;
 mod download;
 mod gradle;
 mod task;
+mod wry;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Opt {
@@ -304,9 +307,10 @@ pub struct BuildTargetArgs {
     /// Build artifacts for target platform.
     #[clap(long, conflicts_with = "device")]
     platform: Option<Platform>,
-    /// Build artifacts for target arch.
+    /// Build artifacts for target arch. Android apks and bundles can
+    /// contain multiple archs.
     #[clap(long, requires = "platform")]
-    arch: Option<Arch>,
+    arch: Vec<Arch>,
     /// Build artifacts for target device. To find the device
     /// identifier of a connected device run `x devices`.
     #[clap(long, conflicts_with = "store")]
@@ -317,10 +321,36 @@ pub struct BuildTargetArgs {
     /// Build artifacts for target app store.
     #[clap(long, conflicts_with = "device", conflicts_with = "format")]
     store: Option<Store>,
-    /// Path to a PEM encoded RSA2048 signing key and certificate
-    /// used to sign artifacts.
-    #[clap(long)]
+    /// Path to a PEM encoded RSA or ECDSA P-256/P-384 signing key and certificate
+    /// used to sign artifacts. An encrypted key is decrypted with the password
+    /// in `X_PASSWORD` or prompted for.
+    #[clap(long, conflicts_with_all = ["pkcs12", "key"])]
     pem: Option<PathBuf>,
+    /// Path to a password protected PKCS#12 key store (`.p12` or `.pfx`)
+    /// used to sign artifacts. The password is read from `X_PASSWORD` or
+    /// prompted for.
+    #[clap(long, conflicts_with = "key")]
+    pkcs12: Option<PathBuf>,
+    /// Path to a PEM encoded signing key used to sign artifacts together with
+    /// the certificate in `--cert`.
+    #[clap(long, requires = "cert")]
+    key: Option<PathBuf>,
+    /// Path to a PEM encoded certificate of the signing key in `--key`.
+    #[clap(long, requires = "key")]
+    cert: Option<PathBuf>,
+    /// Path to a signing certificate lineage used to sign android
+    /// artifacts with a rotated key. Create one with `x rotate-key`.
+    #[clap(long, requires = "original_pem")]
+    lineage: Option<PathBuf>,
+    /// Path to the PEM encoded original signing key and certificate of
+    /// the lineage. Used to sign android artifacts for devices that don't
+    /// support key rotation. Required with `--lineage`.
+    #[clap(long, requires = "lineage")]
+    original_pem: Option<PathBuf>,
+    /// Build a base apk and config splits per android abi and density.
+    /// `x run` only installs the splits the device needs.
+    #[clap(long)]
+    split: bool,
     /// Path to an apple provisioning profile.
     #[clap(long)]
     provisioning_profile: Option<PathBuf>,
@@ -329,13 +359,70 @@ pub struct BuildTargetArgs {
     api_key: Option<PathBuf>,
 }
 
+/// Returns the password of an encrypted signing key from `X_PASSWORD` or
+/// prompts for it.
+fn signing_password() -> Result<String> {
+    if let Ok(password) = std::env::var("X_PASSWORD") {
+        return Ok(password);
+    }
+    let term = console::Term::stderr();
+    anyhow::ensure!(
+        term.features().is_attended(),
+        "the signing key is encrypted, set `X_PASSWORD` to its password"
+    );
+    term.write_str("signing key password: ")?;
+    Ok(term.read_secure_line()?)
+}
+
 impl BuildTargetArgs {
     pub fn build_target(self, config: &Config) -> Result<BuildTarget> {
         let signer = if let Some(pem) = self.pem.as_ref() {
             anyhow::ensure!(pem.exists(), "pem file doesn't exist {}", pem.display());
-            Some(Signer::from_path(pem)?)
+            Some(Signer::from_pem(
+                &std::fs::read_to_string(pem)?,
+                signing_password,
+            )?)
+        } else if let Some(pkcs12) = self.pkcs12.as_ref() {
+            anyhow::ensure!(
+                pkcs12.exists(),
+                "pkcs12 file doesn't exist {}",
+                pkcs12.display()
+            );
+            Some(Signer::from_pkcs12(
+                &std::fs::read(pkcs12)?,
+                &signing_password()?,
+            )?)
+        } else if let (Some(key), Some(cert)) = (self.key.as_ref(), self.cert.as_ref()) {
+            anyhow::ensure!(key.exists(), "key file doesn't exist {}", key.display());
+            anyhow::ensure!(cert.exists(), "cert file doesn't exist {}", cert.display());
+            Some(Signer::from_key_and_cert(
+                &std::fs::read_to_string(key)?,
+                &std::fs::read_to_string(cert)?,
+                signing_password,
+            )?)
         } else if let Ok(pem) = std::env::var("X_PEM") {
-            Some(Signer::new(&pem)?)
+            Some(Signer::from_pem(&pem, signing_password)?)
+        } else {
+            None
+        };
+        let rotation = if let Some(lineage) = self.lineage.as_ref() {
+            anyhow::ensure!(
+                lineage.exists(),
+                "lineage file doesn't exist {}",
+                lineage.display()
+            );
+            let pem = self
+                .original_pem
+                .as_ref()
+                .context("--lineage requires --original-pem")?;
+            anyhow::ensure!(pem.exists(), "pem file doesn't exist {}", pem.display());
+            Some(Rotation {
+                lineage: Lineage::from_path(lineage)?,
+                original: Some(Signer::from_pem(
+                    &std::fs::read_to_string(pem)?,
+                    signing_password,
+                )?),
+            })
         } else {
             None
         };
@@ -362,8 +449,8 @@ impl BuildTargetArgs {
         } else {
             anyhow::bail!("--platform, --store or --device must be provided");
         };
-        let archs = if let Some(arch) = self.arch {
-            vec![arch]
+        let archs = if !self.arch.is_empty() {
+            self.arch
         } else if let Some(store) = store {
             match store {
                 Store::Apple => vec![Arch::X64, Arch::Arm64],
@@ -401,6 +488,10 @@ impl BuildTargetArgs {
         } else {
             None
         };
+        anyhow::ensure!(
+            !self.split || (format == Format::Apk && !config.android().gradle),
+            "--split is only supported for apks built without gradle"
+        );
         let api_key = self.api_key;
         Ok(BuildTarget {
             opt,
@@ -410,6 +501,8 @@ impl BuildTargetArgs {
             device,
             store,
             signer,
+            rotation,
+            split: self.split,
             provisioning_profile,
             api_key,
         })
@@ -425,6 +518,8 @@ pub struct BuildTarget {
     device: Option<Device>,
     store: Option<Store>,
     signer: Option<Signer>,
+    rotation: Option<Rotation>,
+    split: bool,
     provisioning_profile: Option<Vec<u8>>,
     api_key: Option<PathBuf>,
 }
@@ -471,6 +566,14 @@ impl BuildTarget {
         self.signer.as_ref()
     }
 
+    pub fn rotation(&self) -> Option<&Rotation> {
+        self.rotation.as_ref()
+    }
+
+    pub fn split(&self) -> bool {
+        self.split
+    }
+
     pub fn provisioning_profile(&self) -> Option<&[u8]> {
         self.provisioning_profile.as_deref()
     }
@@ -586,6 +689,25 @@ impl BuildEnv {
         self.icon.as_deref()
     }
 
+    /// Returns a scaler of the icon with the configured overrides and
+    /// letterbox color.
+    pub fn icon_scaler(&self) -> Result<Option<Scaler>> {
+        let Some(icon) = self.icon() else {
+            return Ok(None);
+        };
+        let platform = self.target().platform();
+        let mut scaler = Scaler::open(icon)?;
+        for path in self.config.icon_overrides(platform) {
+            scaler.add_override(&self.cargo.package_root().join(path))?;
+        }
+        if let Some(color) = self.config.icon_letterbox(platform) {
+            scaler
+                .set_background(color)
+                .with_context(|| format!("invalid icon_letterbox `{}`", color))?;
+        }
+        Ok(Some(scaler))
+    }
+
     pub fn cargo(&self) -> &Cargo {
         &self.cargo
     }
@@ -611,6 +733,14 @@ impl BuildEnv {
             .join("android.jar")
     }
 
+    pub fn android_d8(&self) -> PathBuf {
+        self.android_sdk()
+            .join("build-tools")
+            .join(crate::dex::BUILD_TOOLS_VERSION)
+            .join("lib")
+            .join("d8.jar")
+    }
+
     pub fn windows_sdk(&self) -> PathBuf {
         self.cache_dir().join("Windows.sdk")
     }
@@ -669,6 +799,9 @@ impl BuildEnv {
                 .target_sdk_version
                 .unwrap();
             cargo.use_android_ndk(&ndk, target_sdk_version)?;
+            if let Some(page_size) = self.config().android().page_size {
+                cargo.add_link_arg(&format!("-Wl,-z,max-page-size={}", page_size));
+            }
         }
         if target.platform() == Platform::Windows {
             let sdk = self.windows_sdk();
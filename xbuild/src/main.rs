@@ -62,6 +62,64 @@ enum Commands {
         /// Path to write a new PEM encoded RSA2048 signing key
         pem: PathBuf,
     },
+    /// Rotates the android signing key by appending the new certificate
+    /// to a signing certificate lineage
+    RotateKey {
+        /// Path to the lineage file. Created if it doesn't exist.
+        #[clap(long)]
+        lineage: PathBuf,
+        /// Path to the PEM encoded current signing key and certificate.
+        #[clap(long)]
+        old_pem: PathBuf,
+        /// Path to the PEM encoded new signing key and certificate.
+        #[clap(long)]
+        new_pem: PathBuf,
+    },
+    /// Prints the resources of an apk or android.jar similar to
+    /// `aapt2 dump resources`
+    DumpResources {
+        /// Only print the resource with this name, like `@string/app_name`
+        /// or `@android:attr/label`, or id like `0x7f010000`.
+        #[clap(long)]
+        resource: Option<String>,
+        /// Path to the apk.
+        apk: PathBuf,
+    },
+    /// Builds a signed apk set with a split per abi, density and language
+    /// and a universal apk from an android app bundle
+    BuildApks {
+        /// Path to a PEM encoded signing key and certificate. Defaults to
+        /// the debug key.
+        #[clap(long)]
+        pem: Option<PathBuf>,
+        /// Path to the aab.
+        aab: PathBuf,
+        /// Path to write the apk set to.
+        apks: PathBuf,
+    },
+    /// Checks that the uncompressed entries of an apk are aligned like
+    /// `zipalign -c -p`
+    CheckAlignment {
+        /// Page size uncompressed native libraries must be aligned to.
+        #[clap(long, default_value = "4096")]
+        page_size: u16,
+        /// Path to the apk.
+        apk: PathBuf,
+    },
+    /// Verifies the signatures of an apk and prints the signer certificates
+    /// like `apksigner verify --print-certs`
+    Verify {
+        /// Fails unless the apk is signed by the certificate with this
+        /// SHA-256 digest.
+        #[clap(long)]
+        sha256: Option<String>,
+        /// Accepts an apk signed with the debug certificate, which fails
+        /// unless its digest is passed with `--sha256`.
+        #[clap(long)]
+        allow_debug: bool,
+        /// Path to the apk.
+        apk: PathBuf,
+    },
     CreateAppleApiKey {
         /// Issuer id.
         #[clap(long)]
@@ -121,6 +179,21 @@ impl Commands {
             } => {
                 app_store_connect::certs_api::generate_signing_certificate(&api_key, r#type, &pem)?
             }
+            Self::RotateKey {
+                lineage,
+                old_pem,
+                new_pem,
+            } => command::rotate_key(&lineage, &old_pem, &new_pem)?,
+            Self::DumpResources { resource, apk } => {
+                command::dump_resources(&apk, resource.as_deref())?
+            }
+            Self::BuildApks { pem, aab, apks } => command::build_apks(&aab, &apks, pem.as_deref())?,
+            Self::CheckAlignment { page_size, apk } => command::check_alignment(&apk, page_size)?,
+            Self::Verify {
+                sha256,
+                allow_debug,
+                apk,
+            } => command::verify(&apk, sha256.as_deref(), allow_debug)?,
             Self::CreateAppleApiKey {
                 issuer_id,
                 key_id,
@@ -0,0 +1,32 @@
+//! Support for android apps using `wry`. Its build script generates kotlin
+//! sources into the `wry` directory of the platform dir, which both the
+//! gradle and the native android builds compile with the app sources.
+
+use crate::BuildEnv;
+use anyhow::Result;
+
+/// Writes the `MainActivity` of the app and sets the environment variables
+/// the `wry` build script reads. Must run before the rust build.
+pub fn prepare(env: &BuildEnv) -> Result<()> {
+    let config = env.config().android();
+    if config.wry {
+        let package = config.manifest.package.as_ref().unwrap();
+        let wry = env.platform_dir().join("wry");
+        std::fs::create_dir_all(&wry)?;
+        if !env.cargo().package_root().join("kotlin").exists() {
+            let main_activity = format!(
+                r#"
+                    package {}
+                    class MainActivity : TauriActivity()
+                "#,
+                package,
+            );
+            std::fs::write(wry.join("MainActivity.kt"), main_activity)?;
+        }
+        let (package, name) = package.rsplit_once('.').unwrap();
+        std::env::set_var("WRY_ANDROID_REVERSED_DOMAIN", package);
+        std::env::set_var("WRY_ANDROID_APP_NAME_SNAKE_CASE", name);
+        std::env::set_var("WRY_ANDROID_KOTLIN_FILES_OUT_DIR", wry);
+    }
+    Ok(())
+}
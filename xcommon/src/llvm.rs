@@ -1,7 +1,7 @@
 //! LLVM utilities
 
 use anyhow::{bail, ensure, Context, Result};
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::ffi::{OsStr, OsString};
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -16,8 +16,8 @@ pub fn list_needed_libs_recursively(
     lib: &Path,
     search_paths: &[&Path],
     provided_libs: &HashSet<OsString>,
-) -> Result<(HashSet<PathBuf>, bool)> {
-    let mut to_copy = HashSet::new();
+) -> Result<(BTreeSet<PathBuf>, bool)> {
+    let mut to_copy = BTreeSet::new();
     let mut needs_cpp_shared = false;
 
     let mut artifacts = vec![lib.to_path_buf()];
@@ -0,0 +1,158 @@
+//! Renders svg icons with resvg.
+
+use anyhow::{Context, Result};
+use image::RgbaImage;
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::roxmltree::{Document, ParsingOptions};
+use resvg::usvg::{Options, Tree};
+use std::str::FromStr;
+use std::sync::Arc;
+
+const SVG_NS: &str = "http://www.w3.org/2000/svg";
+
+/// A parsed svg document.
+#[derive(Clone)]
+pub struct Svg {
+    tree: Arc<Tree>,
+}
+
+impl Svg {
+    /// No fonts are loaded, so that icons render the same on every host.
+    /// Svgs containing text are rejected, text needs to be converted to
+    /// paths to be rendered.
+    pub fn parse(source: String) -> Result<Self> {
+        let opts = ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        };
+        let doc = Document::parse_with_options(&source, opts)?;
+        anyhow::ensure!(
+            !doc.descendants()
+                .any(|node| node.has_tag_name((SVG_NS, "text"))),
+            "svg icons can't contain text, convert text to paths"
+        );
+        let tree = Tree::from_xmltree(&doc, &Options::default())?;
+        Ok(Self {
+            tree: Arc::new(tree),
+        })
+    }
+
+    /// Returns the size of the svg.
+    pub fn size(&self) -> (f32, f32) {
+        let size = self.tree.size();
+        (size.width(), size.height())
+    }
+
+    /// Renders the svg scaled to fit `width` x `height` and centered.
+    pub fn render(&self, width: u32, height: u32) -> Result<RgbaImage> {
+        let (w, h) = self.size();
+        let scale = f32::min(width as f32 / w, height as f32 / h);
+        let dx = (width as f32 - w * scale) / 2.0;
+        let dy = (height as f32 - h * scale) / 2.0;
+        let mut pixmap = Pixmap::new(width, height).context("invalid svg render size")?;
+        let transform = Transform::from_row(scale, 0.0, 0.0, scale, dx, dy);
+        resvg::render(&self.tree, transform, &mut pixmap.as_mut());
+        let pixels = pixmap
+            .pixels()
+            .iter()
+            .flat_map(|pixel| {
+                let pixel = pixel.demultiply();
+                [pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()]
+            })
+            .collect();
+        Ok(RgbaImage::from_raw(width, height, pixels).unwrap())
+    }
+}
+
+/// Parses a css color like `#ffffff`, `rgb(255, 255, 255)` or `white`.
+pub fn parse_color(s: &str) -> Result<[u8; 4]> {
+    let color = svgtypes::Color::from_str(s).with_context(|| format!("invalid color `{}`", s))?;
+    Ok([color.red, color.green, color.blue, color.alpha])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(svg: &str, width: u32, height: u32) -> Result<RgbaImage> {
+        Svg::parse(svg.into())?.render(width, height)
+    }
+
+    #[test]
+    fn render_shapes() -> Result<()> {
+        let img = render(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10">
+                <rect width="10" height="5" fill="#ff0000"/>
+                <circle cx="5" cy="7.5" r="2" style="fill: blue; opacity: 0.5"/>
+            </svg>"##,
+            20,
+            20,
+        )?;
+        assert_eq!(img.get_pixel(10, 2).0, [255, 0, 0, 255]);
+        assert_eq!(img.get_pixel(10, 15).0, [0, 0, 255, 128]);
+        assert_eq!(img.get_pixel(1, 19).0, [0, 0, 0, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn render_gradients() -> Result<()> {
+        let img = render(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10">
+                <defs>
+                    <linearGradient id="gradient">
+                        <stop offset="0" stop-color="#ff0000"/>
+                        <stop offset="1" stop-color="#0000ff"/>
+                    </linearGradient>
+                </defs>
+                <rect width="10" height="10" fill="url(#gradient)"/>
+            </svg>"##,
+            10,
+            10,
+        )?;
+        let left = img.get_pixel(0, 5).0;
+        let right = img.get_pixel(9, 5).0;
+        assert!(left[0] > left[2], "{:?}", left);
+        assert!(right[2] > right[0], "{:?}", right);
+        Ok(())
+    }
+
+    #[test]
+    fn render_letterboxed() -> Result<()> {
+        let img = render(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 20 10">
+                <rect width="20" height="10"/>
+            </svg>"#,
+            10,
+            10,
+        )?;
+        assert_eq!(img.get_pixel(5, 1).0[3], 0);
+        assert_eq!(img.get_pixel(5, 5).0[3], 255);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_text() {
+        let err = render(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10">
+                <g><text x="0" y="10" font-size="10">W</text></g>
+            </svg>"#,
+            10,
+            10,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "svg icons can't contain text, convert text to paths"
+        );
+    }
+
+    #[test]
+    fn colors() -> Result<()> {
+        assert_eq!(parse_color("#fff")?, [255; 4]);
+        assert_eq!(parse_color("#ff000080")?[3], 128);
+        assert_eq!(parse_color("rgb(0, 0, 255)")?, [0, 0, 255, 255]);
+        assert_eq!(parse_color("cornflowerblue")?, [100, 149, 237, 255]);
+        assert!(parse_color("#ff").is_err());
+        Ok(())
+    }
+}